@@ -1,8 +1,8 @@
 mod visitor;
 
-pub use visitor::{visit_ast, Visitor};
+pub use visitor::{visit_ast, visit_stmt, Visitor};
 
-use crate::parse::{Parse, ParseStream};
+use crate::parse::{Delimiters, Parse, ParseStream};
 use crate::{
     error::{Error, Result},
     lex, Span,
@@ -44,23 +44,52 @@ pub type Ast<'a> = Vec<Stmt<'a>>;
 pub enum Stmt<'a> {
     LetLocal(LetLocal<'a>),
     LetIVar(LetIVar<'a>),
+    LetConstant(LetConstant<'a>),
     MessageSend(MessageSendStmt<'a>),
     Return(Return<'a>),
     DefineMethod(DefineMethod<'a>),
+    DefineAbstractMethod(DefineAbstractMethod<'a>),
     DefineClass(DefineClass<'a>),
+    ReopenClass(ReopenClass<'a>),
+    Module(Module<'a>),
+    CaseOf(CaseOfStmt<'a>),
+}
+
+impl<'a> Stmt<'a> {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::LetLocal(inner) => inner.span,
+            Stmt::LetIVar(inner) => inner.span,
+            Stmt::LetConstant(inner) => inner.span,
+            Stmt::MessageSend(inner) => inner.span,
+            Stmt::Return(inner) => inner.span,
+            Stmt::DefineMethod(inner) => inner.span,
+            Stmt::DefineAbstractMethod(inner) => inner.span,
+            Stmt::DefineClass(inner) => inner.span,
+            Stmt::ReopenClass(inner) => inner.span,
+            Stmt::Module(inner) => inner.span,
+            Stmt::CaseOf(inner) => inner.span,
+        }
+    }
 }
 
 impl_into!(Stmt, LetLocal<'a>);
 impl_into!(Stmt, LetIVar<'a>);
+impl_into!(Stmt, LetConstant<'a>);
 impl_into!(Stmt, MessageSend, MessageSendStmt<'a>);
 impl_into!(Stmt, Return<'a>);
 impl_into!(Stmt, DefineMethod<'a>);
+impl_into!(Stmt, DefineAbstractMethod<'a>);
 impl_into!(Stmt, DefineClass<'a>);
+impl_into!(Stmt, ReopenClass<'a>);
+impl_into!(Stmt, Module<'a>);
+impl_into!(Stmt, CaseOf, CaseOfStmt<'a>);
 
 #[derive(Eq, PartialEq, Debug)]
 pub struct LetLocal<'a> {
     pub ident: Ident<'a>,
     pub body: Expr<'a>,
+    pub slot: Slot,
     pub span: Span,
 }
 
@@ -71,6 +100,15 @@ pub struct LetIVar<'a> {
     pub span: Span,
 }
 
+/// `let MAX = 10;`. Constants are visible everywhere once defined, including in methods, and can't
+/// be defined again.
+#[derive(Eq, PartialEq, Debug)]
+pub struct LetConstant<'a> {
+    pub name: ClassName<'a>,
+    pub body: Expr<'a>,
+    pub span: Span,
+}
+
 #[derive(Eq, PartialEq, Debug)]
 pub struct DefineMethod<'a> {
     pub class_name: ClassName<'a>,
@@ -79,12 +117,28 @@ pub struct DefineMethod<'a> {
     pub span: Span,
 }
 
+/// `[Shape defAbstract: #area];`. Subclasses have to define the method before they can be
+/// instantiated.
+#[derive(Eq, PartialEq, Debug)]
+pub struct DefineAbstractMethod<'a> {
+    pub class_name: ClassName<'a>,
+    pub method_name: Selector<'a>,
+    pub span: Span,
+}
+
 #[derive(Eq, PartialEq, Debug)]
 pub struct MessageSendStmt<'a> {
     pub expr: MessageSend<'a>,
     pub span: Span,
 }
 
+/// A `caseOf:` send used as a statement.
+#[derive(Eq, PartialEq, Debug)]
+pub struct CaseOfStmt<'a> {
+    pub expr: CaseOf<'a>,
+    pub span: Span,
+}
+
 #[derive(Eq, PartialEq, Debug)]
 pub struct Return<'a> {
     pub expr: Expr<'a>,
@@ -94,11 +148,33 @@ pub struct Return<'a> {
 #[derive(Eq, PartialEq, Debug)]
 pub struct DefineClass<'a> {
     pub name: ClassNameSelector<'a>,
+    /// The name including the enclosing modules, like `Http::Request`. The parser sets it to
+    /// `name`, and `prep::qualify_names` adds the modules.
+    pub qualified_name: String,
     pub fields: Vec<Selector<'a>>,
     pub super_class: ClassNameSelector<'a>,
     pub span: Span,
 }
 
+/// `[User reopen fields: [#email]];`. Adds fields to a class defined elsewhere.
+#[derive(Eq, PartialEq, Debug)]
+pub struct ReopenClass<'a> {
+    pub class_name: ClassName<'a>,
+    pub fields: Vec<Selector<'a>>,
+    pub span: Span,
+}
+
+/// `module Http { ... }`. Classes defined inside are named `Http::Name`, and class names used
+/// inside refer to the module's classes before global ones.
+#[derive(Eq, PartialEq, Debug)]
+pub struct Module<'a> {
+    pub name: ClassName<'a>,
+    /// The name including the enclosing modules, set like `DefineClass::qualified_name`.
+    pub qualified_name: String,
+    pub body: Vec<Stmt<'a>>,
+    pub span: Span,
+}
+
 //
 // Expressions
 //
@@ -109,12 +185,20 @@ pub enum Expr<'a> {
     IVar(IVar<'a>),
     MessageSend(Box<MessageSend<'a>>),
     ClassNew(ClassNew<'a>),
+    ClassName(ClassName<'a>),
     Block(Block<'a>),
     Number(Number),
+    Str(Str),
     List(List<'a>),
+    /// A list literal whose items are all constants, which `-O` evaluates only once.
+    ConstantList(List<'a>),
+    Selector(Selector<'a>),
     True(True),
     False(False),
     Self_(Self_),
+    Quote(Quote<'a>),
+    Unquote(Unquote<'a>),
+    CaseOf(Box<CaseOf<'a>>),
 }
 
 impl<'a> Expr<'a> {
@@ -124,12 +208,19 @@ impl<'a> Expr<'a> {
             Expr::IVar(inner) => inner.span,
             Expr::MessageSend(inner) => inner.span,
             Expr::ClassNew(inner) => inner.span,
+            Expr::ClassName(inner) => inner.0.span,
             Expr::Block(inner) => inner.span,
             Expr::Number(inner) => inner.span,
+            Expr::Str(inner) => inner.span,
             Expr::List(inner) => inner.span,
+            Expr::ConstantList(inner) => inner.span,
+            Expr::Selector(inner) => inner.span,
             Expr::True(inner) => inner.0,
             Expr::False(inner) => inner.0,
             Expr::Self_(inner) => inner.0,
+            Expr::Quote(inner) => inner.span,
+            Expr::Unquote(inner) => inner.span,
+            Expr::CaseOf(inner) => inner.span,
         }
     }
 }
@@ -137,12 +228,17 @@ impl<'a> Expr<'a> {
 impl_into!(Expr, Local<'a>);
 impl_into!(Expr, IVar<'a>);
 impl_into!(Expr, ClassNew<'a>);
+impl_into!(Expr, ClassName<'a>);
 impl_into!(Expr, Block<'a>);
 impl_into!(Expr, Number);
+impl_into!(Expr, Str);
 impl_into!(Expr, List<'a>);
+impl_into!(Expr, Selector<'a>);
 impl_into!(Expr, True);
 impl_into!(Expr, False);
 impl_into!(Expr, Self_);
+impl_into!(Expr, Quote<'a>);
+impl_into!(Expr, Unquote<'a>);
 
 impl<'a> From<Box<MessageSend<'a>>> for Expr<'a> {
     fn from(inner: Box<MessageSend<'a>>) -> Expr<'a> {
@@ -154,7 +250,12 @@ impl<'a> From<Box<MessageSend<'a>>> for Expr<'a> {
 pub struct ClassName<'a>(pub Ident<'a>);
 
 #[derive(Eq, PartialEq, Debug)]
-pub struct Local<'a>(pub Ident<'a>);
+pub struct Local<'a>(pub Ident<'a>, pub Slot);
+
+/// Where a local is kept in the frame of the scope it's used in. Parsed nodes have no slot, and
+/// `prep::resolve_slots` sets it for every local that's defined where it's used.
+#[derive(Eq, PartialEq, Debug, Default, Copy, Clone)]
+pub struct Slot(pub Option<usize>);
 
 #[derive(Eq, PartialEq, Debug)]
 pub struct IVar<'a> {
@@ -168,6 +269,12 @@ pub struct Number {
     pub span: Span,
 }
 
+#[derive(Eq, PartialEq, Debug)]
+pub struct Str {
+    pub value: String,
+    pub span: Span,
+}
+
 #[derive(Eq, PartialEq, Debug)]
 pub struct List<'a> {
     pub items: Vec<Expr<'a>>,
@@ -189,6 +296,100 @@ pub struct Selector<'a> {
     pub span: Span,
 }
 
+/// `quote { stmts }`, which evaluates to the statements as data.
+#[derive(Eq, PartialEq, Debug)]
+pub struct Quote<'a> {
+    pub body: Vec<Stmt<'a>>,
+    pub span: Span,
+}
+
+/// `[subject caseOf: [[value, body], ...] otherwise: body]`, where `otherwise:` is optional.
+///
+/// Parsed from a message send of that shape, so the arms' values are only evaluated until one of
+/// them equals the subject.
+#[derive(Eq, PartialEq, Debug)]
+pub struct CaseOf<'a> {
+    pub subject: Expr<'a>,
+    pub arms: Vec<CaseArm<'a>>,
+    pub otherwise: Option<Expr<'a>>,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct CaseArm<'a> {
+    pub value: Expr<'a>,
+    pub body: Expr<'a>,
+    pub span: Span,
+}
+
+impl<'a> CaseOf<'a> {
+    /// Whether `send` has the shape of a `caseOf:`.
+    fn is_case_of(send: &MessageSend<'_>) -> bool {
+        let is_arm = |arm: &Expr<'_>| matches!(arm, Expr::List(pair) if pair.items.len() == 2);
+        send.msg.name == "caseOf"
+            && match send.args.as_slice() {
+                [arms]
+                | [arms, Argument {
+                    ident:
+                        Ident {
+                            name: "otherwise", ..
+                        },
+                    ..
+                }] => matches!(&arms.expr, Expr::List(arms) if arms.items.iter().all(is_arm)),
+                _ => false,
+            }
+    }
+
+    /// Convert a send that `is_case_of`.
+    fn from_send(send: MessageSend<'a>) -> Self {
+        let MessageSend {
+            receiver,
+            mut args,
+            span,
+            ..
+        } = send;
+        let otherwise = if args.len() == 2 {
+            args.pop().map(|arg| arg.expr)
+        } else {
+            None
+        };
+        let arms = match args.pop().map(|arg| arg.expr) {
+            Some(Expr::List(arms)) => arms.items.into_iter().map(CaseArm::from_pair).collect(),
+            _ => unreachable!("Internal error: checked to be a list"),
+        };
+
+        CaseOf {
+            subject: receiver,
+            arms,
+            otherwise,
+            span,
+        }
+    }
+}
+
+impl<'a> CaseArm<'a> {
+    fn from_pair(pair: Expr<'a>) -> Self {
+        match pair {
+            Expr::List(List { items, span }) => {
+                let mut items = items.into_iter();
+                match (items.next(), items.next()) {
+                    (Some(value), Some(body)) => CaseArm { value, body, span },
+                    _ => unreachable!("Internal error: checked to be a pair"),
+                }
+            }
+            _ => unreachable!("Internal error: checked to be a list"),
+        }
+    }
+}
+
+/// `~name`, which splices the value of a local into the surrounding quote.
+#[derive(Eq, PartialEq, Debug)]
+pub struct Unquote<'a> {
+    pub ident: Ident<'a>,
+    pub slot: Slot,
+    pub span: Span,
+}
+
 #[derive(Eq, PartialEq, Debug)]
 pub struct ClassNameSelector<'a> {
     pub class_name: ClassName<'a>,
@@ -205,6 +406,7 @@ pub struct Block<'a> {
 #[derive(Eq, PartialEq, Debug)]
 pub struct Parameter<'a> {
     pub ident: Ident<'a>,
+    pub slot: Slot,
     pub span: Span,
 }
 
@@ -234,7 +436,7 @@ pub struct ClassNew<'a> {
 // Misc
 //
 
-#[derive(Eq, PartialEq, Debug, Hash)]
+#[derive(Eq, PartialEq, Debug, Hash, Copy, Clone)]
 pub struct Ident<'a> {
     pub name: &'a str,
     pub span: Span,
@@ -255,10 +457,26 @@ macro_rules! try_parse_node {
 impl<'a> Parse<'a> for Stmt<'a> {
     fn parse(stream: &mut ParseStream<'a>) -> Result<'a, Self> {
         try_parse_node!(DefineClass, stream);
+        try_parse_node!(ReopenClass, stream);
         try_parse_node!(DefineMethod, stream);
+        try_parse_node!(DefineAbstractMethod, stream);
         try_parse_node!(LetLocal, stream);
         try_parse_node!(LetIVar, stream);
-        try_parse_node!(MessageSendStmt, stream);
+        if stream.peek_token::<lex::Let>().is_some() {
+            try_parse_node!(LetConstant, stream);
+        }
+        if let Some(lex::Name { name: "module", .. }) = stream.peek_token::<lex::Name>() {
+            return Ok(stream.parse_node::<Module>()?.into());
+        }
+
+        if let Some(MessageSendStmt { expr, span }) = stream.try_parse_node::<MessageSendStmt>() {
+            if CaseOf::is_case_of(&expr) {
+                let expr = CaseOf::from_send(expr);
+                return Ok(Stmt::CaseOf(CaseOfStmt { expr, span }));
+            }
+            return Ok(Stmt::MessageSend(MessageSendStmt { expr, span }));
+        }
+
         try_parse_node!(Return, stream);
 
         Err(Error::ParseError("stmt parse failed".to_string()))
@@ -276,7 +494,8 @@ impl<'a> Parse<'a> for LetLocal<'a> {
         Ok(LetLocal {
             ident,
             body,
-            span: Span::new(start.from, end.to),
+            slot: Slot::default(),
+            span: start.merge(end),
         })
     }
 }
@@ -293,7 +512,40 @@ impl<'a> Parse<'a> for LetIVar<'a> {
         Ok(LetIVar {
             ident,
             body,
-            span: Span::new(start.from, end.to),
+            span: start.merge(end),
+        })
+    }
+}
+
+impl<'a> Parse<'a> for LetConstant<'a> {
+    fn parse(stream: &mut ParseStream<'a>) -> Result<'a, Self> {
+        let start = stream.parse_token::<lex::Let>()?.span;
+        let name = stream.parse_node::<ClassName>()?;
+        stream.parse_token::<lex::Eq>()?;
+        let body = stream.parse_node::<Expr>()?;
+        let end = stream.parse_token::<lex::Semicolon>()?.span;
+
+        Ok(LetConstant {
+            name,
+            body,
+            span: start.merge(end),
+        })
+    }
+}
+
+impl<'a> Parse<'a> for Module<'a> {
+    fn parse(stream: &mut ParseStream<'a>) -> Result<'a, Self> {
+        let start = stream.parse_specific_ident("module")?.span;
+        let name = stream.parse_node::<ClassName>()?;
+        stream.parse_token::<lex::OBrace>()?;
+        let body = stream.parse_many::<Stmt>();
+        let end = stream.parse_token::<lex::CBrace>()?.span;
+
+        Ok(Module {
+            qualified_name: name.0.name.to_string(),
+            name,
+            body,
+            span: start.merge(end),
         })
     }
 }
@@ -306,7 +558,7 @@ impl<'a> Parse<'a> for Return<'a> {
 
         Ok(Return {
             expr,
-            span: Span::new(start.from, end.to),
+            span: start.merge(end),
         })
     }
 }
@@ -319,7 +571,7 @@ impl<'a> Parse<'a> for MessageSendStmt<'a> {
 
         Ok(MessageSendStmt {
             expr,
-            span: Span::new(start.from, end.to),
+            span: start.merge(end),
         })
     }
 }
@@ -347,7 +599,55 @@ impl<'a> Parse<'a> for DefineMethod<'a> {
             class_name,
             method_name,
             block,
-            span: Span::new(start.from, end.to),
+            span: start.merge(end),
+        })
+    }
+}
+
+impl<'a> Parse<'a> for ReopenClass<'a> {
+    fn parse(stream: &mut ParseStream<'a>) -> Result<'a, Self> {
+        let start = stream.parse_token::<lex::OBracket>()?.span;
+
+        let class_name = stream.parse_node::<ClassName>()?;
+
+        stream.parse_specific_ident("reopen")?;
+
+        stream.parse_specific_ident("fields")?;
+        stream.parse_token::<lex::Colon>()?;
+        stream.parse_token::<lex::OBracket>()?;
+        let fields = stream.parse_many_delimited::<Selector, lex::Comma>(Delimiters::Optional);
+        stream.parse_token::<lex::CBracket>()?;
+
+        stream.parse_token::<lex::CBracket>()?;
+
+        let end = stream.parse_token::<lex::Semicolon>()?.span;
+
+        Ok(ReopenClass {
+            class_name,
+            fields,
+            span: start.merge(end),
+        })
+    }
+}
+
+impl<'a> Parse<'a> for DefineAbstractMethod<'a> {
+    fn parse(stream: &mut ParseStream<'a>) -> Result<'a, Self> {
+        let start = stream.parse_token::<lex::OBracket>()?.span;
+
+        let class_name = stream.parse_node::<ClassName>()?;
+
+        stream.parse_specific_ident("defAbstract")?;
+        stream.parse_token::<lex::Colon>()?;
+        let method_name = stream.parse_node::<Selector>()?;
+
+        stream.parse_token::<lex::CBracket>()?;
+
+        let end = stream.parse_token::<lex::Semicolon>()?.span;
+
+        Ok(DefineAbstractMethod {
+            class_name,
+            method_name,
+            span: start.merge(end),
         })
     }
 }
@@ -374,7 +674,7 @@ impl<'a> Parse<'a> for DefineClass<'a> {
         stream.parse_specific_ident("fields")?;
         stream.parse_token::<lex::Colon>()?;
         stream.parse_token::<lex::OBracket>()?;
-        let fields = stream.parse_many::<Selector>();
+        let fields = stream.parse_many_delimited::<Selector, lex::Comma>(Delimiters::Optional);
         stream.parse_token::<lex::CBracket>()?;
 
         stream.parse_token::<lex::CBracket>()?;
@@ -382,10 +682,11 @@ impl<'a> Parse<'a> for DefineClass<'a> {
         let end = stream.parse_token::<lex::Semicolon>()?.span;
 
         Ok(DefineClass {
+            qualified_name: name.class_name.0.name.to_string(),
             name,
             fields,
             super_class,
-            span: Span::new(start.from, end.to),
+            span: start.merge(end),
         })
     }
 }
@@ -400,22 +701,90 @@ impl<'a> Parse<'a> for Ident<'a> {
 
 impl<'a> Parse<'a> for Expr<'a> {
     fn parse(stream: &mut ParseStream<'a>) -> Result<'a, Self> {
-        try_parse_node!(ClassNew, stream);
-        try_parse_node!(Local, stream);
-        try_parse_node!(IVar, stream);
-        try_parse_node!(Block, stream);
-        try_parse_node!(Number, stream);
-        try_parse_node!(List, stream);
-        try_parse_node!(True, stream);
-        try_parse_node!(False, stream);
-        try_parse_node!(Self_, stream);
-
-        if let Some(inner) = stream.try_parse_node::<MessageSend>() {
-            return Ok(Box::new(inner).into());
-        }
+        parse_binary(stream, 0)
+    }
+}
+
+/// Binary operators grouped from loosest to tightest binding, with the messages they're short
+/// for. `a + b` is the same as `[a add: b]`.
+const BINARY_OPERATORS: &[&[(&str, &str)]] = &[
+    &[
+        ("==", "equals"),
+        ("!=", "notEquals"),
+        ("<", "lessThan"),
+        (">", "greaterThan"),
+    ],
+    &[("+", "add"), ("-", "subtract")],
+    &[("*", "multiply"), ("/", "divide")],
+];
+
+/// Parse operators at `level` of `BINARY_OPERATORS` and tighter, associating to the left.
+fn parse_binary<'a>(stream: &mut ParseStream<'a>, level: usize) -> Result<'a, Expr<'a>> {
+    let operators = match BINARY_OPERATORS.get(level) {
+        Some(operators) => operators,
+        None => return parse_operand(stream),
+    };
+
+    let mut lhs = parse_binary(stream, level + 1)?;
+    loop {
+        let operator = stream.peek_token::<lex::Operator>().and_then(|operator| {
+            operators
+                .iter()
+                .find(|(symbol, _)| *symbol == operator.op)
+                .map(|(_, msg)| (*msg, operator.span))
+        });
+        let (msg, span) = match operator {
+            Some(operator) => operator,
+            None => return Ok(lhs),
+        };
+        stream.parse_token::<lex::Operator>()?;
+        let rhs = parse_binary(stream, level + 1)?;
+
+        let send = MessageSend {
+            span: lhs.span().merge(rhs.span()),
+            receiver: lhs,
+            msg: Ident { name: msg, span },
+            args: vec![Argument {
+                ident: Ident { name: msg, span },
+                span: span.merge(rhs.span()),
+                expr: rhs,
+            }],
+        };
+        lhs = Expr::MessageSend(Box::new(send));
+    }
+}
+
+fn parse_operand<'a>(stream: &mut ParseStream<'a>) -> Result<'a, Expr<'a>> {
+    // `(a + b) * c`. The parentheses only group, so there's no node for them
+    if stream.try_parse_token::<lex::OParen>().is_some() {
+        let expr = stream.parse_node::<Expr>()?;
+        stream.parse_token::<lex::CParen>()?;
+        return Ok(expr);
+    }
 
-        Err(Error::ParseError("expr parse failed".to_string()))
+    try_parse_node!(ClassNew, stream);
+    try_parse_node!(ClassName, stream);
+    try_parse_node!(Quote, stream);
+    try_parse_node!(Unquote, stream);
+    try_parse_node!(Local, stream);
+    try_parse_node!(IVar, stream);
+    try_parse_node!(Block, stream);
+    try_parse_node!(Number, stream);
+    try_parse_node!(Str, stream);
+    try_parse_node!(List, stream);
+    try_parse_node!(Selector, stream);
+    try_parse_node!(True, stream);
+    try_parse_node!(False, stream);
+    try_parse_node!(Self_, stream);
+
+    if let Some(inner) = stream.try_parse_node::<MessageSend>() {
+        if CaseOf::is_case_of(&inner) {
+            return Ok(Expr::CaseOf(Box::new(CaseOf::from_send(inner))));
+        }
+        return Ok(Box::new(inner).into());
     }
+
+    Err(Error::ParseError("expr parse failed".to_string()))
 }
 
 impl<'a> Parse<'a> for Number {
@@ -428,6 +797,16 @@ impl<'a> Parse<'a> for Number {
     }
 }
 
+impl<'a> Parse<'a> for Str {
+    fn parse(stream: &mut ParseStream<'a>) -> Result<'a, Self> {
+        let lex::Str { value, span } = stream.parse_token()?;
+        Ok(Str {
+            value: value.clone(),
+            span: *span,
+        })
+    }
+}
+
 impl<'a> Parse<'a> for ClassName<'a> {
     fn parse(stream: &mut ParseStream<'a>) -> Result<'a, Self> {
         let lex::ClassName { name, span } = stream.parse_token()?;
@@ -438,7 +817,7 @@ impl<'a> Parse<'a> for ClassName<'a> {
 impl<'a> Parse<'a> for Local<'a> {
     fn parse(stream: &mut ParseStream<'a>) -> Result<'a, Self> {
         let lex::Name { name, span } = stream.parse_token()?;
-        Ok(Local(Ident { name, span: *span }))
+        Ok(Local(Ident { name, span: *span }, Slot::default()))
     }
 }
 
@@ -450,7 +829,7 @@ impl<'a> Parse<'a> for IVar<'a> {
 
         Ok(IVar {
             ident,
-            span: Span::new(start.from, end.to),
+            span: start.merge(end),
         })
     }
 }
@@ -463,7 +842,35 @@ impl<'a> Parse<'a> for Selector<'a> {
 
         Ok(Selector {
             ident,
-            span: Span::new(start.from, end.to),
+            span: start.merge(end),
+        })
+    }
+}
+
+impl<'a> Parse<'a> for Quote<'a> {
+    fn parse(stream: &mut ParseStream<'a>) -> Result<'a, Self> {
+        let start = stream.parse_specific_ident("quote")?.span;
+        stream.parse_token::<lex::OBrace>()?;
+        let body = stream.parse_many::<Stmt>();
+        let end = stream.parse_token::<lex::CBrace>()?.span;
+
+        Ok(Quote {
+            body,
+            span: start.merge(end),
+        })
+    }
+}
+
+impl<'a> Parse<'a> for Unquote<'a> {
+    fn parse(stream: &mut ParseStream<'a>) -> Result<'a, Self> {
+        let start = stream.parse_token::<lex::Tilde>()?.span;
+        let ident = stream.parse_node::<Ident>()?;
+        let end = ident.span;
+
+        Ok(Unquote {
+            ident,
+            slot: Slot::default(),
+            span: start.merge(end),
         })
     }
 }
@@ -476,7 +883,7 @@ impl<'a> Parse<'a> for ClassNameSelector<'a> {
 
         Ok(ClassNameSelector {
             class_name,
-            span: Span::new(start.from, end.to),
+            span: start.merge(end),
         })
     }
 }
@@ -505,11 +912,11 @@ impl<'a> Parse<'a> for Self_ {
 impl<'a> Parse<'a> for List<'a> {
     fn parse(stream: &mut ParseStream<'a>) -> Result<'a, Self> {
         let start = stream.parse_token::<lex::OBracket>()?.span;
-        let items = stream.parse_many_delimited::<Expr<'a>, lex::Comma>();
+        let items = stream.parse_many_delimited::<Expr<'a>, lex::Comma>(Delimiters::Required);
         let end = stream.parse_token::<lex::CBracket>()?.span;
         Ok(List {
             items,
-            span: Span::new(start.from, end.to),
+            span: start.merge(end),
         })
     }
 }
@@ -518,10 +925,41 @@ impl<'a> Parse<'a> for MessageSend<'a> {
     fn parse(stream: &mut ParseStream<'a>) -> Result<'a, Self> {
         let start = stream.parse_token::<lex::OBracket>()?.span;
 
-        let receiver = stream.parse_node::<Expr>()?;
-        let msg = stream.parse_node::<Ident>()?;
-
-        let args = stream.parse_many::<Argument>();
+        let mut receiver = stream.parse_node::<Expr>()?;
+
+        // `[list map: block]` is short for `[list map map: block]`
+        let (msg, args) = if let Some(first_arg) = stream.try_parse_node::<Argument>() {
+            let msg = Ident {
+                name: first_arg.ident.name,
+                span: first_arg.ident.span,
+            };
+            let mut args = vec![first_arg];
+            args.extend(stream.parse_many::<Argument>());
+            (msg, args)
+        } else {
+            let mut msg = stream.parse_node::<Ident>()?;
+            let mut args = stream.parse_many::<Argument>();
+
+            // `[list reverse first]` is short for `[[list reverse] first]`. Only the last message
+            // in the chain can take arguments
+            while args.is_empty() {
+                let next = match stream.try_parse_token::<lex::Name>() {
+                    Some(lex::Name { name, span }) => Ident { name, span: *span },
+                    None => break,
+                };
+                let span = start.merge(msg.span);
+                let send = MessageSend {
+                    receiver,
+                    msg,
+                    args,
+                    span,
+                };
+                receiver = Expr::MessageSend(Box::new(send));
+                msg = next;
+                args = stream.parse_many::<Argument>();
+            }
+            (msg, args)
+        };
 
         let end = stream.parse_token::<lex::CBracket>()?.span;
 
@@ -529,14 +967,38 @@ impl<'a> Parse<'a> for MessageSend<'a> {
             receiver,
             msg,
             args,
-            span: Span::new(start.from, end.to),
+            span: start.merge(end),
         })
     }
 }
 
+/// The name before the colon of a keyword argument. `true`, `false`, and `let` can be used as
+/// well, so `[Assert true: value]` and `[Dyn let: #name be: value in: block]` parse.
+fn argument_name<'a>(stream: &mut ParseStream<'a>) -> Result<'a, Ident<'a>> {
+    if let Some(token) = stream.try_parse_token::<lex::True>() {
+        return Ok(Ident {
+            name: "true",
+            span: token.span,
+        });
+    }
+    if let Some(token) = stream.try_parse_token::<lex::False>() {
+        return Ok(Ident {
+            name: "false",
+            span: token.span,
+        });
+    }
+    if let Some(token) = stream.try_parse_token::<lex::Let>() {
+        return Ok(Ident {
+            name: "let",
+            span: token.span,
+        });
+    }
+    stream.parse_node::<Ident>()
+}
+
 impl<'a> Parse<'a> for Argument<'a> {
     fn parse(stream: &mut ParseStream<'a>) -> Result<'a, Self> {
-        let ident = stream.parse_node::<Ident>()?;
+        let ident = argument_name(stream)?;
         let start = ident.span;
         stream.parse_token::<lex::Colon>()?;
 
@@ -546,7 +1008,7 @@ impl<'a> Parse<'a> for Argument<'a> {
         Ok(Argument {
             ident,
             expr,
-            span: Span::new(start.from, end.to),
+            span: start.merge(end),
         })
     }
 }
@@ -564,7 +1026,7 @@ impl<'a> Parse<'a> for Block<'a> {
         Ok(Block {
             parameters,
             body,
-            span: Span::new(start.from, end.to),
+            span: start.merge(end),
         })
     }
 }
@@ -578,7 +1040,8 @@ impl<'a> Parse<'a> for Parameter<'a> {
 
         Ok(Parameter {
             ident,
-            span: Span::new(start.from, end.to),
+            slot: Slot::default(),
+            span: start.merge(end),
         })
     }
 }
@@ -596,7 +1059,7 @@ impl<'a> Parse<'a> for ClassNew<'a> {
         Ok(ClassNew {
             class_name,
             args,
-            span: Span::new(start.from, end.to),
+            span: start.merge(end),
         })
     }
 }
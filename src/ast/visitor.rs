@@ -22,6 +22,10 @@ pub trait Visitor<'a> {
         Ok(())
     }
 
+    fn visit_let_constant(&mut self, _: &'a LetConstant<'a>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
     fn visit_message_send_stmt(&mut self, _: &'a MessageSendStmt<'a>) -> Result<(), Self::Error> {
         Ok(())
     }
@@ -34,10 +38,31 @@ pub trait Visitor<'a> {
         Ok(())
     }
 
+    fn visit_define_abstract_method(
+        &mut self,
+        _: &'a DefineAbstractMethod<'a>,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
     fn visit_define_class(&mut self, _: &'a DefineClass<'a>) -> Result<(), Self::Error> {
         Ok(())
     }
 
+    fn visit_reopen_class(&mut self, _: &'a ReopenClass<'a>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called for `module` statements. Visitors that care about the statements inside visit them
+    /// themselves, so they can keep track of the module they're in.
+    fn visit_module(&mut self, _: &'a Module<'a>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn visit_case_of_stmt(&mut self, _: &'a CaseOfStmt<'a>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
     fn visit_expr(&mut self, _: &'a Expr<'a>) -> Result<(), Self::Error> {
         Ok(())
     }
@@ -58,6 +83,10 @@ pub trait Visitor<'a> {
         Ok(())
     }
 
+    fn visit_class_name(&mut self, _: &'a ClassName<'a>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
     fn visit_block(&mut self, _: &'a Block<'a>) -> Result<(), Self::Error> {
         Ok(())
     }
@@ -66,10 +95,18 @@ pub trait Visitor<'a> {
         Ok(())
     }
 
+    fn visit_str(&mut self, _: &'a Str) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
     fn visit_list(&mut self, _: &'a List<'a>) -> Result<(), Self::Error> {
         Ok(())
     }
 
+    fn visit_selector(&mut self, _: &'a Selector<'a>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
     fn visit_true(&mut self, _: &'a True) -> Result<(), Self::Error> {
         Ok(())
     }
@@ -81,6 +118,18 @@ pub trait Visitor<'a> {
     fn visit_self(&mut self, _: &'a Self_) -> Result<(), Self::Error> {
         Ok(())
     }
+
+    fn visit_quote(&mut self, _: &'a Quote<'a>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn visit_unquote(&mut self, _: &'a Unquote<'a>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn visit_case_of(&mut self, _: &'a CaseOf<'a>) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 pub fn visit_ast<'a, V: Visitor<'a>>(v: &mut V, node: &'a Ast<'a>) -> Result<(), V::Error> {
@@ -93,16 +142,21 @@ pub fn visit_ast<'a, V: Visitor<'a>>(v: &mut V, node: &'a Ast<'a>) -> Result<(),
     Ok(())
 }
 
-fn visit_stmt<'a, V: Visitor<'a>>(v: &mut V, node: &'a Stmt<'a>) -> Result<(), V::Error> {
+pub fn visit_stmt<'a, V: Visitor<'a>>(v: &mut V, node: &'a Stmt<'a>) -> Result<(), V::Error> {
     v.visit_stmt(node)?;
 
     match node {
         Stmt::LetLocal(inner) => visit_let_local(v, inner)?,
         Stmt::LetIVar(inner) => visit_let_ivar(v, inner)?,
+        Stmt::LetConstant(inner) => visit_let_constant(v, inner)?,
         Stmt::MessageSend(inner) => visit_message_send_stmt(v, inner)?,
         Stmt::Return(inner) => visit_return(v, inner)?,
         Stmt::DefineMethod(inner) => visit_define_method(v, inner)?,
+        Stmt::DefineAbstractMethod(inner) => visit_define_abstract_method(v, inner)?,
         Stmt::DefineClass(inner) => visit_define_class(v, inner)?,
+        Stmt::ReopenClass(inner) => visit_reopen_class(v, inner)?,
+        Stmt::Module(inner) => v.visit_module(inner)?,
+        Stmt::CaseOf(inner) => visit_case_of_stmt(v, inner)?,
     }
 
     Ok(())
@@ -116,6 +170,13 @@ fn visit_let_ivar<'a, V: Visitor<'a>>(v: &mut V, node: &'a LetIVar<'a>) -> Resul
     v.visit_let_ivar(node)
 }
 
+fn visit_let_constant<'a, V: Visitor<'a>>(
+    v: &mut V,
+    node: &'a LetConstant<'a>,
+) -> Result<(), V::Error> {
+    v.visit_let_constant(node)
+}
+
 fn visit_message_send_stmt<'a, V: Visitor<'a>>(
     v: &mut V,
     node: &'a MessageSendStmt<'a>,
@@ -134,6 +195,13 @@ fn visit_define_method<'a, V: Visitor<'a>>(
     v.visit_define_method(node)
 }
 
+fn visit_define_abstract_method<'a, V: Visitor<'a>>(
+    v: &mut V,
+    node: &'a DefineAbstractMethod<'a>,
+) -> Result<(), V::Error> {
+    v.visit_define_abstract_method(node)
+}
+
 fn visit_define_class<'a, V: Visitor<'a>>(
     v: &mut V,
     node: &'a DefineClass<'a>,
@@ -141,6 +209,20 @@ fn visit_define_class<'a, V: Visitor<'a>>(
     v.visit_define_class(node)
 }
 
+fn visit_reopen_class<'a, V: Visitor<'a>>(
+    v: &mut V,
+    node: &'a ReopenClass<'a>,
+) -> Result<(), V::Error> {
+    v.visit_reopen_class(node)
+}
+
+fn visit_case_of_stmt<'a, V: Visitor<'a>>(
+    v: &mut V,
+    node: &'a CaseOfStmt<'a>,
+) -> Result<(), V::Error> {
+    v.visit_case_of_stmt(node)
+}
+
 fn visit_expr<'a, V: Visitor<'a>>(v: &mut V, node: &'a Expr<'a>) -> Result<(), V::Error> {
     v.visit_expr(node)?;
 
@@ -149,12 +231,18 @@ fn visit_expr<'a, V: Visitor<'a>>(v: &mut V, node: &'a Expr<'a>) -> Result<(), V
         Expr::IVar(inner) => visit_ivar(v, inner)?,
         Expr::MessageSend(inner) => visit_message_send(v, inner)?,
         Expr::ClassNew(inner) => visit_class_new(v, inner)?,
+        Expr::ClassName(inner) => visit_class_name(v, inner)?,
         Expr::Block(inner) => visit_block(v, inner)?,
         Expr::Number(inner) => visit_number(v, inner)?,
-        Expr::List(inner) => visit_list(v, inner)?,
+        Expr::Str(inner) => visit_str(v, inner)?,
+        Expr::List(inner) | Expr::ConstantList(inner) => visit_list(v, inner)?,
+        Expr::Selector(inner) => visit_selector(v, inner)?,
         Expr::True(inner) => visit_true(v, inner)?,
         Expr::False(inner) => visit_false(v, inner)?,
         Expr::Self_(inner) => visit_self(v, inner)?,
+        Expr::Quote(inner) => visit_quote(v, inner)?,
+        Expr::Unquote(inner) => visit_unquote(v, inner)?,
+        Expr::CaseOf(inner) => visit_case_of(v, inner)?,
     }
 
     Ok(())
@@ -179,6 +267,13 @@ fn visit_class_new<'a, V: Visitor<'a>>(v: &mut V, node: &'a ClassNew<'a>) -> Res
     v.visit_class_new(node)
 }
 
+fn visit_class_name<'a, V: Visitor<'a>>(
+    v: &mut V,
+    node: &'a ClassName<'a>,
+) -> Result<(), V::Error> {
+    v.visit_class_name(node)
+}
+
 fn visit_block<'a, V: Visitor<'a>>(v: &mut V, node: &'a Block<'a>) -> Result<(), V::Error> {
     v.visit_block(node)
 }
@@ -187,10 +282,18 @@ fn visit_number<'a, V: Visitor<'a>>(v: &mut V, node: &'a Number) -> Result<(), V
     v.visit_number(node)
 }
 
+fn visit_str<'a, V: Visitor<'a>>(v: &mut V, node: &'a Str) -> Result<(), V::Error> {
+    v.visit_str(node)
+}
+
 fn visit_list<'a, V: Visitor<'a>>(v: &mut V, node: &'a List<'a>) -> Result<(), V::Error> {
     v.visit_list(node)
 }
 
+fn visit_selector<'a, V: Visitor<'a>>(v: &mut V, node: &'a Selector<'a>) -> Result<(), V::Error> {
+    v.visit_selector(node)
+}
+
 fn visit_true<'a, V: Visitor<'a>>(v: &mut V, node: &'a True) -> Result<(), V::Error> {
     v.visit_true(node)
 }
@@ -202,3 +305,15 @@ fn visit_false<'a, V: Visitor<'a>>(v: &mut V, node: &'a False) -> Result<(), V::
 fn visit_self<'a, V: Visitor<'a>>(v: &mut V, node: &'a Self_) -> Result<(), V::Error> {
     v.visit_self(node)
 }
+
+fn visit_quote<'a, V: Visitor<'a>>(v: &mut V, node: &'a Quote<'a>) -> Result<(), V::Error> {
+    v.visit_quote(node)
+}
+
+fn visit_unquote<'a, V: Visitor<'a>>(v: &mut V, node: &'a Unquote<'a>) -> Result<(), V::Error> {
+    v.visit_unquote(node)
+}
+
+fn visit_case_of<'a, V: Visitor<'a>>(v: &mut V, node: &'a CaseOf<'a>) -> Result<(), V::Error> {
+    v.visit_case_of(node)
+}
@@ -0,0 +1,465 @@
+//! Which methods a program's methods may call, for `oops callgraph`.
+//!
+//! Messages are sent dynamically, so this is an approximation worked out from the source. The
+//! receiver's class is known when it's `self`, a literal, a class name, or a local bound to
+//! `[Class new]` in the same method. A message sent to anything else may call every method with
+//! its name. Methods only reached through `[Runtime eval: source]` or by building selectors at
+//! runtime look unused.
+
+use crate::{
+    ast::{Argument, Ast, CaseOf, Expr, Stmt},
+    prep::{self, Class, Classes},
+};
+use serde_json::json;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write,
+    str::FromStr,
+};
+
+/// The name of the caller standing for statements outside methods.
+const TOP_LEVEL: &str = "<top level>";
+
+/// The formats call graphs can be written in.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    /// Graphviz, rendered with `dot -Tsvg`.
+    Dot,
+    /// JSON, for other tools to read.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(Format::Dot),
+            "json" => Ok(Format::Json),
+            other => Err(format!(
+                "unknown format `{}`, expected `dot` or `json`",
+                other
+            )),
+        }
+    }
+}
+
+/// What a message is known to be sent to.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+enum Receiver<'a> {
+    /// `self` in a method of the class. Subclasses can override the method that's called.
+    Self_(&'a str),
+    /// An instance of exactly the class.
+    Instance(&'a str),
+    /// The class itself, like `Console` in `[Console println: ...]`.
+    Class(&'a str),
+    Unknown,
+}
+
+impl Receiver<'_> {
+    fn describe(self) -> Option<String> {
+        match self {
+            Receiver::Self_(class) | Receiver::Instance(class) => Some(class.to_string()),
+            Receiver::Class(class) => Some(format!("{} class", class)),
+            Receiver::Unknown => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+struct Send<'a> {
+    selector: &'a str,
+    receiver: Receiver<'a>,
+}
+
+/// The messages sent by a method, or by the statements outside methods.
+#[derive(Debug)]
+struct Caller<'a> {
+    /// `Class#method`, like in error messages.
+    name: String,
+    sends: BTreeSet<Send<'a>>,
+    /// The methods the sends may call, and whether the receiver's class was known.
+    calls: BTreeSet<(String, bool)>,
+}
+
+/// The call graph of the program `ast`, whose classes are `classes`, written in `format`.
+pub fn render<'a>(ast: &'a Ast<'a>, classes: &Classes<'a>, format: Format) -> String {
+    let callers = callers(ast, classes);
+    let called = callers
+        .iter()
+        .flat_map(|caller| caller.calls.iter().map(|(name, _)| name.as_str()))
+        .collect::<BTreeSet<_>>();
+    let unused = callers
+        .iter()
+        .map(|caller| caller.name.as_str())
+        .filter(|name| *name != TOP_LEVEL && !called.contains(name))
+        .collect::<BTreeSet<_>>();
+
+    match format {
+        Format::Dot => dot(&callers, &unused),
+        Format::Json => json(&callers, &unused),
+    }
+}
+
+/// Every method in `classes` sorted by name, after the statements outside methods.
+fn callers<'a>(ast: &'a Ast<'a>, classes: &Classes<'a>) -> Vec<Caller<'a>> {
+    let mut top_level = Collector::new(classes, None, None);
+    top_level.stmts(ast);
+    let mut callers = vec![top_level.finish(TOP_LEVEL.to_string())];
+
+    let mut methods = vec![];
+    for class in classes.values() {
+        for method in class.methods.borrow().values() {
+            let mut collector = Collector::new(classes, Some(class.name.name), method.module);
+            collector.stmts(method.body);
+            methods.push(collector.finish(format!("{}#{}", class.name.name, method.name.name)));
+        }
+    }
+    methods.sort_by(|a, b| a.name.cmp(&b.name));
+    callers.extend(methods);
+
+    for caller in &mut callers {
+        let calls = caller
+            .sends
+            .iter()
+            .flat_map(|send| {
+                let known = send.receiver != Receiver::Unknown;
+                targets(classes, *send)
+                    .into_iter()
+                    .map(move |target| (target, known))
+            })
+            .collect();
+        caller.calls = calls;
+    }
+    callers
+}
+
+/// The methods `send` may call.
+fn targets(classes: &Classes<'_>, send: Send<'_>) -> Vec<String> {
+    let name = |class: &str| format!("{}#{}", class, send.selector);
+    match send.receiver {
+        Receiver::Self_(class) => {
+            let mut targets = owner(classes, class, send.selector)
+                .map(name)
+                .into_iter()
+                .collect::<Vec<_>>();
+            for subclass in classes.values() {
+                if subclass.name.name != class
+                    && inherits_from(subclass, class)
+                    && subclass.methods.borrow().contains_key(send.selector)
+                {
+                    targets.push(name(subclass.name.name));
+                }
+            }
+            targets
+        }
+        Receiver::Instance(class) => owner(classes, class, send.selector)
+            .map(name)
+            .into_iter()
+            .collect(),
+        Receiver::Class(_) => vec![],
+        Receiver::Unknown => classes
+            .values()
+            .filter(|class| class.methods.borrow().contains_key(send.selector))
+            .map(|class| name(class.name.name))
+            .collect(),
+    }
+}
+
+/// The class whose method instances of `class` run for `selector`.
+fn owner<'a>(classes: &Classes<'a>, class: &str, selector: &str) -> Option<&'a str> {
+    classes.get(class)?.method_owner(selector)
+}
+
+fn inherits_from(class: &Class<'_>, ancestor: &str) -> bool {
+    let mut current = Some(class);
+    while let Some(class) = current {
+        if class.name.name == ancestor {
+            return true;
+        }
+        current = class
+            .super_class
+            .as_deref()
+            .filter(|super_class| super_class.name.name != class.name.name);
+    }
+    false
+}
+
+/// Finds the messages sent by a list of statements.
+struct Collector<'a, 'b> {
+    classes: &'b Classes<'a>,
+    /// The class of the method the statements are in, if any.
+    class: Option<&'a str>,
+    module: Option<&'a str>,
+    /// The classes of locals bound to `[Class new]`.
+    locals: HashMap<&'a str, &'a str>,
+    sends: BTreeSet<Send<'a>>,
+}
+
+impl<'a, 'b> Collector<'a, 'b> {
+    fn new(classes: &'b Classes<'a>, class: Option<&'a str>, module: Option<&'a str>) -> Self {
+        Self {
+            classes,
+            class,
+            module,
+            locals: HashMap::new(),
+            sends: BTreeSet::new(),
+        }
+    }
+
+    fn finish(self, name: String) -> Caller<'a> {
+        Caller {
+            name,
+            sends: self.sends,
+            calls: BTreeSet::new(),
+        }
+    }
+
+    fn stmts(&mut self, stmts: &'a [Stmt<'a>]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt<'a>) {
+        match stmt {
+            Stmt::LetLocal(inner) => {
+                self.expr(&inner.body);
+                match self.receiver(&inner.body) {
+                    Receiver::Instance(class) => {
+                        self.locals.insert(inner.ident.name, class);
+                    }
+                    _ => {
+                        self.locals.remove(inner.ident.name);
+                    }
+                }
+            }
+            Stmt::LetIVar(inner) => self.expr(&inner.body),
+            Stmt::LetConstant(inner) => self.expr(&inner.body),
+            Stmt::MessageSend(inner) => {
+                self.message_send(&inner.expr.receiver, inner.expr.msg.name, &inner.expr.args)
+            }
+            Stmt::Return(inner) => self.expr(&inner.expr),
+            Stmt::CaseOf(inner) => self.expr_case_of(&inner.expr),
+            Stmt::Module(inner) => {
+                let outer = self.module;
+                self.module = Some(&inner.qualified_name);
+                self.stmts(&inner.body);
+                self.module = outer;
+            }
+            // Methods defined at the top level are callers of their own, and the bodies of those
+            // defined while the program runs aren't known to be called by anything
+            Stmt::DefineMethod(_)
+            | Stmt::DefineAbstractMethod(_)
+            | Stmt::DefineClass(_)
+            | Stmt::ReopenClass(_) => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &'a Expr<'a>) {
+        match expr {
+            Expr::MessageSend(inner) => {
+                self.message_send(&inner.receiver, inner.msg.name, &inner.args)
+            }
+            Expr::ClassNew(inner) => self.args(&inner.args),
+            Expr::Block(inner) => {
+                // Locals bound in the block aren't visible after it
+                let locals = self.locals.clone();
+                self.stmts(&inner.body);
+                self.locals = locals;
+            }
+            Expr::List(inner) | Expr::ConstantList(inner) => {
+                for item in &inner.items {
+                    self.expr(item);
+                }
+            }
+            Expr::CaseOf(inner) => self.expr_case_of(inner),
+            Expr::Local(_)
+            | Expr::IVar(_)
+            | Expr::ClassName(_)
+            | Expr::Number(_)
+            | Expr::Str(_)
+            | Expr::Selector(_)
+            | Expr::True(_)
+            | Expr::False(_)
+            | Expr::Self_(_)
+            | Expr::Quote(_)
+            | Expr::Unquote(_) => {}
+        }
+    }
+
+    fn expr_case_of(&mut self, case_of: &'a CaseOf<'a>) {
+        self.expr(&case_of.subject);
+        for arm in &case_of.arms {
+            self.expr(&arm.value);
+            self.expr(&arm.body);
+        }
+        if let Some(otherwise) = &case_of.otherwise {
+            self.expr(otherwise);
+        }
+    }
+
+    fn message_send(
+        &mut self,
+        receiver: &'a Expr<'a>,
+        selector: &'a str,
+        args: &'a [Argument<'a>],
+    ) {
+        self.expr(receiver);
+        self.args(args);
+        self.sends.insert(Send {
+            selector,
+            receiver: self.receiver(receiver),
+        });
+    }
+
+    fn args(&mut self, args: &'a [Argument<'a>]) {
+        for arg in args {
+            self.expr(&arg.expr);
+            // Selectors passed as arguments, like `#name` in `[users map: #name]`, are usually sent
+            // to something
+            if let Expr::Selector(selector) = &arg.expr {
+                self.sends.insert(Send {
+                    selector: selector.ident.name,
+                    receiver: Receiver::Unknown,
+                });
+            }
+        }
+    }
+
+    fn receiver(&self, expr: &'a Expr<'a>) -> Receiver<'a> {
+        let class = |name: &'a str| prep::resolve_class_name(self.classes, self.module, name);
+        match expr {
+            Expr::Self_(_) => self.class.map_or(Receiver::Unknown, Receiver::Self_),
+            Expr::Local(local) => self
+                .locals
+                .get(local.0.name)
+                .map_or(Receiver::Unknown, |class| Receiver::Instance(class)),
+            Expr::ClassNew(new) => {
+                class(new.class_name.0.name).map_or(Receiver::Unknown, Receiver::Instance)
+            }
+            Expr::ClassName(name) => class(name.0.name).map_or(Receiver::Unknown, Receiver::Class),
+            Expr::Number(_) => Receiver::Instance("Number"),
+            Expr::Str(_) => Receiver::Instance("String"),
+            Expr::List(_) | Expr::ConstantList(_) => Receiver::Instance("List"),
+            Expr::True(_) | Expr::False(_) => Receiver::Instance("Boolean"),
+            _ => Receiver::Unknown,
+        }
+    }
+}
+
+fn dot(callers: &[Caller<'_>], unused: &BTreeSet<&str>) -> String {
+    let mut out = String::new();
+    out.push_str("digraph calls {\n");
+    for caller in callers {
+        if unused.contains(caller.name.as_str()) {
+            writeln!(out, "    \"{}\" [style=dashed];", caller.name).unwrap();
+        } else {
+            writeln!(out, "    \"{}\";", caller.name).unwrap();
+        }
+    }
+    for caller in callers {
+        for (target, known) in &caller.calls {
+            // Dashed edges are calls that only happen if the receiver turns out to have the method
+            let style = if *known { "" } else { " [style=dashed]" };
+            writeln!(out, "    \"{}\" -> \"{}\"{};", caller.name, target, style).unwrap();
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn json(callers: &[Caller<'_>], unused: &BTreeSet<&str>) -> String {
+    let callers = callers
+        .iter()
+        .map(|caller| {
+            let sends = caller
+                .sends
+                .iter()
+                .map(|send| json!({ "selector": send.selector, "receiver": send.receiver.describe() }))
+                .collect::<Vec<_>>();
+            let calls = caller
+                .calls
+                .iter()
+                .map(|(target, known)| json!({ "method": target, "receiverKnown": known }))
+                .collect::<Vec<_>>();
+            json!({ "caller": caller.name, "sends": sends, "calls": calls })
+        })
+        .collect::<Vec<_>>();
+    let graph = json!({ "callers": callers, "unused": unused });
+    let mut out = serde_json::to_string_pretty(&graph).expect("Internal error: JSON is valid");
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    use crate::{lex::lex, parse::parse, prep::find_classes_and_methods};
+
+    const PROGRAM: &str = r#"
+        [Object subclass name: #Shape fields: []];
+        [Shape def: #area do: || { return 0; }];
+        [Shape def: #describe do: || { return [self area]; }];
+        [Shape subclass name: #Square fields: [#side]];
+        [Square def: #area do: || { return @side * @side; }];
+        [Square def: #unused do: || { return [Console println: "never"]; }];
+        [Object subclass name: #Report fields: []];
+        [Report def: #print do: |shapes:| { [shapes each: #describe]; }];
+        [String def: #shout do: || { return self; }];
+        let report = [Report new];
+        [report print shapes: [[Square new side: 2]]];
+        ["hi" shout];
+    "#;
+
+    fn call_graph(format: Format) -> String {
+        let tokens = lex(PROGRAM).unwrap();
+        let mut ast = parse(&tokens).unwrap();
+        prep::qualify_names(&mut ast);
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+        render(&ast, &classes, format)
+    }
+
+    #[test]
+    fn dot_graph() {
+        let expected = [
+            "digraph calls {",
+            "    \"<top level>\";",
+            "    \"Report#print\";",
+            "    \"Shape#area\";",
+            "    \"Shape#describe\";",
+            "    \"Square#area\";",
+            "    \"Square#unused\" [style=dashed];",
+            "    \"String#shout\";",
+            "    \"<top level>\" -> \"Report#print\";",
+            "    \"<top level>\" -> \"String#shout\";",
+            "    \"Report#print\" -> \"Shape#describe\" [style=dashed];",
+            "    \"Shape#describe\" -> \"Shape#area\";",
+            "    \"Shape#describe\" -> \"Square#area\";",
+            "}",
+            "",
+        ]
+        .join("\n");
+
+        assert_eq!(expected, call_graph(Format::Dot));
+    }
+
+    #[test]
+    fn json_graph() {
+        let graph = serde_json::from_str::<serde_json::Value>(&call_graph(Format::Json)).unwrap();
+
+        assert_eq!(json!(["Square#unused"]), graph["unused"]);
+        assert_eq!(
+            json!([
+                { "selector": "describe", "receiver": null },
+                { "selector": "each", "receiver": null },
+            ]),
+            graph["callers"][1]["sends"]
+        );
+        assert_eq!(
+            json!({ "selector": "println", "receiver": "Console class" }),
+            graph["callers"][5]["sends"][0]
+        );
+    }
+}
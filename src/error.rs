@@ -1,8 +1,9 @@
-use crate::Span;
-use std::{fmt, io};
+use crate::{import::Sources, LineCol, Span};
+use std::{fmt, io, path::Path};
 
 pub type Result<'a, T> = std::result::Result<T, Error<'a>>;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum Error<'a> {
     LexError {
@@ -39,7 +40,17 @@ pub enum Error<'a> {
         span: Span,
     },
     NoSelf(Span),
-    MessageSentToNonInstance(Span),
+    /// A message that isn't understood was sent to a value that isn't an instance of a class
+    /// defined by the program.
+    MessageSentToNonInstance {
+        class: &'a str,
+        /// How the receiver is shown by `Console println:`.
+        value: String,
+        selector: &'a str,
+        /// The class and method the message was sent from, if any.
+        method: Option<(&'a str, &'a str)>,
+        span: Span,
+    },
     IVarAccessedWithoutSelf(Span),
     IVarAccessedOnNonInstanceValue(Span),
     UndefinedMethod {
@@ -55,6 +66,254 @@ pub enum Error<'a> {
         name: &'a str,
         span: Span,
     },
+    WrongNumberOfBlockParameters {
+        expected: usize,
+        given: usize,
+        span: Span,
+    },
+    NotCallable(Span),
+    FrozenInstanceModified {
+        name: &'a str,
+        span: Span,
+    },
+    InvalidSource {
+        message: String,
+        span: Span,
+    },
+    UnexpectedType {
+        expected: &'static str,
+        given: &'a str,
+        span: Span,
+    },
+    CapabilityNotGranted {
+        capability: &'static str,
+        flag: &'static str,
+        span: Span,
+    },
+    UnquoteOutsideQuote(Span),
+    /// The program evaluated more statements, expressions, and calls than its budget allows.
+    BudgetExceeded {
+        limit: usize,
+        span: Span,
+    },
+    /// A list, string, or byte array longer than the interpreter allows would have been built.
+    LengthLimitExceeded {
+        limit: usize,
+        span: Span,
+    },
+    /// More method and block calls were being run at once than the interpreter allows, usually
+    /// because of recursion without a base case.
+    RecursionLimitExceeded {
+        limit: usize,
+        method: Option<(&'a str, &'a str)>,
+        span: Span,
+    },
+    IncomparableValues {
+        lhs: &'a str,
+        rhs: &'a str,
+        span: Span,
+    },
+    /// `method` is the class and name of the method that was running, if any.
+    Cancelled {
+        method: Option<(&'a str, &'a str)>,
+        span: Span,
+    },
+    /// Raised by `[Exception raise: message]` and not rescued. Re-raised errors keep their
+    /// original code.
+    Raised {
+        message: String,
+        code: &'static str,
+        span: Span,
+    },
+    /// A failed `Assert`. `message` describes the values involved.
+    AssertionFailed {
+        message: String,
+        span: Span,
+    },
+    /// A class was instantiated, or an abstract method called, without implementations of its
+    /// abstract methods.
+    AbstractMethodsNotImplemented {
+        class: &'a str,
+        methods: Vec<&'a str>,
+        span: Span,
+    },
+    /// A number was divided by zero.
+    DivisionByZero(Span),
+    /// `let NAME = value;` ran for a constant that was already defined, or that has the name of a
+    /// class.
+    ConstantAlreadyDefined {
+        name: &'a str,
+        first_span: Span,
+        second_span: Span,
+    },
+    /// The result of arithmetic on numbers didn't fit in a number. `BigInt` has no such limit.
+    Overflow(Span),
+    /// `at:` was sent to a list, string, or bytes with an index that isn't within them.
+    IndexOutOfBounds {
+        index: i32,
+        length: usize,
+        span: Span,
+    },
+    /// A file imported with `[Oops import: path]` couldn't be read.
+    ImportFailed {
+        path: String,
+        importer: String,
+        message: String,
+        span: Span,
+    },
+    /// A file imported itself, directly or through other files. `cycle` starts and ends with that
+    /// file.
+    ImportCycle {
+        cycle: Vec<String>,
+        span: Span,
+    },
+    /// A call into a host object panicked, or a send returned after taking longer than
+    /// `Interpreter::with_host_time_limit` allows. `reason` says which.
+    HostObjectFailed {
+        class: &'a str,
+        method: &'a str,
+        reason: String,
+        span: Span,
+    },
+    /// A class was used outside the file defining it, but that file's `[Oops export: [...]]`
+    /// doesn't list it.
+    ClassNotExported {
+        class: String,
+        path: String,
+        span: Span,
+    },
+    /// Raised by `[System exit: status]`. Not an error as such, but it stops the program the same
+    /// way.
+    Exit {
+        status: i32,
+        span: Span,
+    },
+    /// An error raised by source evaluated at runtime. Its span points into that source, so the
+    /// span of the call that evaluated it is kept as well.
+    InEvaluatedSource {
+        error: Box<Error<'a>>,
+        span: Span,
+    },
+}
+
+impl Error<'_> {
+    /// A stable identifier for the kind of error, printed alongside the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::LexError { .. } => "E0001",
+            Error::IoError(_) => "E0002",
+            Error::ParseError(_) => "E0003",
+            Error::ClassNotDefined { .. } => "E0004",
+            Error::ClassAlreadyDefined { .. } => "E0005",
+            Error::MethodAlreadyDefined { .. } => "E0006",
+            Error::UndefinedLocal { .. } => "E0007",
+            Error::MissingArgument { .. } => "E0008",
+            Error::UnexpectedArgument { .. } => "E0009",
+            Error::NoSelf(_) => "E0010",
+            Error::MessageSentToNonInstance { .. } => "E0011",
+            Error::IVarAccessedWithoutSelf(_) => "E0012",
+            Error::IVarAccessedOnNonInstanceValue(_) => "E0013",
+            Error::UndefinedMethod { .. } => "E0014",
+            Error::IVarAccessedOutsideMethod { .. } => "E0015",
+            Error::UndefinedIVar { .. } => "E0016",
+            Error::WrongNumberOfBlockParameters { .. } => "E0017",
+            Error::NotCallable(_) => "E0018",
+            Error::FrozenInstanceModified { .. } => "E0019",
+            Error::InvalidSource { .. } => "E0020",
+            Error::UnexpectedType { .. } => "E0021",
+            Error::CapabilityNotGranted { .. } => "E0022",
+            Error::UnquoteOutsideQuote(_) => "E0023",
+            Error::IncomparableValues { .. } => "E0025",
+            Error::Cancelled { .. } => "E0026",
+            Error::Exit { .. } => "E0027",
+            Error::Raised { code, .. } => code,
+            Error::AssertionFailed { .. } => "E0029",
+            Error::AbstractMethodsNotImplemented { .. } => "E0030",
+            Error::DivisionByZero(_) => "E0031",
+            Error::Overflow(_) => "E0032",
+            Error::IndexOutOfBounds { .. } => "E0036",
+            Error::ConstantAlreadyDefined { .. } => "E0033",
+            Error::ImportFailed { .. } => "E0034",
+            Error::ImportCycle { .. } => "E0035",
+            Error::ClassNotExported { .. } => "E0040",
+            Error::HostObjectFailed { .. } => "E0041",
+            Error::RecursionLimitExceeded { .. } => "E0037",
+            Error::LengthLimitExceeded { .. } => "E0038",
+            Error::BudgetExceeded { .. } => "E0039",
+            Error::InEvaluatedSource { error, .. } => error.code(),
+        }
+    }
+
+    /// Whether `[Exception try: block rescue: handler]` can rescue the error.
+    ///
+    /// Errors found before the program runs, and those that stop it on purpose, like being
+    /// cancelled or running out of budget, can't be rescued.
+    pub fn is_rescuable(&self) -> bool {
+        match self {
+            Error::LexError { .. }
+            | Error::ParseError(_)
+            | Error::ClassAlreadyDefined { .. }
+            | Error::ImportFailed { .. }
+            | Error::ImportCycle { .. }
+            | Error::ClassNotExported { .. }
+            | Error::IoError(_)
+            | Error::CapabilityNotGranted { .. }
+            | Error::LengthLimitExceeded { .. }
+            | Error::BudgetExceeded { .. }
+            | Error::RecursionLimitExceeded { .. }
+            | Error::Cancelled { .. }
+            | Error::Exit { .. } => false,
+            Error::InEvaluatedSource { error, .. } => error.is_rescuable(),
+            _ => true,
+        }
+    }
+
+    /// The location in the source the error points at, if any.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Error::LexError { at } => Some(Span::new(*at, *at)),
+            Error::IoError(_) => None,
+            Error::ParseError(_) => None,
+            Error::ClassNotDefined { span, .. } => Some(*span),
+            Error::ClassAlreadyDefined { second_span, .. } => Some(*second_span),
+            Error::MethodAlreadyDefined { second_span, .. } => Some(*second_span),
+            Error::UndefinedLocal { span, .. } => Some(*span),
+            Error::MissingArgument { span, .. } => Some(*span),
+            Error::UnexpectedArgument { span, .. } => Some(*span),
+            Error::NoSelf(span) => Some(*span),
+            Error::MessageSentToNonInstance { span, .. } => Some(*span),
+            Error::IVarAccessedWithoutSelf(span) => Some(*span),
+            Error::IVarAccessedOnNonInstanceValue(span) => Some(*span),
+            Error::UndefinedMethod { span, .. } => Some(*span),
+            Error::IVarAccessedOutsideMethod { span, .. } => Some(*span),
+            Error::UndefinedIVar { span, .. } => Some(*span),
+            Error::WrongNumberOfBlockParameters { span, .. } => Some(*span),
+            Error::NotCallable(span) => Some(*span),
+            Error::FrozenInstanceModified { span, .. } => Some(*span),
+            Error::InvalidSource { span, .. } => Some(*span),
+            Error::UnexpectedType { span, .. } => Some(*span),
+            Error::CapabilityNotGranted { span, .. } => Some(*span),
+            Error::UnquoteOutsideQuote(span) => Some(*span),
+            Error::RecursionLimitExceeded { span, .. } => Some(*span),
+            Error::LengthLimitExceeded { span, .. } => Some(*span),
+            Error::BudgetExceeded { span, .. } => Some(*span),
+            Error::IncomparableValues { span, .. } => Some(*span),
+            Error::Cancelled { span, .. } => Some(*span),
+            Error::Exit { span, .. } => Some(*span),
+            Error::Raised { span, .. } => Some(*span),
+            Error::AssertionFailed { span, .. } => Some(*span),
+            Error::AbstractMethodsNotImplemented { span, .. } => Some(*span),
+            Error::DivisionByZero(span) => Some(*span),
+            Error::Overflow(span) => Some(*span),
+            Error::IndexOutOfBounds { span, .. } => Some(*span),
+            Error::ConstantAlreadyDefined { second_span, .. } => Some(*second_span),
+            Error::ImportFailed { span, .. } => Some(*span),
+            Error::ImportCycle { span, .. } => Some(*span),
+            Error::ClassNotExported { span, .. } => Some(*span),
+            Error::HostObjectFailed { span, .. } => Some(*span),
+            Error::InEvaluatedSource { span, .. } => Some(*span),
+        }
+    }
 }
 
 impl From<io::Error> for Error<'_> {
@@ -71,11 +330,11 @@ impl fmt::Display for Error<'_> {
             Error::ParseError(other) => write!(f, "{}", other),
             Error::ClassNotDefined {
                 class,
-                ..
+                span,
             } => write!(
                 f,
-                "The class `{}` is not defined",
-                class,
+                "The class `{}` is not defined. Referenced at {}",
+                class, span,
             ),
             Error::ClassAlreadyDefined {
                 class,
@@ -125,11 +384,19 @@ impl fmt::Display for Error<'_> {
                 "`self` called outside method at {}",
                 span,
             ),
-            Error::MessageSentToNonInstance(span) => write!(
-                f,
-                "Message sent to non instance value at {}",
-                span,
-            ),
+            Error::MessageSentToNonInstance {
+                class, value, selector, method, span
+            } => {
+                write!(
+                    f,
+                    "Message `{}` sent to non instance value `{}` of class `{}` at {}",
+                    selector, value, class, span
+                )?;
+                if let Some((class, method)) = method {
+                    write!(f, " in `{}#{}`", class, method)?;
+                }
+                Ok(())
+            }
             Error::UndefinedMethod {
                 class, method, span
             } => write!(
@@ -161,12 +428,205 @@ impl fmt::Display for Error<'_> {
                 "Instance variabled access on `self` that isn't an instance at {}",
                 span
             ),
+            Error::WrongNumberOfBlockParameters {
+                expected, given, span
+            } => write!(
+                f,
+                "Expected a block taking {} argument(s) but it takes {} at {}",
+                expected, given, span
+            ),
+            Error::NotCallable(span) => write!(
+                f,
+                "Expected a block or a selector at {}",
+                span
+            ),
+            Error::FrozenInstanceModified {
+                name, span
+            } => write!(
+                f,
+                "Instance variable `{}` assigned on a frozen instance at {}",
+                name, span
+            ),
+            Error::InvalidSource {
+                message, span
+            } => write!(
+                f,
+                "Source passed at {} could not be parsed: {}",
+                span, message
+            ),
+            Error::UnexpectedType {
+                expected, given, span
+            } => write!(
+                f,
+                "Expected a {} but got a {} at {}",
+                expected, given, span
+            ),
+            Error::CapabilityNotGranted {
+                capability, flag, span
+            } => write!(
+                f,
+                "The `{}` capability is required at {}. Run with `{}` to grant it",
+                capability, span, flag
+            ),
+            Error::UnquoteOutsideQuote(span) => write!(
+                f,
+                "`~` used outside `quote {{ ... }}` at {}",
+                span
+            ),
+            Error::BudgetExceeded { limit, span } => write!(
+                f,
+                "Stopped after evaluating {} statements, expressions, and calls at {}",
+                limit, span
+            ),
+            Error::LengthLimitExceeded { limit, span } => write!(
+                f,
+                "Stopped building a value longer than {} at {}",
+                limit, span
+            ),
+            Error::RecursionLimitExceeded { limit, method, span } => {
+                write!(f, "Stopped after {} nested calls at {}", limit, span)?;
+                if let Some((class, method)) = method {
+                    write!(f, " in `{}#{}`", class, method)?;
+                }
+                Ok(())
+            }
+            Error::IncomparableValues {
+                lhs, rhs, span
+            } => write!(
+                f,
+                "A {} can't be compared with a {} at {}",
+                lhs, rhs, span
+            ),
+            Error::Cancelled { method, span } => {
+                write!(f, "The program was cancelled at {}", span)?;
+                if let Some((class, method)) = method {
+                    write!(f, " in `{}#{}`", class, method)?;
+                }
+                Ok(())
+            }
+            Error::Exit { status, span } => write!(
+                f,
+                "The program exited with status {} at {}",
+                status, span
+            ),
+            Error::Raised { message, span, .. } => write!(
+                f,
+                "{} (raised at {})",
+                message, span
+            ),
+            Error::AssertionFailed { message, span } => write!(
+                f,
+                "Assertion failed at {}: {}",
+                span, message
+            ),
+            Error::AbstractMethodsNotImplemented {
+                class, methods, span
+            } => write!(
+                f,
+                "The class `{}` doesn't implement the abstract method(s) {} at {}",
+                class,
+                methods.iter().map(|method| format!("`{}`", method)).collect::<Vec<_>>().join(", "),
+                span
+            ),
+            Error::DivisionByZero(span) => write!(
+                f,
+                "Division by zero at {}",
+                span
+            ),
+            Error::ConstantAlreadyDefined {
+                name,
+                first_span,
+                second_span,
+            } => write!(
+                f,
+                "The constant `{}` was defined more than once. First time at {}, second time at {}",
+                name, first_span, second_span
+            ),
+            Error::Overflow(span) => write!(
+                f,
+                "Integer overflow at {}",
+                span
+            ),
+            Error::ImportFailed {
+                path,
+                importer,
+                message,
+                ..
+            } => write!(
+                f,
+                "Couldn't import `{}` from `{}`: {}",
+                path, importer, message
+            ),
+            Error::ImportCycle { cycle, .. } => write!(
+                f,
+                "Import cycle: `{}`",
+                cycle.join("` imports `")
+            ),
+            Error::HostObjectFailed {
+                class, method, reason, span
+            } => write!(
+                f,
+                "`{}#{}` {} at {}",
+                class, method, reason, span
+            ),
+            Error::ClassNotExported { class, path, .. } => write!(
+                f,
+                "The class `{}` isn't exported from `{}`",
+                class, path
+            ),
+            Error::IndexOutOfBounds {
+                index,
+                length,
+                span,
+            } => write!(
+                f,
+                "Index {} is out of bounds for length {} at {}",
+                index, length, span
+            ),
+            Error::InEvaluatedSource {
+                error, span
+            } => write!(
+                f,
+                "{} (in source evaluated at {})",
+                error, span
+            ),
         }
     }
 }
 
 impl std::error::Error for Error<'_> {}
 
+/// Print an error to stderr with the file, line, and column it happened at.
+pub fn print_error(file: &Path, source: &str, err: &Error<'_>) {
+    let start = err.span().map(|span| span.line_col_range(source).0);
+    print_error_at(file, start, err);
+}
+
+/// Print an error in a program that may import other files, with the file it's in.
+pub fn print_error_in(sources: &Sources<'_>, err: &Error<'_>) {
+    match err.span() {
+        Some(span) => {
+            let (file, start) = sources.locate(span);
+            print_error_at(file, Some(start), err);
+        }
+        None => print_error_at(sources.main_path(), None, err),
+    }
+}
+
+fn print_error_at(file: &Path, start: Option<LineCol>, err: &Error<'_>) {
+    match start {
+        Some(start) => eprintln!(
+            "{}:{}: error[{}]: {}",
+            file.display(),
+            start,
+            err.code(),
+            err
+        ),
+        None => eprintln!("{}: error[{}]: {}", file.display(), err.code(), err),
+    }
+}
+
+#[cfg(test)]
 macro_rules! assert_error {
     ($result:expr, $pat:pat) => {
         match $result {
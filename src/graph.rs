@@ -0,0 +1,300 @@
+//! Diagrams of the class hierarchy of a program, for `oops graph`.
+//!
+//! Only the classes the program defines are shown, along with the classes they inherit from and
+//! built-in classes the program defines methods on. Classes are sorted by name so the output
+//! doesn't change between runs.
+
+use crate::prep::{self, Class, Classes};
+use std::{fmt::Write, str::FromStr};
+
+/// The languages diagrams can be written in.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    /// Graphviz, rendered with `dot -Tsvg`.
+    Dot,
+    /// Mermaid, which GitHub renders in Markdown files.
+    Mermaid,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(Format::Dot),
+            "mermaid" => Ok(Format::Mermaid),
+            other => Err(format!(
+                "unknown format `{}`, expected `dot` or `mermaid`",
+                other
+            )),
+        }
+    }
+}
+
+/// A method shown in a diagram, and how it relates to the super classes.
+struct MethodEntry<'a> {
+    name: &'a str,
+    is_abstract: bool,
+    /// The closest super class that also has the method, and whether it's abstract there.
+    inherited_from: Option<(&'a str, bool)>,
+}
+
+impl MethodEntry<'_> {
+    fn describe(&self) -> String {
+        match (self.is_abstract, self.inherited_from) {
+            (true, _) => format!("{} (abstract)", self.name),
+            (false, Some((class, true))) => format!("{} (implements {})", self.name, class),
+            (false, Some((class, false))) => format!("{} (overrides {})", self.name, class),
+            (false, None) => self.name.to_string(),
+        }
+    }
+}
+
+/// The class hierarchy of `classes` written in `format`. Methods are listed in each class when
+/// `methods` is set.
+pub fn render(classes: &Classes<'_>, format: Format, methods: bool) -> String {
+    let shown = shown_classes(classes);
+    match format {
+        Format::Dot => dot(&shown, methods),
+        Format::Mermaid => mermaid(&shown, methods),
+    }
+}
+
+/// The classes to show, sorted by name.
+fn shown_classes<'a, 'b>(classes: &'b Classes<'a>) -> Vec<&'b Class<'a>> {
+    let built_in = prep::built_in_classes();
+    let mut shown = Vec::new();
+    for class in classes.values() {
+        let defined = !built_in.contains_key(class.name.name)
+            || !class.methods.borrow().is_empty()
+            || !class.abstract_methods.is_empty();
+        if !defined {
+            continue;
+        }
+
+        let mut current = Some(&**class);
+        while let Some(class) = current {
+            if !shown
+                .iter()
+                .any(|shown: &&Class| shown.name.name == class.name.name)
+            {
+                shown.push(class);
+            }
+            current = class.super_class.as_deref();
+        }
+    }
+    shown.sort_by_key(|class| class.name.name);
+    shown
+}
+
+/// The super class of `class`, unless it's `Object` which is its own super class.
+fn super_class<'a, 'b>(class: &'b Class<'a>) -> Option<&'b Class<'a>> {
+    class
+        .super_class
+        .as_deref()
+        .filter(|super_class| super_class.name.name != class.name.name)
+}
+
+/// The methods defined and declared abstract by `class` itself, sorted by name.
+fn method_entries<'a>(class: &Class<'a>) -> Vec<MethodEntry<'a>> {
+    let defined = class
+        .methods
+        .borrow()
+        .keys()
+        .map(|name| (*name, false))
+        .collect::<Vec<_>>();
+    let declared = class.abstract_methods.keys().map(|name| (*name, true));
+
+    let mut entries = defined
+        .into_iter()
+        .chain(declared)
+        .map(|(name, is_abstract)| MethodEntry {
+            name,
+            is_abstract,
+            inherited_from: inherited_from(class, name),
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.name);
+    entries
+}
+
+fn inherited_from<'a>(class: &Class<'a>, name: &str) -> Option<(&'a str, bool)> {
+    let mut current = super_class(class);
+    while let Some(class) = current {
+        if class.methods.borrow().contains_key(name) {
+            return Some((class.name.name, false));
+        }
+        if class.abstract_methods.contains_key(name) {
+            return Some((class.name.name, true));
+        }
+        current = super_class(class);
+    }
+    None
+}
+
+fn dot(classes: &[&Class<'_>], methods: bool) -> String {
+    let mut out = String::new();
+    out.push_str("digraph classes {\n");
+    out.push_str("    rankdir=BT;\n");
+    out.push_str("    node [shape=box];\n");
+    for class in classes {
+        let name = class.name.name;
+        let mut label = name.to_string();
+        if methods {
+            for entry in method_entries(class) {
+                label.push_str("\\l");
+                label.push_str(&entry.describe());
+            }
+            if label != name {
+                label.push_str("\\l");
+            }
+        }
+        writeln!(out, "    \"{}\" [label=\"{}\"];", name, label).unwrap();
+    }
+    for class in classes {
+        if let Some(super_class) = super_class(class) {
+            writeln!(
+                out,
+                "    \"{}\" -> \"{}\";",
+                class.name.name, super_class.name.name
+            )
+            .unwrap();
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn mermaid(classes: &[&Class<'_>], methods: bool) -> String {
+    let mut out = String::new();
+    out.push_str("classDiagram\n");
+    for class in classes {
+        // Mermaid identifiers can't contain `::`, so qualified names are shown as labels
+        let name = class.name.name;
+        let id = mermaid_id(name);
+        let label = if id == name {
+            String::new()
+        } else {
+            format!("[\"{}\"]", name)
+        };
+        let entries = if methods {
+            method_entries(class)
+        } else {
+            vec![]
+        };
+
+        if entries.is_empty() {
+            writeln!(out, "    class {}{}", id, label).unwrap();
+        } else {
+            writeln!(out, "    class {}{} {{", id, label).unwrap();
+            for entry in entries {
+                // A trailing `*` is how Mermaid marks abstract methods
+                let suffix = match entry.inherited_from {
+                    _ if entry.is_abstract => "*".to_string(),
+                    Some((class, true)) => format!(" implements {}", mermaid_id(class)),
+                    Some((class, false)) => format!(" overrides {}", mermaid_id(class)),
+                    None => String::new(),
+                };
+                writeln!(out, "        {}(){}", entry.name, suffix).unwrap();
+            }
+            out.push_str("    }\n");
+        }
+    }
+    for class in classes {
+        if let Some(super_class) = super_class(class) {
+            writeln!(
+                out,
+                "    {} <|-- {}",
+                mermaid_id(super_class.name.name),
+                mermaid_id(class.name.name)
+            )
+            .unwrap();
+        }
+    }
+    out
+}
+
+fn mermaid_id(name: &str) -> String {
+    name.replace("::", "_")
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    use crate::{lex::lex, parse::parse, prep::find_classes_and_methods};
+
+    const PROGRAM: &str = r#"
+        [Object subclass name: #Shape fields: []];
+        [Shape defAbstract: #area];
+        [Shape def: #describe do: || { return "shape"; }];
+        [Shape subclass name: #Square fields: [#side]];
+        [Square def: #area do: || { return @side * @side; }];
+        [Square def: #describe do: || { return "square"; }];
+        module Geo {
+            [Object subclass name: #Point fields: []];
+        }
+        [String def: #shout do: || { return self; }];
+    "#;
+
+    fn graph(format: Format, methods: bool) -> String {
+        let tokens = lex(PROGRAM).unwrap();
+        let mut ast = parse(&tokens).unwrap();
+        prep::qualify_names(&mut ast);
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+        render(&classes, format, methods)
+    }
+
+    #[test]
+    fn dot_with_methods() {
+        let expected = [
+            "digraph classes {",
+            "    rankdir=BT;",
+            "    node [shape=box];",
+            "    \"Geo::Point\" [label=\"Geo::Point\"];",
+            "    \"Object\" [label=\"Object\"];",
+            "    \"Shape\" [label=\"Shape\\larea (abstract)\\ldescribe\\l\"];",
+            "    \"Square\" [label=\"Square\\larea (implements Shape)\\ldescribe (overrides Shape)\\l\"];",
+            "    \"String\" [label=\"String\\lshout\\l\"];",
+            "    \"Geo::Point\" -> \"Object\";",
+            "    \"Shape\" -> \"Object\";",
+            "    \"Square\" -> \"Shape\";",
+            "    \"String\" -> \"Object\";",
+            "}",
+            "",
+        ]
+        .join("\n");
+
+        assert_eq!(expected, graph(Format::Dot, true));
+    }
+
+    #[test]
+    fn mermaid_without_methods() {
+        let expected = [
+            "classDiagram",
+            "    class Geo_Point[\"Geo::Point\"]",
+            "    class Object",
+            "    class Shape",
+            "    class Square",
+            "    class String",
+            "    Object <|-- Geo_Point",
+            "    Object <|-- Shape",
+            "    Shape <|-- Square",
+            "    Object <|-- String",
+            "",
+        ]
+        .join("\n");
+
+        assert_eq!(expected, graph(Format::Mermaid, false));
+    }
+
+    #[test]
+    fn mermaid_with_methods() {
+        let output = graph(Format::Mermaid, true);
+
+        assert!(output.contains("    class Shape {\n        area()*\n        describe()\n    }\n"));
+        assert!(output.contains(
+            "    class Square {\n        area() implements Shape\n        describe() overrides Shape\n    }\n"
+        ));
+    }
+}
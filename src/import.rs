@@ -0,0 +1,533 @@
+//! Programs split over several files with `[Oops import: "lib/list_utils.oops"];`.
+//!
+//! Imports at the top level of a file are replaced by the statements of the imported file before
+//! the program is prepped, so its classes and methods can be used everywhere and its other
+//! statements run first. Paths are relative to the importing file, and a file imported more than
+//! once is only included the first time.
+//!
+//! `[Oops import: "shop/user.oops" as: Shop];` puts the statements of the file in `module Shop`,
+//! so its classes are named like `Shop::User` and can't collide with classes of the same name in
+//! other files. A file imported more than once is only included under the alias of the first
+//! import.
+//!
+//! A file with `[Oops export: [List, Stack]];` keeps the other classes it defines to itself.
+//! Using them in another file is an error, found once all the files have been read.
+//!
+//! Spans are offsets into the source, so each imported file is lexed at offsets after those of the
+//! files read before it. `Sources` turns a span back into a file and a line.
+
+use crate::{
+    ast::{Ast, CaseOf, ClassName, Expr, Ident, MessageSend, Module, Stmt},
+    error::{Error, Result},
+    parse::parse_source,
+    prep::qualify,
+    LineCol, Span,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A file that's part of the program.
+#[derive(Debug)]
+pub struct SourceFile<'a> {
+    pub path: PathBuf,
+    /// The file's text, preceded by a space for each byte of the files before it.
+    text: &'a str,
+    /// The offset the file's text starts at.
+    start: usize,
+    /// The classes listed by the file's `[Oops export: [...]]`, if it has one.
+    exports: Option<Vec<&'a str>>,
+    /// Where the import that read the file is, if it was imported.
+    imported_at: Option<Span>,
+}
+
+/// The files a program is made of, starting with the one that was run.
+#[derive(Debug)]
+pub struct Sources<'a> {
+    files: Vec<SourceFile<'a>>,
+    /// The canonical paths of the files, to find the ones imported more than once.
+    imported: HashSet<PathBuf>,
+}
+
+impl<'a> Sources<'a> {
+    pub fn new(path: PathBuf, text: &'a str) -> Self {
+        let mut imported = HashSet::new();
+        imported.extend(fs::canonicalize(&path).ok());
+        Self {
+            files: vec![SourceFile {
+                path,
+                text,
+                start: 0,
+                exports: None,
+                imported_at: None,
+            }],
+            imported,
+        }
+    }
+
+    /// Replace the imports at the top level of `ast`, the program in the first file, with the
+    /// statements of the files they import.
+    pub fn resolve_imports(&mut self, ast: Ast<'a>) -> Result<'a, Ast<'a>> {
+        let mut resolved = Vec::with_capacity(ast.len());
+        let mut importing = self.imported.iter().cloned().collect::<Vec<_>>();
+        self.merge(0, ast, &mut importing, &mut resolved)?;
+        self.check_exports(&resolved)?;
+        Ok(resolved)
+    }
+
+    /// Put the statements of a file the program doesn't import before those of `ast`, like the
+    /// prelude.
+    pub fn prepend(&mut self, path: PathBuf, text: &str, ast: Ast<'a>) -> Result<'a, Ast<'a>> {
+        let mut prepended = self.parse_file(path, text.to_string())?;
+        prepended.extend(ast);
+        Ok(prepended)
+    }
+
+    /// The file `span` is in, and the line and column it starts at in that file.
+    pub fn locate(&self, span: Span) -> (&Path, LineCol) {
+        let file = &self.files[self.file_of(span)];
+        let local = Span::new(span.from - file.start, span.to - file.start);
+        let (start, _) = local.line_col_range(&file.text[file.start..]);
+        (&file.path, start)
+    }
+
+    /// Where in the first file the code at `span` comes from: the import that read its file, or the
+    /// start for files that weren't imported, like the prelude.
+    pub fn main_span(&self, mut span: Span) -> Span {
+        loop {
+            match self.file_of(span) {
+                0 => return span,
+                file => match self.files[file].imported_at {
+                    Some(import) => span = import,
+                    None => return Span::new(0, 0),
+                },
+            }
+        }
+    }
+
+    /// The index of the file `span` is in.
+    fn file_of(&self, span: Span) -> usize {
+        self.files
+            .iter()
+            .rposition(|file| file.start <= span.from)
+            .unwrap_or(0)
+    }
+
+    /// The file the program was run from.
+    pub fn main_path(&self) -> &Path {
+        &self.files[0].path
+    }
+
+    /// Add the statements of the file at index `file` to `resolved`, with its imports replaced.
+    /// `importing` is the canonical paths of the files whose imports are being resolved.
+    fn merge(
+        &mut self,
+        file: usize,
+        ast: Ast<'a>,
+        importing: &mut Vec<PathBuf>,
+        resolved: &mut Vec<Stmt<'a>>,
+    ) -> Result<'a, ()> {
+        for stmt in ast {
+            if let Some(names) = export_names(&stmt) {
+                self.files[file]
+                    .exports
+                    .get_or_insert_with(Vec::new)
+                    .extend(names);
+                continue;
+            }
+
+            let (relative, alias, span) = match import_path(&stmt) {
+                Some(import) => import,
+                None => {
+                    resolved.push(stmt);
+                    continue;
+                }
+            };
+
+            let importer = &self.files[file].path;
+            let path = importer
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .join(relative);
+            let failed = |err: std::io::Error| Error::ImportFailed {
+                path: path.display().to_string(),
+                importer: importer.display().to_string(),
+                message: err.to_string(),
+                span,
+            };
+
+            let canonical = fs::canonicalize(&path).map_err(failed)?;
+            if let Some(idx) = importing.iter().position(|p| *p == canonical) {
+                let mut cycle = importing[idx..]
+                    .iter()
+                    .map(|p| self.display_path(p))
+                    .collect::<Vec<_>>();
+                cycle.push(path.display().to_string());
+                return Err(Error::ImportCycle { cycle, span });
+            }
+            if !self.imported.insert(canonical.clone()) {
+                continue;
+            }
+
+            let text = fs::read_to_string(&path).map_err(failed)?;
+            let importer = importer.display().to_string();
+            let ast = self.parse_file(path, text).map_err(|err| match err {
+                // Parse errors don't say where they are, so they point at the import instead
+                Error::ParseError(message) => Error::ImportFailed {
+                    path: self.files[self.files.len() - 1].path.display().to_string(),
+                    importer,
+                    message,
+                    span,
+                },
+                other => other,
+            })?;
+
+            let imported = self.files.len() - 1;
+            self.files[imported].imported_at = Some(span);
+
+            importing.push(canonical);
+            match alias {
+                None => self.merge(imported, ast, importing, resolved)?,
+                Some(alias) => {
+                    let mut body = vec![];
+                    self.merge(imported, ast, importing, &mut body)?;
+                    resolved.push(Stmt::Module(Module {
+                        name: ClassName(alias),
+                        qualified_name: alias.name.to_string(),
+                        body,
+                        span,
+                    }));
+                }
+            }
+            importing.pop();
+        }
+        Ok(())
+    }
+
+    /// Lex and parse an imported file at the offsets after the files read so far.
+    ///
+    /// The source and AST have to live as long as the program, so they're leaked. There's one of
+    /// each per imported file.
+    fn parse_file(&mut self, path: PathBuf, text: String) -> Result<'a, Ast<'a>> {
+        let last = self.files.last().expect("Internal error: no main file");
+        let start = last.text.len() + 1;
+        let mut padded = " ".repeat(start);
+        padded.push_str(&text);
+
+        let text: &'a str = Box::leak(padded.into_boxed_str());
+        self.files.push(SourceFile {
+            path,
+            text,
+            start,
+            exports: None,
+            imported_at: None,
+        });
+
+        parse_source(text)
+    }
+
+    /// Make sure the classes of files with an export list are only used in other files if
+    /// they're exported.
+    fn check_exports(&self, ast: &Ast<'a>) -> Result<'a, ()> {
+        if self.files.iter().all(|file| file.exports.is_none()) {
+            return Ok(());
+        }
+
+        let mut visibility = Visibility {
+            sources: self,
+            classes: HashMap::new(),
+        };
+        visibility.define(ast, None);
+        visibility.stmts(ast, None)
+    }
+
+    /// How the file with canonical path `canonical` was referred to when it was read.
+    fn display_path(&self, canonical: &Path) -> String {
+        self.files
+            .iter()
+            .find(|file| fs::canonicalize(&file.path).is_ok_and(|p| p == canonical))
+            .map_or_else(
+                || canonical.display().to_string(),
+                |file| file.path.display().to_string(),
+            )
+    }
+}
+
+/// The path imported by `[Oops import: "path"];` or `[Oops import: "path" as: Alias];`, the
+/// alias, and where the import is.
+fn import_path<'b, 'a>(stmt: &'b Stmt<'a>) -> Option<(&'b str, Option<Ident<'a>>, Span)> {
+    let send = match stmt {
+        Stmt::MessageSend(stmt) => &stmt.expr,
+        _ => return None,
+    };
+    let (path, alias) = match (&send.receiver, send.msg.name, send.args.as_slice()) {
+        (Expr::ClassName(class), "import", [path]) if class.0.name == "Oops" => (path, None),
+        (Expr::ClassName(class), "import", [path, alias])
+            if class.0.name == "Oops" && alias.ident.name == "as" =>
+        {
+            match &alias.expr {
+                Expr::ClassName(alias) => (path, Some(alias.0)),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    match &path.expr {
+        Expr::Str(path) => Some((&path.value, alias, send.span)),
+        _ => None,
+    }
+}
+
+/// The classes listed by `[Oops export: [List, Stack]];`.
+fn export_names<'a>(stmt: &Stmt<'a>) -> Option<Vec<&'a str>> {
+    let send = match stmt {
+        Stmt::MessageSend(stmt) => &stmt.expr,
+        _ => return None,
+    };
+    match (&send.receiver, send.msg.name, send.args.as_slice()) {
+        (Expr::ClassName(class), "export", [arg]) if class.0.name == "Oops" => match &arg.expr {
+            Expr::List(list) => list
+                .items
+                .iter()
+                .map(|item| match item {
+                    Expr::ClassName(class) => Some(class.0.name),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Finds the classes used outside the files that define them without being exported.
+struct Visibility<'s, 'a> {
+    sources: &'s Sources<'a>,
+    /// The classes defined by the program, by qualified name, with the index of the file defining
+    /// them and whether it exports them.
+    classes: HashMap<String, (usize, bool)>,
+}
+
+impl<'a> Visibility<'_, 'a> {
+    fn define(&mut self, stmts: &[Stmt<'a>], module: Option<&str>) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::DefineClass(inner) => {
+                    let name = inner.name.class_name.0.name;
+                    let file = self.sources.file_of(inner.span);
+                    let exported = self.sources.files[file]
+                        .exports
+                        .as_ref()
+                        .is_none_or(|names| names.contains(&name));
+                    self.classes.insert(qualify(module, name), (file, exported));
+                }
+                Stmt::Module(inner) => {
+                    let module = qualify(module, inner.name.0.name);
+                    self.define(&inner.body, Some(&module));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn stmts(&self, stmts: &[Stmt<'a>], module: Option<&str>) -> Result<'a, ()> {
+        stmts.iter().try_for_each(|stmt| self.stmt(stmt, module))
+    }
+
+    fn stmt(&self, stmt: &Stmt<'a>, module: Option<&str>) -> Result<'a, ()> {
+        match stmt {
+            Stmt::LetLocal(inner) => self.expr(&inner.body, module),
+            Stmt::LetIVar(inner) => self.expr(&inner.body, module),
+            Stmt::LetConstant(inner) => self.expr(&inner.body, module),
+            Stmt::MessageSend(inner) => self.message_send(&inner.expr, module),
+            Stmt::CaseOf(inner) => self.case_of(&inner.expr, module),
+            Stmt::Return(inner) => self.expr(&inner.expr, module),
+            Stmt::DefineMethod(inner) => {
+                self.class_name(&inner.class_name, module)?;
+                self.stmts(&inner.block.body, module)
+            }
+            Stmt::DefineAbstractMethod(inner) => self.class_name(&inner.class_name, module),
+            Stmt::DefineClass(inner) => self.class_name(&inner.super_class.class_name, module),
+            Stmt::ReopenClass(inner) => self.class_name(&inner.class_name, module),
+            Stmt::Module(inner) => {
+                let module = qualify(module, inner.name.0.name);
+                self.stmts(&inner.body, Some(&module))
+            }
+        }
+    }
+
+    fn expr(&self, node: &Expr<'a>, module: Option<&str>) -> Result<'a, ()> {
+        match node {
+            Expr::ClassName(inner) => self.class_name(inner, module),
+            Expr::ClassNew(inner) => {
+                self.class_name(&inner.class_name, module)?;
+                inner
+                    .args
+                    .iter()
+                    .try_for_each(|arg| self.expr(&arg.expr, module))
+            }
+            Expr::MessageSend(inner) => self.message_send(inner, module),
+            Expr::Block(inner) => self.stmts(&inner.body, module),
+            Expr::List(inner) | Expr::ConstantList(inner) => inner
+                .items
+                .iter()
+                .try_for_each(|item| self.expr(item, module)),
+            Expr::CaseOf(inner) => self.case_of(inner, module),
+            Expr::Quote(inner) => self.stmts(&inner.body, module),
+            Expr::Local(_)
+            | Expr::IVar(_)
+            | Expr::Unquote(_)
+            | Expr::Number(_)
+            | Expr::Str(_)
+            | Expr::Selector(_)
+            | Expr::True(_)
+            | Expr::False(_)
+            | Expr::Self_(_) => Ok(()),
+        }
+    }
+
+    fn message_send(&self, send: &MessageSend<'a>, module: Option<&str>) -> Result<'a, ()> {
+        self.expr(&send.receiver, module)?;
+        send.args
+            .iter()
+            .try_for_each(|arg| self.expr(&arg.expr, module))
+    }
+
+    fn case_of(&self, case_of: &CaseOf<'a>, module: Option<&str>) -> Result<'a, ()> {
+        self.expr(&case_of.subject, module)?;
+        for arm in &case_of.arms {
+            self.expr(&arm.value, module)?;
+            self.expr(&arm.body, module)?;
+        }
+        match &case_of.otherwise {
+            Some(otherwise) => self.expr(otherwise, module),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the class `name` refers to, looked up like `prep::resolve_class_name` does.
+    fn class_name(&self, name: &ClassName<'a>, module: Option<&str>) -> Result<'a, ()> {
+        let ident = name.0;
+        let mut candidates = vec![];
+        let mut current = module;
+        while let Some(outer) = current {
+            candidates.push(format!("{}::{}", outer, ident.name));
+            current = outer.rsplit_once("::").map(|(outer, _)| outer);
+        }
+        candidates.push(ident.name.to_string());
+
+        let found = candidates
+            .into_iter()
+            .find_map(|class| Some((self.classes.get(&class)?, class)));
+        match found {
+            Some((&(file, false), class)) if file != self.sources.file_of(ident.span) => {
+                Err(Error::ClassNotExported {
+                    class,
+                    path: self.sources.files[file].path.display().to_string(),
+                    span: ident.span,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn imports_files_once_in_order() {
+        let dir = std::env::temp_dir().join(format!("oops-import-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("lib/util.oops"), "let UTIL = 1;\n").unwrap();
+        fs::write(
+            dir.join("lib/shapes.oops"),
+            "[Oops import: \"util.oops\"];\n\nlet SHAPES = 2;\n",
+        )
+        .unwrap();
+
+        let main = "[Oops import: \"lib/shapes.oops\"];\n[Oops import: \"lib/util.oops\"];\nlet MAIN = 3;\n";
+        let ast = parse_source(main).unwrap();
+        let mut sources = Sources::new(dir.join("main.oops"), main);
+        let resolved = sources.resolve_imports(ast).unwrap();
+
+        let names = resolved
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::LetConstant(constant) => constant.name.0.name,
+                other => panic!("unexpected statement {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["UTIL", "SHAPES", "MAIN"], names);
+
+        let (path, line_col) = sources.locate(resolved[1].span());
+        assert_eq!(dir.join("lib/shapes.oops"), path);
+        assert_eq!(LineCol { line: 3, col: 1 }, line_col);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_exported_classes_are_used_in_other_files() {
+        let dir = std::env::temp_dir().join(format!("oops-export-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("shop.oops"),
+            "[Oops export: [Cart]];\n[Object subclass name: #Cart fields: []];\n[Object subclass name: #Cache fields: []];\nlet CACHE = [Cache new];\n",
+        )
+        .unwrap();
+
+        let resolve = |main: &'static str| {
+            let ast = parse_source(main).unwrap();
+            Sources::new(dir.join("main.oops"), main).resolve_imports(ast)
+        };
+
+        assert!(resolve("[Oops import: \"shop.oops\"];\nlet cart = [Cart new];\n").is_ok());
+        assert!(resolve(
+            "[Oops import: \"shop.oops\"];\nmodule Local {\n[Object subclass name: #Cache fields: []];\nlet cache = [Cache new];\n}\n"
+        )
+        .is_ok());
+        match resolve("[Oops import: \"shop.oops\"];\nlet cache = [Cache new];\n") {
+            Err(Error::ClassNotExported { class, .. }) => assert_eq!("Cache", class),
+            other => panic!("expected ClassNotExported, got {:?}", other.map(|_| ())),
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn aliased_imports_define_classes_in_a_module() {
+        let dir = std::env::temp_dir().join(format!("oops-alias-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("shop.oops"),
+            "[Object subclass name: #User fields: []];\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("forum.oops"),
+            "[Object subclass name: #User fields: []];\n",
+        )
+        .unwrap();
+
+        let main = "[Oops import: \"shop.oops\" as: Shop];\n[Oops import: \"forum.oops\" as: Forum];\nlet user = [Shop::User new];\n";
+        let ast = parse_source(main).unwrap();
+        let mut ast = Sources::new(dir.join("main.oops"), main)
+            .resolve_imports(ast)
+            .unwrap();
+        crate::prep::qualify_names(&mut ast);
+        let ast = Box::leak(Box::new(ast));
+        let classes =
+            crate::prep::find_classes_and_methods(ast, crate::prep::built_in_classes()).unwrap();
+
+        assert!(classes.contains_key("Shop::User"));
+        assert!(classes.contains_key("Forum::User"));
+        assert!(!classes.contains_key("User"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+//! Where source evaluated at runtime is kept.
+//!
+//! Blocks created by evaluated source refer to its syntax tree, and can outlive the call that
+//! evaluated it, so the source and tree are kept for as long as the interpreter's program is. The
+//! host creates the arena next to the program and lends it to the interpreter with
+//! [`Interpreter::with_eval_arena`](super::Interpreter::with_eval_arena).
+
+use crate::ast::Ast;
+use std::cell::{Cell, OnceCell};
+
+/// The source and syntax tree of everything evaluated with `[Oops eval: source]` and
+/// `[Runtime eval: source]`.
+#[derive(Default)]
+pub struct EvalArena<'a> {
+    sources: Arena<String>,
+    asts: Arena<Ast<'a>>,
+}
+
+impl<'a> EvalArena<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(super) fn source(&'a self, source: String) -> &'a str {
+        self.sources.alloc(source)
+    }
+
+    pub(super) fn ast(&'a self, ast: Ast<'a>) -> &'a Ast<'a> {
+        self.asts.alloc(ast)
+    }
+}
+
+/// Values that stay where they are until the arena is dropped. They're kept in chunks that double
+/// in size, so adding one only has to look through a few chunks.
+struct Arena<T> {
+    len: Cell<usize>,
+    first: Chunk<T>,
+}
+
+struct Chunk<T> {
+    items: Box<[OnceCell<T>]>,
+    next: OnceCell<Box<Chunk<T>>>,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self {
+            len: Cell::new(0),
+            first: Chunk::new(16),
+        }
+    }
+}
+
+impl<T> Arena<T> {
+    fn alloc(&self, value: T) -> &T {
+        let index = self.len.get();
+        self.len.set(index + 1);
+
+        let mut chunk = &self.first;
+        let mut start = 0;
+        while index >= start + chunk.items.len() {
+            start += chunk.items.len();
+            let size = chunk.items.len() * 2;
+            chunk = chunk.next.get_or_init(|| Box::new(Chunk::new(size)));
+        }
+
+        let item = &chunk.items[index - start];
+        if item.set(value).is_err() {
+            unreachable!("Internal error: arena slot already taken");
+        }
+        item.get().expect("Internal error: arena slot is empty")
+    }
+}
+
+impl<T> Chunk<T> {
+    fn new(size: usize) -> Self {
+        Self {
+            items: (0..size).map(|_| OnceCell::new()).collect(),
+            next: OnceCell::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn values_stay_put_as_chunks_are_added() {
+        let arena = Arena::default();
+        let values = (0..100).map(|n| arena.alloc(n)).collect::<Vec<_>>();
+        assert_eq!(
+            (0..100).collect::<Vec<_>>(),
+            values.into_iter().copied().collect::<Vec<_>>()
+        );
+    }
+}
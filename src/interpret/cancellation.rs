@@ -0,0 +1,37 @@
+use signal_hook::consts::SIGINT;
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Stops a running program from another thread.
+///
+/// The interpreter checks the token before each statement and message send, and fails with
+/// `Error::Cancelled` once it has been cancelled. Clones share the same state.
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Cancel the token when the process receives SIGINT, for example from Ctrl-C. A second
+    /// SIGINT exits the process immediately with `exit_code`, in case the program doesn't stop.
+    pub fn cancel_on_interrupt(&self, exit_code: i32) -> io::Result<()> {
+        signal_hook::flag::register_conditional_shutdown(SIGINT, exit_code, Arc::clone(&self.0))?;
+        signal_hook::flag::register(SIGINT, Arc::clone(&self.0))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+//! The messages understood by the `Hash` class, and the UUIDs created by `[Uuid new]`.
+//!
+//! Hashes are returned as lowercase hex strings. They accept strings, which are hashed as UTF-8,
+//! or `Bytes`.
+
+use super::{bytes, random::Rng, single_argument, ArgumentValue, Interpreter, Value};
+use crate::{
+    error::{Error, Result},
+    Span,
+};
+use sha2::{Digest, Sha256};
+use std::rc::Rc;
+
+pub(super) fn send_hash<'a>(
+    interpreter: &Interpreter<'a>,
+    msg: &'a str,
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    let hash: fn(&[u8]) -> String = match msg {
+        "sha256" => |data| bytes::to_hex(&Sha256::digest(data)),
+        "crc32" => |data| format!("{:08x}", crc32fast::hash(data)),
+        _ => return Err(interpreter.not_understood_by_class("Hash", msg, call_site)),
+    };
+
+    let hex = match single_argument(args, msg, call_site)? {
+        Value::String(string) => hash(string.as_bytes()),
+        Value::Bytes(data) => hash(&data),
+        other => {
+            return Err(Error::UnexpectedType {
+                expected: "String or Bytes",
+                given: other.class_name(),
+                span: call_site,
+            })
+        }
+    };
+    Ok(Value::String(Rc::from(hex)))
+}
+
+/// A random (version 4) UUID drawn from `rng`, so `--seed` makes them repeat as well.
+pub(super) fn uuid_v4(rng: &mut Rng) -> String {
+    let mut data = [0; 16];
+    data[..8].copy_from_slice(&rng.next_u64().to_be_bytes());
+    data[8..].copy_from_slice(&rng.next_u64().to_be_bytes());
+    data[6] = (data[6] & 0x0f) | 0x40;
+    data[8] = (data[8] & 0x3f) | 0x80;
+
+    let hex = bytes::to_hex(&data);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
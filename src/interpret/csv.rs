@@ -0,0 +1,154 @@
+//! The messages understood by the `Csv` class.
+//!
+//! Rows are parsed into lists of strings, or into dicts keyed by the first row when `headers: true`
+//! is given. Rows of different lengths are allowed.
+
+use super::{bind_arguments, dict_get, require_files, ArgumentValue, Interpreter, Value};
+use crate::{
+    error::{Error, Result},
+    Span,
+};
+use std::{fs, io, rc::Rc};
+
+pub(super) fn send<'a>(
+    interpreter: &Interpreter<'a>,
+    msg: &'a str,
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    match msg {
+        "parse" | "parseFile" => {
+            let mut parameters = vec![msg];
+            if args.iter().any(|arg| arg.name == "headers") {
+                parameters.push("headers");
+            }
+            let mut args = bind_arguments(parameters, call_site, args)?;
+            let source = match args.remove(msg) {
+                Some(Value::String(source)) => source,
+                other => return Err(unexpected_type("String", other, call_site)),
+            };
+            let headers = args
+                .remove("headers")
+                .is_some_and(|value| value.is_truthy());
+
+            let source = if msg == "parseFile" {
+                require_files(interpreter, call_site)?;
+                Rc::from(fs::read_to_string(&*source)?)
+            } else {
+                source
+            };
+            parse(&source, headers).map_err(|err| Error::InvalidSource {
+                message: err.to_string(),
+                span: call_site,
+            })
+        }
+        "write" => {
+            require_files(interpreter, call_site)?;
+            let mut args = bind_arguments(vec!["write", "rows"], call_site, args)?;
+            let path = match args.remove("write") {
+                Some(Value::String(path)) => path,
+                other => return Err(unexpected_type("String", other, call_site)),
+            };
+            let rows = match args.remove("rows") {
+                Some(Value::List(rows)) => rows,
+                other => return Err(unexpected_type("List", other, call_site)),
+            };
+
+            let csv = generate(&rows, call_site)?;
+            fs::write(&*path, csv)?;
+            Ok(Value::Nil)
+        }
+        _ => Err(interpreter.not_understood_by_class("Csv", msg, call_site)),
+    }
+}
+
+fn parse<'a>(source: &str, headers: bool) -> std::result::Result<Value<'a>, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(source.as_bytes());
+    let mut records = reader.records();
+
+    let header = if headers {
+        match records.next() {
+            Some(record) => Some(record?),
+            None => return Ok(Value::List(Rc::new(vec![]))),
+        }
+    } else {
+        None
+    };
+
+    let mut rows = vec![];
+    for record in records {
+        let record = record?;
+        let row = match &header {
+            Some(header) => Value::Dict(Rc::new(
+                header
+                    .iter()
+                    .zip(record.iter().map(Some).chain(std::iter::repeat(None)))
+                    .map(|(key, field)| (Rc::from(key), field.map_or(Value::Nil, string)))
+                    .collect(),
+            )),
+            None => Value::List(Rc::new(record.iter().map(string).collect())),
+        };
+        rows.push(row);
+    }
+    Ok(Value::List(Rc::new(rows)))
+}
+
+/// Rows have to be all lists or all dicts. For dicts, the keys of the first row become the header.
+fn generate<'a>(rows: &[Value<'a>], call_site: Span) -> Result<'a, Vec<u8>> {
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(vec![]);
+    let header = match rows.first() {
+        Some(Value::Dict(entries)) => Some(
+            entries
+                .iter()
+                .map(|(key, _)| Rc::clone(key))
+                .collect::<Vec<_>>(),
+        ),
+        _ => None,
+    };
+    if let Some(header) = &header {
+        writer
+            .write_record(header.iter().map(|key| key.as_bytes()))
+            .map_err(io::Error::from)?;
+    }
+
+    for row in rows {
+        let fields: Vec<String> = match (row, &header) {
+            (Value::List(items), None) => items.iter().map(field).collect(),
+            (Value::Dict(entries), Some(header)) => header
+                .iter()
+                .map(|key| dict_get(entries, key).map_or_else(String::new, field))
+                .collect(),
+            (other, _) => {
+                let expected = if header.is_some() { "Dict" } else { "List" };
+                return Err(unexpected_type(expected, Some(other.to_owned()), call_site));
+            }
+        };
+        writer.write_record(&fields).map_err(io::Error::from)?;
+    }
+
+    writer
+        .into_inner()
+        .map_err(|err| Error::IoError(err.into_error()))
+}
+
+fn string<'a>(field: &str) -> Value<'a> {
+    Value::String(Rc::from(field))
+}
+
+fn field(value: &Value<'_>) -> String {
+    match value {
+        Value::Nil => String::new(),
+        other => other.to_string(),
+    }
+}
+
+fn unexpected_type<'a>(expected: &'static str, given: Option<Value<'a>>, span: Span) -> Error<'a> {
+    Error::UnexpectedType {
+        expected,
+        given: given.map_or("Nil", |value| value.class_name()),
+        span,
+    }
+}
@@ -0,0 +1,68 @@
+//! The messages understood by the `Encode` class.
+//!
+//! `base64:` and `urlEncode:` take strings, which are encoded as UTF-8, or `Bytes`. Decoding
+//! returns `nil` for input that isn't valid.
+
+use super::{single_argument, ArgumentValue, Interpreter, Value};
+use crate::{
+    error::{Error, Result},
+    Span,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use percent_encoding::{
+    percent_decode_str, percent_encode, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC,
+};
+use std::rc::Rc;
+
+/// Everything except the characters RFC 3986 calls unreserved.
+const URL_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+pub(super) fn send<'a>(
+    interpreter: &Interpreter<'a>,
+    msg: &'a str,
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    if !matches!(msg, "base64" | "fromBase64" | "urlEncode" | "urlDecode") {
+        return Err(interpreter.not_understood_by_class("Encode", msg, call_site));
+    }
+
+    let arg = single_argument(args, msg, call_site)?;
+    let value = match (msg, &arg) {
+        ("base64", Value::String(string)) => string_value(STANDARD.encode(string.as_bytes())),
+        ("base64", Value::Bytes(bytes)) => string_value(STANDARD.encode(&**bytes)),
+        ("fromBase64", Value::String(string)) => STANDARD
+            .decode(string.as_bytes())
+            .map_or(Value::Nil, |bytes| Value::Bytes(Rc::new(bytes))),
+        ("urlEncode", Value::String(string)) => {
+            string_value(utf8_percent_encode(string, URL_COMPONENT).to_string())
+        }
+        ("urlEncode", Value::Bytes(bytes)) => {
+            string_value(percent_encode(bytes, URL_COMPONENT).to_string())
+        }
+        ("urlDecode", Value::String(string)) => percent_decode_str(string)
+            .decode_utf8()
+            .map_or(Value::Nil, |decoded| string_value(decoded.into_owned())),
+        ("fromBase64", _) | ("urlDecode", _) => {
+            return Err(unexpected_type("String", &arg, call_site))
+        }
+        _ => return Err(unexpected_type("String or Bytes", &arg, call_site)),
+    };
+    Ok(value)
+}
+
+fn string_value<'a>(string: String) -> Value<'a> {
+    Value::String(Rc::from(string))
+}
+
+fn unexpected_type<'a>(expected: &'static str, given: &Value<'a>, span: Span) -> Error<'a> {
+    Error::UnexpectedType {
+        expected,
+        given: given.class_name(),
+        span,
+    }
+}
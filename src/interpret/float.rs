@@ -0,0 +1,13 @@
+//! How floats are written as text.
+//!
+//! Everything that shows a float goes through `format`, so a float looks the same whether it's
+//! printed, converted to a string, written to CSV, or generated as JSON.
+
+/// The shortest text that parses back to the same float.
+///
+/// Whole floats keep a `.0` so they can be told apart from numbers. Floats from `1e-4` up to
+/// `1e16` are written out in full and others with an exponent, like `1e16` and `2.5e-7`. `NaN`,
+/// `inf`, and `-inf` have no other representation, and aren't valid JSON.
+pub(super) fn format(float: f64) -> String {
+    format!("{:?}", float)
+}
@@ -0,0 +1,80 @@
+//! The locals of the program, a method call, or a block call.
+//!
+//! Locals are kept in slots that `prep::resolve_slots` numbers before the program runs, in the
+//! order they're defined. A block's frame starts as a copy of the one it was created in, so the
+//! locals it captured keep their slots and its own come after them.
+
+use super::Value;
+use crate::ast::Slot;
+
+#[derive(Debug, Default)]
+pub struct Frame<'a> {
+    values: Vec<Value<'a>>,
+    /// The name of the local in each slot. Evaluated source and REPL input are resolved against
+    /// the frame they run in, so they need to know what's defined.
+    names: Vec<&'a str>,
+}
+
+impl<'a> Frame<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value in `slot`, or `None` if the local isn't defined.
+    pub fn get(&self, slot: Slot) -> Option<&Value<'a>> {
+        self.values.get(slot.0?)
+    }
+
+    /// Define the local in `slot`, or change its value if it's already defined. Locals are defined
+    /// in the order of their slots.
+    pub fn insert(&mut self, slot: Slot, name: &'a str, value: Value<'a>) {
+        let slot = slot
+            .0
+            .unwrap_or_else(|| panic!("Internal error: local `{}` has no slot", name));
+        if slot < self.values.len() {
+            self.values[slot] = value;
+        } else {
+            assert_eq!(
+                slot,
+                self.values.len(),
+                "Internal error: local `{}` defined out of order",
+                name
+            );
+            self.values.push(value);
+            self.names.push(name);
+        }
+    }
+
+    /// Take the value of a local out, leaving `nil` until it's put back with `insert`.
+    pub fn take(&mut self, slot: Slot) -> Option<Value<'a>> {
+        self.values
+            .get_mut(slot.0?)
+            .map(|value| std::mem::replace(value, Value::Nil))
+    }
+
+    /// The value of the local named `name`, for hosts and tests. The interpreter uses slots.
+    pub fn lookup(&self, name: &str) -> Option<&Value<'a>> {
+        let slot = self.names.iter().position(|slot| *slot == name)?;
+        self.values.get(slot)
+    }
+
+    pub fn names(&self) -> &[&'a str] {
+        &self.names
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value<'a>> {
+        self.values.iter()
+    }
+
+    /// A frame with the same locals, for a block to start from.
+    pub fn copy(&self) -> Self {
+        Self {
+            values: self.values.iter().map(Value::to_owned).collect(),
+            names: self.names.clone(),
+        }
+    }
+}
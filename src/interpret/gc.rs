@@ -0,0 +1,161 @@
+//! Freeing instances that are only kept alive by reference cycles, like the nodes of a
+//! doubly-linked list.
+//!
+//! Values are reference counted, so most are freed as soon as nothing refers to them. Instances
+//! are also registered in a `Heap`, which collects cycles every so often or when the program sends
+//! `[System gc]`. A collection looks at the instances, lists, dicts, and blocks reachable from the
+//! registered instances and counts how many references to each come from the others. Those with
+//! more references than that are referred to from somewhere else, such as a local or a value the
+//! collection doesn't look inside, so they and everything they refer to are alive. The instance
+//! variables of the remaining instances are cleared, which breaks the cycles so they're freed.
+
+use super::{Instance, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    rc::{Rc, Weak},
+};
+
+/// Collect automatically once this many instances are registered, and at least twice as many as
+/// were alive after the last collection.
+const MIN_THRESHOLD: usize = 10_000;
+
+pub(super) struct Heap<'a> {
+    instances: Vec<Weak<Instance<'a>>>,
+    threshold: usize,
+}
+
+impl Default for Heap<'_> {
+    fn default() -> Self {
+        Self {
+            instances: Vec::new(),
+            threshold: MIN_THRESHOLD,
+        }
+    }
+}
+
+impl<'a> Heap<'a> {
+    /// Keep track of `instance`, collecting first if enough instances have been registered since
+    /// the last collection.
+    pub(super) fn register(&mut self, instance: &Rc<Instance<'a>>) {
+        if self.instances.len() >= self.threshold {
+            self.collect();
+            self.threshold = MIN_THRESHOLD.max(self.instances.len() * 2);
+        }
+        self.instances.push(Rc::downgrade(instance));
+    }
+
+    /// Free the instances that are only referred to by cycles and return how many there were.
+    pub(super) fn collect(&mut self) -> usize {
+        // The collection holds exactly one reference to each value it finds, so it knows how many
+        // of their references are its own
+        let mut found = HashMap::<usize, Value<'a>>::new();
+        let mut children = HashMap::<usize, Vec<usize>>::new();
+        let mut pending = self
+            .instances
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|instance| {
+                let value = Value::Instance(instance);
+                (address(&value).expect("instances have an address"), value)
+            })
+            .collect::<Vec<_>>();
+        let mut seen = pending
+            .iter()
+            .map(|(address, _)| *address)
+            .collect::<HashSet<_>>();
+
+        while let Some((parent, value)) = pending.pop() {
+            let mut addresses = vec![];
+            for_each_child(&value, |child| {
+                if let Some(child_address) = address(child) {
+                    addresses.push(child_address);
+                    if seen.insert(child_address) {
+                        pending.push((child_address, child.to_owned()));
+                    }
+                }
+            });
+            children.insert(parent, addresses);
+            found.insert(parent, value);
+        }
+
+        let mut internal = HashMap::<usize, usize>::new();
+        for child in children.values().flatten() {
+            *internal.entry(*child).or_default() += 1;
+        }
+
+        let mut alive = HashSet::new();
+        let mut pending = found
+            .iter()
+            .filter(|(address, value)| {
+                let strong = strong_count(value) - 1;
+                strong > internal.get(address).copied().unwrap_or(0)
+            })
+            .map(|(address, _)| *address)
+            .collect::<Vec<_>>();
+        while let Some(address) = pending.pop() {
+            if alive.insert(address) {
+                pending.extend(&children[&address]);
+            }
+        }
+
+        let garbage = found
+            .into_iter()
+            .filter(|(address, _)| !alive.contains(address))
+            .filter_map(|(_, value)| match value {
+                Value::Instance(instance) => Some(instance),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let collected = garbage.len();
+
+        // Dicts and blocks can't be changed after they're created, and lists are only changed in
+        // place when nothing else refers to them, so every cycle goes through an instance
+        // variable, and clearing those frees everything in the cycles
+        let ivars = garbage
+            .iter()
+            .map(|instance| mem::take(&mut *instance.ivars.borrow_mut()))
+            .collect::<Vec<_>>();
+        drop(ivars);
+        drop(garbage);
+
+        self.instances
+            .retain(|instance| instance.strong_count() > 0);
+        collected
+    }
+}
+
+/// Where the value's reference counted data is, if it's a value collections look inside.
+fn address(value: &Value<'_>) -> Option<usize> {
+    let pointer = match value {
+        Value::Instance(instance) => Rc::as_ptr(instance) as *const u8,
+        Value::List(items) => Rc::as_ptr(items) as *const u8,
+        Value::Dict(entries) => Rc::as_ptr(entries) as *const u8,
+        Value::Block(closure) => Rc::as_ptr(closure) as *const u8,
+        _ => return None,
+    };
+    Some(pointer as usize)
+}
+
+fn strong_count(value: &Value<'_>) -> usize {
+    match value {
+        Value::Instance(instance) => Rc::strong_count(instance),
+        Value::List(items) => Rc::strong_count(items),
+        Value::Dict(entries) => Rc::strong_count(entries),
+        Value::Block(closure) => Rc::strong_count(closure),
+        _ => 0,
+    }
+}
+
+fn for_each_child<'a>(value: &Value<'a>, mut f: impl FnMut(&Value<'a>)) {
+    match value {
+        Value::Instance(instance) => instance.ivars.borrow().values().for_each(f),
+        Value::List(items) => items.iter().for_each(f),
+        Value::Dict(entries) => entries.iter().for_each(|(_, value)| f(value)),
+        Value::Block(closure) => {
+            closure.self_.iter().for_each(&mut f);
+            closure.locals.values().for_each(f);
+        }
+        _ => {}
+    }
+}
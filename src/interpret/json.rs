@@ -0,0 +1,95 @@
+//! Converting between JSON and values, for the `Json` class.
+//!
+//! Objects become dicts, arrays become lists, and `null` becomes `nil`. Whole numbers that fit
+//! become numbers and other numbers become floats.
+
+use super::{float, Value};
+use serde_json::{Map, Number, Value as Json};
+use std::{convert::TryFrom, rc::Rc};
+
+/// Parse JSON into a value. Errors are described as strings.
+pub(super) fn parse<'a>(source: &str) -> Result<Value<'a>, String> {
+    let json = serde_json::from_str::<Json>(source).map_err(|err| err.to_string())?;
+    Ok(from_json(json))
+}
+
+/// Generate compact JSON from a value. Fails with the class name of the first value that has no
+/// JSON representation.
+pub(super) fn generate<'a>(value: &Value<'a>) -> Result<String, &'a str> {
+    let mut out = String::new();
+    write_json(&to_json(value)?, &mut out);
+    Ok(out)
+}
+
+/// Write `json` like `serde_json` does, except for floats which are written like everywhere else.
+fn write_json(json: &Json, out: &mut String) {
+    match json {
+        Json::Number(number) if number.is_f64() => {
+            out.push_str(&float::format(number.as_f64().unwrap_or(f64::NAN)))
+        }
+        Json::Array(items) => {
+            out.push('[');
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                write_json(item, out);
+            }
+            out.push(']');
+        }
+        Json::Object(entries) => {
+            out.push('{');
+            for (idx, (key, value)) in entries.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Json::from(key.as_str()).to_string());
+                out.push(':');
+                write_json(value, out);
+            }
+            out.push('}');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+fn from_json<'a>(json: Json) -> Value<'a> {
+    match json {
+        Json::Null => Value::Nil,
+        Json::Bool(bool) => Value::from(bool),
+        Json::Number(number) => number
+            .as_i64()
+            .and_then(|n| i32::try_from(n).ok())
+            .map(Value::Number)
+            .unwrap_or_else(|| Value::Float(number.as_f64().unwrap_or(f64::NAN))),
+        Json::String(string) => Value::String(Rc::from(string)),
+        Json::Array(items) => Value::List(Rc::new(items.into_iter().map(from_json).collect())),
+        Json::Object(entries) => Value::Dict(Rc::new(
+            entries
+                .into_iter()
+                .map(|(key, value)| (Rc::from(key), from_json(value)))
+                .collect(),
+        )),
+    }
+}
+
+fn to_json<'a>(value: &Value<'a>) -> Result<Json, &'a str> {
+    let json = match value {
+        Value::Nil => Json::Null,
+        Value::True => Json::Bool(true),
+        Value::False => Json::Bool(false),
+        Value::Number(n) => Json::from(*n),
+        Value::Float(f) => Json::Number(Number::from_f64(*f).ok_or("Float")?),
+        Value::String(string) => Json::String(string.to_string()),
+        Value::List(items) => Json::Array(items.iter().map(to_json).collect::<Result<_, _>>()?),
+        Value::Dict(entries) => {
+            let mut map = Map::new();
+            for (key, value) in entries.iter() {
+                map.insert(key.to_string(), to_json(value)?);
+            }
+            Json::Object(map)
+        }
+        other => return Err(other.class_name()),
+    };
+    Ok(json)
+}
@@ -0,0 +1,189 @@
+//! The messages understood by the `Math` class.
+//!
+//! Arguments can be numbers or floats. Results are numbers when they can be represented as one
+//! and floats otherwise, so `[Math sqrt: 4]` is `2.0` while `[Math pow: 2 exp: 3]` is `8`.
+
+use super::{bind_arguments, ArgumentValue, Interpreter, Value};
+use crate::{
+    error::{Error, Result},
+    Span,
+};
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+use std::{convert::TryFrom, rc::Rc};
+
+pub(super) fn send<'a>(
+    interpreter: &Interpreter<'a>,
+    msg: &'a str,
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    let parameters = match msg {
+        "sqrt" | "abs" | "floor" | "ceil" => vec![msg],
+        "pow" => vec!["pow", "exp"],
+        "min" | "max" => vec![msg, "and"],
+        _ => return Err(interpreter.not_understood_by_class("Math", msg, call_site)),
+    };
+    let mut args = bind_arguments(parameters, call_site, args)?;
+    let mut arg = |name| {
+        let value = args
+            .remove(name)
+            .expect("Internal error: bound argument missing");
+        match value {
+            Value::Number(_) | Value::Float(_) => Ok(value),
+            other => Err(Error::UnexpectedType {
+                expected: "Number",
+                given: other.class_name(),
+                span: call_site,
+            }),
+        }
+    };
+
+    let value = match msg {
+        "sqrt" => Value::Float(to_f64(&arg("sqrt")?).sqrt()),
+        "pow" => match (arg("pow")?, arg("exp")?) {
+            (Value::Number(base), Value::Number(exp)) => u32::try_from(exp)
+                .ok()
+                .and_then(|exp| base.checked_pow(exp))
+                .map(Value::Number)
+                .unwrap_or_else(|| Value::Float(f64::from(base).powf(f64::from(exp)))),
+            (base, exp) => Value::Float(to_f64(&base).powf(to_f64(&exp))),
+        },
+        "abs" => match arg("abs")? {
+            Value::Number(n) => n
+                .checked_abs()
+                .map(Value::Number)
+                .unwrap_or_else(|| Value::Float(f64::from(n).abs())),
+            other => Value::Float(to_f64(&other).abs()),
+        },
+        "min" | "max" => {
+            let lhs = arg(msg)?;
+            let rhs = arg("and")?;
+            let lhs_is_smaller = to_f64(&lhs) <= to_f64(&rhs);
+            if lhs_is_smaller == (msg == "min") {
+                lhs
+            } else {
+                rhs
+            }
+        }
+        "floor" => round(arg("floor")?, f64::floor),
+        "ceil" => round(arg("ceil")?, f64::ceil),
+        _ => unreachable!("Internal error: unknown Math message `{}`", msg),
+    };
+    Ok(value)
+}
+
+/// `add:`, `subtract:`, `multiply:`, and `divide:` sent to a number, big int, or float.
+///
+/// Integer results stay integers, and numbers raise an overflow error rather than wrapping when
+/// the result doesn't fit. Dividing integers unevenly, or involving a float, gives a float.
+pub(super) fn arithmetic<'a>(
+    msg: &'a str,
+    lhs: Value<'a>,
+    rhs: Value<'a>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    if !matches!(rhs, Value::Number(_) | Value::BigInt(_) | Value::Float(_)) {
+        return Err(Error::UnexpectedType {
+            expected: "Number",
+            given: rhs.class_name(),
+            span: call_site,
+        });
+    }
+    if msg == "divide" && to_f64(&rhs) == 0.0 {
+        return Err(Error::DivisionByZero(call_site));
+    }
+
+    let value = match (&lhs, &rhs) {
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            let checked = match msg {
+                "add" => lhs.checked_add(*rhs),
+                "subtract" => lhs.checked_sub(*rhs),
+                "multiply" => lhs.checked_mul(*rhs),
+                _ if lhs.checked_rem(*rhs).is_some_and(|rem| rem != 0) => {
+                    return Ok(Value::Float(f64::from(*lhs) / f64::from(*rhs)))
+                }
+                _ => lhs.checked_div(*rhs),
+            };
+            checked
+                .map(Value::Number)
+                .ok_or(Error::Overflow(call_site))?
+        }
+        (Value::Float(_), _) | (_, Value::Float(_)) => {
+            let (lhs, rhs) = (to_f64(&lhs), to_f64(&rhs));
+            Value::Float(match msg {
+                "add" => lhs + rhs,
+                "subtract" => lhs - rhs,
+                "multiply" => lhs * rhs,
+                _ => lhs / rhs,
+            })
+        }
+        _ => {
+            let (lhs_int, rhs_int) = (to_big_int(&lhs), to_big_int(&rhs));
+            let result = match msg {
+                "add" => lhs_int + rhs_int,
+                "subtract" => lhs_int - rhs_int,
+                "multiply" => lhs_int * rhs_int,
+                _ if !(&lhs_int % &rhs_int).is_zero() => {
+                    return Ok(Value::Float(to_f64(&lhs) / to_f64(&rhs)))
+                }
+                _ => lhs_int / rhs_int,
+            };
+            Value::BigInt(Rc::new(result))
+        }
+    };
+    Ok(value)
+}
+
+/// `[BigInt from: value]`, where the value is a number, a big int, or a string of digits. Strings
+/// that aren't integers give `nil`, like `Parse`.
+pub(super) fn big_int<'a>(value: Value<'a>, call_site: Span) -> Result<'a, Value<'a>> {
+    let value = match value {
+        Value::Number(n) => Value::BigInt(Rc::new(BigInt::from(n))),
+        Value::BigInt(n) => Value::BigInt(n),
+        Value::String(string) => string
+            .parse()
+            .map(|n| Value::BigInt(Rc::new(n)))
+            .unwrap_or(Value::Nil),
+        other => {
+            return Err(Error::UnexpectedType {
+                expected: "Number or String",
+                given: other.class_name(),
+                span: call_site,
+            })
+        }
+    };
+    Ok(value)
+}
+
+fn to_f64(value: &Value<'_>) -> f64 {
+    match value {
+        Value::Number(n) => f64::from(*n),
+        Value::BigInt(n) => n.to_f64().unwrap_or(f64::NAN),
+        Value::Float(f) => *f,
+        _ => unreachable!("Internal error: expected a number"),
+    }
+}
+
+fn to_big_int(value: &Value<'_>) -> BigInt {
+    match value {
+        Value::Number(n) => BigInt::from(*n),
+        Value::BigInt(n) => BigInt::clone(n),
+        _ => unreachable!("Internal error: expected an integer"),
+    }
+}
+
+/// Round a float to a number with `f`, keeping it a float if it's too large for a number.
+fn round<'a>(value: Value<'a>, f: fn(f64) -> f64) -> Value<'a> {
+    match value {
+        Value::Float(float) => {
+            let rounded = f(float);
+            if rounded >= f64::from(i32::MIN) && rounded <= f64::from(i32::MAX) {
+                Value::Number(rounded as i32)
+            } else {
+                Value::Float(rounded)
+            }
+        }
+        number => number,
+    }
+}
@@ -0,0 +1,115 @@
+//! Blocks returned by `[block memoized]`, which remember what they returned for each argument.
+
+use super::{Closure, Value};
+use num_bigint::BigInt;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+};
+
+/// How many results `[block memoized]` remembers.
+pub(super) const DEFAULT_LIMIT: usize = 1000;
+
+#[derive(Debug)]
+pub struct Memoized<'a> {
+    pub(super) closure: Rc<Closure<'a>>,
+    limit: usize,
+    cache: RefCell<Cache<'a>>,
+}
+
+#[derive(Debug, Default)]
+struct Cache<'a> {
+    /// The arguments are kept alive alongside the result so keys using the address of an object
+    /// can't be reused by another object.
+    results: HashMap<Vec<Key<'a>>, (Vec<Value<'a>>, Value<'a>)>,
+    /// Keys in the order they were added. The oldest is forgotten when the cache is full.
+    order: VecDeque<Vec<Key<'a>>>,
+}
+
+impl<'a> Memoized<'a> {
+    pub(super) fn new(closure: Rc<Closure<'a>>, limit: usize) -> Self {
+        Self {
+            closure,
+            limit,
+            cache: RefCell::default(),
+        }
+    }
+
+    pub(super) fn get(&self, args: &[Value<'a>]) -> Option<Value<'a>> {
+        let key = args.iter().map(Key::new).collect::<Vec<_>>();
+        self.cache
+            .borrow()
+            .results
+            .get(&key)
+            .map(|(_, value)| value.to_owned())
+    }
+
+    pub(super) fn insert(&self, args: Vec<Value<'a>>, value: Value<'a>) {
+        if self.limit == 0 {
+            return;
+        }
+
+        let key = args.iter().map(Key::new).collect::<Vec<_>>();
+        let mut cache = self.cache.borrow_mut();
+        if cache.results.len() >= self.limit {
+            if let Some(oldest) = cache.order.pop_front() {
+                cache.results.remove(&oldest);
+            }
+        }
+        if cache.results.insert(key.clone(), (args, value)).is_none() {
+            cache.order.push_back(key);
+        }
+    }
+}
+
+/// What arguments are compared by. Immutable values are compared by what they contain and
+/// everything else by identity.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key<'a> {
+    Number(i32),
+    BigInt(Rc<BigInt>),
+    Float(u64),
+    String(Rc<str>),
+    True,
+    False,
+    Nil,
+    Selector(&'a str),
+    Range(i32, i32),
+    Bytes(Vec<u8>),
+    List(Vec<Key<'a>>),
+    Dict(Vec<(Rc<str>, Key<'a>)>),
+    Identity(usize),
+}
+
+impl<'a> Key<'a> {
+    fn new(value: &Value<'a>) -> Self {
+        match value {
+            Value::Number(n) => Key::Number(*n),
+            Value::BigInt(n) => Key::BigInt(Rc::clone(n)),
+            Value::Float(f) => Key::Float(f.to_bits()),
+            Value::String(string) => Key::String(Rc::clone(string)),
+            Value::True => Key::True,
+            Value::False => Key::False,
+            Value::Nil => Key::Nil,
+            Value::Selector(name) => Key::Selector(name),
+            Value::Range(from, to) => Key::Range(*from, *to),
+            Value::List(items) => Key::List(items.iter().map(Key::new).collect()),
+            Value::Dict(entries) => Key::Dict(
+                entries
+                    .iter()
+                    .map(|(key, value)| (Rc::clone(key), Key::new(value)))
+                    .collect(),
+            ),
+            Value::Instance(instance) => Key::Identity(Rc::as_ptr(instance) as usize),
+            Value::Block(closure) => Key::Identity(Rc::as_ptr(closure) as usize),
+            Value::Object(object) => Key::Identity(Rc::as_ptr(object) as *const () as usize),
+            Value::Memoized(memoized) => Key::Identity(Rc::as_ptr(memoized) as usize),
+            Value::Class(class) => Key::Identity(Rc::as_ptr(class) as usize),
+            Value::Regex(regex) => Key::Identity(Rc::as_ptr(regex) as usize),
+            Value::Bytes(bytes) => Key::Bytes(bytes.to_vec()),
+            Value::Exception(exception) => Key::Identity(Rc::as_ptr(exception) as usize),
+            Value::StringBuilder(builder) => Key::Identity(Rc::as_ptr(builder) as usize),
+        }
+    }
+}
@@ -1,161 +1,1132 @@
-use crate::prep::{self, Class, Field};
+mod arena;
+mod bytes;
+mod cancellation;
+mod crypto;
+mod csv;
+mod encode;
+mod float;
+mod frame;
+mod gc;
+mod json;
+mod math;
+mod memo;
+mod object;
+mod parsing;
+mod random;
+mod report;
+mod syntax;
+mod table;
+mod tail_call;
+mod term;
+
+use crate::prep::{self, Class, Constructor, Method};
 use crate::{
-    ast::{visit_ast, Ast, Visitor, *},
+    ast::{visit_ast, visit_stmt, Ast, Visitor, *},
     error::{Error, Result},
+    lex::lex,
+    parse::parse_source,
     Span,
 };
 use std::{
-    collections::{hash_map::Keys, HashMap},
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::HashMap,
+    convert::TryFrom,
+    fmt,
+    io::{self, BufRead, IsTerminal, Write},
     rc::Rc,
+    time::Duration,
 };
 
+pub use arena::EvalArena;
+pub use cancellation::CancellationToken;
+use frame::Frame;
+use memo::Memoized;
+use num_bigint::BigInt;
+pub use object::OopsObject;
+use random::Rng;
+pub use report::Report;
+use tail_call::{Tail, TailCall};
+
 pub type VTable<'a, T> = HashMap<&'a str, T>;
 
 pub type ClassVTable<'a> = VTable<'a, Rc<Class<'a>>>;
 
-pub fn interpret<'a>(interpreter: &'a mut Interpreter<'a>, ast: &'a Ast<'a>) -> Result<'a, ()> {
+#[cfg_attr(feature = "observability", tracing::instrument(skip_all))]
+pub fn interpret<'a>(interpreter: &mut Interpreter<'a>, ast: &'a Ast<'a>) -> Result<'a, ()> {
     visit_ast(interpreter, ast)?;
-    dbg!(&interpreter.locals);
     Ok(())
 }
 
+/// Run a single statement. Locals it defines are visible to statements run afterwards.
+pub fn interpret_stmt<'a>(interpreter: &mut Interpreter<'a>, stmt: &'a Stmt<'a>) -> Result<'a, ()> {
+    visit_stmt(interpreter, stmt)
+}
+
+/// Things programs may only do when the user has opted in.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Capabilities {
+    /// Evaluate source with `[Runtime eval: source]`.
+    pub eval: bool,
+    /// Read and write files with `File`.
+    pub files: bool,
+}
+
+impl Capabilities {
+    /// What this build of `oops` can do with these capabilities, for `[Runtime features]` and
+    /// `oops --version --features`.
+    pub fn features(self) -> Vec<&'static str> {
+        let enabled = [
+            ("eval", self.eval),
+            ("files", self.files),
+            ("observability", cfg!(feature = "observability")),
+        ];
+        enabled
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
 pub struct Interpreter<'a> {
     classes: Rc<ClassVTable<'a>>,
-    locals: VTable<'a, Value<'a>>,
+    locals: Frame<'a>,
     self_: Option<Value<'a>>,
     return_value: Option<Value<'a>>,
+    stdout: Rc<RefCell<dyn Write>>,
+    stdin: Rc<RefCell<dyn BufRead>>,
+    capabilities: Capabilities,
+    /// The number of statements, expressions, and calls evaluated so far, shared by every copy of
+    /// the interpreter.
+    spent: Rc<Cell<usize>>,
+    budget: Option<usize>,
+    /// The most items or bytes a list, string, or byte array built by a built-in message can have.
+    max_length: Option<usize>,
+    /// How long a host object may take to respond to a message.
+    host_time_limit: Option<Duration>,
+    /// How many method and block calls are being run, including this one.
+    depth: usize,
+    max_depth: usize,
+    /// The source of `Random`, shared by every copy of the interpreter.
+    rng: Rc<RefCell<Rng>>,
+    cancellation: Option<CancellationToken>,
+    /// The class and name of the method being run, if any.
+    method: Option<(&'a str, &'a str)>,
+    /// What `System args` returns.
+    args: Rc<[String]>,
+    /// The environment variables `System env:` can see.
+    env: Rc<HashMap<String, String>>,
+    /// Variables bound with `[Dyn let: #name be: value in: block]`, innermost last. They're
+    /// visible to everything called while the block runs, so they're shared by every copy of the
+    /// interpreter.
+    dynamic: Rc<RefCell<Vec<(&'a str, Value<'a>)>>>,
+    report: Option<Rc<RefCell<Report<'a>>>>,
+    /// Whether stdout is a terminal, which decides if `Term` writes escape codes.
+    terminal: bool,
+    /// Constants defined with `let NAME = value;` and where they were defined, shared by every
+    /// copy of the interpreter.
+    constants: Rc<RefCell<VTable<'a, (Value<'a>, Span)>>>,
+    /// How many characters of a value error messages show before cutting it short.
+    render_limit: usize,
+    /// The module the code being run was written in, which class names are resolved in.
+    module: Option<&'a str>,
+    /// Every instance created, for collecting those only kept alive by cycles. Shared by every
+    /// copy of the interpreter.
+    heap: Rc<RefCell<gc::Heap<'a>>>,
+    /// Whether `return` may leave a call in tail position for `run_call` to make, which it only
+    /// does while running a method or block.
+    tail_calls: bool,
+    tail_call: Option<TailCall<'a>>,
+    /// The value of each constant list evaluated so far, by the address of its node. Shared by
+    /// every copy of the interpreter.
+    constant_lists: Rc<RefCell<HashMap<usize, Value<'a>>>>,
+    /// Where evaluated source is kept. Evaluating source isn't possible without one.
+    eval_arena: Option<&'a EvalArena<'a>>,
 }
 
+/// The `render_limit` used unless `with_render_limit` is called. Enough for a short list or a
+/// line of text.
+const DEFAULT_RENDER_LIMIT: usize = 200;
+
+/// The `max_depth` used unless `with_max_depth` is called. Recursion deeper than this is more
+/// likely to be a missing base case than on purpose.
+const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+/// Calls recurse on the Rust stack, so once less than `RED_ZONE` bytes of it are left a call
+/// continues on a new stack of `STACK_SIZE` bytes rather than overflowing it.
+const RED_ZONE: usize = 256 * 1024;
+const STACK_SIZE: usize = 4 * 1024 * 1024;
+
 impl<'a> Interpreter<'a> {
-    pub fn new(classes: prep::Classes<'a>) -> Self {
+    /// Create an interpreter where `Console` writes to `stdout` and reads from `stdin`.
+    pub fn new(
+        classes: prep::Classes<'a>,
+        stdout: Rc<RefCell<dyn Write>>,
+        stdin: Rc<RefCell<dyn BufRead>>,
+    ) -> Self {
         Self {
             classes: Rc::new(classes),
-            locals: HashMap::new(),
+            locals: Frame::new(),
             self_: None,
             return_value: None,
+            stdout,
+            stdin,
+            capabilities: Capabilities::default(),
+            spent: Rc::new(Cell::new(0)),
+            budget: None,
+            rng: Rc::new(RefCell::new(Rng::from_time())),
+            cancellation: None,
+            method: None,
+            args: Rc::from(vec![]),
+            env: Rc::new(HashMap::new()),
+            dynamic: Rc::new(RefCell::new(vec![])),
+            max_length: None,
+            host_time_limit: None,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            report: None,
+            terminal: false,
+            constants: Rc::new(RefCell::new(HashMap::new())),
+            render_limit: DEFAULT_RENDER_LIMIT,
+            module: None,
+            heap: Rc::new(RefCell::new(gc::Heap::default())),
+            tail_calls: false,
+            tail_call: None,
+            constant_lists: Rc::new(RefCell::new(HashMap::new())),
+            eval_arena: None,
         }
     }
 
-    fn copy_for_method_call(
-        &self,
-        new_self: Value<'a>,
-        locals: VTable<'a, Value<'a>>,
-    ) -> Interpreter<'a> {
+    /// Make `Console` write to `stdout` from now on.
+    pub fn with_stdout(mut self, stdout: Rc<RefCell<dyn Write>>) -> Self {
+        self.stdout = stdout;
+        self
+    }
+
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Keep source evaluated by the program in `arena`, which has to be given for the `eval`
+    /// capability to be usable.
+    pub fn with_eval_arena(mut self, arena: &'a EvalArena<'a>) -> Self {
+        self.eval_arena = Some(arena);
+        self
+    }
+
+    /// Stop the program with an error once it has evaluated `budget` statements, expressions, and
+    /// method and block calls, so programs that loop forever can't hang the host. What was
+    /// evaluated before the budget is set doesn't count.
+    pub fn with_budget(mut self, budget: usize) -> Self {
+        self.spent.set(0);
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Stop the program with an error instead of building a list, string, or byte array longer
+    /// than `max_length`. Built-in messages like `toList` on ranges can otherwise allocate any
+    /// amount of memory in a single step.
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    /// Raise an error when a host object takes longer than `limit` to respond to a message. Calls
+    /// into the host can't be interrupted, so this only reports overruns once the call returns. A
+    /// call that never returns still hangs the interpreter.
+    pub fn with_host_time_limit(mut self, limit: Duration) -> Self {
+        self.host_time_limit = Some(limit);
+        self
+    }
+
+    /// Stop the program with an error once `max_depth` method and block calls are being run at
+    /// once.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    fn copy_for_call(&self, new_self: Option<Value<'a>>, locals: Frame<'a>) -> Interpreter<'a> {
+        let count = locals.len();
+        self.record(|report| report.locals(count));
+
         Interpreter {
             classes: Rc::clone(&self.classes),
             locals,
-            self_: Some(new_self),
+            self_: new_self,
             return_value: None,
+            stdout: Rc::clone(&self.stdout),
+            stdin: Rc::clone(&self.stdin),
+            capabilities: self.capabilities,
+            spent: Rc::clone(&self.spent),
+            budget: self.budget,
+            rng: Rc::clone(&self.rng),
+            cancellation: self.cancellation.clone(),
+            method: self.method,
+            args: Rc::clone(&self.args),
+            env: Rc::clone(&self.env),
+            dynamic: Rc::clone(&self.dynamic),
+            max_length: self.max_length,
+            host_time_limit: self.host_time_limit,
+            depth: self.depth + 1,
+            max_depth: self.max_depth,
+            report: self.report.clone(),
+            terminal: self.terminal,
+            constants: Rc::clone(&self.constants),
+            render_limit: self.render_limit,
+            module: self.module,
+            heap: Rc::clone(&self.heap),
+            tail_calls: false,
+            tail_call: None,
+            constant_lists: Rc::clone(&self.constant_lists),
+            eval_arena: self.eval_arena,
+        }
+    }
+
+    /// Seed `Random` so programs using it produce the same output every time.
+    pub fn with_seed(self, seed: u64) -> Self {
+        *self.rng.borrow_mut() = Rng::new(seed);
+        self
+    }
+
+    /// The command line arguments returned by `System args`.
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = Rc::from(args);
+        self
+    }
+
+    /// The environment variables `System env:` can look up. Programs see none by default.
+    pub fn with_env(mut self, env: HashMap<String, String>) -> Self {
+        self.env = Rc::new(env);
+        self
+    }
+
+    /// Let `Term` write escape codes. Output is assumed not to be a terminal by default.
+    pub fn with_terminal(mut self, terminal: bool) -> Self {
+        self.terminal = terminal;
+        self
+    }
+
+    /// Cut values shown in error messages, like the values compared by a failed assertion, short
+    /// after `limit` characters.
+    pub fn with_render_limit(mut self, limit: usize) -> Self {
+        self.render_limit = limit;
+        self
+    }
+
+    /// `rendered`, the way `value` is shown, cut short with a note of the value's size if it's
+    /// longer than the render limit.
+    fn bounded(&self, value: &Value<'a>, rendered: String) -> String {
+        let length = rendered.chars().count();
+        if length <= self.render_limit {
+            return rendered;
+        }
+
+        let size = match value {
+            Value::List(items) => format!("{} items", items.len()),
+            Value::Dict(entries) => format!("{} entries", entries.len()),
+            Value::String(string) => format!("{} characters", string.chars().count()),
+            _ => format!("{} characters", length),
+        };
+        let shown = rendered.chars().take(self.render_limit).collect::<String>();
+        format!("{}... ({})", shown, size)
+    }
+
+    /// Stop the program with an error once `token` is cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    fn check_cancelled(&self, span: Span) -> Result<'a, ()> {
+        match &self.cancellation {
+            Some(token) if token.is_cancelled() => Err(Error::Cancelled {
+                method: self.method,
+                span,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Collect statistics about the run in `report`.
+    pub fn with_report(mut self, report: Rc<RefCell<Report<'a>>>) -> Self {
+        self.report = Some(report);
+        self
+    }
+
+    /// The value of `list`, which is only evaluated the first time. Lists are copied before
+    /// they're changed when anything else refers to them, so every evaluation can share it.
+    fn constant_list(&self, list: &'a List<'a>) -> Result<'a, Value<'a>> {
+        let address = list as *const List as usize;
+        if let Some(value) = self.constant_lists.borrow().get(&address) {
+            return Ok(value.to_owned());
+        }
+
+        let value = list.eval(self)?;
+        self.constant_lists
+            .borrow_mut()
+            .insert(address, value.to_owned());
+        Ok(value)
+    }
+
+    /// Whether `send` changes the list in the local in `slot`, as in `let xs = [xs push: 1];`.
+    fn changes_local_list(&self, slot: Slot, send: &MessageSend<'a>) -> bool {
+        let msg = send.msg.name;
+        match (&send.receiver, self.locals.get(slot)) {
+            (Expr::Local(local), Some(list @ Value::List(_))) if local.1 == slot => {
+                changes_list(msg, send.args.iter().map(|arg| arg.ident.name))
+                    && self
+                        .built_in_type_class(list)
+                        .is_none_or(|class| class.find_method(msg).is_none())
+            }
+            _ => false,
+        }
+    }
+
+    /// Send a message that changes the list in the local `let_local` defines again. The list is
+    /// taken out of the local while it's changed, so it's changed in place unless something else
+    /// refers to it.
+    fn change_local_list(
+        &mut self,
+        let_local: &'a LetLocal<'a>,
+        node: &'a MessageSend<'a>,
+    ) -> Result<'a, Value<'a>> {
+        let slot = let_local.slot;
+        let args = eval_argument_values(self, &node.args)?;
+        // Evaluated source in the arguments can change the local
+        if !self.changes_local_list(slot, node) {
+            let receiver = node.receiver.eval(self)?;
+            return send(self, receiver, node.msg.name, args, node.span);
+        }
+        let list = self
+            .locals
+            .get(slot)
+            .expect("Internal error: changed local is undefined");
+        count_send(self, list, node.msg.name, node.span)?;
+
+        let mut items = match self.locals.take(slot) {
+            Some(Value::List(items)) => items,
+            _ => panic!(
+                "Internal error: local `{}` isn't a list",
+                let_local.ident.name
+            ),
+        };
+        let result = change_list(self, &mut items, node.msg.name, args, node.span);
+        match result {
+            Ok(()) => Ok(Value::List(items)),
+            Err(err) => {
+                self.locals
+                    .insert(slot, let_local.ident.name, Value::List(items));
+                Err(err)
+            }
+        }
+    }
+
+    /// Put a new instance on the heap.
+    fn allocate(&self, instance: Instance<'a>) -> Rc<Instance<'a>> {
+        self.record(Report::allocation);
+        let instance = Rc::new(instance);
+        self.heap.borrow_mut().register(&instance);
+        instance
+    }
+
+    fn record(&self, f: impl FnOnce(&mut Report<'a>)) {
+        if let Some(report) = &self.report {
+            f(&mut report.borrow_mut());
+        }
+    }
+
+    fn spend(&self, span: Span) -> Result<'a, ()> {
+        let spent = self.spent.get() + 1;
+        self.spent.set(spent);
+
+        match self.budget {
+            Some(limit) if spent > limit => Err(Error::BudgetExceeded { limit, span }),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_length(&self, length: usize, call_site: Span) -> Result<'a, ()> {
+        match self.max_length {
+            Some(limit) if length > limit => Err(Error::LengthLimitExceeded {
+                limit,
+                span: call_site,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Run the statements of a method or block called at `call_site` with this interpreter.
+    fn run_call(&mut self, body: &'a Ast<'a>, call_site: Span) -> Result<'a, Value<'a>> {
+        if self.depth > self.max_depth {
+            return Err(Error::RecursionLimitExceeded {
+                limit: self.max_depth,
+                method: self.method,
+                span: call_site,
+            });
+        }
+
+        self.tail_calls = true;
+        let mut body = body;
+        loop {
+            self.spend(call_site)?;
+            stacker::maybe_grow(RED_ZONE, STACK_SIZE, || visit_ast(self, body))?;
+            body = match self.tail_call.take() {
+                Some(call) => self.enter_tail_call(call)?,
+                None => return Ok(self.return_value.take().unwrap_or(Value::Nil)),
+            };
+        }
+    }
+
+    /// Run `call` in place of the call that just finished, which returns what it returns, and
+    /// return the statements to run.
+    fn enter_tail_call(&mut self, call: TailCall<'a>) -> Result<'a, &'a Ast<'a>> {
+        self.return_value = None;
+        match call {
+            TailCall::Method {
+                class,
+                self_,
+                method,
+                msg,
+                args,
+                call_site,
+            } => {
+                self.record(|report| report.method_call(class.name.name, msg));
+                self.locals = method_locals(&method, args, call_site)?;
+                self.self_ = Some(self_);
+                self.method = Some((class.name.name, msg));
+                self.module = method.module;
+                Ok(method.body)
+            }
+            TailCall::Block {
+                closure,
+                args,
+                call_site,
+            } => {
+                self.check_cancelled(call_site)?;
+                self.locals = block_locals(&closure, args, call_site)?;
+                self.self_ = closure.self_.as_ref().map(Value::to_owned);
+                self.module = closure.module;
+                Ok(&closure.block.body)
+            }
+        }
+    }
+
+    /// The value of a local defined by the statements run so far.
+    pub fn local(&self, name: &str) -> Option<Value<'a>> {
+        self.locals.lookup(name).map(Value::to_owned)
+    }
+
+    /// The names of the locals defined by the statements run so far, which more statements run
+    /// by this interpreter are resolved against with `prep::resolve_slots`.
+    pub fn local_names(&self) -> &[&'a str] {
+        self.locals.names()
+    }
+
+    /// Send `selector` to `receiver` with keyword arguments, as if the program had sent
+    /// `[receiver selector: value ...]`.
+    ///
+    /// Meant for hosts that want to call methods on objects created by a program without
+    /// generating source. Errors point at an empty span since there's no source to point into.
+    pub fn send(
+        &mut self,
+        receiver: Value<'a>,
+        selector: &'a str,
+        args: &[(&'a str, Value<'a>)],
+    ) -> Result<'a, Value<'a>> {
+        let call_site = Span::new(0, 0);
+        let args = args
+            .iter()
+            .map(|(name, value)| ArgumentValue {
+                name,
+                value: value.to_owned(),
+                span: call_site,
+            })
+            .collect();
+        send(self, receiver, selector, args, call_site)
+    }
+
+    /// The error for `receiver` not understanding `msg`, pointing at the method it was sent from.
+    fn not_understood(&self, receiver: &Value<'a>, msg: &'a str, span: Span) -> Error<'a> {
+        Error::MessageSentToNonInstance {
+            class: receiver.class_name(),
+            value: self.bounded(receiver, receiver.to_string()),
+            selector: msg,
+            method: self.method,
+            span,
+        }
+    }
+
+    /// Like `not_understood`, for messages sent to the built-in class `class`.
+    fn not_understood_by_class(&self, class: &'a str, msg: &'a str, span: Span) -> Error<'a> {
+        Error::MessageSentToNonInstance {
+            class: "Class",
+            value: class.to_string(),
+            selector: msg,
+            method: self.method,
+            span,
         }
     }
 
     fn lookup_class(&self, name: &'a str, call_site: Span) -> Result<'a, Rc<Class<'a>>> {
-        let class = self
-            .classes
-            .get(name)
-            .ok_or_else(|| Error::ClassNotDefined {
+        let class = prep::resolve_class_name(&self.classes, self.module, name)
+            .and_then(|key| self.classes.get(key))
+            .ok_or(Error::ClassNotDefined {
                 class: name,
                 span: call_site,
             })?;
-        Ok(Rc::clone(&class))
+        Ok(Rc::clone(class))
+    }
+
+    /// The class programs can define methods for values of built-in types on, like
+    /// `[Number def: #double do: ...]`.
+    fn built_in_type_class(&self, value: &Value<'a>) -> Option<Rc<Class<'a>>> {
+        match value {
+            Value::Number(_) | Value::String(_) | Value::True | Value::False | Value::List(_) => {
+                self.classes.get(value.class_name()).map(Rc::clone)
+            }
+            _ => None,
+        }
+    }
+
+    fn current_instance(&self, ivar_name: &'a str, span: Span) -> Result<'a, Rc<Instance<'a>>> {
+        match &self.self_ {
+            Some(Value::Instance(instance)) => Ok(Rc::clone(instance)),
+            Some(_) => Err(Error::IVarAccessedOnNonInstanceValue(span)),
+            None => Err(Error::IVarAccessedOutsideMethod {
+                name: ivar_name,
+                span,
+            }),
+        }
     }
 }
 
 impl<'a> Visitor<'a> for Interpreter<'a> {
     type Error = Error<'a>;
 
+    fn visit_stmt(&mut self, node: &'a Stmt<'a>) -> Result<'a, ()> {
+        self.check_cancelled(node.span())?;
+        self.spend(node.span())?;
+
+        self.record(Report::statement);
+        Ok(())
+    }
+
     fn visit_let_local(&mut self, node: &'a LetLocal<'a>) -> Result<'a, ()> {
-        if self.return_value.is_some() {
-            return Ok(());
+        let value = match &node.body {
+            Expr::MessageSend(send) if self.changes_local_list(node.slot, send) => {
+                self.change_local_list(node, send)?
+            }
+            body => body.eval(self)?,
+        };
+        self.locals.insert(node.slot, node.ident.name, value);
+        let count = self.locals.len();
+        self.record(|report| report.locals(count));
+        Ok(())
+    }
+
+    fn visit_let_constant(&mut self, node: &'a LetConstant<'a>) -> Result<'a, ()> {
+        let name = node.name.0.name;
+        let first_span = match (self.constants.borrow().get(name), self.classes.get(name)) {
+            (Some((_, span)), _) => Some(*span),
+            (None, Some(class)) => Some(class.span),
+            (None, None) => None,
+        };
+        if let Some(first_span) = first_span {
+            return Err(Error::ConstantAlreadyDefined {
+                name,
+                first_span,
+                second_span: node.span,
+            });
         }
 
-        let name = &node.ident.name;
         let value = node.body.eval(self)?;
-        self.locals.insert(name, value);
+        self.constants.borrow_mut().insert(name, (value, node.span));
         Ok(())
     }
 
     fn visit_let_ivar(&mut self, node: &'a LetIVar<'a>) -> Result<'a, ()> {
-        if self.return_value.is_some() {
-            return Ok(());
+        let name = node.ident.name;
+        let span = node.span;
+
+        let instance = self.current_instance(name, span)?;
+        let value = node.body.eval(self)?;
+
+        if instance.frozen.get() {
+            return Err(Error::FrozenInstanceModified { name, span });
         }
 
-        unimplemented!("TODO: visit_let_ivar")
+        let mut ivars = instance.ivars.borrow_mut();
+        let ivar = ivars
+            .get_mut(name)
+            .ok_or(Error::UndefinedIVar { name, span })?;
+        *ivar = value;
+
+        Ok(())
     }
 
     fn visit_message_send_stmt(&mut self, node: &'a MessageSendStmt<'a>) -> Result<'a, ()> {
-        if self.return_value.is_some() {
-            return Ok(());
-        }
         node.expr.eval(self)?;
         Ok(())
     }
 
+    fn visit_case_of_stmt(&mut self, node: &'a CaseOfStmt<'a>) -> Result<'a, ()> {
+        node.expr.eval(self)?;
+        Ok(())
+    }
+
+    /// Definitions at the top level are added before the program runs, but those inside blocks and
+    /// methods are added when they're run.
+    fn visit_define_method(&mut self, node: &'a DefineMethod<'a>) -> Result<'a, ()> {
+        let class = self.lookup_class(node.class_name.0.name, node.span)?;
+        class.define_method(Method::new(node, self.module))?;
+        prep::flatten_method_tables(&self.classes);
+        Ok(())
+    }
+
+    /// Classes and methods in modules are added before the program runs, like those outside, so
+    /// this only runs the other statements.
+    fn visit_module(&mut self, node: &'a Module<'a>) -> Result<'a, ()> {
+        let outer = self.module;
+        self.module = Some(&node.qualified_name);
+        let result = node.body.iter().try_for_each(|stmt| visit_stmt(self, stmt));
+        self.module = outer;
+        result
+    }
+
     fn visit_return(&mut self, node: &'a Return<'a>) -> Result<'a, ()> {
-        let value = node.expr.eval(self)?;
+        let value = if self.tail_calls {
+            match tail_call::eval(&node.expr, self)? {
+                Tail::Value(value) => value,
+                Tail::Call(call) => {
+                    self.tail_call = Some(call);
+                    self.return_value = Some(Value::Nil);
+                    return Ok(());
+                }
+            }
+        } else {
+            node.expr.eval(self)?
+        };
+        self.tail_call = None;
         self.return_value = Some(value);
         Ok(())
     }
 }
 
+/// A value in a running program.
 #[derive(Debug)]
-enum Value<'a> {
+pub enum Value<'a> {
     Number(i32),
+    /// An integer without a size limit, created with `[BigInt from: value]`.
+    BigInt(Rc<BigInt>),
+    /// A 64 bit float. There is no literal syntax for floats, `[Parse float: "2.5"]` makes one.
+    Float(f64),
     True,
     False,
     Nil,
+    String(Rc<str>),
     List(Rc<Vec<Value<'a>>>),
+    /// The numbers from the first to the second, including both.
+    Range(i32, i32),
+    /// String keys and their values, in the order they were added. Dicts can't be modified.
+    Dict(Rc<Vec<(Rc<str>, Value<'a>)>>),
+    /// A pattern created with `[Regex compile: pattern]`.
+    Regex(Rc<regex::Regex>),
+    /// An error passed to the handler of `[Exception try: block rescue: handler]`.
+    Exception(Rc<Exception<'a>>),
+    /// Binary data, from `[File readBytes: path]` or `Bytes`. Bytes can't be modified.
+    Bytes(Rc<Vec<u8>>),
+    /// A string created with `[StringBuilder new]` that can be appended to in place.
+    StringBuilder(Rc<RefCell<String>>),
     Instance(Rc<Instance<'a>>),
+    Selector(&'a str),
+    Block(Rc<Closure<'a>>),
+    /// A Rust value handed to the program by the host.
+    Object(Rc<dyn OopsObject>),
+    /// A block returned by `[block memoized]`.
+    Memoized(Rc<Memoized<'a>>),
+    Class(Rc<Class<'a>>),
 }
 
 impl<'a> Value<'a> {
     fn to_owned(&self) -> Self {
         match self {
             Value::Number(n) => Value::Number(*n),
+            Value::Float(f) => Value::Float(*f),
+            Value::BigInt(n) => Value::BigInt(Rc::clone(n)),
+            Value::String(string) => Value::String(Rc::clone(string)),
             Value::List(values) => Value::List(Rc::clone(values)),
+            Value::Range(from, to) => Value::Range(*from, *to),
+            Value::Dict(entries) => Value::Dict(Rc::clone(entries)),
+            Value::Regex(regex) => Value::Regex(Rc::clone(regex)),
+            Value::Exception(exception) => Value::Exception(Rc::clone(exception)),
+            Value::Bytes(bytes) => Value::Bytes(Rc::clone(bytes)),
+            Value::StringBuilder(builder) => Value::StringBuilder(Rc::clone(builder)),
             Value::True => Value::True,
             Value::False => Value::False,
             Value::Nil => Value::Nil,
             Value::Instance(instance) => Value::Instance(Rc::clone(instance)),
+            Value::Selector(name) => Value::Selector(name),
+            Value::Block(closure) => Value::Block(Rc::clone(closure)),
+            Value::Object(object) => Value::Object(Rc::clone(object)),
+            Value::Memoized(memoized) => Value::Memoized(Rc::clone(memoized)),
+            Value::Class(class) => Value::Class(Rc::clone(class)),
+        }
+    }
+
+    /// Instances, strings, lists, and blocks are identical if they're the same object. Everything else is
+    /// identical if it's the same value.
+    fn is_identical_to(&self, other: &Value<'a>) -> bool {
+        match (self, other) {
+            (Value::Instance(lhs), Value::Instance(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::String(lhs), Value::String(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::List(lhs), Value::List(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::Dict(lhs), Value::Dict(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::Regex(lhs), Value::Regex(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::Exception(lhs), Value::Exception(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::Bytes(lhs), Value::Bytes(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::StringBuilder(lhs), Value::StringBuilder(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::Block(lhs), Value::Block(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::Object(lhs), Value::Object(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::Memoized(lhs), Value::Memoized(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::Class(lhs), Value::Class(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::Number(lhs), Value::Number(rhs)) => lhs == rhs,
+            (Value::BigInt(lhs), Value::BigInt(rhs)) => lhs == rhs,
+            (Value::Float(lhs), Value::Float(rhs)) => lhs == rhs,
+            (Value::Range(lhs_from, lhs_to), Value::Range(rhs_from, rhs_to)) => {
+                lhs_from == rhs_from && lhs_to == rhs_to
+            }
+            (Value::Selector(lhs), Value::Selector(rhs)) => lhs == rhs,
+            (Value::True, Value::True) => true,
+            (Value::False, Value::False) => true,
+            (Value::Nil, Value::Nil) => true,
+            _ => false,
+        }
+    }
+
+    /// The name of the class of the value, as shown to users.
+    fn class_name(&self) -> &'a str {
+        match self {
+            Value::Number(_) => "Number",
+            Value::BigInt(_) => "BigInt",
+            Value::Float(_) => "Float",
+            Value::String(_) => "String",
+            Value::True | Value::False => "Boolean",
+            Value::Nil => "Nil",
+            Value::List(_) => "List",
+            Value::Range(_, _) => "Range",
+            Value::Dict(_) => "Dict",
+            Value::Regex(_) => "Regex",
+            Value::Exception(exception) => exception.kind(),
+            Value::Bytes(_) => "Bytes",
+            Value::StringBuilder(_) => "StringBuilder",
+            Value::Instance(instance) => instance.class.name.name,
+            Value::Object(object) => object::class_name(&**object),
+            Value::Selector(_) => "Selector",
+            Value::Block(_) | Value::Memoized(_) => "Block",
+            Value::Class(_) => "Class",
+        }
+    }
+
+    fn is_truthy(&self) -> bool {
+        !matches!(self, Value::False | Value::Nil)
+    }
+}
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::BigInt(n) => write!(f, "{}", n),
+            Value::Float(float) => write!(f, "{}", float::format(*float)),
+            Value::String(string) => write!(f, "{}", string),
+            Value::True => write!(f, "true"),
+            Value::False => write!(f, "false"),
+            Value::Nil => write!(f, "nil"),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", Nested(item))?;
+                }
+                write!(f, "]")
+            }
+            Value::Range(from, to) => write!(f, "[{} to: {}]", from, to),
+            Value::Dict(entries) => {
+                write!(f, "{{")?;
+                for (idx, (key, value)) in entries.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}: {}", key, Nested(value))?;
+                }
+                write!(f, "}}")
+            }
+            Value::Regex(regex) => write!(f, "<Regex {:?}>", regex.as_str()),
+            Value::Object(object) => write!(f, "{}", object::inspect(&**object)),
+            Value::Exception(exception) => write!(f, "<Exception {:?}>", exception.message),
+            Value::Bytes(bytes) => write!(f, "<Bytes {}>", bytes::to_hex(bytes)),
+            Value::StringBuilder(builder) => write!(f, "<StringBuilder {:?}>", builder.borrow()),
+            Value::Instance(instance) => {
+                write!(f, "<{}", instance.class.name.name)?;
+                for (name, value) in instance.sorted_ivars() {
+                    write!(f, " {}: {}", name, Nested(&value))?;
+                }
+                write!(f, ">")
+            }
+            Value::Selector(name) => write!(f, "#{}", name),
+            Value::Block(closure) => write!(f, "<block {}>", Parameters(closure)),
+            Value::Memoized(memoized) => {
+                write!(f, "<memoized block {}>", Parameters(&memoized.closure))
+            }
+            Value::Class(class) => write!(f, "{}", class.name.name),
+        }
+    }
+}
+
+/// Renders the parameters of a block that haven't been bound yet, like `|a: b:|`.
+struct Parameters<'c, 'a>(&'c Closure<'a>);
+
+impl fmt::Display for Parameters<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "|")?;
+        for (idx, param) in self.0.parameters().enumerate() {
+            if idx > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}:", param)?;
+        }
+        write!(f, "|")
+    }
+}
+
+/// Renders a value nested inside a list or an instance. Strings are quoted so `["a b"]` doesn't
+/// look like a list of two elements.
+struct Nested<'v, 'a>(&'v Value<'a>);
+
+impl fmt::Display for Nested<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Value::String(string) => write!(f, "{:?}", string),
+            other => write!(f, "{}", other),
+        }
+    }
+}
+
+/// Render a value for printing.
+///
+/// Works like `Display` except that instances whose class defines `describe` are rendered as
+/// whatever that method returns.
+fn describe<'a>(
+    interpreter: &Interpreter<'a>,
+    value: &Value<'a>,
+    call_site: Span,
+) -> Result<'a, String> {
+    match value {
+        Value::Instance(instance) if instance.class.find_method("describe").is_some() => {
+            let description = call_method(
+                interpreter,
+                Rc::clone(instance),
+                "describe",
+                vec![],
+                call_site,
+            )?;
+            Ok(description.to_string())
+        }
+        Value::Instance(instance) => {
+            let mut acc = format!("<{}", instance.class.name.name);
+            for (name, value) in instance.sorted_ivars() {
+                let value = describe_nested(interpreter, &value, call_site)?;
+                acc.push_str(&format!(" {}: {}", name, value));
+            }
+            acc.push('>');
+            Ok(acc)
+        }
+        Value::List(items) => {
+            let items = items
+                .iter()
+                .map(|item| describe_nested(interpreter, item, call_site))
+                .collect::<Result<'a, Vec<_>>>()?;
+            Ok(format!("[{}]", items.join(", ")))
+        }
+        Value::Dict(entries) => {
+            let entries = entries
+                .iter()
+                .map(|(key, value)| {
+                    let value = describe_nested(interpreter, value, call_site)?;
+                    Ok(format!("{:?}: {}", key, value))
+                })
+                .collect::<Result<'a, Vec<_>>>()?;
+            Ok(format!("{{{}}}", entries.join(", ")))
+        }
+        other => Ok(other.to_string()),
+    }
+}
+
+fn describe_nested<'a>(
+    interpreter: &Interpreter<'a>,
+    value: &Value<'a>,
+    call_site: Span,
+) -> Result<'a, String> {
+    match value {
+        Value::String(_) => Ok(Nested(value).to_string()),
+        other => describe(interpreter, other, call_site),
+    }
+}
+
+impl<'a> From<bool> for Value<'a> {
+    fn from(value: bool) -> Self {
+        if value {
+            Value::True
+        } else {
+            Value::False
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Exception<'a> {
+    message: String,
+    code: &'static str,
+    span: Span,
+    /// The method that was undefined or the argument that was missing, if any.
+    selector: Option<&'a str>,
+    /// The class that didn't define the method, for undefined methods.
+    class: Option<&'a str>,
+}
+
+impl<'a> Exception<'a> {
+    fn from_error(err: &Error<'a>, call_site: Span) -> Self {
+        let message = match err {
+            Error::Raised { message, .. } => message.clone(),
+            other => other.to_string(),
+        };
+        let (selector, class) = match err {
+            Error::UndefinedMethod { class, method, .. } => (Some(*method), Some(*class)),
+            Error::MissingArgument { name, .. } => (Some(*name), None),
+            _ => (None, None),
+        };
+        Self {
+            message,
+            code: err.code(),
+            span: err.span().unwrap_or(call_site),
+            selector,
+            class,
+        }
+    }
+
+    /// The name of the built-in error class the exception is an instance of. It's derived from
+    /// the code so re-raised errors keep their class.
+    fn kind(&self) -> &'static str {
+        match self.code {
+            "E0008" => "MissingArgument",
+            "E0014" => "UndefinedMethod",
+            "E0031" => "DivisionByZero",
+            "E0032" => "Overflow",
+            "E0036" => "IndexOutOfBounds",
+            _ => "Exception",
         }
     }
 }
 
 #[derive(Debug)]
-struct Instance<'a> {
+pub struct Instance<'a> {
     class: Rc<Class<'a>>,
-    ivars: VTable<'a, Value<'a>>,
+    ivars: RefCell<VTable<'a, Value<'a>>>,
+    frozen: Cell<bool>,
+}
+
+impl<'a> Instance<'a> {
+    fn new(class: Rc<Class<'a>>, ivars: VTable<'a, Value<'a>>) -> Self {
+        Self {
+            class,
+            ivars: RefCell::new(ivars),
+            frozen: Cell::new(false),
+        }
+    }
+
+    fn ivar(&self, name: &str) -> Option<Value<'a>> {
+        self.ivars.borrow().get(name).map(|value| value.to_owned())
+    }
+
+    /// A copy of the instance variables that isn't affected by later assignments.
+    fn ivars(&self) -> Vec<(&'a str, Value<'a>)> {
+        self.ivars
+            .borrow()
+            .iter()
+            .map(|(name, value)| (*name, value.to_owned()))
+            .collect()
+    }
+
+    fn sorted_ivars(&self) -> Vec<(&'a str, Value<'a>)> {
+        let mut ivars = self.ivars();
+        ivars.sort_by_key(|(name, _)| *name);
+        ivars
+    }
+}
+
+/// A block together with the `self` and locals that were in scope when it was created.
+#[derive(Debug)]
+pub struct Closure<'a> {
+    block: &'a Block<'a>,
+    self_: Option<Value<'a>>,
+    locals: Frame<'a>,
+    /// How many of the block's parameters have been bound with `withFirst:`. Their values are in
+    /// `locals`.
+    bound: usize,
+    /// The module the block was written in.
+    module: Option<&'a str>,
+}
+
+impl<'a> Closure<'a> {
+    /// The names of the parameters that haven't been bound yet.
+    fn parameters(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.block.parameters[self.bound..]
+            .iter()
+            .map(|param| param.ident.name)
+    }
 }
 
 trait Eval<'a> {
-    fn eval(&self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>>;
+    fn eval(&'a self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>>;
 }
 
 impl<'a> Eval<'a> for Expr<'a> {
-    fn eval(&self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+    fn eval(&'a self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+        interpreter.spend(self.span())?;
         match self {
             Expr::Local(inner) => inner.eval(interpreter),
             Expr::Number(inner) => inner.eval(interpreter),
+            Expr::Str(inner) => inner.eval(interpreter),
+            Expr::Quote(inner) => inner.eval(interpreter),
+            Expr::Unquote(inner) => inner.eval(interpreter),
             Expr::List(inner) => inner.eval(interpreter),
+            Expr::ConstantList(inner) => interpreter.constant_list(inner),
             Expr::True(inner) => inner.eval(interpreter),
             Expr::False(inner) => inner.eval(interpreter),
             Expr::ClassNew(inner) => inner.eval(interpreter),
+            Expr::ClassName(inner) => inner.eval(interpreter),
             Expr::Self_(inner) => inner.eval(interpreter),
             Expr::MessageSend(inner) => inner.eval(interpreter),
             Expr::IVar(inner) => inner.eval(interpreter),
-
-            Expr::Block(_) => unimplemented!("eval Block"),
+            Expr::Selector(inner) => inner.eval(interpreter),
+            Expr::Block(inner) => inner.eval(interpreter),
+            Expr::CaseOf(inner) => inner.eval(interpreter),
         }
     }
 }
 
 impl<'a> Eval<'a> for Local<'a> {
-    fn eval(&self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+    fn eval(&'a self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
         let name = self.0.name;
         let value = interpreter
             .locals
-            .get(name)
-            .ok_or_else(|| Error::UndefinedLocal {
+            .get(self.1)
+            .ok_or(Error::UndefinedLocal {
                 name,
                 span: self.0.span,
             })?;
@@ -164,14 +1135,48 @@ impl<'a> Eval<'a> for Local<'a> {
 }
 
 impl<'a> Eval<'a> for Number {
-    fn eval(&self, _: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+    fn eval(&'a self, _: &Interpreter<'a>) -> Result<'a, Value<'a>> {
         let number = self.number;
         Ok(Value::Number(number))
     }
 }
 
+impl<'a> Eval<'a> for Quote<'a> {
+    fn eval(&'a self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+        syntax::quote(&self.body, &|unquote| {
+            let name = unquote.ident.name;
+            let value = interpreter
+                .locals
+                .get(unquote.slot)
+                .ok_or(Error::UndefinedLocal {
+                    name,
+                    span: unquote.span,
+                })?;
+
+            syntax::splice(value.to_owned()).ok_or_else(|| Error::UnexpectedType {
+                expected: "syntax node",
+                given: value.class_name(),
+                span: unquote.span,
+            })
+        })
+    }
+}
+
+impl<'a> Eval<'a> for Unquote<'a> {
+    fn eval(&'a self, _: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+        Err(Error::UnquoteOutsideQuote(self.span))
+    }
+}
+
+impl<'a> Eval<'a> for Str {
+    fn eval(&'a self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+        interpreter.record(Report::allocation);
+        Ok(Value::String(Rc::from(self.value.as_str())))
+    }
+}
+
 impl<'a> Eval<'a> for List<'a> {
-    fn eval(&self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+    fn eval(&'a self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
         let items = &self.items;
         let values: Result<'a, Vec<Value<'a>>> =
             items
@@ -182,126 +1187,4200 @@ impl<'a> Eval<'a> for List<'a> {
                     Ok(acc)
                 });
         let values = values?;
+        interpreter.record(Report::allocation);
         Ok(Value::List(Rc::new(values)))
     }
 }
 
+impl<'a> Eval<'a> for Selector<'a> {
+    fn eval(&'a self, _: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+        Ok(Value::Selector(self.ident.name))
+    }
+}
+
+impl<'a> Eval<'a> for Block<'a> {
+    fn eval(&'a self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+        let locals = interpreter.locals.copy();
+        let self_ = interpreter.self_.as_ref().map(|value| value.to_owned());
+
+        let closure = Closure {
+            block: self,
+            self_,
+            locals,
+            bound: 0,
+            module: interpreter.module,
+        };
+
+        Ok(Value::Block(Rc::new(closure)))
+    }
+}
+
 impl<'a> Eval<'a> for True {
-    fn eval(&self, _: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+    fn eval(&'a self, _: &Interpreter<'a>) -> Result<'a, Value<'a>> {
         Ok(Value::True)
     }
 }
 
 impl<'a> Eval<'a> for False {
-    fn eval(&self, _: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+    fn eval(&'a self, _: &Interpreter<'a>) -> Result<'a, Value<'a>> {
         Ok(Value::False)
     }
 }
 
 impl<'a> Eval<'a> for Self_ {
-    fn eval(&self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
-        let self_ = interpreter
-            .self_
-            .as_ref()
-            .ok_or_else(|| Error::NoSelf(self.0))?;
+    fn eval(&'a self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+        let self_ = interpreter.self_.as_ref().ok_or(Error::NoSelf(self.0))?;
         Ok(self_.to_owned())
     }
 }
 
+impl<'a> Eval<'a> for ClassName<'a> {
+    fn eval(&'a self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+        if let Some((value, _)) = interpreter.constants.borrow().get(self.0.name) {
+            return Ok(value.to_owned());
+        }
+        let class = interpreter.lookup_class(self.0.name, self.0.span)?;
+        Ok(Value::Class(class))
+    }
+}
+
+/// The value `[Class new]` creates for built-in classes whose values aren't instances.
+fn construct<'a>(interpreter: &Interpreter<'a>, constructor: Constructor) -> Value<'a> {
+    match constructor {
+        Constructor::StringBuilder => Value::StringBuilder(Rc::new(RefCell::new(String::new()))),
+        Constructor::Uuid => {
+            let uuid = crypto::uuid_v4(&mut interpreter.rng.borrow_mut());
+            Value::String(Rc::from(uuid))
+        }
+    }
+}
+
 impl<'a> Eval<'a> for ClassNew<'a> {
-    fn eval(&self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+    fn eval(&'a self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
         let class_name = self.class_name.0.name;
         let call_site = self.class_name.0.span;
         let class = interpreter.lookup_class(class_name, call_site)?;
 
+        if let Some(constructor) = class.constructor {
+            eval_arguments(interpreter, vec![], call_site, &self.args)?;
+            return Ok(construct(interpreter, constructor));
+        }
+
+        let missing = class.missing_implementations();
+        if !missing.is_empty() {
+            return Err(Error::AbstractMethodsNotImplemented {
+                class: class_name,
+                methods: missing,
+                span: call_site,
+            });
+        }
+
         let parameters = class.fields.keys().copied().collect::<Vec<_>>();
         let ivars = eval_arguments(interpreter, parameters, call_site, &self.args)?;
 
-        let instance = Instance { class, ivars };
-
-        Ok(Value::Instance(Rc::new(instance)))
+        let instance = interpreter.allocate(Instance::new(class, ivars));
+        Ok(Value::Instance(instance))
     }
 }
 
+struct ArgumentValue<'a> {
+    name: &'a str,
+    value: Value<'a>,
+    span: Span,
+}
+
 fn eval_arguments<'a>(
     interpreter: &Interpreter<'a>,
     parameters: Vec<&'a str>,
     call_site: Span,
-    args: &[Argument<'a>],
+    args: &'a [Argument<'a>],
 ) -> Result<'a, VTable<'a, Value<'a>>> {
-    let mut arg_values = VTable::with_capacity(args.len());
+    let args = eval_argument_values(interpreter, args)?;
+    bind_arguments(parameters, call_site, args)
+}
+
+fn eval_argument_values<'a>(
+    interpreter: &Interpreter<'a>,
+    args: &'a [Argument<'a>],
+) -> Result<'a, Vec<ArgumentValue<'a>>> {
+    let mut values = Vec::with_capacity(args.len());
     for arg in args {
         let value = arg.expr.eval(interpreter)?;
-        arg_values.insert(&arg.ident.name, (value, arg.span));
+        values.push(ArgumentValue {
+            name: arg.ident.name,
+            value,
+            span: arg.span,
+        });
     }
+    Ok(values)
+}
 
-    let mut ivars = VTable::with_capacity(args.len());
-    for param in parameters {
-        let (value, _) = arg_values
-            .remove(param)
-            .ok_or_else(|| Error::MissingArgument {
-                name: param,
-                span: call_site,
-            })?;
+fn bind_arguments<'a>(
+    parameters: Vec<&'a str>,
+    call_site: Span,
+    args: Vec<ArgumentValue<'a>>,
+) -> Result<'a, VTable<'a, Value<'a>>> {
+    let mut arg_values = VTable::with_capacity(args.len());
+    for arg in args {
+        arg_values.insert(arg.name, (arg.value, arg.span));
+    }
+
+    let mut ivars = VTable::with_capacity(arg_values.len());
+    for param in parameters {
+        let (value, _) = arg_values.remove(param).ok_or(Error::MissingArgument {
+            name: param,
+            span: call_site,
+        })?;
         ivars.insert(param, value);
     }
 
-    for (name, (_value, span)) in arg_values {
-        Err(Error::UnexpectedArgument { name, span })?;
+    if let Some((name, (_value, span))) = arg_values.into_iter().next() {
+        return Err(Error::UnexpectedArgument { name, span });
     }
 
     Ok(ivars)
 }
 
+/// Where `index` is in a list, string, or bytes of `length` items, if it's within them. Negative
+/// indices count from the end, so `-1` is the last item.
+fn position(index: i32, length: usize) -> Option<usize> {
+    let idx = if index < 0 {
+        length.checked_sub(index.unsigned_abs() as usize)?
+    } else {
+        index as usize
+    };
+    Some(idx).filter(|idx| *idx < length)
+}
+
+/// The body of the first arm whose value equals the subject is used, or `otherwise:` if none do.
+/// Bodies that are blocks are called and the others are used as they are. Without a match or an
+/// `otherwise:` the result is `nil`.
+impl<'a> Eval<'a> for CaseOf<'a> {
+    fn eval(&'a self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+        match case_of_body(self, interpreter)? {
+            Some(body) => match body.eval(interpreter)? {
+                block @ (Value::Block(_) | Value::Memoized(_)) => {
+                    call_with_arguments(interpreter, &block, vec![], body.span())
+                }
+                value => Ok(value),
+            },
+            None => Ok(Value::Nil),
+        }
+    }
+}
+
+/// The body of the arm whose value equals the subject, or the `otherwise:` body.
+fn case_of_body<'a>(
+    case_of: &'a CaseOf<'a>,
+    interpreter: &Interpreter<'a>,
+) -> Result<'a, Option<&'a Expr<'a>>> {
+    let subject = case_of.subject.eval(interpreter)?;
+
+    for arm in &case_of.arms {
+        let value = arm.value.eval(interpreter)?;
+        if values_equal(interpreter, &subject, &value, arm.span)? {
+            return Ok(Some(&arm.body));
+        }
+    }
+    Ok(case_of.otherwise.as_ref())
+}
+
 impl<'a> Eval<'a> for MessageSend<'a> {
-    fn eval(&self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+    fn eval(&'a self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
         let receiver = self.receiver.eval(interpreter)?;
-        let receiver = match receiver {
-            Value::Instance(instance) => instance,
-            _ => return Err(Error::MessageSentToNonInstance(self.span)),
-        };
+        let args = eval_argument_values(interpreter, &self.args)?;
+        send(interpreter, receiver, self.msg.name, args, self.span)
+    }
+}
 
-        let method = receiver.class.get_method_named(self.msg.name, self.span)?;
+fn send<'a>(
+    interpreter: &Interpreter<'a>,
+    receiver: Value<'a>,
+    msg: &'a str,
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    count_send(interpreter, &receiver, msg, call_site)?;
+    dispatch(interpreter, receiver, msg, args, call_site)
+}
 
-        let new_self = Value::Instance(Rc::clone(&receiver));
+/// Check for cancellation before a message send, and count it for the report.
+#[cfg_attr(not(feature = "observability"), allow(unused_variables))]
+fn count_send<'a>(
+    interpreter: &Interpreter<'a>,
+    receiver: &Value<'a>,
+    msg: &'a str,
+    call_site: Span,
+) -> Result<'a, ()> {
+    #[cfg(feature = "observability")]
+    tracing::trace!(receiver = receiver.class_name(), msg, %call_site, "send");
 
-        let parameters = method
-            .parameters
-            .iter()
-            .map(|param| param.ident.name)
-            .collect::<Vec<_>>();
-        let new_locals = eval_arguments(interpreter, parameters, self.span, &self.args)?;
+    interpreter.check_cancelled(call_site)?;
+    interpreter.record(|report| match receiver {
+        Value::Class(class) => report.send(class.name.name),
+        other => report.send(other.class_name()),
+    });
+    Ok(())
+}
+
+/// The method defined in OOPS that runs when `msg` is sent to `receiver`, and the class it's run
+/// as. Built-in types find methods on their class, like `[List def: #sum do: ...]`, and instances
+/// on theirs, except `identicalTo`, which only the built-in types can redefine.
+fn resolve_method<'a>(
+    interpreter: &Interpreter<'a>,
+    receiver: &Value<'a>,
+    msg: &'a str,
+) -> Option<(Rc<Class<'a>>, Method<'a>)> {
+    let class = match (interpreter.built_in_type_class(receiver), receiver) {
+        (Some(class), _) => class,
+        (None, Value::Instance(instance)) if msg != "identicalTo" => Rc::clone(&instance.class),
+        _ => return None,
+    };
+    let method = class.find_method(msg)?;
+    Some((class, method))
+}
+
+/// Send a message that has been counted by `count_send`.
+fn dispatch<'a>(
+    interpreter: &Interpreter<'a>,
+    receiver: Value<'a>,
+    msg: &'a str,
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    if let Some((class, method)) = resolve_method(interpreter, &receiver, msg) {
+        return run_method(interpreter, &class, method, receiver, msg, args, call_site);
+    }
+
+    let host_responds = match &receiver {
+        Value::Object(object) if msg != "identicalTo" => {
+            object::responds_to(&**object, msg, call_site)?
+        }
+        _ => false,
+    };
+
+    match (receiver, msg) {
+        (receiver, "identicalTo") => {
+            let other = single_argument(args, "identicalTo", call_site)?;
+            Ok(Value::from(receiver.is_identical_to(&other)))
+        }
+        (Value::Object(object), _) if host_responds => {
+            let args = args.into_iter().map(|arg| (arg.name, arg.value)).collect();
+            object::send(&*object, msg, args, interpreter.host_time_limit, call_site)
+        }
+        (receiver, "equals") => {
+            let other = single_argument(args, "equals", call_site)?;
+            let equal = values_equal(interpreter, &receiver, &other, call_site)?;
+            Ok(Value::from(equal))
+        }
+        (receiver, "notEquals") => {
+            let other = single_argument(args, "notEquals", call_site)?;
+            let equal = values_equal(interpreter, &receiver, &other, call_site)?;
+            Ok(Value::from(!equal))
+        }
+        (receiver, "isNil") | (receiver, "notNil") => {
+            bind_arguments(vec![], call_site, args)?;
+            let is_nil = matches!(receiver, Value::Nil);
+            Ok(Value::from(is_nil == (msg == "isNil")))
+        }
+        (receiver, "ifNil") => {
+            let mut args = bind_arguments(vec!["ifNil", "ifNotNil"], call_site, args)?;
+            let mut block = |name| {
+                args.remove(name)
+                    .expect("Internal error: bound argument missing")
+            };
+            let (if_nil, if_not_nil) = (block("ifNil"), block("ifNotNil"));
+            match receiver {
+                Value::Nil => call_with_arguments(interpreter, &if_nil, vec![], call_site),
+                value => call_with_one_argument(interpreter, &if_not_nil, value, call_site),
+            }
+        }
+        (receiver @ Value::Instance(_), "copy") | (receiver @ Value::List(_), "copy") => {
+            bind_arguments(vec![], call_site, args)?;
+            copy_value(interpreter, &receiver, false, call_site)
+        }
+        (receiver @ Value::Instance(_), "deepCopy") | (receiver @ Value::List(_), "deepCopy") => {
+            bind_arguments(vec![], call_site, args)?;
+            copy_value(interpreter, &receiver, true, call_site)
+        }
+        (Value::Instance(instance), "freeze") => {
+            bind_arguments(vec![], call_site, args)?;
+            instance.frozen.set(true);
+            Ok(Value::Instance(instance))
+        }
+        (Value::Instance(instance), "isFrozen") => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(Value::from(instance.frozen.get()))
+        }
+        (Value::Instance(instance), _) => call_method(interpreter, instance, msg, args, call_site),
+        (Value::Block(closure), "call") => call_block(interpreter, &closure, args, call_site),
+        (Value::Block(closure), "memoized") => {
+            bind_arguments(vec![], call_site, args)?;
+            let memoized = Memoized::new(closure, memo::DEFAULT_LIMIT);
+            Ok(Value::Memoized(Rc::new(memoized)))
+        }
+        (Value::Block(closure), "memoizedUpTo") => {
+            let limit = number_argument(args, "memoizedUpTo", call_site)?;
+            let memoized = Memoized::new(closure, usize::try_from(limit).unwrap_or(0));
+            Ok(Value::Memoized(Rc::new(memoized)))
+        }
+        (Value::Memoized(memoized), "call") => {
+            let mut args =
+                bind_arguments(memoized.closure.parameters().collect(), call_site, args)?;
+            let args = memoized
+                .closure
+                .parameters()
+                .map(|name| {
+                    args.remove(name)
+                        .expect("Internal error: bound argument missing")
+                })
+                .collect();
+            call_memoized(interpreter, &memoized, args, call_site)
+        }
+        (Value::Block(closure), "withFirst") => {
+            let value = single_argument(args, "withFirst", call_site)?;
+            let param = closure.block.parameters[closure.bound..].first().ok_or(
+                Error::WrongNumberOfBlockParameters {
+                    expected: 1,
+                    given: 0,
+                    span: call_site,
+                },
+            )?;
+
+            let mut locals = closure.locals.copy();
+            locals.insert(param.slot, param.ident.name, value);
+
+            let partial = Closure {
+                block: closure.block,
+                self_: closure.self_.as_ref().map(Value::to_owned),
+                locals,
+                bound: closure.bound + 1,
+                module: closure.module,
+            };
+            Ok(Value::Block(Rc::new(partial)))
+        }
+        (Value::Class(class), "print") | (Value::Class(class), "println")
+            if class.name.name == "Console" =>
+        {
+            let value = single_argument(args, msg, call_site)?;
+            let description = describe(interpreter, &value, call_site)?;
+
+            let mut stdout = interpreter.stdout.borrow_mut();
+            if msg == "println" {
+                writeln!(stdout, "{}", description)?;
+            } else {
+                write!(stdout, "{}", description)?;
+            }
+
+            Ok(Value::Nil)
+        }
+        (Value::Class(class), "render") if class.name.name == "Table" => {
+            let mut args = bind_arguments(vec!["render", "headers"], call_site, args)?;
+            let mut list = |name| match args.remove(name) {
+                Some(Value::List(items)) => Ok(items),
+                other => Err(Error::UnexpectedType {
+                    expected: "List",
+                    given: other.map_or("Nil", |value| value.class_name()),
+                    span: call_site,
+                }),
+            };
+            let (rows, headers) = (list("render")?, list("headers")?);
+
+            let headers = headers
+                .iter()
+                .map(|header| describe(interpreter, header, call_site))
+                .collect::<Result<Vec<_>>>()?;
+            let mut cells = Vec::with_capacity(rows.len());
+            for row in rows.iter() {
+                // Dict rows are looked up by header and list rows by position
+                let row = match row {
+                    Value::List(items) => items
+                        .iter()
+                        .map(|item| describe(interpreter, item, call_site))
+                        .collect::<Result<Vec<_>>>()?,
+                    Value::Dict(entries) => headers
+                        .iter()
+                        .map(|header| match dict_get(entries, header) {
+                            Some(value) => describe(interpreter, value, call_site),
+                            None => Ok(String::new()),
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                    other => {
+                        return Err(Error::UnexpectedType {
+                            expected: "List",
+                            given: other.class_name(),
+                            span: call_site,
+                        })
+                    }
+                };
+                cells.push(row);
+            }
+
+            let table = table::render(&headers, &cells);
+            write!(interpreter.stdout.borrow_mut(), "{}", table)?;
+            Ok(Value::Nil)
+        }
+        (Value::Class(class), "flush") if class.name.name == "IO" => {
+            bind_arguments(vec![], call_site, args)?;
+            interpreter.stdout.borrow_mut().flush()?;
+            Ok(Value::Nil)
+        }
+        (Value::Class(class), "prompt") if class.name.name == "IO" => {
+            let prompt = single_argument(args, "prompt", call_site)?;
+            write!(interpreter.stdout.borrow_mut(), "{} ", prompt)?;
+            read_line(interpreter)
+        }
+        (Value::Class(class), "readPassword") if class.name.name == "IO" => {
+            let prompt = single_argument(args, "readPassword", call_site)?;
+            write!(interpreter.stdout.borrow_mut(), "{} ", prompt)?;
+            if !interpreter.terminal || !io::stdin().is_terminal() {
+                return read_line(interpreter);
+            }
+
+            term::set_echo(false)?;
+            let line = read_line(interpreter);
+            term::set_echo(true)?;
+            // The newline typed after the password wasn't echoed either
+            writeln!(interpreter.stdout.borrow_mut())?;
+            line
+        }
+        (Value::Class(class), "readLine") if class.name.name == "Console" => {
+            bind_arguments(vec![], call_site, args)?;
+            read_line(interpreter)
+        }
+        (Value::Class(class), _) if class.name.name == "Math" => {
+            math::send(interpreter, msg, args, call_site)
+        }
+        (Value::Class(class), _) if class.name.name == "Csv" => {
+            self::csv::send(interpreter, msg, args, call_site)
+        }
+        (Value::Class(class), _) if class.name.name == "Term" => {
+            term::send(interpreter, msg, args, call_site)
+        }
+        (Value::Class(class), "new") if class.constructor.is_some() => {
+            bind_arguments(vec![], call_site, args)?;
+            let constructor = class.constructor.expect("Internal error: checked above");
+            Ok(construct(interpreter, constructor))
+        }
+        (Value::Class(class), _) if class.name.name == "Hash" => {
+            crypto::send_hash(interpreter, msg, args, call_site)
+        }
+        (Value::Class(class), _) if class.name.name == "Encode" => {
+            encode::send(interpreter, msg, args, call_site)
+        }
+        (Value::Class(class), _) if class.name.name == "Parse" => {
+            parsing::send(interpreter, msg, args, call_site)
+        }
+        (Value::Class(class), "parse") if class.name.name == "Json" => {
+            let source = string_argument(args, "parse", call_site)?;
+            json::parse(&source).map_err(|message| Error::InvalidSource {
+                message,
+                span: call_site,
+            })
+        }
+        (Value::Class(class), "generate") if class.name.name == "Json" => {
+            let value = single_argument(args, "generate", call_site)?;
+            let json = json::generate(&value).map_err(|given| Error::UnexpectedType {
+                expected: "JSON value",
+                given,
+                span: call_site,
+            })?;
+            Ok(Value::String(Rc::from(json)))
+        }
+        (Value::Class(class), "next") if class.name.name == "Random" => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(Value::Float(interpreter.rng.borrow_mut().next_f64()))
+        }
+        (Value::Class(class), "between") if class.name.name == "Random" => {
+            let mut args = bind_arguments(vec!["between", "and"], call_site, args)?;
+            let mut bound = |name| match args.remove(name) {
+                Some(Value::Number(n)) => Ok(n),
+                other => Err(Error::UnexpectedType {
+                    expected: "Number",
+                    given: other.map_or("Nil", |value| value.class_name()),
+                    span: call_site,
+                }),
+            };
+            let (low, high) = (bound("between")?, bound("and")?);
+            Ok(Value::Number(
+                interpreter.rng.borrow_mut().between(low, high),
+            ))
+        }
+        (Value::Class(class), "seed") if class.name.name == "Random" => {
+            let seed = number_argument(args, "seed", call_site)?;
+            *interpreter.rng.borrow_mut() = Rng::new(seed as u64);
+            Ok(Value::Nil)
+        }
+        (Value::Class(class), "lex") if class.name.name == "Lexer" => {
+            let source = string_argument(args, "lex", call_site)?;
+            let tokens = lex(&source).map_err(|err| invalid_source(err, call_site))?;
+            Ok(syntax::tokens(&tokens, &source))
+        }
+        (Value::Class(class), "parse") if class.name.name == "Parser" => {
+            let source = string_argument(args, "parse", call_site)?;
+            let ast = parse_source(&source).map_err(|err| invalid_source(err, call_site))?;
+            Ok(syntax::ast(&ast))
+        }
+        (Value::Class(class), "version") if class.name.name == "Runtime" => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(Value::String(Rc::from(env!("CARGO_PKG_VERSION"))))
+        }
+        (Value::Class(class), "features") if class.name.name == "Runtime" => {
+            bind_arguments(vec![], call_site, args)?;
+            let features = interpreter.capabilities.features();
+            Ok(Value::List(Rc::new(
+                features.into_iter().map(Value::Selector).collect(),
+            )))
+        }
+        (Value::Class(class), "eval") | (Value::Class(class), "run")
+            if class.name.name == "Runtime" =>
+        {
+            if !interpreter.capabilities.eval {
+                return Err(Error::CapabilityNotGranted {
+                    capability: "eval",
+                    flag: "--allow-eval",
+                    span: call_site,
+                });
+            }
+
+            let in_current_scope = args.iter().any(|arg| arg.name == "scope");
+            let mut args = if in_current_scope {
+                bind_arguments(vec![msg, "scope"], call_site, args)?
+            } else {
+                bind_arguments(vec![msg], call_site, args)?
+            };
+
+            let scope = match args.remove("scope") {
+                None => Scope::Fresh,
+                Some(Value::Selector("fresh")) => Scope::Fresh,
+                Some(Value::Selector("current")) => Scope::Current,
+                Some(other) => {
+                    return Err(Error::UnexpectedType {
+                        expected: "#fresh or #current",
+                        given: other.class_name(),
+                        span: call_site,
+                    })
+                }
+            };
+
+            let source = match (msg, args.remove(msg)) {
+                ("eval", Some(Value::String(source))) => source.to_string(),
+                ("eval", Some(other)) => {
+                    return Err(Error::UnexpectedType {
+                        expected: "String",
+                        given: other.class_name(),
+                        span: call_site,
+                    })
+                }
+                (_, Some(stmts)) => {
+                    syntax::source(&stmts).map_err(|message| Error::InvalidSource {
+                        message,
+                        span: call_site,
+                    })?
+                }
+                (_, None) => unreachable!("Internal error: bound argument missing"),
+            };
+
+            eval_source(interpreter, &source, scope, Outcome::Returned, call_site)
+        }
+        (Value::Class(class), "eval") if class.name.name == "Oops" => {
+            if !interpreter.capabilities.eval {
+                return Err(Error::CapabilityNotGranted {
+                    capability: "eval",
+                    flag: "--allow-eval",
+                    span: call_site,
+                });
+            }
+            let source = string_argument(args, "eval", call_site)?;
+            eval_source(
+                interpreter,
+                &source,
+                Scope::Current,
+                Outcome::LastValue,
+                call_site,
+            )
+        }
+        (lhs, "lessThan") | (lhs, "greaterThan") => {
+            let rhs = single_argument(args, msg, call_site)?;
+            let ordering = compare(&lhs, &rhs, call_site)?;
+            let expected = if msg == "lessThan" {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            };
+            Ok(Value::from(ordering == expected))
+        }
+        (lhs, "compare") => {
+            let rhs = single_argument(args, "compare", call_site)?;
+            let ordering = compare(&lhs, &rhs, call_site)?;
+            Ok(Value::Number(ordering as i32))
+        }
+        (Value::String(string), "length") => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(Value::Number(string.chars().count() as i32))
+        }
+        (Value::String(string), "concat") => {
+            let other = string_argument(args, "concat", call_site)?;
+            interpreter.check_length(string.len() + other.len(), call_site)?;
+            Ok(Value::String(Rc::from(format!("{}{}", string, other))))
+        }
+        (Value::String(string), "split") => {
+            let separator = string_argument(args, "split", call_site)?;
+            let parts = string
+                .split(&*separator)
+                .map(|part| Value::String(Rc::from(part)))
+                .collect();
+            Ok(Value::List(Rc::new(parts)))
+        }
+        (Value::String(string), "contains") => {
+            let other = string_argument(args, "contains", call_site)?;
+            Ok(Value::from(string.contains(&*other)))
+        }
+        (Value::String(string), "toUpper") => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(Value::String(Rc::from(string.to_uppercase())))
+        }
+        (Value::String(string), "toLower") => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(Value::String(Rc::from(string.to_lowercase())))
+        }
+        (Value::String(string), "toNumber") => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(string
+                .trim()
+                .parse()
+                .map(Value::Number)
+                .unwrap_or(Value::Nil))
+        }
+        (lhs @ Value::Number(_), "add" | "subtract" | "multiply" | "divide")
+        | (lhs @ Value::BigInt(_), "add" | "subtract" | "multiply" | "divide")
+        | (lhs @ Value::Float(_), "add" | "subtract" | "multiply" | "divide") => {
+            let rhs = single_argument(args, msg, call_site)?;
+            math::arithmetic(msg, lhs, rhs, call_site)
+        }
+        (Value::Number(from), "to") => {
+            let to = number_argument(args, "to", call_site)?;
+            Ok(Value::Range(from, to))
+        }
+        (Value::Range(from, to), "each") => {
+            let block = single_argument(args, "each", call_site)?;
+
+            for n in from..=to {
+                call_with_one_argument(interpreter, &block, Value::Number(n), call_site)?;
+            }
+            Ok(Value::Range(from, to))
+        }
+        (Value::Range(from, to), "map") => {
+            let block = single_argument(args, "map", call_site)?;
+
+            let values = (from..=to)
+                .map(|n| call_with_one_argument(interpreter, &block, Value::Number(n), call_site))
+                .collect::<Result<'a, Vec<_>>>()?;
+            Ok(Value::List(Rc::new(values)))
+        }
+        (Value::Range(from, to), "contains") => {
+            let contains = match single_argument(args, "contains", call_site)? {
+                Value::Number(n) => (from..=to).contains(&n),
+                _ => false,
+            };
+            Ok(Value::from(contains))
+        }
+        (Value::Range(from, to), "toList") => {
+            bind_arguments(vec![], call_site, args)?;
+            let length = (i64::from(to) - i64::from(from) + 1).max(0);
+            interpreter.check_length(length as usize, call_site)?;
+            Ok(Value::List(Rc::new(
+                (from..=to).map(Value::Number).collect(),
+            )))
+        }
+        (Value::Class(class), "args") if class.name.name == "System" => {
+            bind_arguments(vec![], call_site, args)?;
+            let args = interpreter
+                .args
+                .iter()
+                .map(|arg| Value::String(Rc::from(arg.as_str())))
+                .collect();
+            Ok(Value::List(Rc::new(args)))
+        }
+        (Value::Class(class), "env") if class.name.name == "System" => {
+            let name = string_argument(args, "env", call_site)?;
+            let value = interpreter
+                .env
+                .get(&*name)
+                .map_or(Value::Nil, |value| Value::String(Rc::from(value.as_str())));
+            Ok(value)
+        }
+        (Value::Class(class), "gc") if class.name.name == "System" => {
+            bind_arguments(vec![], call_site, args)?;
+            let collected = interpreter.heap.borrow_mut().collect();
+            Ok(Value::Number(collected as i32))
+        }
+        (Value::Class(class), "exit") if class.name.name == "System" => {
+            let status = number_argument(args, "exit", call_site)?;
+            Err(Error::Exit {
+                status,
+                span: call_site,
+            })
+        }
+        (Value::Class(class), "raise") if class.name.name == "Exception" => {
+            let err = match single_argument(args, "raise", call_site)? {
+                Value::String(message) => Error::Raised {
+                    message: message.to_string(),
+                    code: "E0028",
+                    span: call_site,
+                },
+                Value::Exception(exception) => Error::Raised {
+                    message: exception.message.clone(),
+                    code: exception.code,
+                    span: exception.span,
+                },
+                other => {
+                    return Err(Error::UnexpectedType {
+                        expected: "String",
+                        given: other.class_name(),
+                        span: call_site,
+                    })
+                }
+            };
+            Err(err)
+        }
+        (Value::Class(class), "try") if class.name.name == "Exception" => {
+            let mut parameters = vec!["try"];
+            for name in &["rescue", "ensure"] {
+                if args.iter().any(|arg| arg.name == *name) {
+                    parameters.push(name);
+                }
+            }
+            let mut args = bind_arguments(parameters, call_site, args)?;
+            let block = args
+                .remove("try")
+                .expect("Internal error: bound argument missing");
+            let handler = args.remove("rescue");
+            let cleanup = args.remove("ensure");
+
+            let result = call_with_arguments(interpreter, &block, vec![], call_site);
+            let result = match (result, handler) {
+                (Err(err), Some(handler)) if err.is_rescuable() => {
+                    let exception =
+                        Value::Exception(Rc::new(Exception::from_error(&err, call_site)));
+                    call_with_one_argument(interpreter, &handler, exception, call_site)
+                }
+                (result, _) => result,
+            };
+
+            if let Some(cleanup) = cleanup {
+                if let Err(Error::Cancelled { .. }) = result {
+                    // Let the cleanup run to completion even though the program was cancelled
+                    let mut uncancellable = interpreter.copy_for_call(
+                        interpreter.self_.as_ref().map(Value::to_owned),
+                        Frame::new(),
+                    );
+                    uncancellable.cancellation = None;
+                    call_with_arguments(&uncancellable, &cleanup, vec![], call_site)?;
+                } else {
+                    call_with_arguments(interpreter, &cleanup, vec![], call_site)?;
+                }
+            }
+            result
+        }
+        (Value::Class(class), "let") if class.name.name == "Dyn" => {
+            let mut args = bind_arguments(vec!["let", "be", "in"], call_site, args)?;
+            let name = match args.remove("let") {
+                Some(Value::Selector(name)) => name,
+                other => {
+                    return Err(Error::UnexpectedType {
+                        expected: "Selector",
+                        given: other.map_or("Nil", |value| value.class_name()),
+                        span: call_site,
+                    })
+                }
+            };
+            let value = args
+                .remove("be")
+                .expect("Internal error: bound argument missing");
+            let block = args
+                .remove("in")
+                .expect("Internal error: bound argument missing");
+
+            interpreter.dynamic.borrow_mut().push((name, value));
+            let result = call_with_arguments(interpreter, &block, vec![], call_site);
+            interpreter.dynamic.borrow_mut().pop();
+            result
+        }
+        (Value::Class(class), "get") if class.name.name == "Dyn" => {
+            let name = match single_argument(args, "get", call_site)? {
+                Value::Selector(name) => name,
+                other => {
+                    return Err(Error::UnexpectedType {
+                        expected: "Selector",
+                        given: other.class_name(),
+                        span: call_site,
+                    })
+                }
+            };
+            let value = interpreter
+                .dynamic
+                .borrow()
+                .iter()
+                .rev()
+                .find(|(bound, _)| *bound == name)
+                .map_or(Value::Nil, |(_, value)| value.to_owned());
+            Ok(value)
+        }
+        (Value::Class(class), "equal") if class.name.name == "Assert" => {
+            let mut args = bind_arguments(vec!["equal", "to"], call_site, args)?;
+            let actual = args
+                .remove("equal")
+                .expect("Internal error: bound argument missing");
+            let expected = args
+                .remove("to")
+                .expect("Internal error: bound argument missing");
+
+            if values_equal(interpreter, &actual, &expected, call_site)? {
+                return Ok(Value::Nil);
+            }
+            let actual_description = describe_nested(interpreter, &actual, call_site)?;
+            let expected_description = describe_nested(interpreter, &expected, call_site)?;
+            Err(Error::AssertionFailed {
+                message: format!(
+                    "expected {} to equal {}",
+                    interpreter.bounded(&actual, actual_description),
+                    interpreter.bounded(&expected, expected_description),
+                ),
+                span: call_site,
+            })
+        }
+        (Value::Class(class), "true") if class.name.name == "Assert" => {
+            let value = single_argument(args, "true", call_site)?;
+
+            if value.is_truthy() {
+                return Ok(Value::Nil);
+            }
+            let description = describe_nested(interpreter, &value, call_site)?;
+            Err(Error::AssertionFailed {
+                message: format!(
+                    "expected a truthy value but got {}",
+                    interpreter.bounded(&value, description)
+                ),
+                span: call_site,
+            })
+        }
+        (Value::Class(class), "raises") if class.name.name == "Assert" => {
+            let mut args = bind_arguments(vec!["raises", "block"], call_site, args)?;
+            let code = match args.remove("raises") {
+                Some(Value::String(code)) => code,
+                other => {
+                    return Err(Error::UnexpectedType {
+                        expected: "String",
+                        given: other.map_or("Nil", |value| value.class_name()),
+                        span: call_site,
+                    })
+                }
+            };
+            let block = args
+                .remove("block")
+                .expect("Internal error: bound argument missing");
+
+            let message = match call_with_arguments(interpreter, &block, vec![], call_site) {
+                Err(err) if err.is_rescuable() && err.code() == &*code => return Ok(Value::Nil),
+                Err(err) if err.is_rescuable() => {
+                    format!(
+                        "expected an error with code {} but got {}",
+                        code,
+                        err.code()
+                    )
+                }
+                Err(err) => return Err(err),
+                Ok(_) => format!(
+                    "expected an error with code {} but nothing was raised",
+                    code
+                ),
+            };
+            Err(Error::AssertionFailed {
+                message,
+                span: call_site,
+            })
+        }
+        (Value::Exception(exception), "message") => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(Value::String(Rc::from(exception.message.as_str())))
+        }
+        (Value::Exception(exception), "code") => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(Value::String(Rc::from(exception.code)))
+        }
+        (Value::Exception(exception), "kind") => {
+            bind_arguments(vec![], call_site, args)?;
+            let class = interpreter.lookup_class(exception.kind(), call_site)?;
+            Ok(Value::Class(class))
+        }
+        (Value::Exception(exception), "isA") => {
+            let kind = match single_argument(args, "isA", call_site)? {
+                Value::Class(class) => class.name.name,
+                other => {
+                    return Err(Error::UnexpectedType {
+                        expected: "Class",
+                        given: other.class_name(),
+                        span: call_site,
+                    })
+                }
+            };
+            Ok(Value::from(kind == "Exception" || kind == exception.kind()))
+        }
+        (Value::Exception(exception), "selector") => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(exception.selector.map_or(Value::Nil, Value::Selector))
+        }
+        (Value::Exception(exception), "class") => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(exception
+                .class
+                .map_or(Value::Nil, |class| Value::String(Rc::from(class))))
+        }
+        (Value::Exception(exception), "span") => {
+            bind_arguments(vec![], call_site, args)?;
+            let span = vec![
+                Value::Number(exception.span.from as i32),
+                Value::Number(exception.span.to as i32),
+            ];
+            Ok(Value::List(Rc::new(span)))
+        }
+        (Value::Class(class), "from") if class.name.name == "BigInt" => {
+            let value = single_argument(args, "from", call_site)?;
+            math::big_int(value, call_site)
+        }
+        (Value::Class(class), "fromHex") if class.name.name == "Bytes" => {
+            let hex = string_argument(args, "fromHex", call_site)?;
+            Ok(bytes::from_hex(&hex)
+                .map(|bytes| Value::Bytes(Rc::new(bytes)))
+                .unwrap_or(Value::Nil))
+        }
+        (Value::Class(class), "fromString") if class.name.name == "Bytes" => {
+            let string = string_argument(args, "fromString", call_site)?;
+            Ok(Value::Bytes(Rc::new(string.as_bytes().to_vec())))
+        }
+        (Value::Bytes(bytes), "length") => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(Value::Number(bytes.len() as i32))
+        }
+        (Value::Bytes(bytes), "from") => {
+            let mut args = bind_arguments(vec!["from", "to"], call_site, args)?;
+            let mut number = |name| match args.remove(name) {
+                Some(Value::Number(n)) => Ok(n),
+                other => Err(Error::UnexpectedType {
+                    expected: "Number",
+                    given: other.map_or("Nil", |value| value.class_name()),
+                    span: call_site,
+                }),
+            };
+            // Both ends are included, like ranges, and negative indices count from the end
+            let mut index = |name| {
+                let index = number(name)?;
+                position(index, bytes.len()).ok_or(Error::IndexOutOfBounds {
+                    index,
+                    length: bytes.len(),
+                    span: call_site,
+                })
+            };
+            let (from, to) = (index("from")?, index("to")?);
+            let to = (to + 1).max(from);
+            Ok(Value::Bytes(Rc::new(bytes[from..to].to_vec())))
+        }
+        (Value::Bytes(bytes), "toHex") => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(Value::String(Rc::from(bytes::to_hex(&bytes))))
+        }
+        (Value::Bytes(bytes), "toString") => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(std::str::from_utf8(&bytes)
+                .map(|string| Value::String(Rc::from(string)))
+                .unwrap_or(Value::Nil))
+        }
+        (Value::Bytes(bytes), "toList") => {
+            bind_arguments(vec![], call_site, args)?;
+            let items = bytes
+                .iter()
+                .map(|byte| Value::Number(i32::from(*byte)))
+                .collect();
+            Ok(Value::List(Rc::new(items)))
+        }
+        (Value::Class(class), "readBytes") | (Value::Class(class), "writeBytes")
+            if class.name.name == "File" =>
+        {
+            require_files(interpreter, call_site)?;
+
+            if msg == "readBytes" {
+                let path = string_argument(args, "readBytes", call_site)?;
+                let bytes = std::fs::read(&*path)?;
+                return Ok(Value::Bytes(Rc::new(bytes)));
+            }
+
+            let mut args = bind_arguments(vec!["writeBytes", "to"], call_site, args)?;
+            let (bytes, path) = match (args.remove("writeBytes"), args.remove("to")) {
+                (Some(Value::Bytes(bytes)), Some(Value::String(path))) => (bytes, path),
+                (Some(Value::Bytes(_)), other) => {
+                    return Err(Error::UnexpectedType {
+                        expected: "String",
+                        given: other.map_or("Nil", |value| value.class_name()),
+                        span: call_site,
+                    })
+                }
+                (other, _) => {
+                    return Err(Error::UnexpectedType {
+                        expected: "Bytes",
+                        given: other.map_or("Nil", |value| value.class_name()),
+                        span: call_site,
+                    })
+                }
+            };
+            std::fs::write(&*path, &**bytes)?;
+            Ok(Value::Nil)
+        }
+        (Value::StringBuilder(builder), "append") => {
+            // Rendered before borrowing, since the value can be the builder itself
+            let value = single_argument(args, "append", call_site)?.to_string();
+            let length = builder.borrow().len() + value.len();
+            interpreter.check_length(length, call_site)?;
+            builder.borrow_mut().push_str(&value);
+            Ok(Value::StringBuilder(builder))
+        }
+        (Value::StringBuilder(builder), "toString") => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(Value::String(Rc::from(builder.borrow().as_str())))
+        }
+        (Value::Class(class), "compile") if class.name.name == "Regex" => {
+            let pattern = string_argument(args, "compile", call_site)?;
+            let regex = regex::Regex::new(&pattern).map_err(|err| Error::InvalidSource {
+                message: err.to_string(),
+                span: call_site,
+            })?;
+            Ok(Value::Regex(Rc::new(regex)))
+        }
+        (Value::Regex(regex), "matches") => {
+            let string = string_argument(args, "matches", call_site)?;
+            Ok(Value::from(regex.is_match(&string)))
+        }
+        (Value::Regex(regex), "find") => {
+            let string = string_argument(args, "find", call_site)?;
+            let captures = match regex.captures(&string) {
+                Some(captures) => captures,
+                None => return Ok(Value::Nil),
+            };
+            let groups = captures
+                .iter()
+                .map(|group| {
+                    group.map_or(Value::Nil, |group| Value::String(Rc::from(group.as_str())))
+                })
+                .collect();
+            Ok(Value::List(Rc::new(groups)))
+        }
+        (Value::Regex(regex), "replace") => {
+            let mut args = bind_arguments(vec!["replace", "with"], call_site, args)?;
+            let mut string = |name| match args.remove(name) {
+                Some(Value::String(string)) => Ok(string),
+                other => Err(Error::UnexpectedType {
+                    expected: "String",
+                    given: other.map_or("Nil", |value| value.class_name()),
+                    span: call_site,
+                }),
+            };
+            let (string, replacement) = (string("replace")?, string("with")?);
+            let replaced = regex.replace_all(&string, &*replacement);
+            Ok(Value::String(Rc::from(replaced.as_ref())))
+        }
+        (Value::Dict(entries), "at") => {
+            let key = string_argument(args, "at", call_site)?;
+            let value = dict_get(&entries, &key).map_or(Value::Nil, Value::to_owned);
+            Ok(value)
+        }
+        (Value::Dict(entries), "keys") => {
+            bind_arguments(vec![], call_site, args)?;
+            let keys = entries
+                .iter()
+                .map(|(key, _)| Value::String(Rc::clone(key)))
+                .collect();
+            Ok(Value::List(Rc::new(keys)))
+        }
+        (Value::Dict(entries), "values") => {
+            bind_arguments(vec![], call_site, args)?;
+            let values = entries.iter().map(|(_, value)| value.to_owned()).collect();
+            Ok(Value::List(Rc::new(values)))
+        }
+        (Value::List(mut items), _) if changes_list(msg, args.iter().map(|arg| arg.name)) => {
+            change_list(interpreter, &mut items, msg, args, call_site)?;
+            Ok(Value::List(items))
+        }
+        (collection @ (Value::List(_) | Value::String(_) | Value::Bytes(_)), "at") => {
+            let parameters = if args.iter().any(|arg| arg.name == "ifAbsent") {
+                vec!["at", "ifAbsent"]
+            } else {
+                vec!["at"]
+            };
+            let mut args = bind_arguments(parameters, call_site, args)?;
+            let index = match args.remove("at") {
+                Some(Value::Number(n)) => n,
+                other => {
+                    return Err(Error::UnexpectedType {
+                        expected: "Number",
+                        given: other.map_or("Nil", |value| value.class_name()),
+                        span: call_site,
+                    })
+                }
+            };
+
+            let length = match &collection {
+                Value::List(items) => items.len(),
+                Value::String(string) => string.chars().count(),
+                Value::Bytes(bytes) => bytes.len(),
+                _ => unreachable!("Internal error: not a collection"),
+            };
+            let item = position(index, length).map(|idx| match &collection {
+                Value::List(items) => items[idx].to_owned(),
+                Value::String(string) => {
+                    let char = string
+                        .chars()
+                        .nth(idx)
+                        .expect("Internal error: checked length");
+                    Value::String(Rc::from(char.to_string()))
+                }
+                Value::Bytes(bytes) => Value::Number(i32::from(bytes[idx])),
+                _ => unreachable!("Internal error: not a collection"),
+            });
+
+            match (item, args.remove("ifAbsent")) {
+                (Some(item), _) => Ok(item),
+                (None, Some(block @ (Value::Block(_) | Value::Memoized(_)))) => {
+                    call_with_arguments(interpreter, &block, vec![], call_site)
+                }
+                (None, Some(default)) => Ok(default),
+                (None, None) => Err(Error::IndexOutOfBounds {
+                    index,
+                    length,
+                    span: call_site,
+                }),
+            }
+        }
+        (Value::List(items), "length") => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(Value::Number(items.len() as i32))
+        }
+        (Value::List(items), "map") => {
+            let block = single_argument(args, "map", call_site)?;
 
-        let mut method_interpreter = interpreter.copy_for_method_call(new_self, new_locals);
+            let values = items
+                .iter()
+                .map(|item| call_with_one_argument(interpreter, &block, item.to_owned(), call_site))
+                .collect::<Result<'a, Vec<_>>>()?;
+            Ok(Value::List(Rc::new(values)))
+        }
+        (Value::List(items), "sort") => {
+            bind_arguments(vec![], call_site, args)?;
+            let items = items.iter().map(Value::to_owned).collect();
+            let sorted = merge_sort(items, &mut |lhs, rhs| compare(lhs, rhs, call_site))?;
+            Ok(Value::List(Rc::new(sorted)))
+        }
+        (Value::List(items), "sortBy") => {
+            let block = single_argument(args, "sortBy", call_site)?;
+            let items = items.iter().map(Value::to_owned).collect();
+            let sorted = merge_sort(items, &mut |lhs, rhs| {
+                let args = vec![lhs.to_owned(), rhs.to_owned()];
+                match call_with_arguments(interpreter, &block, args, call_site)? {
+                    Value::Number(n) => Ok(n.cmp(&0)),
+                    other => Err(Error::UnexpectedType {
+                        expected: "Number",
+                        given: other.class_name(),
+                        span: call_site,
+                    }),
+                }
+            })?;
+            Ok(Value::List(Rc::new(sorted)))
+        }
+        (Value::List(items), "each") => {
+            let block = single_argument(args, "each", call_site)?;
+
+            for item in items.iter() {
+                call_with_one_argument(interpreter, &block, item.to_owned(), call_site)?;
+            }
+            Ok(Value::List(items))
+        }
+        (Value::List(items), "filter") => {
+            let block = single_argument(args, "filter", call_site)?;
+
+            let mut values = vec![];
+            for item in items.iter() {
+                if call_with_one_argument(interpreter, &block, item.to_owned(), call_site)?
+                    .is_truthy()
+                {
+                    values.push(item.to_owned());
+                }
+            }
+            Ok(Value::List(Rc::new(values)))
+        }
+        (Value::List(items), "reduce") => {
+            let mut args = bind_arguments(vec!["reduce", "initial"], call_site, args)?;
+            let block = args
+                .remove("reduce")
+                .expect("Internal error: bound argument missing");
+            let initial = args
+                .remove("initial")
+                .expect("Internal error: bound argument missing");
 
-        visit_ast(&mut method_interpreter, method.body)?;
+            items.iter().try_fold(initial, |acc, item| {
+                call_with_arguments(interpreter, &block, vec![acc, item.to_owned()], call_site)
+            })
+        }
+        (Value::List(items), "any") | (Value::List(items), "all") => {
+            let block = single_argument(args, msg, call_site)?;
+            let looking_for = msg == "any";
 
-        let return_value = method_interpreter
-            .return_value
-            .unwrap_or_else(|| Value::Nil);
-        Ok(return_value)
+            for item in items.iter() {
+                let result =
+                    call_with_one_argument(interpreter, &block, item.to_owned(), call_site)?;
+                if result.is_truthy() == looking_for {
+                    return Ok(Value::from(looking_for));
+                }
+            }
+            Ok(Value::from(!looking_for))
+        }
+        (Value::Object(object), _) => Err(Error::UndefinedMethod {
+            class: object::class_name(&*object),
+            method: msg,
+            span: call_site,
+        }),
+        (receiver, _) => Err(interpreter.not_understood(&receiver, msg, call_site)),
     }
 }
 
-impl<'a> Eval<'a> for IVar<'a> {
-    fn eval(&self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
-        let name = &self.ident.name;
-        let span = self.span;
+/// Fail unless the program may read and write files.
+fn require_files<'a>(interpreter: &Interpreter<'a>, call_site: Span) -> Result<'a, ()> {
+    if interpreter.capabilities.files {
+        Ok(())
+    } else {
+        Err(Error::CapabilityNotGranted {
+            capability: "files",
+            flag: "--allow-files",
+            span: call_site,
+        })
+    }
+}
 
-        let instance = match &interpreter.self_ {
-            Some(inner) => match inner.to_owned() {
-                Value::Instance(instance) => instance,
-                _ => return Err(Error::MessageSentToNonInstance(self.span)),
-            },
-            None => return Err(Error::IVarAccessedOutsideMethod { name, span }),
-        };
+fn dict_get<'v, 'a>(entries: &'v [(Rc<str>, Value<'a>)], key: &str) -> Option<&'v Value<'a>> {
+    entries
+        .iter()
+        .find(|(entry_key, _)| &**entry_key == key)
+        .map(|(_, value)| value)
+}
 
-        let value = instance
-            .ivars
-            .get(name)
-            .ok_or_else(|| Error::UndefinedIVar { name, span })?
-            .to_owned();
+/// Whether `msg` with arguments named `arg_names` is one of the messages `change_list` handles.
+fn changes_list<'n>(msg: &str, mut arg_names: impl Iterator<Item = &'n str>) -> bool {
+    match msg {
+        "push" | "concat" | "reverse" => true,
+        "at" => arg_names.any(|name| name == "put"),
+        _ => false,
+    }
+}
 
-        Ok(value)
+/// Handle `push:`, `concat:`, `reverse`, or `at:put:` sent to the list `items`. `items` is only
+/// changed if the message succeeds.
+fn change_list<'a>(
+    interpreter: &Interpreter<'a>,
+    items: &mut Rc<Vec<Value<'a>>>,
+    msg: &'a str,
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, ()> {
+    match msg {
+        "push" => {
+            let item = single_argument(args, "push", call_site)?;
+            interpreter.check_length(items.len() + 1, call_site)?;
+            list_to_change(interpreter, items).push(item);
+        }
+        "concat" => {
+            let other = match single_argument(args, "concat", call_site)? {
+                Value::List(other) => other,
+                other => {
+                    return Err(Error::UnexpectedType {
+                        expected: "List",
+                        given: other.class_name(),
+                        span: call_site,
+                    })
+                }
+            };
+            interpreter.check_length(items.len() + other.len(), call_site)?;
+            list_to_change(interpreter, items).extend(other.iter().map(Value::to_owned));
+        }
+        "reverse" => {
+            bind_arguments(vec![], call_site, args)?;
+            list_to_change(interpreter, items).reverse();
+        }
+        _ => {
+            let mut args = bind_arguments(vec!["at", "put"], call_site, args)?;
+            let index = match args.remove("at") {
+                Some(Value::Number(n)) => n,
+                other => {
+                    return Err(Error::UnexpectedType {
+                        expected: "Number",
+                        given: other.map_or("Nil", |value| value.class_name()),
+                        span: call_site,
+                    })
+                }
+            };
+            let length = items.len();
+            let idx = position(index, length).ok_or(Error::IndexOutOfBounds {
+                index,
+                length,
+                span: call_site,
+            })?;
+            list_to_change(interpreter, items)[idx] = args
+                .remove("put")
+                .expect("Internal error: bound argument missing");
+        }
+    }
+    Ok(())
+}
+
+/// The items of a list that's being changed. Lists are values, so they're copied first if
+/// anything else refers to them, and changed in place otherwise.
+fn list_to_change<'a, 'l>(
+    interpreter: &Interpreter<'a>,
+    items: &'l mut Rc<Vec<Value<'a>>>,
+) -> &'l mut Vec<Value<'a>> {
+    if Rc::get_mut(items).is_none() {
+        interpreter.record(Report::allocation);
+        *items = Rc::new(items.iter().map(Value::to_owned).collect());
+    }
+    Rc::get_mut(items).expect("Internal error: copied list is shared")
+}
+
+fn single_argument<'a>(
+    args: Vec<ArgumentValue<'a>>,
+    name: &'a str,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    let value = bind_arguments(vec![name], call_site, args)?
+        .remove(name)
+        .expect("Internal error: bound argument missing");
+    Ok(value)
+}
+
+enum Scope {
+    /// Only the classes are shared with the caller.
+    Fresh,
+    /// The caller's locals and `self` are visible as well.
+    Current,
+}
+
+/// Read a line from stdin without its line ending, or `nil` at the end of input.
+fn read_line<'a>(interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+    // Make sure prompts printed with `print:` are visible before waiting for input
+    interpreter.stdout.borrow_mut().flush()?;
+
+    let mut line = String::new();
+    if interpreter.stdin.borrow_mut().read_line(&mut line)? == 0 {
+        return Ok(Value::Nil);
+    }
+
+    let line = line.trim_end_matches('\n').trim_end_matches('\r');
+    Ok(Value::String(Rc::from(line)))
+}
+
+/// What evaluated source produces when it doesn't return.
+enum Outcome {
+    /// `nil`, as for a method without a `return`.
+    Returned,
+    /// The value of the last statement, if it's a message send or `let`.
+    LastValue,
+}
+
+/// Run source given to the program at runtime and return what it returns, or what `outcome` says.
+///
+/// The source and syntax tree are kept in the interpreter's `EvalArena` since values created by
+/// the source, such as blocks, can outlive the call. Locals the source defines aren't visible to
+/// the caller, and it can't define classes or methods.
+fn eval_source<'a>(
+    interpreter: &Interpreter<'a>,
+    source: &str,
+    scope: Scope,
+    outcome: Outcome,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    let arena = interpreter.eval_arena.ok_or(Error::CapabilityNotGranted {
+        capability: "eval",
+        flag: "--allow-eval",
+        span: call_site,
+    })?;
+    let source = arena.source(source.to_string());
+    let mut ast = parse_source(source).map_err(|err| invalid_source(err, call_site))?;
+    prep::remove_unreachable(&mut ast);
+    prep::qualify_names(&mut ast);
+    match scope {
+        Scope::Fresh => prep::resolve_slots(&mut ast, &[]),
+        Scope::Current => prep::resolve_slots(&mut ast, interpreter.locals.names()),
+    }
+    let ast = arena.ast(ast);
+
+    let defines_classes_or_methods = ast.iter().any(|stmt| {
+        matches!(
+            stmt,
+            Stmt::DefineClass(_)
+                | Stmt::Module(_)
+                | Stmt::ReopenClass(_)
+                | Stmt::DefineMethod(_)
+                | Stmt::DefineAbstractMethod(_)
+        )
+    });
+    if defines_classes_or_methods {
+        return Err(Error::InvalidSource {
+            message: "classes and methods can't be defined in evaluated source".to_string(),
+            span: call_site,
+        });
+    }
+
+    let mut eval_interpreter = match scope {
+        Scope::Fresh => interpreter.copy_for_call(None, Frame::new()),
+        Scope::Current => {
+            let locals = interpreter.locals.copy();
+            let self_ = interpreter.self_.as_ref().map(|value| value.to_owned());
+            interpreter.copy_for_call(self_, locals)
+        }
+    };
+
+    let in_evaluated_source = |error| Error::InEvaluatedSource {
+        error: Box::new(error),
+        span: call_site,
+    };
+    let (last, init) = match (outcome, ast.split_last()) {
+        (Outcome::LastValue, Some((last, init))) => (Some(last), init),
+        _ => (None, &ast[..]),
+    };
+
+    for stmt in init {
+        visit_stmt(&mut eval_interpreter, stmt).map_err(in_evaluated_source)?;
+    }
+    let last_value = match last {
+        Some(stmt @ Stmt::MessageSend(inner)) => Visitor::visit_stmt(&mut eval_interpreter, stmt)
+            .and_then(|()| inner.expr.eval(&eval_interpreter))
+            .map_err(in_evaluated_source)?,
+        Some(stmt) => {
+            visit_stmt(&mut eval_interpreter, stmt).map_err(in_evaluated_source)?;
+            match stmt {
+                Stmt::LetLocal(inner) => eval_interpreter
+                    .locals
+                    .get(inner.slot)
+                    .map_or(Value::Nil, |value| value.to_owned()),
+                _ => Value::Nil,
+            }
+        }
+        None => Value::Nil,
+    };
+
+    Ok(eval_interpreter.return_value.unwrap_or(last_value))
+}
+
+fn string_argument<'a>(
+    args: Vec<ArgumentValue<'a>>,
+    name: &'a str,
+    call_site: Span,
+) -> Result<'a, Rc<str>> {
+    match single_argument(args, name, call_site)? {
+        Value::String(string) => Ok(string),
+        other => Err(Error::UnexpectedType {
+            expected: "String",
+            given: other.class_name(),
+            span: call_site,
+        }),
+    }
+}
+
+fn number_argument<'a>(
+    args: Vec<ArgumentValue<'a>>,
+    name: &'a str,
+    call_site: Span,
+) -> Result<'a, i32> {
+    match single_argument(args, name, call_site)? {
+        Value::Number(number) => Ok(number),
+        other => Err(Error::UnexpectedType {
+            expected: "Number",
+            given: other.class_name(),
+            span: call_site,
+        }),
+    }
+}
+
+/// Errors from lexing or parsing source given to the program at runtime point into that source, so
+/// they're reported at the call site instead.
+fn invalid_source<'a>(err: Error<'_>, call_site: Span) -> Error<'a> {
+    Error::InvalidSource {
+        message: err.to_string(),
+        span: call_site,
+    }
+}
+
+/// The natural order of values, used by `compare:` and `sort`. Numbers and floats can be compared
+/// with each other and strings with strings. Everything else, including `NaN`, is incomparable.
+fn compare<'a>(lhs: &Value<'a>, rhs: &Value<'a>, call_site: Span) -> Result<'a, Ordering> {
+    let ordering = match (lhs, rhs) {
+        (Value::Number(lhs), Value::Number(rhs)) => Some(lhs.cmp(rhs)),
+        (Value::Number(lhs), Value::Float(rhs)) => f64::from(*lhs).partial_cmp(rhs),
+        (Value::Float(lhs), Value::Number(rhs)) => lhs.partial_cmp(&f64::from(*rhs)),
+        (Value::Float(lhs), Value::Float(rhs)) => lhs.partial_cmp(rhs),
+        (Value::BigInt(lhs), Value::BigInt(rhs)) => Some(lhs.cmp(rhs)),
+        (Value::BigInt(lhs), Value::Number(rhs)) => Some(lhs.as_ref().cmp(&BigInt::from(*rhs))),
+        (Value::Number(lhs), Value::BigInt(rhs)) => Some(BigInt::from(*lhs).cmp(rhs)),
+        (Value::String(lhs), Value::String(rhs)) => Some(lhs.cmp(rhs)),
+        _ => None,
+    };
+
+    ordering.ok_or_else(|| Error::IncomparableValues {
+        lhs: lhs.class_name(),
+        rhs: rhs.class_name(),
+        span: call_site,
+    })
+}
+
+/// A stable sort that stops at the first error from `compare`.
+///
+/// Used instead of `slice::sort_by` since comparators written in OOPS aren't guaranteed to be a
+/// total order, which the standard library sorts are allowed to panic on.
+fn merge_sort<'a>(
+    mut items: Vec<Value<'a>>,
+    compare: &mut dyn FnMut(&Value<'a>, &Value<'a>) -> Result<'a, Ordering>,
+) -> Result<'a, Vec<Value<'a>>> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, compare)?;
+    let right = merge_sort(right, compare)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+
+    while let (Some(lhs), Some(rhs)) = (left.peek(), right.peek()) {
+        if compare(lhs, rhs)? == Ordering::Greater {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+
+    Ok(merged)
+}
+
+/// Compare two values using the `equals:` protocol.
+///
+/// Instances whose class defines `equals:` are compared by sending it that message. All other
+/// instances are equal if they're of the same class and all their instance variables are equal.
+/// Strings are equal if they have the same contents.
+fn values_equal<'a>(
+    interpreter: &Interpreter<'a>,
+    lhs: &Value<'a>,
+    rhs: &Value<'a>,
+    call_site: Span,
+) -> Result<'a, bool> {
+    let equal = match (lhs, rhs) {
+        (Value::Instance(instance), _) if instance.class.find_method("equals").is_some() => {
+            let args = vec![ArgumentValue {
+                name: "equals",
+                value: rhs.to_owned(),
+                span: call_site,
+            }];
+            let result = call_method(interpreter, Rc::clone(instance), "equals", args, call_site)?;
+            result.is_truthy()
+        }
+        (Value::String(lhs), Value::String(rhs)) => lhs == rhs,
+        (Value::Bytes(lhs), Value::Bytes(rhs)) => lhs == rhs,
+        (Value::Object(lhs), Value::Object(rhs)) => {
+            Rc::ptr_eq(lhs, rhs) || object::equals(&**lhs, &**rhs, call_site)?
+        }
+        (Value::BigInt(lhs), Value::Number(rhs)) | (Value::Number(rhs), Value::BigInt(lhs)) => {
+            **lhs == BigInt::from(*rhs)
+        }
+        (Value::Instance(lhs), Value::Instance(rhs)) => {
+            let lhs_ivars = lhs.ivars();
+            if !Rc::ptr_eq(&lhs.class, &rhs.class) || lhs_ivars.len() != rhs.ivars().len() {
+                return Ok(false);
+            }
+
+            for (name, lhs_value) in lhs_ivars {
+                let rhs_value = match rhs.ivar(name) {
+                    Some(value) => value,
+                    None => return Ok(false),
+                };
+
+                if !values_equal(interpreter, &lhs_value, &rhs_value, call_site)? {
+                    return Ok(false);
+                }
+            }
+
+            true
+        }
+        (Value::List(lhs), Value::List(rhs)) => {
+            if lhs.len() != rhs.len() {
+                return Ok(false);
+            }
+
+            for (lhs_value, rhs_value) in lhs.iter().zip(rhs.iter()) {
+                if !values_equal(interpreter, lhs_value, rhs_value, call_site)? {
+                    return Ok(false);
+                }
+            }
+
+            true
+        }
+        (Value::Dict(lhs), Value::Dict(rhs)) => {
+            if lhs.len() != rhs.len() {
+                return Ok(false);
+            }
+
+            for (key, lhs_value) in lhs.iter() {
+                let rhs_value = match dict_get(rhs, key) {
+                    Some(value) => value,
+                    None => return Ok(false),
+                };
+
+                if !values_equal(interpreter, lhs_value, rhs_value, call_site)? {
+                    return Ok(false);
+                }
+            }
+
+            true
+        }
+        _ => lhs.is_identical_to(rhs),
+    };
+
+    Ok(equal)
+}
+
+/// Copy an instance or a list. Other values are immutable and returned as is.
+///
+/// A shallow copy shares the values held by the original, a deep copy copies those as well.
+/// Copies of frozen instances aren't frozen.
+/// Instances whose class defines `postCopy` are sent that message after being copied.
+fn copy_value<'a>(
+    interpreter: &Interpreter<'a>,
+    value: &Value<'a>,
+    deep: bool,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    let copy_inner = |value: &Value<'a>| {
+        if deep {
+            copy_value(interpreter, value, deep, call_site)
+        } else {
+            Ok(value.to_owned())
+        }
+    };
+
+    match value {
+        Value::Instance(instance) => {
+            let mut ivars = VTable::new();
+            for (name, value) in instance.ivars() {
+                ivars.insert(name, copy_inner(&value)?);
+            }
+
+            let copy = interpreter.allocate(Instance::new(Rc::clone(&instance.class), ivars));
+
+            if copy.class.find_method("postCopy").is_some() {
+                call_method(interpreter, Rc::clone(&copy), "postCopy", vec![], call_site)?;
+            }
+
+            Ok(Value::Instance(copy))
+        }
+        Value::List(items) => {
+            let items = items
+                .iter()
+                .map(copy_inner)
+                .collect::<Result<'a, Vec<_>>>()?;
+            interpreter.record(Report::allocation);
+            Ok(Value::List(Rc::new(items)))
+        }
+        other => Ok(other.to_owned()),
+    }
+}
+
+#[cfg_attr(
+    feature = "observability",
+    tracing::instrument(level = "debug", skip_all, fields(class = receiver.class.name.name, msg))
+)]
+fn call_method<'a>(
+    interpreter: &Interpreter<'a>,
+    receiver: Rc<Instance<'a>>,
+    msg: &'a str,
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    let new_self = Value::Instance(Rc::clone(&receiver));
+    invoke_method(interpreter, &receiver.class, new_self, msg, args, call_site)
+}
+
+/// Run the method named `msg` on `class` or one of its super classes, with `new_self` as `self`.
+fn invoke_method<'a>(
+    interpreter: &Interpreter<'a>,
+    class: &Class<'a>,
+    new_self: Value<'a>,
+    msg: &'a str,
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    let method = class.get_method_named(msg, call_site)?;
+    run_method(interpreter, class, method, new_self, msg, args, call_site)
+}
+
+/// Run `method`, found from `class`, with `new_self` as `self`.
+fn run_method<'a>(
+    interpreter: &Interpreter<'a>,
+    class: &Class<'a>,
+    method: Method<'a>,
+    new_self: Value<'a>,
+    msg: &'a str,
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    interpreter.record(|report| report.method_call(class.name.name, msg));
+
+    let new_locals = method_locals(&method, args, call_site)?;
+
+    let mut method_interpreter = interpreter.copy_for_call(Some(new_self), new_locals);
+    method_interpreter.method = Some((class.name.name, msg));
+    method_interpreter.module = method.module;
+
+    method_interpreter.run_call(method.body, call_site)
+}
+
+fn call_block<'a>(
+    interpreter: &Interpreter<'a>,
+    closure: &Closure<'a>,
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    // Blocks with empty bodies don't run any statements, so loops calling them would never notice
+    interpreter.check_cancelled(call_site)?;
+
+    let new_locals = block_locals(closure, args, call_site)?;
+
+    let new_self = closure.self_.as_ref().map(|value| value.to_owned());
+    let mut block_interpreter = interpreter.copy_for_call(new_self, new_locals);
+    block_interpreter.module = closure.module;
+
+    block_interpreter.run_call(&closure.block.body, call_site)
+}
+
+/// The locals a method starts with, `args` bound to its parameters.
+fn method_locals<'a>(
+    method: &Method<'a>,
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, Frame<'a>> {
+    let mut locals = Frame::new();
+    bind_parameters(&mut locals, method.parameters, args, call_site)?;
+    Ok(locals)
+}
+
+/// The locals a block starts with, the ones it captured and `args` bound to its parameters.
+fn block_locals<'a>(
+    closure: &Closure<'a>,
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, Frame<'a>> {
+    let mut locals = closure.locals.copy();
+    bind_parameters(
+        &mut locals,
+        &closure.block.parameters[closure.bound..],
+        args,
+        call_site,
+    )?;
+    Ok(locals)
+}
+
+/// Define `parameters` in `locals`, with the values of `args` bound to them.
+fn bind_parameters<'a>(
+    locals: &mut Frame<'a>,
+    parameters: &'a [Parameter<'a>],
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, ()> {
+    let names = parameters.iter().map(|param| param.ident.name).collect();
+    let mut args = bind_arguments(names, call_site, args)?;
+    for param in parameters {
+        // A parameter named twice gets the value once, like a local defined twice
+        if let Some(value) = args.remove(param.ident.name) {
+            locals.insert(param.slot, param.ident.name, value);
+        }
+    }
+    Ok(())
+}
+
+/// Call a memoized block with arguments in the order of its parameters, or return what it
+/// returned the last time it was called with the same arguments.
+fn call_memoized<'a>(
+    interpreter: &Interpreter<'a>,
+    memoized: &Memoized<'a>,
+    args: Vec<Value<'a>>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    if let Some(value) = memoized.get(&args) {
+        return Ok(value);
+    }
+
+    let arg_values = memoized
+        .closure
+        .parameters()
+        .zip(args.iter())
+        .map(|(name, value)| ArgumentValue {
+            name,
+            value: value.to_owned(),
+            span: call_site,
+        })
+        .collect();
+    let value = call_block(interpreter, &memoized.closure, arg_values, call_site)?;
+    memoized.insert(args, value.to_owned());
+    Ok(value)
+}
+
+/// Call something that is used where a block taking a single argument is expected.
+///
+/// Selectors are accepted as well and behave like a block that sends that message to its
+/// argument, so `[users map: #id]` is the same as `[users map: |user:| { return [user id]; }]`.
+fn call_with_one_argument<'a>(
+    interpreter: &Interpreter<'a>,
+    callable: &Value<'a>,
+    arg: Value<'a>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    call_with_arguments(interpreter, callable, vec![arg], call_site)
+}
+
+/// Call something that is used where a block taking `args.len()` arguments is expected. The
+/// arguments are bound to the block's parameters in order.
+///
+/// Selectors send their message to the first argument, with the rest as keyword arguments named
+/// after the selector, so `[names reduce: #concat initial: ""]` sends `[acc concat: name]`.
+fn call_with_arguments<'a>(
+    interpreter: &Interpreter<'a>,
+    callable: &Value<'a>,
+    args: Vec<Value<'a>>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    match callable {
+        Value::Block(closure) => {
+            let parameters = closure.parameters().collect::<Vec<_>>();
+            if parameters.len() != args.len() {
+                return Err(Error::WrongNumberOfBlockParameters {
+                    expected: args.len(),
+                    given: parameters.len(),
+                    span: call_site,
+                });
+            }
+
+            let args = parameters
+                .into_iter()
+                .zip(args)
+                .map(|(name, value)| ArgumentValue {
+                    name,
+                    value,
+                    span: call_site,
+                })
+                .collect();
+            call_block(interpreter, closure, args, call_site)
+        }
+        Value::Memoized(memoized) => {
+            let expected = memoized.closure.parameters().count();
+            if expected != args.len() {
+                return Err(Error::WrongNumberOfBlockParameters {
+                    expected: args.len(),
+                    given: expected,
+                    span: call_site,
+                });
+            }
+            call_memoized(interpreter, memoized, args, call_site)
+        }
+        Value::Selector(name) => {
+            // A selector sends itself to its first argument, so it can't be called without one
+            let mut args = args.into_iter();
+            let receiver = args.next().ok_or(Error::WrongNumberOfBlockParameters {
+                expected: 0,
+                given: 1,
+                span: call_site,
+            })?;
+            let args = args
+                .map(|value| ArgumentValue {
+                    name,
+                    value,
+                    span: call_site,
+                })
+                .collect();
+            send(interpreter, receiver, name, args, call_site)
+        }
+        _ => Err(Error::NotCallable(call_site)),
+    }
+}
+
+impl<'a> Eval<'a> for IVar<'a> {
+    fn eval(&'a self, interpreter: &Interpreter<'a>) -> Result<'a, Value<'a>> {
+        let name = self.ident.name;
+        let span = self.span;
+
+        let instance = interpreter.current_instance(name, span)?;
+        let value = instance
+            .ivar(name)
+            .ok_or(Error::UndefinedIVar { name, span })?;
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    use crate::{
+        lex::{lex, Token},
+        prep::find_classes_and_methods,
+    };
+    use std::io;
+
+    /// Parse `tokens` and resolve the slots of their locals, like the program is before it runs.
+    fn parse<'a>(tokens: &'a Vec<Token<'a>>) -> Result<'a, Ast<'a>> {
+        let mut ast = crate::parse::parse(tokens)?;
+        prep::qualify_names(&mut ast);
+        prep::resolve_slots(&mut ast, &[]);
+        Ok(ast)
+    }
+
+    fn interpreter<'a>(
+        ast: &'a Ast<'a>,
+        stdout: Rc<RefCell<Vec<u8>>>,
+        stdin: &str,
+    ) -> Result<'a, Interpreter<'a>> {
+        let classes = find_classes_and_methods(ast, prep::built_in_classes())?;
+        let stdin = Rc::new(RefCell::new(io::Cursor::new(stdin.to_string())));
+        // Tests don't outlive the process, so the arena can live as long as it does
+        let eval_arena = Box::leak(Box::new(EvalArena::new()));
+        let mut interpreter = Interpreter::new(classes, stdout, stdin)
+            .with_capabilities(Capabilities {
+                eval: true,
+                files: true,
+            })
+            .with_eval_arena(eval_arena);
+        visit_ast(&mut interpreter, ast)?;
+        Ok(interpreter)
+    }
+
+    fn run<'a>(ast: &'a Ast<'a>) -> Result<'a, Frame<'a>> {
+        let stdout = Rc::new(RefCell::new(Vec::new()));
+        Ok(interpreter(ast, stdout, "")?.locals)
+    }
+
+    fn output<'a>(ast: &'a Ast<'a>) -> Result<'a, String> {
+        output_with_input(ast, "")
+    }
+
+    fn output_with_input<'a>(ast: &'a Ast<'a>, stdin: &str) -> Result<'a, String> {
+        let stdout = Rc::new(RefCell::new(Vec::new()));
+        interpreter(ast, Rc::clone(&stdout), stdin)?;
+        let output = stdout.borrow().clone();
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    fn numbers(value: Option<&Value<'_>>) -> Vec<i32> {
+        match value {
+            Some(Value::List(items)) => items
+                .iter()
+                .map(|item| match item {
+                    Value::Number(n) => *n,
+                    other => panic!("Expected a number but got {:?}", other),
+                })
+                .collect(),
+            other => panic!("Expected a list but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn calling_blocks() {
+        let program = r#"
+            let n = 1;
+            let block = |x:| { return [[n, x] map: |y:| { return y; }]; };
+            let result = [block call x: 2];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let locals = run(&ast).unwrap();
+
+        assert_eq!(vec![1, 2], numbers(locals.lookup("result")));
+    }
+
+    #[test]
+    fn selectors_can_be_used_as_unary_blocks() {
+        let program = r#"
+            [Object subclass name: #User fields: [#id]];
+            [User def: #id do: || { return @id; }];
+            let users = [[User new id: 1], [User new id: 2]];
+            let ids = [users map: #id];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let locals = run(&ast).unwrap();
+
+        assert_eq!(vec![1, 2], numbers(locals.lookup("ids")));
+    }
+
+    #[test]
+    fn mapping_with_a_block_that_takes_too_many_arguments() {
+        let program = r#"
+            let ids = [[1, 2] map: |a: b:| { return a; }];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let result = run(&ast);
+
+        assert_error!(result, Error::WrongNumberOfBlockParameters { .. });
+    }
+
+    fn boolean(value: Option<&Value<'_>>) -> bool {
+        match value {
+            Some(Value::True) => true,
+            Some(Value::False) => false,
+            other => panic!("Expected a boolean but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn structural_equality() {
+        let program = r#"
+            [Object subclass name: #Point fields: [#x #y]];
+            let a = [[Point new x: 1 y: 2], [Point new x: 3 y: 4]];
+            let b = [[Point new x: 1 y: 2], [Point new x: 3 y: 4]];
+            let c = [[Point new x: 1 y: 2], [Point new x: 3 y: 5]];
+            let same = [a equals: b];
+            let different = [a equals: c];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let locals = run(&ast).unwrap();
+
+        assert!(boolean(locals.lookup("same")));
+        assert!(!boolean(locals.lookup("different")));
+    }
+
+    #[test]
+    fn overriding_equals() {
+        let program = r#"
+            [Object subclass name: #User fields: [#id #name]];
+            [User def: #id do: || { return @id; }];
+            [User def: #equals do: |equals:| { return [@id equals: [equals id]]; }];
+            let a = [[User new id: 1 name: 2]];
+            let b = [[User new id: 1 name: 3]];
+            let same = [a equals: b];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let locals = run(&ast).unwrap();
+
+        assert!(boolean(locals.lookup("same")));
+    }
+
+    #[test]
+    fn collecting_cycles() {
+        let program = r#"
+            [Object subclass name: #Node fields: [#other]];
+            [Node def: #link do: |other:| { let @other = other; }];
+            [Node def: #other do: || { return @other; }];
+            let makeCycle = || {
+                let a = [Node new other: 0];
+                let b = [Node new other: a];
+                [a link other: [b, || { return b; }]];
+            };
+            [makeCycle call];
+            [makeCycle call];
+            let kept = [Node new other: 0];
+            [kept link other: kept];
+            let collected = [System gc];
+            let again = [System gc];
+            let stillLinked = [[kept other] identicalTo: kept];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let locals = run(&ast).unwrap();
+
+        assert!(matches!(locals.lookup("collected"), Some(Value::Number(4))));
+        assert!(matches!(locals.lookup("again"), Some(Value::Number(0))));
+        assert!(boolean(locals.lookup("stillLinked")));
+    }
+
+    #[test]
+    fn method_tables() {
+        let program = r#"
+            [Object subclass name: #A fields: []];
+            [A def: #name do: || { return "a"; }];
+            [A subclass name: #B fields: []];
+            [B def: #name do: || { return "b"; }];
+            [B subclass name: #C fields: []];
+            let c = [C new];
+            let addGreeting = || { [A def: #greet do: || { return [self name]; }]; };
+            [addGreeting call];
+            [Console println: [[c name], [c greet]]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+
+        assert_eq!(Some("B"), classes["C"].method_owner("name"));
+        assert_eq!(None, classes["C"].method_owner("greet"));
+        assert_eq!("[\"b\", \"b\"]\n", output(&ast).unwrap());
+    }
+
+    #[test]
+    fn cached_method_lookups() {
+        let program = r#"
+            [Object subclass name: #Animal fields: []];
+            [Animal def: #speak do: || { return "..."; }];
+            [Animal subclass name: #Dog fields: []];
+            [Animal subclass name: #Cat fields: []];
+            [Cat def: #speak do: || { return "meow"; }];
+            [Animal subclass name: #Cow fields: []];
+            [Cow def: #speak do: || { return "moo"; }];
+            [Animal subclass name: #Owl fields: []];
+            [Animal subclass name: #Pig fields: []];
+            [Pig def: #speak do: || { return "oink"; }];
+
+            let animals = [[Dog new], [Cat new], [Cow new], [Owl new], [Pig new], [Dog new]];
+            let speak = || { return [animals map: |animal:| { return [animal speak]; }]; };
+            [Console println: [speak call]];
+            let teach = || { [Dog def: #speak do: || { return "woof"; }]; };
+            [teach call];
+            [Console println: [speak call]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            [
+                r#"["...", "meow", "moo", "...", "oink", "..."]"#,
+                r#"["woof", "meow", "moo", "...", "oink", "woof"]"#,
+                "",
+            ]
+            .join("\n"),
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn frames() {
+        let program = r#"
+            let a = 1;
+            let b = 2;
+            let a = [a add: b];
+            let sum = |c:| { let a = [a add: c]; return a; };
+            let d = [sum call c: 10];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let locals = run(&ast).unwrap();
+
+        assert_eq!(4, locals.len());
+        assert!(matches!(locals.lookup("a"), Some(Value::Number(3))));
+        assert!(matches!(locals.lookup("d"), Some(Value::Number(13))));
+    }
+
+    #[test]
+    fn resolving_slots() {
+        let program = r#"
+            let a = 1;
+            let early = || { return later; };
+            let later = 2;
+            let shadow = |a: b:| { let c = [a add: b]; let a = [c add: 1]; return a; };
+            let nested = || { let c = 10; return || { return [a add: c]; }; };
+            [Console println: [shadow call a: 20 b: 30]];
+            [Console println: [[nested call] call]];
+            [Console println: a];
+            [Console println: [early call]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        match &ast[3] {
+            Stmt::LetLocal(LetLocal {
+                body: Expr::Block(block),
+                slot,
+                ..
+            }) => {
+                assert_eq!(Slot(Some(3)), *slot);
+                // `a` keeps the slot it has outside the block, `b` comes after the captured locals
+                let slots = block.parameters.iter().map(|param| param.slot);
+                assert_eq!(
+                    vec![Slot(Some(0)), Slot(Some(3))],
+                    slots.collect::<Vec<_>>()
+                );
+            }
+            other => panic!("unexpected statement {:?}", other),
+        }
+
+        // Blocks only capture the locals defined before them
+        let stdout = Rc::new(RefCell::new(Vec::new()));
+        let result = interpreter(&ast, Rc::clone(&stdout), "").map(|_| ());
+        assert_error!(result, Error::UndefinedLocal { name: "later", .. });
+        assert_eq!(
+            "51\n11\n1\n",
+            String::from_utf8(stdout.borrow().clone()).unwrap()
+        );
+    }
+
+    #[test]
+    fn identity() {
+        let program = r#"
+            [Object subclass name: #Point fields: [#x]];
+            let a = [Point new x: 1];
+            let b = [Point new x: 1];
+            let list = [a, b];
+            let same_instance = [a identicalTo: a];
+            let equal_instance = [a identicalTo: b];
+            let same_list = [list identicalTo: list];
+            let equal_list = [list identicalTo: [a, b]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let locals = run(&ast).unwrap();
+
+        assert!(boolean(locals.lookup("same_instance")));
+        assert!(!boolean(locals.lookup("equal_instance")));
+        assert!(boolean(locals.lookup("same_list")));
+        assert!(!boolean(locals.lookup("equal_list")));
+    }
+
+    #[test]
+    fn copying() {
+        let program = r#"
+            [Object subclass name: #Box fields: [#items]];
+            [Box def: #items do: || { return @items; }];
+            let box = [Box new items: [[Box new items: []]]];
+            let shallow = [box copy];
+            let deep = [box deepCopy];
+            let shallow_is_new = [[shallow identicalTo: box] equals: false];
+            let shallow_shares_items = [[shallow items] identicalTo: [box items]];
+            let deep_shares_items = [[deep items] identicalTo: [box items]];
+            let deep_is_equal = [deep equals: box];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let locals = run(&ast).unwrap();
+
+        assert!(boolean(locals.lookup("shallow_is_new")));
+        assert!(boolean(locals.lookup("shallow_shares_items")));
+        assert!(!boolean(locals.lookup("deep_shares_items")));
+        assert!(boolean(locals.lookup("deep_is_equal")));
+    }
+
+    #[test]
+    fn assigning_ivars() {
+        let program = r#"
+            [Object subclass name: #Counter fields: [#count]];
+            [Counter def: #set do: |count:| { let @count = count; }];
+            [Counter def: #count do: || { return @count; }];
+            let counter = [Counter new count: 1];
+            [counter set count: 2];
+            let counts = [[counter count]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let locals = run(&ast).unwrap();
+
+        assert_eq!(vec![2], numbers(locals.lookup("counts")));
+    }
+
+    #[test]
+    fn assigning_ivars_on_frozen_instances() {
+        let program = r#"
+            [Object subclass name: #Counter fields: [#count]];
+            [Counter def: #set do: |count:| { let @count = count; }];
+            let counter = [[Counter new count: 1] freeze];
+            [counter set count: 2];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let result = run(&ast);
+
+        assert_error!(result, Error::FrozenInstanceModified { name: "count", .. });
+    }
+
+    #[test]
+    fn describing_values() {
+        let program = r#"
+            [Object subclass name: #Point fields: [#x #y]];
+            [Object subclass name: #Money fields: [#cents]];
+            [Money def: #describe do: || { return [#cents, @cents]; }];
+            let values = [1, true, [], #id, [Point new x: 1 y: [2]], [Money new cents: 3]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        let interpreter = interpreter(&ast, Rc::new(RefCell::new(Vec::new())), "").unwrap();
+
+        let values = interpreter.locals.lookup("values").unwrap();
+        assert_eq!(
+            "[1, true, [], #id, <Point x: 1 y: [2]>, <Money cents: 3>]",
+            values.to_string()
+        );
+        assert_eq!(
+            "[1, true, [], #id, <Point x: 1 y: [2]>, [#cents, 3]]",
+            describe(&interpreter, values, Span::new(0, 0)).unwrap()
+        );
+    }
+
+    #[test]
+    fn printing_to_the_console() {
+        let program = r#"
+            [Console print: 1];
+            [Console println: [2, #three]];
+            [Console println: Console];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!("1[2, #three]\nConsole\n", output(&ast).unwrap());
+    }
+
+    #[test]
+    fn reading_lines_from_the_console() {
+        let program = r#"
+            let first = [Console readLine];
+            let second = [Console readLine];
+            [Console println: [first, second, [Console readLine]]];
+            [Console println: first];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "[\"Alice\", \"Bob\", nil]\nAlice\n",
+            output_with_input(&ast, "Alice\r\nBob").unwrap()
+        );
+    }
+
+    #[test]
+    fn prompting_for_input() {
+        let program = r#"
+            let name = [IO prompt: "Name?"];
+            let password = [IO readPassword: "Password?"];
+            let missing = [IO prompt: "More?"];
+            [Console println: [name, password, missing]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "Name? Password? More? [\"Alice\", \"hunter2\", nil]\n",
+            output_with_input(&ast, "Alice\nhunter2\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn string_literals() {
+        let program = r#"
+            let a = "a \"quoted\" string";
+            [Console println: a];
+            [Console println: [a, [a equals: "a \"quoted\" string"]]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "a \"quoted\" string\n[\"a \\\"quoted\\\" string\", true]\n",
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn lexing_and_parsing_source_at_runtime() {
+        let program = r#"
+            [Console println: [Lexer lex: "let x = 1;"]];
+            [Console println: [Parser parse: "let x = [#a b: 1]; return self;"]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            [
+                r#"[[#let, "let", 0, 3], [#name, "x", 4, 5], [#eq, "=", 6, 7], [#number, "1", 8, 9], [#semicolon, ";", 9, 10]]"#,
+                r#"[[#letLocal, "x", [#send, [#selector, "a"], "b", [["b", [#number, 1]]]]], [#return, [#self]]]"#,
+                "",
+            ]
+            .join("\n"),
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn parsing_invalid_source_at_runtime() {
+        let program = r#"
+            let tokens = [Lexer lex: "let x = $;"];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_error!(run(&ast), Error::InvalidSource { .. });
+    }
+
+    #[test]
+    fn indexing() {
+        let program = r#"
+            let list = [10, 20, 30];
+            let bytes = [Bytes fromHex: "0102"];
+            [Console println: [[list at: 0], [list at: 0 - 1], [list at: 0 - 3], [list length]]];
+            [Console println: [["abc" at: 0 - 2], [bytes at: 0 - 1], [list at: 3 ifAbsent: 0]]];
+            [Console println: [list at: 0 - 4 ifAbsent: || { return "missing"; }]];
+            [Console println: [Exception try: || { return [list at: 3]; } rescue: #kind]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "[10, 30, 10, 3]\n[\"b\", 2, 0]\nmissing\nIndexOutOfBounds\n",
+            output(&ast).unwrap()
+        );
+
+        let program = r#"[["a", "b"] at: 2];"#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(
+            run(&ast),
+            Error::IndexOutOfBounds {
+                index: 2,
+                length: 2,
+                ..
+            }
+        );
+    }
+
+    #[test]
+    fn string_methods() {
+        let program = r#"
+            let s = "Hello, World";
+            [Console println: [[s length], [s concat: "!"], [s split: ", "], [s contains: "World"]]];
+            [Console println: [[s toUpper], [s toLower], ["42" toNumber], ["nope" toNumber]]];
+            [Console println: [[s at: 0], [s at: 11], [s at: 0 - 1], [s at: 12 ifAbsent: "none"]]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            [
+                r#"[12, "Hello, World!", ["Hello", "World"], true]"#,
+                r#"["HELLO, WORLD", "hello, world", 42, nil]"#,
+                r#"["H", "d", "d", "none"]"#,
+                "",
+            ]
+            .join("\n"),
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn evaluating_source_at_runtime() {
+        let program = r#"
+            let x = 1;
+            let fresh = [Runtime eval: "return [1, 2];"];
+            let current = [Runtime eval: "let y = [x]; return y;" scope: #current];
+            let block = [Runtime eval: "return |a:| { return [a, a]; };"];
+            let nothing = [Runtime eval: "let z = 3;"];
+            [Console println: [fresh, current, [block call a: 2], nothing]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!("[[1, 2], [1], [2, 2], nil]\n", output(&ast).unwrap());
+    }
+
+    #[test]
+    fn evaluating_source_with_oops_eval() {
+        let program = r#"
+            let x = 1;
+            let send = [Oops eval: "[\"a\" concat: \"b\"];"];
+            let local = [Oops eval: "let y = [x, 3];"];
+            let early = [Oops eval: "return 4; [\"c\" concat: \"d\"];"];
+            let nothing = [Oops eval: ""];
+            [Console println: [send, local, early, nothing]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!("[\"ab\", [1, 3], 4, nil]\n", output(&ast).unwrap());
+    }
+
+    #[test]
+    fn runtime_version_and_features() {
+        let program = r#"
+            [Console println: [Runtime version]];
+            [Console println: [Runtime features]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let output = output(&ast).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+
+        assert_eq!(env!("CARGO_PKG_VERSION"), lines[0]);
+        assert!(lines[1].starts_with("[#eval, #files"));
+        assert_eq!(
+            Vec::<&str>::new(),
+            Capabilities::default()
+                .features()
+                .into_iter()
+                .filter(|feature| *feature != "observability")
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn errors_in_evaluated_source() {
+        let program = r#"
+            let x = 1;
+            let y = [Runtime eval: "return x;"];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(run(&ast), Error::InEvaluatedSource { .. });
+
+        let program = r#"
+            let y = [Runtime eval: "[Object subclass name: #A fields: []];"];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(run(&ast), Error::InvalidSource { .. });
+    }
+
+    #[test]
+    fn quoting_and_running_code() {
+        let program = r#"
+            let n = 2;
+            let body = quote { return [#a, ~n]; };
+            let wrapped = quote { let x = quote { return ~n; }; };
+            let block = quote { return |x:| { return [x, "y"]; }; };
+            [Console println: body];
+            [Console println: wrapped];
+            [Console println: [Runtime run: body]];
+            [Console println: [[Runtime run: block] call x: 1]];
+            [Console println: [Runtime run: [Parser parse: "return [1];"]]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            [
+                "[[#return, [#list, [[#selector, \"a\"], [#number, 2]]]]]",
+                "[[#letLocal, \"x\", [#quote, [[#return, [#unquote, \"n\"]]]]]]",
+                "[#a, 2]",
+                "[1, \"y\"]",
+                "[1]",
+                "",
+            ]
+            .join("\n"),
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn unquoting_outside_quote() {
+        let program = r#"
+            let n = 2;
+            let m = ~n;
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(run(&ast), Error::UnquoteOutsideQuote(_));
+
+        let program = r#"
+            let n = [1, 2];
+            let m = quote { return ~n; };
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(run(&ast), Error::UnexpectedType { .. });
+    }
+
+    #[test]
+    fn higher_order_list_operations() {
+        let program = r#"
+            let words = ["a", "bb", "ccc"];
+            [words each: |word:| { [Console print: word]; }];
+            [Console println: ""];
+            [Console println: [words filter: |word:| { return [word contains: "b"]; }]];
+            [Console println: [words reduce: #concat initial: ">"]];
+            [Console println: [words reduce: |acc: word:| { return [word concat: acc]; } initial: ""]];
+            [Console println: [[words any: |word:| { return [word equals: "bb"]; }], [words all: #length]]];
+            [Console println: [[[] any: #length], [[] all: #length], [words any: |word:| { return false; }]]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            [
+                "abbccc",
+                "[\"bb\"]",
+                ">abbccc",
+                "cccbba",
+                "[true, true]",
+                "[false, true, false]",
+                "",
+            ]
+            .join("\n"),
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn reduce_with_a_block_taking_the_wrong_number_of_parameters() {
+        let program = r#"
+            let sum = [[1, 2] reduce: |x:| { return x; } initial: 0];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_error!(
+            run(&ast),
+            Error::WrongNumberOfBlockParameters {
+                expected: 2,
+                given: 1,
+                ..
+            }
+        );
+    }
+
+    #[test]
+    fn budget() {
+        let program = r#"
+            [[1 to: 1000000] each: |n:| {}];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+        let stdin = Rc::new(RefCell::new(io::empty()));
+        let stdout = Rc::new(RefCell::new(io::sink()));
+        let mut interpreter = Interpreter::new(classes, stdout, stdin).with_budget(100);
+
+        assert_error!(
+            visit_ast(&mut interpreter, &ast),
+            Error::BudgetExceeded { limit: 100, .. }
+        );
+    }
+
+    #[test]
+    fn recursion_limit() {
+        let program = r#"
+            [Object subclass name: #Counter fields: []];
+            [Counter def: #down do: |from:| {
+                return [from == 0 caseOf: [[true, 0]] otherwise: || {
+                    return [self down from: from - 1] + 1;
+                }];
+            }];
+            [Console println: [[Counter new] down from: 4000]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!("4000\n", output(&ast).unwrap());
+
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+        let stdin = Rc::new(RefCell::new(io::empty()));
+        let stdout = Rc::new(RefCell::new(io::sink()));
+        let mut interpreter = Interpreter::new(classes, stdout, stdin).with_max_depth(100);
+
+        assert_error!(
+            visit_ast(&mut interpreter, &ast),
+            Error::RecursionLimitExceeded {
+                limit: 100,
+                method: Some(("Counter", "down")),
+                ..
+            }
+        );
+    }
+
+    #[test]
+    fn tail_calls() {
+        let program = r#"
+            [Object subclass name: #Counter fields: []];
+            [Counter def: #count do: |to: acc:| {
+                return [to == 0 caseOf: [[true, acc]] otherwise: || {
+                    return [self count to: to - 1 acc: acc + 1];
+                }];
+            }];
+            [Counter def: #isEven do: |n:| {
+                return [n == 0 caseOf: [[true, true]] otherwise: || { return [self isOdd n: n - 1]; }];
+            }];
+            [Counter def: #isOdd do: |n:| {
+                return [n == 0 caseOf: [[true, false]] otherwise: || { return [self isEven n: n - 1]; }];
+            }];
+            [Counter def: #twice do: |block:| { return [block call]; }];
+            [Console println: [[Counter new] count to: 5000 acc: 0]];
+            [Console println: [[Counter new] isEven n: 5001]];
+            [Console println: [[Counter new] twice block: || { return [[Counter new] isOdd n: 3]; }]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+        let stdin = Rc::new(RefCell::new(io::empty()));
+        let stdout = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter =
+            Interpreter::new(classes, Rc::clone(&stdout) as Rc<RefCell<dyn Write>>, stdin)
+                .with_max_depth(10);
+
+        visit_ast(&mut interpreter, &ast).unwrap();
+        let output = String::from_utf8(stdout.borrow().clone()).unwrap();
+        assert_eq!("5000\nfalse\ntrue\n", output);
+    }
+
+    #[test]
+    fn identical_to_is_only_redefined_for_built_in_types() {
+        let program = r#"
+            [Object subclass name: #Thing fields: []];
+            [Thing def: #identicalTo do: |identicalTo:| { return true; }];
+            [List def: #identicalTo do: |identicalTo:| { return "list"; }];
+            [Thing def: #same do: |other:| { return [self identicalTo: other]; }];
+            [Thing def: #list do: || { return [[1] identicalTo: 2]; }];
+            let thing = [Thing new];
+            [Console println: [[thing identicalTo: [Thing new]], [thing same other: [Thing new]]]];
+            [Console println: [[[1] identicalTo: 2], [thing list]]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "[false, false]\n[\"list\", \"list\"]\n",
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn unreachable_statements() {
+        let program = r#"
+            [Object subclass name: #Early fields: []];
+            [Early def: #value do: || {
+                return 1;
+                [Console println: "after return"];
+                return 2;
+            }];
+            let block = || {
+                return [[Early new] value];
+                [Early def: #late do: || { return 3; }];
+            };
+            [Console println: [block call]];
+            [Console println: [[Early new] hoisted]];
+            return 4;
+            [Console println: "after top level return"];
+            [Early def: #hoisted do: || { return 5; }];
+        "#;
+        let tokens = lex(program).unwrap();
+        let mut ast = parse(&tokens).unwrap();
+        assert_eq!(ast.len(), 8);
+        prep::remove_unreachable(&mut ast);
+        assert_eq!(ast.len(), 7);
+
+        assert_eq!("1\n5\n", output(&ast).unwrap());
+    }
+
+    #[test]
+    fn sorting_lists() {
+        let program = r#"
+            [Console println: [[3, 1, 2] sort]];
+            [Console println: [["b", "c", "a"] sort]];
+            [Console println: [[] sort]];
+            [Console println: [["bb", "a", "ccc"] sortBy: |a: b:| { return [[b length] compare: [a length]]; }]];
+            [Console println: [[2, 3, 1] sortBy: #compare]];
+            [Console println: [[1 compare: 2], [2 compare: 2], ["b" compare: "a"]]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            [
+                "[1, 2, 3]",
+                "[\"a\", \"b\", \"c\"]",
+                "[]",
+                "[\"ccc\", \"bb\", \"a\"]",
+                "[1, 2, 3]",
+                "[-1, 0, 1]",
+                "",
+            ]
+            .join("\n"),
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn changing_lists() {
+        let program = r#"
+            let xs = [1, 2, 3];
+            let ys = [xs push: 4];
+            let zs = [[[xs reverse] at: 0 put: 9] concat: ys];
+            let block = || { return [xs at: 0 - 1 put: 0]; };
+            [Console println: [xs, ys, zs, [block call], xs]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "[[1, 2, 3], [1, 2, 3, 4], [9, 2, 1, 1, 2, 3, 4], [1, 2, 0], [1, 2, 3]]\n",
+            output(&ast).unwrap()
+        );
+
+        let tokens = lex("[[1, 2] at: 2 put: 3];").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(run(&ast), Error::IndexOutOfBounds { index: 2, .. });
+
+        let tokens = lex("[[1, 2] concat: 3];").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(
+            run(&ast),
+            Error::UnexpectedType {
+                expected: "List",
+                ..
+            }
+        );
+    }
+
+    #[test]
+    fn changing_lists_in_locals() {
+        fn allocations(pushes: usize) -> String {
+            let program = (0..pushes).fold("let xs = [];".to_string(), |program, n| {
+                program + &format!("let xs = [xs push: {}];", n)
+            });
+            let tokens = lex(&program).unwrap();
+            let ast = parse(&tokens).unwrap();
+            let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+            let stdin = Rc::new(RefCell::new(io::empty()));
+            let stdout = Rc::new(RefCell::new(io::sink()));
+            let report = Rc::new(RefCell::new(Report::default()));
+            let mut interpreter =
+                Interpreter::new(classes, stdout, stdin).with_report(Rc::clone(&report));
+            visit_ast(&mut interpreter, &ast).unwrap();
+            let report = report.borrow().to_string();
+            report.lines().nth(1).unwrap().to_string()
+        }
+
+        // Only the list literal is allocated, every push changes it in place
+        assert_eq!("Objects allocated: 1", allocations(10));
+        assert_eq!(allocations(10), allocations(1000));
+
+        let program = r#"
+            let xs = [1, 2];
+            let ys = xs;
+            let xs = [xs push: 3];
+            let xs = [xs concat: xs];
+            [Console println: [xs, ys]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!("[[1, 2, 3, 1, 2, 3], [1, 2]]\n", output(&ast).unwrap());
+
+        // Like in the REPL, the local keeps its list when changing it fails
+        let tokens = lex("let xs = [1, 2]; let xs = [xs at: 5 put: 0];").unwrap();
+        let ast = parse(&tokens).unwrap();
+        let stdout = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new(
+            find_classes_and_methods(&ast, prep::built_in_classes()).unwrap(),
+            stdout,
+            Rc::new(RefCell::new(io::empty())),
+        );
+        assert_error!(
+            visit_ast(&mut interpreter, &ast),
+            Error::IndexOutOfBounds { index: 5, .. }
+        );
+        assert_eq!(
+            "[1, 2]",
+            interpreter.locals.lookup("xs").unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn sorting_incomparable_values() {
+        let program = r#"
+            let sorted = [[1, "a"] sort];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(
+            run(&ast),
+            Error::IncomparableValues {
+                lhs: "Number",
+                rhs: "String",
+                ..
+            }
+        );
+
+        let program = r#"
+            let sorted = [[1, 2] sortBy: |a: b:| { return true; }];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(run(&ast), Error::UnexpectedType { .. });
+    }
+
+    #[test]
+    fn report() {
+        let program = r#"
+            [Object subclass name: #Point fields: [#x]];
+            [Point def: #x do: || { return @x; }];
+            let p = [Point new x: 1];
+            let xs = [p x];
+            let ys = [[p x], [p x]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+        let stdin = Rc::new(RefCell::new(io::empty()));
+        let stdout = Rc::new(RefCell::new(io::sink()));
+        let report = Rc::new(RefCell::new(Report::default()));
+        let mut interpreter =
+            Interpreter::new(classes, stdout, stdin).with_report(Rc::clone(&report));
+        visit_ast(&mut interpreter, &ast).unwrap();
+
+        assert_eq!(
+            [
+                "Statements executed: 8",
+                "Objects allocated: 2",
+                "Peak locals in one scope: 3",
+                "Messages sent per class:",
+                "         3  Point",
+                "Hottest methods:",
+                "         3  Point#x",
+                "",
+            ]
+            .join("\n"),
+            report.borrow().to_string()
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let program = r#"
+            let range = [1 to: 3];
+            [range each: |n:| { [Console print: n]; }];
+            [Console println: ""];
+            [Console println: [range map: |n:| { return [n equals: 2]; }]];
+            [Console println: [range toList]];
+            [Console println: [[range contains: 3], [range contains: 4], [range contains: "a"]]];
+            [Console println: [[3 to: 1] toList]];
+            [Console println: [range equals: [1 to: 3]]];
+            [Console println: range];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            [
+                "123",
+                "[false, true, false]",
+                "[1, 2, 3]",
+                "[true, false, false]",
+                "[]",
+                "true",
+                "[1 to: 3]",
+                "",
+            ]
+            .join("\n"),
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn math() {
+        let program = r#"
+            [Console println: [Math sqrt: 4]];
+            [Console println: [Math sqrt: 2]];
+            [Console println: [Math pow: 2 exp: 10]];
+            [Console println: [Math pow: 2 exp: 40]];
+            [Console println: [Math abs: [Math floor: [Math sqrt: 2]]]];
+            [Console println: [[Math min: 3 and: 2], [Math max: 3 and: 2]]];
+            [Console println: [[Math floor: [Math sqrt: 8]], [Math ceil: [Math sqrt: 8]]]];
+            [Console println: [[Math sqrt: 2] compare: 1]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            [
+                "2.0",
+                "1.4142135623730951",
+                "1024",
+                "1099511627776.0",
+                "1",
+                "[2, 3]",
+                "[2, 3]",
+                "1",
+                "",
+            ]
+            .join("\n"),
+            output(&ast).unwrap()
+        );
+
+        let program = r#"
+            let x = [Math sqrt: "4"];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(
+            run(&ast),
+            Error::UnexpectedType {
+                expected: "Number",
+                given: "String",
+                ..
+            }
+        );
+    }
+
+    #[test]
+    fn random() {
+        let program = r#"
+            [Random seed: 42];
+            let first = [[1 to: 5] map: |n:| { return [Random between: 1 and: 6]; }];
+            [Random seed: 42];
+            let second = [[1 to: 5] map: |n:| { return [Random between: 6 and: 1]; }];
+            let inRange = [first all: |n:| { return [[1 to: 6] contains: n]; }];
+            let float = [Random next];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let locals = run(&ast).unwrap();
+
+        assert_eq!(
+            numbers(locals.lookup("first")),
+            numbers(locals.lookup("second"))
+        );
+        assert!(matches!(locals.lookup("inRange"), Some(Value::True)));
+        assert!(matches!(locals.lookup("float"), Some(Value::Float(f)) if (0.0..1.0).contains(f)));
+
+        let program = "let n = [Random between: 1 and: 100];";
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+        let run_with_seed = |seed| {
+            let stdin = Rc::new(RefCell::new(io::empty()));
+            let stdout = Rc::new(RefCell::new(io::sink()));
+            let mut interpreter = Interpreter::new(classes.clone(), stdout, stdin).with_seed(seed);
+            visit_ast(&mut interpreter, &ast).unwrap();
+            match interpreter.locals.lookup("n") {
+                Some(Value::Number(n)) => *n,
+                other => panic!("Expected a number but got {:?}", other),
+            }
+        };
+        assert_eq!(run_with_seed(7), run_with_seed(7));
+    }
+
+    #[test]
+    fn uuids_and_hashes() {
+        let program = r#"
+            [Random seed: 1];
+            let first = [Uuid new];
+            [Random seed: 1];
+            let second = [Uuid new];
+            let sha = [Hash sha256: "abc"];
+            let crc = [Hash crc32: [Bytes fromString: "123456789"]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let locals = run(&ast).unwrap();
+
+        let uuid = locals.lookup("first").unwrap().to_string();
+        let uuid_shape = regex::Regex::new(
+            "^[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}$",
+        );
+        assert!(uuid_shape.unwrap().is_match(&uuid), "{}", uuid);
+        assert_eq!(uuid, locals.lookup("second").unwrap().to_string());
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            locals.lookup("sha").unwrap().to_string()
+        );
+        assert_eq!("cbf43926", locals.lookup("crc").unwrap().to_string());
+    }
+
+    #[test]
+    fn encoding() {
+        let program = r#"
+            let encoded = [Encode base64: "hi there"];
+            let bytes = [Encode base64: [Bytes fromHex: "ff00"]];
+            let decoded = [[Encode fromBase64: encoded] toString];
+            let invalid = [Encode fromBase64: "!!"];
+            let url = [Encode urlEncode: "a b&c/é"];
+            let back = [Encode urlDecode: url];
+            [Console println: [encoded, bytes, decoded, invalid, url, back]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "[\"aGkgdGhlcmU=\", \"/wA=\", \"hi there\", nil, \"a%20b%26c%2F%C3%A9\", \"a b&c/é\"]\n",
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn binary_operators() {
+        let program = r#"
+            let precedence = 1 + 2 * 3;
+            let left = 10 - 4 - 3;
+            let halves = [7 / 2, 6 / 3];
+            let comparisons = [1 < 2, 2 > 3, 2 + 1 == 3, "a" != "b"];
+            let grouped = (1 + 2) * (10 - (4 - 3));
+            [Console println: [precedence, left, halves, comparisons, grouped]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "[7, 3, [3.5, 2], [true, false, true, true], 27]\n",
+            output(&ast).unwrap()
+        );
+
+        let program = "let n = 1 / 0;";
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(run(&ast), Error::DivisionByZero(_));
+    }
+
+    #[test]
+    fn checked_and_big_integer_arithmetic() {
+        let program = r#"
+            let big = [BigInt from: 2147483647] + 1;
+            let product = [BigInt from: "123456789012345678901234567890"] * 10;
+            let rescued = [Exception try: || { return 2147483647 + 1; } rescue: #kind];
+            let mixed = [[BigInt from: 7] / 2, 1 + [BigInt from: 1] == 2, big > 2147483647];
+            [Console println: [big, product, rescued, mixed, [BigInt from: "nope"]]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "[2147483648, 1234567890123456789012345678900, Overflow, [3.5, true, true], nil]\n",
+            output(&ast).unwrap()
+        );
+
+        let program = "let n = 0 - 2147483647 - 2;";
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(run(&ast), Error::Overflow(_));
+    }
+
+    #[test]
+    fn constants() {
+        let program = r#"
+            let MAX = 10;
+            [Object subclass name: #Limiter fields: []];
+            [Limiter def: #clamp do: |value:| { return [Math min: value and: MAX]; }];
+            [Console println: [[[Limiter new] clamp value: 42], MAX * 2]];
+            [Console println: [Exception try: || { let MAX = 11; } rescue: #code]];
+            [Console println: [Exception try: || { let Limiter = 1; } rescue: #code]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!("[10, 20]\nE0033\nE0033\n", output(&ast).unwrap());
+    }
+
+    #[test]
+    fn modules() {
+        let program = r#"
+            [Object subclass name: #Client fields: []];
+            [Client def: #name do: || { return "global"; }];
+            module Http {
+                [Object subclass name: #Client fields: []];
+                [Client def: #name do: || { return "http"; }];
+                [Client def: #describe do: || { return [[Client new] name]; }];
+                [Console println: [[Client new] name]];
+            }
+            [Object subclass name: #Proxy fields: []];
+            [Proxy def: #kind do: || { return "proxy"; }];
+            module Http::Secure {
+                [Proxy subclass name: #Client fields: []];
+                [Client def: #name do: || { return "secure"; }];
+            }
+            module Admin {
+                module Api {
+                    [Object subclass name: #Client fields: []];
+                    [Client def: #name do: || { return "admin"; }];
+                }
+            }
+            [Console println: [[Client new] name]];
+            [Console println: [[Http::Client new] describe]];
+            [Console println: [[Http::Secure::Client new] name]];
+            [Console println: [[Http::Secure::Client new] kind]];
+            [Console println: [[Admin::Api::Client new] name]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "http\nglobal\nhttp\nsecure\nproxy\nadmin\n",
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn nil_checks() {
+        let program = r#"
+            let missing = [Parse int: "nope"];
+            let checks = [[missing isNil], [missing notNil], [1 isNil], [1 notNil]];
+            let describe = |value:| {
+                return [value ifNil: || { return "nothing"; } ifNotNil: |n:| { return n + 1; }];
+            };
+            [Console println: [checks, [describe call value: missing], [describe call value: 41]]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "[[true, false, false, true], \"nothing\", 42]\n",
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn parsing_numbers_and_dates() {
+        let program = r#"
+            let ints = [[Parse int: "-42"], [Parse int: " 42"], [Parse int: "1,000"]];
+            let floats = [[Parse float: "2.5e3"], [Parse float: "1."], [Parse float: "inf"]];
+            let date = [Parse isoDate: "2024-02-29"];
+            let invalid = [[Parse isoDate: "2023-02-29"], [Parse isoDate: "2024-2-1"]];
+            [Console println: [ints, floats, [date at: "month"], invalid]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "[[-42, nil, nil], [2500.0, nil, nil], 2, [nil, nil]]\n",
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn case_of() {
+        let program = r#"
+            let describe = |n:| {
+                return [n caseOf: [
+                    [1, "one"],
+                    [1 + 1, || { return "two"; }],
+                    [undefinedLocal, "unreachable"]
+                ] otherwise: "many"];
+            };
+            [2 caseOf: [[2, || { [Console println: "statement"]; }]]];
+            let unmatched = ["c" caseOf: [["a", 1]]];
+            let quoted = [Runtime run: quote { return [3 caseOf: [[3, "three"]]]; }];
+            [Console println: [[describe call n: 1], [describe call n: 2], unmatched, quoted]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "statement\n[\"one\", \"two\", nil, \"three\"]\n",
+            output(&ast).unwrap()
+        );
+
+        let program = "let n = [3 caseOf: [[1, 1], [undefinedLocal, 2]] otherwise: 0];";
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(run(&ast), Error::UndefinedLocal { .. });
+    }
+
+    #[test]
+    fn sending_messages_from_rust() {
+        let program = r#"
+            [Object subclass name: #Counter fields: [#count]];
+            [Counter def: #add do: |add:| { let @count = [Math max: @count and: add]; }];
+            [Counter def: #count do: || { return @count; }];
+            let counter = [Counter new count: 1];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let mut interpreter = interpreter(&ast, Rc::new(RefCell::new(Vec::new())), "").unwrap();
+
+        let counter = interpreter.local("counter").unwrap();
+        interpreter
+            .send(counter.to_owned(), "add", &[("add", Value::Number(5))])
+            .unwrap();
+        let count = interpreter.send(counter.to_owned(), "count", &[]).unwrap();
+        assert!(matches!(count, Value::Number(5)));
+
+        assert_error!(
+            interpreter.send(counter, "add", &[]),
+            Error::MissingArgument { name: "add", .. }
+        );
+    }
+
+    #[test]
+    fn host_objects() {
+        #[derive(Debug)]
+        struct Temperature(i32);
+
+        impl OopsObject for Temperature {
+            fn class_name(&self) -> &'static str {
+                "Temperature"
+            }
+
+            fn responds_to(&self, selector: &str) -> bool {
+                matches!(selector, "celsius" | "warmer" | "fail")
+            }
+
+            fn send<'a>(
+                &self,
+                selector: &str,
+                args: Vec<(&'a str, Value<'a>)>,
+            ) -> std::result::Result<Value<'a>, String> {
+                match (selector, args.as_slice()) {
+                    ("celsius", []) => Ok(Value::Number(self.0)),
+                    ("warmer", [(_, Value::Number(by))]) => {
+                        Ok(Value::Object(Rc::new(Temperature(self.0 + by))))
+                    }
+                    _ => Err(format!("can't {}", selector)),
+                }
+            }
+
+            fn inspect(&self) -> String {
+                format!("{}°C", self.0)
+            }
+        }
+
+        let program = r#"
+            let describe = |thing:| {
+                return [
+                    [thing celsius],
+                    [thing warmer: 2],
+                    [thing equals: thing],
+                    [Exception try: || { [thing fail]; } rescue: #message],
+                    [Exception try: || { [thing freeze]; } rescue: #kind]
+                ];
+            };
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let mut interpreter = interpreter(&ast, Rc::new(RefCell::new(Vec::new())), "").unwrap();
+
+        let describe = interpreter.local("describe").unwrap();
+        let temperature = Value::Object(Rc::new(Temperature(21)));
+        let description = interpreter
+            .send(describe, "call", &[("thing", temperature)])
+            .unwrap();
+        assert_eq!(
+            "[21, 23°C, true, \"can't fail\", UndefinedMethod]",
+            description.to_string()
+        );
+    }
+
+    #[test]
+    fn host_object_failures() {
+        #[derive(Debug)]
+        struct Faulty;
+
+        impl OopsObject for Faulty {
+            fn class_name(&self) -> &'static str {
+                "Faulty"
+            }
+
+            fn responds_to(&self, selector: &str) -> bool {
+                match selector {
+                    "crash" | "stall" => true,
+                    "puzzle" => panic!("no idea"),
+                    _ => false,
+                }
+            }
+
+            fn send<'a>(
+                &self,
+                selector: &str,
+                _: Vec<(&'a str, Value<'a>)>,
+            ) -> std::result::Result<Value<'a>, String> {
+                if selector == "crash" {
+                    panic!("out of cheese");
+                }
+                std::thread::sleep(Duration::from_millis(20));
+                Ok(Value::Nil)
+            }
+
+            fn inspect(&self) -> String {
+                panic!("shy")
+            }
+
+            fn equals(&self, _: &dyn OopsObject) -> bool {
+                panic!("incomparable")
+            }
+        }
+
+        let program = r#"
+            let crash = |faulty:| { return [faulty crash]; };
+            let stall = |faulty:| { return [faulty stall]; };
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let mut interpreter = interpreter(&ast, Rc::new(RefCell::new(Vec::new())), "")
+            .unwrap()
+            .with_host_time_limit(Duration::from_millis(1));
+
+        let crash = interpreter.local("crash").unwrap();
+        let faulty = Value::Object(Rc::new(Faulty));
+        match interpreter.send(crash, "call", &[("faulty", faulty.to_owned())]) {
+            Err(Error::HostObjectFailed {
+                class: "Faulty",
+                method: "crash",
+                reason,
+                ..
+            }) => assert_eq!("panicked: out of cheese", reason),
+            other => panic!("expected HostObjectFailed, got {:?}", other.map(|_| ())),
+        }
+
+        let stall = interpreter.local("stall").unwrap();
+        assert_error!(
+            interpreter.send(stall, "call", &[("faulty", faulty.to_owned())]),
+            Error::HostObjectFailed {
+                method: "stall",
+                ..
+            }
+        );
+
+        assert_error!(
+            interpreter.send(faulty.to_owned(), "puzzle", &[]),
+            Error::HostObjectFailed {
+                method: "respondsTo",
+                ..
+            }
+        );
+        let other = Value::Object(Rc::new(Faulty));
+        assert_error!(
+            interpreter.send(faulty.to_owned(), "equals", &[("equals", other)]),
+            Error::HostObjectFailed {
+                method: "equals",
+                ..
+            }
+        );
+        assert_eq!("<Faulty panicked: shy>", faulty.to_string());
+    }
+
+    #[test]
+    fn prelude() {
+        let program = format!(
+            "{}\n{}",
+            crate::PRELUDE,
+            r#"
+            [Console println: [[[1, 2, 3] sum], [[] sum], [[] isEmpty], [[1, 2] includes: 2]]];
+            [Console println: [[4 isEven], [3 isOdd], [3 max: 7], [3 min: 7]]];
+            [Console println: [[true not], [true and: false], [false and: || { return 1 / 0; }]]];
+            [Console println: [[false or: true], [true or: || { return 1 / 0; }]]];
+            "#
+        );
+        let tokens = lex(&program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "[6, 0, true, true]\n[true, true, 7, 3]\n[false, false, false]\n[true, true]\n",
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn float_formatting() {
+        let program = r#"
+            let floats = [
+                [Parse float: "0.1"] + [Parse float: "0.2"],
+                [Parse float: "1e15"],
+                [Parse float: "1e16"],
+                [Parse float: "0.0000025"],
+                [Parse float: "2"]
+            ];
+            [Console println: floats];
+            [Console println: [Json generate: floats]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            [
+                "[0.30000000000000004, 1000000000000000.0, 1e16, 2.5e-6, 2.0]",
+                "[0.30000000000000004,1000000000000000.0,1e16,2.5e-6,2.0]",
+                "",
+            ]
+            .join("\n"),
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn json() {
+        let program = r#"
+            let data = [Json parse: "{\"name\": \"Alice\", \"tags\": [1, 2.5, null, true]}"];
+            [Console println: data];
+            [Console println: [data at: "name"]];
+            [Console println: [data at: "missing"]];
+            [Console println: [data keys]];
+            [Console println: [Json generate: data]];
+            [Console println: [data equals: [Json parse: [Json generate: data]]]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            [
+                r#"{"name": "Alice", "tags": [1, 2.5, nil, true]}"#,
+                "Alice",
+                "nil",
+                r#"["name", "tags"]"#,
+                r#"{"name":"Alice","tags":[1,2.5,null,true]}"#,
+                "true",
+                "",
+            ]
+            .join("\n"),
+            output(&ast).unwrap()
+        );
+
+        let program = r#"let data = [Json parse: "{"];"#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(run(&ast), Error::InvalidSource { .. });
+
+        let program = r#"let json = [Json generate: [1 to: 2]];"#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(
+            run(&ast),
+            Error::UnexpectedType {
+                expected: "JSON value",
+                given: "Range",
+                ..
+            }
+        );
+    }
+
+    #[test]
+    fn regex() {
+        let program = r#"
+            let date = [Regex compile: "(\\d+)-(\\d+)(-x)?"];
+            [Console println: date];
+            [Console println: [[date matches: "on 2020-01"], [date matches: "never"]]];
+            [Console println: [date find: "on 2020-01 and 2021-02"]];
+            [Console println: [date find: "never"]];
+            [Console println: [date replace: "2020-01 and 2021-02" with: "$2/$1"]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            [
+                r#"<Regex "(\\d+)-(\\d+)(-x)?">"#,
+                "[true, false]",
+                r#"["2020-01", "2020", "01", nil]"#,
+                "nil",
+                "01/2020 and 02/2021",
+                "",
+            ]
+            .join("\n"),
+            output(&ast).unwrap()
+        );
+
+        let program = r#"let regex = [Regex compile: "("];"#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(run(&ast), Error::InvalidSource { .. });
+    }
+
+    #[test]
+    fn cancellation() {
+        let program = r#"
+            let xs = [[1 to: 3] map: |n:| { return n; }];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+        let stdin = Rc::new(RefCell::new(io::empty()));
+        let stdout = Rc::new(RefCell::new(io::sink()));
+        let token = CancellationToken::new();
+        let mut interpreter =
+            Interpreter::new(classes, stdout, stdin).with_cancellation(token.clone());
+
+        visit_ast(&mut interpreter, &ast).unwrap();
+        token.cancel();
+        assert_error!(visit_ast(&mut interpreter, &ast), Error::Cancelled { .. });
+    }
+
+    #[test]
+    fn ensure_runs_after_cancellation() {
+        /// Cancels the program as soon as it prints something.
+        struct CancelOnWrite(Vec<u8>, CancellationToken);
+
+        impl Write for CancelOnWrite {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.1.cancel();
+                self.0.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let program = r#"
+            [Object subclass name: #Worker fields: []];
+            [Worker def: #work do: || {
+                [Console println: "working"];
+                [Console println: "unreachable"];
+            }];
+            [Exception try: || {
+                let worker = [Worker new];
+                [worker work];
+            } ensure: || {
+                [Console println: "cleaned up"];
+            }];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+        let stdin = Rc::new(RefCell::new(io::empty()));
+        let token = CancellationToken::new();
+        let stdout = Rc::new(RefCell::new(CancelOnWrite(vec![], token.clone())));
+        let mut interpreter =
+            Interpreter::new(classes, Rc::clone(&stdout) as Rc<RefCell<dyn Write>>, stdin)
+                .with_cancellation(token);
+
+        assert_error!(
+            visit_ast(&mut interpreter, &ast),
+            Error::Cancelled {
+                method: Some(("Worker", "work")),
+                ..
+            }
+        );
+        assert_eq!(
+            "working\ncleaned up\n",
+            String::from_utf8(stdout.borrow().0.clone()).unwrap()
+        );
+    }
+
+    #[test]
+    fn system() {
+        let program = r#"
+            let args = [System args];
+            let home = [System env: "HOME"];
+            let missing = [System env: "MISSING"];
+            [System exit: 2];
+            let unreachable = 1;
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+        let stdin = Rc::new(RefCell::new(io::empty()));
+        let stdout = Rc::new(RefCell::new(io::sink()));
+        let env = vec![("HOME".to_string(), "/home/alice".to_string())];
+        let mut interpreter = Interpreter::new(classes, stdout, stdin)
+            .with_args(vec!["a".to_string()])
+            .with_env(env.into_iter().collect());
+
+        assert_error!(
+            visit_ast(&mut interpreter, &ast),
+            Error::Exit { status: 2, .. }
+        );
+        assert_eq!(
+            r#"["a"]"#,
+            interpreter.locals.lookup("args").unwrap().to_string()
+        );
+        assert_eq!(
+            "/home/alice",
+            interpreter.locals.lookup("home").unwrap().to_string()
+        );
+        assert!(matches!(
+            interpreter.locals.lookup("missing"),
+            Some(Value::Nil)
+        ));
+        assert!(interpreter.locals.lookup("unreachable").is_none());
+    }
+
+    #[test]
+    fn terminal_control() {
+        let program = r#"
+            [Term clear];
+            [Term moveToRow: 2 column: 3];
+            [Console println: [Term color: #red text: "error"]];
+            [Console println: [Term bold: 1]];
+            [Console println: [[Term width], [Term isTerminal]]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!("error\n1\n[80, false]\n", output(&ast).unwrap());
+
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+        let stdin = Rc::new(RefCell::new(io::empty()));
+        let stdout = Rc::new(RefCell::new(Vec::new()));
+        let env = vec![("COLUMNS".to_string(), "120".to_string())];
+        let mut interpreter = Interpreter::new(classes, Rc::clone(&stdout) as _, stdin)
+            .with_env(env.into_iter().collect())
+            .with_terminal(true);
+        visit_ast(&mut interpreter, &ast).unwrap();
+
+        assert_eq!(
+            "\x1b[2J\x1b[H\x1b[2;3H\x1b[31merror\x1b[0m\n\x1b[1m1\x1b[0m\n[120, true]\n",
+            String::from_utf8(stdout.borrow().clone()).unwrap()
+        );
+    }
+
+    #[test]
+    fn raising_and_rescuing() {
+        let program = r#"
+            let rescued = [Exception try: || {
+                [Exception raise: "boom"];
+                [Console println: "unreachable"];
+            } rescue: |e:| { return [e message]; }];
+            let fine = [Exception try: || { return 1; } rescue: |e:| { return 2; }];
+            let undefined = [Exception try: || { return missing; } rescue: #code];
+            let reraised = [Exception try: || {
+                [Exception try: || { return missing; } rescue: |e:| { [Exception raise: e]; }];
+            } rescue: #code];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let locals = run(&ast).unwrap();
+
+        assert_eq!("boom", locals.lookup("rescued").unwrap().to_string());
+        assert!(matches!(locals.lookup("fine"), Some(Value::Number(1))));
+        assert_eq!("E0007", locals.lookup("undefined").unwrap().to_string());
+        assert_eq!("E0007", locals.lookup("reraised").unwrap().to_string());
+
+        let program = r#"
+            [Exception raise: "boom"];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(run(&ast), Error::Raised { code: "E0028", .. });
+    }
+
+    #[test]
+    fn rescuing_structured_errors() {
+        let program = r#"
+            [Object subclass name: #User fields: []];
+            let describe = |e:| {
+                return [[e kind], [e isA: UndefinedMethod], [e isA: Exception], [e selector], [e class]];
+            };
+            [Console println: [Exception try: || { [[User new] greet]; } rescue: describe]];
+            [Console println: [Exception try: || { [|a:| { return a; } call]; } rescue: describe]];
+            [Console println: [Exception try: || { return [1 divide: 0]; } rescue: describe]];
+            [Console println: [Exception try: || { [Exception raise: "boom"]; } rescue: describe]];
+            [Console println: [Exception try: || {
+                [Exception try: || { return [1 divide: 0]; } rescue: |e:| { [Exception raise: e]; }];
+            } rescue: #kind]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "[UndefinedMethod, true, true, #greet, \"User\"]\n\
+             [MissingArgument, false, true, #a, nil]\n\
+             [DivisionByZero, false, true, nil, nil]\n\
+             [Exception, false, true, nil, nil]\n\
+             DivisionByZero\n",
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn partial_application() {
+        let program = r#"
+            let pair = |a: b: c:| { return [a, b, c]; };
+            let withA = [pair withFirst: 1];
+            let withAB = [withA withFirst: 2];
+            [Console println: [withA, withAB]];
+            [Console println: [withAB call c: 3]];
+            [Console println: [[3 to: 4] map: withAB]];
+            [Console println: [pair call a: 7 b: 8 c: 9]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            [
+                "[<block |b: c:|>, <block |c:|>]",
+                "[1, 2, 3]",
+                "[[1, 2, 3], [1, 2, 4]]",
+                "[7, 8, 9]",
+                "",
+            ]
+            .join("\n"),
+            output(&ast).unwrap()
+        );
+
+        let program = r#"
+            let block = [|| { return 1; } withFirst: 1];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(
+            run(&ast),
+            Error::WrongNumberOfBlockParameters {
+                expected: 1,
+                given: 0,
+                ..
+            }
+        );
+    }
+
+    #[test]
+    fn ensure() {
+        let program = r#"
+            let value = [Exception try: || { return 1; } ensure: || { [Console println: "a"]; }];
+            let rescued = [Exception try: || {
+                [Exception raise: "boom"];
+            } rescue: #message ensure: || { [Console println: "b"]; }];
+            let raised = [Exception try: || {
+                [Exception try: || { [Exception raise: "boom"]; } ensure: || {
+                    [Console println: "c"];
+                }];
+            } rescue: #message];
+            let fromHandler = [Exception try: || {
+                [Exception try: || { [Exception raise: "boom"]; } rescue: |e:| {
+                    [Exception raise: "again"];
+                } ensure: || { [Console println: "d"]; }];
+            } rescue: #message];
+            [Console println: [value, rescued, raised, fromHandler]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "a\nb\nc\nd\n[1, \"boom\", \"boom\", \"again\"]\n",
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn assertions() {
+        let program = r#"
+            [Assert equal: [1, "a"] to: [1, "a"]];
+            [Assert true: [1 equals: 1]];
+            [Assert raises: "E0007" block: || { return missing; }];
+            let failures = [
+                [Exception try: || { [Assert equal: 1 to: 2]; } rescue: #message],
+                [Exception try: || { [Assert true: false]; } rescue: #message],
+                [Exception try: || { [Assert raises: "E0007" block: || {}]; } rescue: #message],
+                [Exception try: || {
+                    [Assert raises: "E0007" block: || { [Exception raise: "boom"]; }];
+                } rescue: #message]
+            ];
+            [failures each: |failure:| { [Console println: failure]; }];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let output = output(&ast).unwrap();
+        let messages = output.lines().collect::<Vec<_>>();
+
+        assert_eq!(4, messages.len());
+        assert!(messages[0].ends_with("expected 1 to equal 2"));
+        assert!(messages[1].ends_with("expected a truthy value but got false"));
+        assert!(messages[2].ends_with("expected an error with code E0007 but nothing was raised"));
+        assert!(messages[3].ends_with("expected an error with code E0007 but got E0028"));
+    }
+
+    #[test]
+    fn bounded_rendering_in_errors() {
+        let program = r#"
+            let numbers = [[1 to: 1000] toList];
+            let failure = [Exception try: || { [Assert equal: numbers to: [1, 2]]; } rescue: #message];
+            let undefined = [Exception try: || { [numbers shout]; } rescue: #message];
+            let short = [Exception try: || { [Assert equal: 1 to: 2]; } rescue: #message];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+        let stdout = Rc::new(RefCell::new(Vec::new()));
+        let stdin = Rc::new(RefCell::new(io::empty()));
+        let mut interpreter = Interpreter::new(classes, stdout, stdin).with_render_limit(9);
+        visit_ast(&mut interpreter, &ast).unwrap();
+
+        let message = |name| interpreter.local(name).unwrap().to_string();
+        assert!(message("failure").ends_with("expected [1, 2, 3,... (1000 items) to equal [1, 2]"));
+        assert!(message("undefined").contains("value `[1, 2, 3,... (1000 items)`"));
+        assert!(message("short").ends_with("expected 1 to equal 2"));
+    }
+
+    #[test]
+    fn memoized_blocks() {
+        let program = r#"
+            let slow = |x:| { [Console print: x]; return [x equals: 1]; };
+            let fast = [slow memoized];
+            let results = [[1, 2, 1, 2, [1, 2], [1, 2]] map: fast];
+            [Console println: ""];
+            [Console println: [fast call x: 1]];
+            [Console println: fast];
+
+            let small = [slow memoizedUpTo: 1];
+            [[1, 1, 2, 1] each: small];
+            [Console println: ""];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "12[1, 2]\ntrue\n<memoized block |x:|>\n121\n",
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn dynamic_variables() {
+        let program = r#"
+            let show = || { [Console println: [Dyn get: #indent]]; };
+            [show call];
+            [Dyn let: #indent be: 2 in: || {
+                [show call];
+                [Dyn let: #indent be: 4 in: show];
+                [show call];
+            }];
+            [Exception try: || {
+                [Dyn let: #indent be: 8 in: || { [Exception raise: "boom"]; }];
+            } rescue: |e:| {}];
+            [show call];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!("nil\n2\n4\n2\nnil\n", output(&ast).unwrap());
+    }
+
+    #[test]
+    fn abstract_methods() {
+        let program = r#"
+            [Object subclass name: #Shape fields: []];
+            [Shape defAbstract: #area];
+            [Shape def: #describe do: || { return [self area]; }];
+            [Shape subclass name: #Square fields: [#side]];
+            [Square def: #area do: || { return @side; }];
+            [Console println: [[Square new side: 4] describe]];
+            [Console println: [Exception try: || { let shape = [Shape new]; } rescue: #code]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!("4\nE0030\n", output(&ast).unwrap());
+    }
+
+    #[test]
+    fn string_builders() {
+        let program = r#"
+            let builder = [StringBuilder new];
+            [[builder append: "a, "] append: 1];
+            [[1 to: 3] each: |n:| { [builder append: n]; }];
+            [Console println: [builder toString]];
+            [Console println: builder];
+            let class = StringBuilder;
+            [Console println: [[[class new] append: "b"] toString]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "a, 1123\n<StringBuilder \"a, 1123\">\nb\n",
+            output(&ast).unwrap()
+        );
+
+        let program = r#"
+            let builder = [[StringBuilder new] append: "ab"];
+            [Console println: [[builder append: builder] toString]];
+            [[1 to: 10] each: |n:| { [builder append: builder]; }];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let stdout = Rc::new(RefCell::new(Vec::new()));
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+        let stdin = Rc::new(RefCell::new(io::empty()));
+        let mut interpreter =
+            Interpreter::new(classes, Rc::clone(&stdout) as _, stdin).with_max_length(100);
+
+        assert_error!(
+            visit_ast(&mut interpreter, &ast),
+            Error::LengthLimitExceeded { limit: 100, .. }
+        );
+        assert_eq!(
+            "ab<StringBuilder \"ab\">\n",
+            String::from_utf8(stdout.borrow().clone()).unwrap()
+        );
+    }
+
+    #[test]
+    fn bytes() {
+        let path = std::env::temp_dir().join(format!("oops-bytes-{}", std::process::id()));
+        let program = format!(
+            r#"
+            let bytes = [Bytes fromHex: "48690aff"];
+            [Console println: bytes];
+            [Console println: [bytes length]];
+            [Console println: [bytes at: 3]];
+            [Console println: [bytes at: 0 - 4]];
+            [Console println: [[bytes from: 0 to: 1] toString]];
+            [Console println: [[bytes from: 2 to: 0 - 1] toList]];
+            [Console println: [[bytes from: 3 to: 1] length]];
+            [Console println: [bytes toString]];
+            [Console println: [Bytes fromHex: "+f"]];
+            [File writeBytes: bytes to: {path:?}];
+            [Console println: [[File readBytes: {path:?}] equals: bytes]];
+            [Console println: [[Bytes fromString: "é"] toHex]];
+            "#,
+            path = path.display().to_string()
+        );
+        let tokens = lex(&program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let output = output(&ast).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            "<Bytes 48690aff>\n4\n255\n72\nHi\n[10, 255]\n0\nnil\nnil\ntrue\nc3a9\n",
+            output
+        );
+
+        let tokens = lex(r#"[[Bytes fromHex: "4869"] from: 0 to: 2];"#).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_error!(
+            run(&ast),
+            Error::IndexOutOfBounds {
+                index: 2,
+                length: 2,
+                ..
+            }
+        );
+    }
+
+    #[test]
+    fn csv() {
+        let path = std::env::temp_dir().join(format!("oops-csv-{}.csv", std::process::id()));
+        let program = format!(
+            r#"
+            let source = "name,age\nAlice,30\n\"Bob, Jr.\",\n";
+            [Console println: [Csv parse: source]];
+            let people = [Csv parse: source headers: true];
+            [Console println: people];
+            [Csv write: {path:?} rows: people];
+            [Console println: [Csv parseFile: {path:?}]];
+            [Csv write: {path:?} rows: [["a", 1, true], ["say \"hi\""]]];
+            [Console println: [Csv parseFile: {path:?}]];
+            "#,
+            path = path.display().to_string()
+        );
+        let tokens = lex(&program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let output = output(&ast).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            concat!(
+                "[[\"name\", \"age\"], [\"Alice\", \"30\"], [\"Bob, Jr.\", \"\"]]\n",
+                "[{\"name\": \"Alice\", \"age\": \"30\"}, {\"name\": \"Bob, Jr.\", \"age\": \"\"}]\n",
+                "[[\"name\", \"age\"], [\"Alice\", \"30\"], [\"Bob, Jr.\", \"\"]]\n",
+                "[[\"a\", \"1\", \"true\"], [\"say \\\"hi\\\"\"]]\n",
+            ),
+            output
+        );
+    }
+
+    #[test]
+    fn tables() {
+        let program = r#"
+            let rows = [["Alice", 30], ["Bob, Jr.", 5, "extra"], ["Eve"]];
+            [Table render: rows headers: ["name", "age"]];
+            let dicts = [Json parse: "[{\"id\": 1, \"name\": \"ünï\"}]"];
+            [Table render: dicts headers: ["name", "id", "missing"]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            concat!(
+                "+----------+-----+\n",
+                "| name     | age |\n",
+                "+----------+-----+\n",
+                "| Alice    | 30  |\n",
+                "| Bob, Jr. | 5   |\n",
+                "| Eve      |     |\n",
+                "+----------+-----+\n",
+                "+------+----+---------+\n",
+                "| name | id | missing |\n",
+                "+------+----+---------+\n",
+                "| ünï  | 1  |         |\n",
+                "+------+----+---------+\n",
+            ),
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn reopening_classes() {
+        let program = r#"
+            [User reopen fields: [#email]];
+            [Object subclass name: #User fields: [#name]];
+            [User def: #name do: || { return @name; }];
+            [User reopen fields: [#admin]];
+            [User def: #email do: || { return @email; }];
+            let user = [User new name: "Alice" email: "alice@example.com" admin: false];
+            [Console println: [user name]];
+            [Console println: [user email]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!("Alice\nalice@example.com\n", output(&ast).unwrap());
+    }
+
+    #[test]
+    fn methods_on_built_in_types() {
+        let program = r#"
+            [Number def: #sign do: || { return [self compare: 0]; }];
+            [String def: #shout do: || { return [[self toUpper] concat: "!"]; }];
+            [String def: #length do: || { return "overridden"; }];
+            [List def: #shoutAll do: || { return [self map: #shout]; }];
+            [Boolean def: #isTrue do: || { return [self equals: true]; }];
+            [Console println: [5 sign]];
+            [Console println: ["hi" shout]];
+            [Console println: ["hi" length]];
+            [Console println: [["a", "b"] shoutAll]];
+            [Console println: [false isTrue]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            "1\nHI!\noverridden\n[\"A!\", \"B!\"]\nfalse\n",
+            output(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn defining_methods_at_runtime() {
+        let program = r#"
+            [Object subclass name: #User fields: [#name]];
+            let user = [User new name: "Alice"];
+            let install = || {
+                [User def: #name do: || { return @name; }];
+            };
+            [Console println: [Exception try: || { return [user name]; } rescue: #code]];
+            [install call];
+            [install call];
+            [Console println: [user name]];
+            [Console println: [Exception try: || {
+                [User def: #name do: || { return "other"; }];
+            } rescue: #code]];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!("E0014\nAlice\nE0006\n", output(&ast).unwrap());
+    }
+
+    #[test]
+    fn flushing_output() {
+        let program = r#"
+            [Console print: "flushed"];
+            [IO flush];
+            [Console print: "buffered"];
+        "#;
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+        let stdin = Rc::new(RefCell::new(io::empty()));
+        let stdout = Rc::new(RefCell::new(io::BufWriter::new(Vec::new())));
+        let mut interpreter =
+            Interpreter::new(classes, Rc::clone(&stdout) as Rc<RefCell<dyn Write>>, stdin);
+
+        visit_ast(&mut interpreter, &ast).unwrap();
+        assert_eq!(b"flushed", stdout.borrow().get_ref().as_slice());
     }
 }
@@ -0,0 +1,138 @@
+//! Values implemented in Rust by programs embedding the interpreter.
+
+use super::Value;
+use crate::{
+    error::{Error, Result},
+    Span,
+};
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+
+/// A Rust type that responds to messages like an OOPS object.
+///
+/// Wrap it in [`Value::Object`] and pass it to the program, for example as an argument to
+/// [`Interpreter::send`](super::Interpreter::send). Messages it doesn't respond to fall back to
+/// the ones all values understand, like `equals:` and `identicalTo:`, and raise an undefined
+/// method error otherwise.
+pub trait OopsObject: fmt::Debug {
+    /// The class name shown in errors.
+    fn class_name(&self) -> &'static str;
+
+    /// Whether `send` handles `selector`.
+    fn responds_to(&self, selector: &str) -> bool;
+
+    /// Respond to `selector` with keyword arguments in the order they were given. An `Err` is
+    /// raised as an exception with the message, so programs can rescue it.
+    fn send<'a>(
+        &self,
+        selector: &str,
+        args: Vec<(&'a str, Value<'a>)>,
+    ) -> std::result::Result<Value<'a>, String>;
+
+    /// How the object is shown by `Console println:`.
+    fn inspect(&self) -> String {
+        format!("<{}>", self.class_name())
+    }
+
+    /// Whether the object equals another object that isn't the same one. Objects are only equal
+    /// to themselves by default.
+    fn equals(&self, _other: &dyn OopsObject) -> bool {
+        false
+    }
+}
+
+// Every call into a host object goes through the functions below, which turn panics into errors
+// at the call site so a buggy object can't take down the interpreter. Where there's no error to
+// return, like when rendering a value, a placeholder is used instead.
+
+/// Send `selector` to a host object. A call taking longer than `time_limit` is an error too, but
+/// calls can't be interrupted, so one that never returns still hangs. The limit only reports
+/// overruns once the call has returned.
+pub(super) fn send<'a>(
+    object: &dyn OopsObject,
+    selector: &'a str,
+    args: Vec<(&'a str, Value<'a>)>,
+    time_limit: Option<Duration>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    let started = Instant::now();
+    let result = guard(object, selector, call_site, || object.send(selector, args))?;
+
+    let elapsed = started.elapsed();
+    match time_limit {
+        Some(limit) if elapsed > limit => Err(Error::HostObjectFailed {
+            class: class_name(object),
+            method: selector,
+            reason: format!(
+                "took {}ms, longer than the limit of {}ms",
+                elapsed.as_millis(),
+                limit.as_millis()
+            ),
+            span: call_site,
+        }),
+        _ => result.map_err(|message| Error::Raised {
+            message,
+            code: "E0028",
+            span: call_site,
+        }),
+    }
+}
+
+/// Whether a host object responds to `selector`.
+pub(super) fn responds_to<'a>(
+    object: &dyn OopsObject,
+    selector: &'a str,
+    call_site: Span,
+) -> Result<'a, bool> {
+    guard(object, "respondsTo", call_site, || {
+        object.responds_to(selector)
+    })
+}
+
+/// Whether a host object equals another one.
+pub(super) fn equals<'a>(
+    object: &dyn OopsObject,
+    other: &dyn OopsObject,
+    call_site: Span,
+) -> Result<'a, bool> {
+    guard(object, "equals", call_site, || object.equals(other))
+}
+
+/// The class name of a host object, or `Object` if getting it panics.
+pub(super) fn class_name(object: &dyn OopsObject) -> &'static str {
+    caught(|| object.class_name()).unwrap_or("Object")
+}
+
+/// How a host object is shown, or a placeholder if showing it panics.
+pub(super) fn inspect(object: &dyn OopsObject) -> String {
+    caught(|| object.inspect())
+        .unwrap_or_else(|reason| format!("<{} panicked: {}>", class_name(object), reason))
+}
+
+fn guard<'a, T>(
+    object: &dyn OopsObject,
+    method: &'a str,
+    call_site: Span,
+    call: impl FnOnce() -> T,
+) -> Result<'a, T> {
+    caught(call).map_err(|reason| Error::HostObjectFailed {
+        class: class_name(object),
+        method,
+        reason: format!("panicked: {}", reason),
+        span: call_site,
+    })
+}
+
+/// Run `call`, with the message it panicked with if it does.
+fn caught<T>(call: impl FnOnce() -> T) -> std::result::Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(call)).map_err(|payload| {
+        payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Box<dyn Any>".to_string())
+    })
+}
@@ -0,0 +1,77 @@
+//! The messages understood by the `Parse` class.
+//!
+//! Parsing is strict and doesn't depend on the locale: no surrounding whitespace, no thousands
+//! separators, and `.` as the decimal point. Input that doesn't parse returns `nil`.
+
+use super::{string_argument, ArgumentValue, Interpreter, Value};
+use crate::{error::Result, Span};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::rc::Rc;
+
+lazy_static! {
+    static ref INT: Regex = Regex::new(r"\A[+-]?[0-9]+\z").unwrap();
+    static ref FLOAT: Regex = Regex::new(r"\A[+-]?[0-9]+(\.[0-9]+)?([eE][+-]?[0-9]+)?\z").unwrap();
+    static ref ISO_DATE: Regex = Regex::new(r"\A([0-9]{4})-([0-9]{2})-([0-9]{2})\z").unwrap();
+}
+
+pub(super) fn send<'a>(
+    interpreter: &Interpreter<'a>,
+    msg: &'a str,
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    let parse: fn(&str) -> Option<Value<'a>> = match msg {
+        "int" => int,
+        "float" => float,
+        "isoDate" => iso_date,
+        _ => return Err(interpreter.not_understood_by_class("Parse", msg, call_site)),
+    };
+    let string = string_argument(args, msg, call_site)?;
+    Ok(parse(&string).unwrap_or(Value::Nil))
+}
+
+fn int<'a>(string: &str) -> Option<Value<'a>> {
+    if !INT.is_match(string) {
+        return None;
+    }
+    string.parse().ok().map(Value::Number)
+}
+
+fn float<'a>(string: &str) -> Option<Value<'a>> {
+    if !FLOAT.is_match(string) {
+        return None;
+    }
+    string
+        .parse::<f64>()
+        .ok()
+        .filter(|float| float.is_finite())
+        .map(Value::Float)
+}
+
+/// A `YYYY-MM-DD` date as a dict with `year`, `month`, and `day`.
+fn iso_date<'a>(string: &str) -> Option<Value<'a>> {
+    let captures = ISO_DATE.captures(string)?;
+    let part = |idx: usize| captures[idx].parse::<i32>().ok();
+    let (year, month, day) = (part(1)?, part(2)?, part(3)?);
+
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let entries = vec![
+        (Rc::from("year"), Value::Number(year)),
+        (Rc::from("month"), Value::Number(month)),
+        (Rc::from("day"), Value::Number(day)),
+    ];
+    Some(Value::Dict(Rc::new(entries)))
+}
+
+fn days_in_month(year: i32, month: i32) -> i32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
@@ -0,0 +1,47 @@
+//! The random number generator behind the `Random` class.
+//!
+//! This is SplitMix64. It's small, fast, and good enough for programs that want dice rolls and
+//! shuffles, but it must not be used for anything security related.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// An RNG seeded from the current time.
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or(0);
+        Self::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A float between 0, inclusive, and 1, exclusive.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A number between `a` and `b`, including both. They can be given in either order.
+    pub fn between(&mut self, a: i32, b: i32) -> i32 {
+        let (low, high) = (a.min(b), a.max(b));
+        let range = (i64::from(high) - i64::from(low)) as u64 + 1;
+        let offset = self.next_u64() % range;
+        (i64::from(low) + offset as i64) as i32
+    }
+}
@@ -0,0 +1,79 @@
+//! Statistics about a single run, printed by `oops --report`.
+//!
+//! Nothing is collected unless the interpreter is given a report with
+//! [`Interpreter::with_report`](super::Interpreter::with_report), and nothing leaves the process.
+
+use std::{collections::HashMap, fmt};
+
+/// How many of the hottest methods to show.
+const HOTTEST_METHODS: usize = 10;
+
+#[derive(Debug, Default)]
+pub struct Report<'a> {
+    statements: usize,
+    /// Messages sent, by the class of the receiver. Messages sent to classes, like
+    /// `[Console println: 1]`, are counted under the name of that class.
+    sends: HashMap<&'a str, usize>,
+    method_calls: HashMap<(&'a str, &'a str), usize>,
+    /// Instances, list literals, string literals, and copies. Lists and strings returned by
+    /// built-in messages aren't counted.
+    allocations: usize,
+    peak_locals: usize,
+}
+
+impl<'a> Report<'a> {
+    pub(super) fn statement(&mut self) {
+        self.statements += 1;
+    }
+
+    pub(super) fn send(&mut self, class: &'a str) {
+        *self.sends.entry(class).or_insert(0) += 1;
+    }
+
+    pub(super) fn method_call(&mut self, class: &'a str, method: &'a str) {
+        *self.method_calls.entry((class, method)).or_insert(0) += 1;
+    }
+
+    pub(super) fn allocation(&mut self) {
+        self.allocations += 1;
+    }
+
+    pub(super) fn locals(&mut self, count: usize) {
+        self.peak_locals = self.peak_locals.max(count);
+    }
+}
+
+/// Sort by count, most first, and then by name so the output is stable.
+fn by_count<K: Ord + Copy>(counts: &HashMap<K, usize>) -> Vec<(K, usize)> {
+    let mut counts = counts
+        .iter()
+        .map(|(key, count)| (*key, *count))
+        .collect::<Vec<_>>();
+    counts.sort_by(|(a_key, a_count), (b_key, b_count)| {
+        b_count.cmp(a_count).then_with(|| a_key.cmp(b_key))
+    });
+    counts
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Statements executed: {}", self.statements)?;
+        writeln!(f, "Objects allocated: {}", self.allocations)?;
+        writeln!(f, "Peak locals in one scope: {}", self.peak_locals)?;
+
+        writeln!(f, "Messages sent per class:")?;
+        for (class, count) in by_count(&self.sends) {
+            writeln!(f, "  {:>8}  {}", count, class)?;
+        }
+
+        writeln!(f, "Hottest methods:")?;
+        for ((class, method), count) in by_count(&self.method_calls)
+            .into_iter()
+            .take(HOTTEST_METHODS)
+        {
+            writeln!(f, "  {:>8}  {}#{}", count, class, method)?;
+        }
+
+        Ok(())
+    }
+}
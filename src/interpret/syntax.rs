@@ -0,0 +1,438 @@
+//! Converting tokens and syntax trees into values and back, for the `Lexer` and `Parser` classes
+//! and for quoting.
+//!
+//! Every token and node becomes a list whose first item is a selector naming its kind. Names are
+//! converted to strings so the values don't borrow from the source they were parsed from.
+
+use super::Value;
+use crate::{ast::*, error::Result, lex::Token};
+use std::rc::Rc;
+
+pub(super) fn tokens<'a>(tokens: &[Token<'_>], source: &str) -> Value<'a> {
+    let tokens = tokens
+        .iter()
+        .map(|token| {
+            let span = token.span();
+            list(vec![
+                Value::Selector(token.kind()),
+                string(&source[span.from..span.to]),
+                Value::Number(span.from as i32),
+                Value::Number(span.to as i32),
+            ])
+        })
+        .collect();
+    list(tokens)
+}
+
+/// Convert statements into values. `~name` unquotes are kept as `[#unquote, "name"]` nodes.
+pub(super) fn ast<'a>(ast: &[Stmt<'_>]) -> Value<'a> {
+    let to_value = ToValue {
+        splice: &|unquote| Ok(keep_unquote(unquote)),
+    };
+    to_value
+        .stmts(ast)
+        .expect("Internal error: keeping unquotes failed")
+}
+
+/// Convert statements into values, replacing each `~name` unquote with whatever `splice` returns.
+/// Unquotes in nested quotes are kept.
+pub(super) fn quote<'a, 'b>(
+    stmts: &'b [Stmt<'b>],
+    splice: &dyn Fn(&'b Unquote<'b>) -> Result<'a, Value<'a>>,
+) -> Result<'a, Value<'a>> {
+    ToValue { splice }.stmts(stmts)
+}
+
+fn keep_unquote<'a>(unquote: &Unquote<'_>) -> Value<'a> {
+    node("unquote", vec![ident(&unquote.ident)])
+}
+
+struct ToValue<'s, 'a, 'b> {
+    splice: &'s dyn Fn(&'b Unquote<'b>) -> Result<'a, Value<'a>>,
+}
+
+impl<'s, 'a, 'b> ToValue<'s, 'a, 'b> {
+    fn stmts(&self, stmts: &'b [Stmt<'b>]) -> Result<'a, Value<'a>> {
+        let stmts = stmts
+            .iter()
+            .map(|stmt| self.stmt(stmt))
+            .collect::<Result<'a, Vec<_>>>()?;
+        Ok(list(stmts))
+    }
+
+    fn stmt(&self, stmt: &'b Stmt<'b>) -> Result<'a, Value<'a>> {
+        let value = match stmt {
+            Stmt::LetLocal(inner) => node(
+                "letLocal",
+                vec![ident(&inner.ident), self.expr(&inner.body)?],
+            ),
+            Stmt::LetIVar(inner) => node(
+                "letIVar",
+                vec![ident(&inner.ident), self.expr(&inner.body)?],
+            ),
+            Stmt::LetConstant(inner) => node(
+                "letConstant",
+                vec![ident(&inner.name.0), self.expr(&inner.body)?],
+            ),
+            Stmt::Module(inner) => node(
+                "module",
+                vec![ident(&inner.name.0), self.stmts(&inner.body)?],
+            ),
+            Stmt::MessageSend(inner) => self.message_send(&inner.expr)?,
+            Stmt::CaseOf(inner) => self.case_of(&inner.expr)?,
+            Stmt::Return(inner) => node("return", vec![self.expr(&inner.expr)?]),
+            Stmt::DefineMethod(inner) => node(
+                "defineMethod",
+                vec![
+                    ident(&inner.class_name.0),
+                    ident(&inner.method_name.ident),
+                    self.block(&inner.block)?,
+                ],
+            ),
+            Stmt::DefineAbstractMethod(inner) => node(
+                "defineAbstractMethod",
+                vec![ident(&inner.class_name.0), ident(&inner.method_name.ident)],
+            ),
+            Stmt::DefineClass(inner) => node(
+                "defineClass",
+                vec![
+                    ident(&inner.name.class_name.0),
+                    list(
+                        inner
+                            .fields
+                            .iter()
+                            .map(|field| ident(&field.ident))
+                            .collect(),
+                    ),
+                    ident(&inner.super_class.class_name.0),
+                ],
+            ),
+            Stmt::ReopenClass(inner) => node(
+                "reopenClass",
+                vec![
+                    ident(&inner.class_name.0),
+                    list(
+                        inner
+                            .fields
+                            .iter()
+                            .map(|field| ident(&field.ident))
+                            .collect(),
+                    ),
+                ],
+            ),
+        };
+        Ok(value)
+    }
+
+    fn expr(&self, expr: &'b Expr<'b>) -> Result<'a, Value<'a>> {
+        let value = match expr {
+            Expr::Local(inner) => node("local", vec![ident(&inner.0)]),
+            Expr::IVar(inner) => node("ivar", vec![ident(&inner.ident)]),
+            Expr::MessageSend(inner) => self.message_send(inner)?,
+            Expr::ClassNew(inner) => node(
+                "new",
+                vec![ident(&inner.class_name.0), self.args(&inner.args)?],
+            ),
+            Expr::ClassName(inner) => node("className", vec![ident(&inner.0)]),
+            Expr::Block(inner) => self.block(inner)?,
+            Expr::Number(inner) => node("number", vec![Value::Number(inner.number)]),
+            Expr::Str(inner) => node("string", vec![string(&inner.value)]),
+            Expr::List(inner) | Expr::ConstantList(inner) => {
+                let items = inner
+                    .items
+                    .iter()
+                    .map(|item| self.expr(item))
+                    .collect::<Result<'a, Vec<_>>>()?;
+                node("list", vec![list(items)])
+            }
+            Expr::Selector(inner) => node("selector", vec![ident(&inner.ident)]),
+            Expr::True(_) => node("true", vec![]),
+            Expr::False(_) => node("false", vec![]),
+            Expr::Self_(_) => node("self", vec![]),
+            Expr::Quote(inner) => node("quote", vec![ast(&inner.body)]),
+            Expr::Unquote(inner) => (self.splice)(inner)?,
+            Expr::CaseOf(inner) => self.case_of(inner)?,
+        };
+        Ok(value)
+    }
+
+    fn message_send(&self, send: &'b MessageSend<'b>) -> Result<'a, Value<'a>> {
+        Ok(node(
+            "send",
+            vec![
+                self.expr(&send.receiver)?,
+                ident(&send.msg),
+                self.args(&send.args)?,
+            ],
+        ))
+    }
+
+    /// `caseOf:` is written as a message send, so it becomes the same node as one.
+    fn case_of(&self, case_of: &'b CaseOf<'b>) -> Result<'a, Value<'a>> {
+        let arms = case_of
+            .arms
+            .iter()
+            .map(|arm| {
+                let pair = list(vec![self.expr(&arm.value)?, self.expr(&arm.body)?]);
+                Ok(node("list", vec![pair]))
+            })
+            .collect::<Result<'a, Vec<_>>>()?;
+        let mut args = vec![list(vec![string("caseOf"), node("list", vec![list(arms)])])];
+        if let Some(otherwise) = &case_of.otherwise {
+            args.push(list(vec![string("otherwise"), self.expr(otherwise)?]));
+        }
+
+        Ok(node(
+            "send",
+            vec![self.expr(&case_of.subject)?, string("caseOf"), list(args)],
+        ))
+    }
+
+    fn block(&self, block: &'b Block<'b>) -> Result<'a, Value<'a>> {
+        let parameters = block
+            .parameters
+            .iter()
+            .map(|parameter| ident(&parameter.ident))
+            .collect();
+        Ok(node(
+            "block",
+            vec![list(parameters), self.stmts(&block.body)?],
+        ))
+    }
+
+    fn args(&self, args: &'b [Argument<'b>]) -> Result<'a, Value<'a>> {
+        let args = args
+            .iter()
+            .map(|arg| Ok(list(vec![ident(&arg.ident), self.expr(&arg.expr)?])))
+            .collect::<Result<'a, Vec<_>>>()?;
+        Ok(list(args))
+    }
+}
+
+/// Convert a value into the node it stands for when it's spliced into a quote. Nodes are used as
+/// they are, and numbers, strings, booleans, and selectors become literals.
+pub(super) fn splice(value: Value<'_>) -> Option<Value<'_>> {
+    let node = match value {
+        Value::List(items) if matches!(items.first(), Some(Value::Selector(_))) => {
+            Value::List(items)
+        }
+        Value::Number(number) => node("number", vec![Value::Number(number)]),
+        Value::String(string) => node("string", vec![Value::String(string)]),
+        Value::True => node("true", vec![]),
+        Value::False => node("false", vec![]),
+        Value::Selector(name) => node("selector", vec![self::string(name)]),
+        _ => return None,
+    };
+    Some(node)
+}
+
+/// Convert statements, as produced by `Parser parse:` or quoting, back into source.
+pub(super) fn source(stmts: &Value<'_>) -> std::result::Result<String, String> {
+    let mut acc = String::new();
+    for stmt in items(stmts)? {
+        write_stmt(&mut acc, stmt)?;
+        acc.push('\n');
+    }
+    Ok(acc)
+}
+
+fn write_stmt(acc: &mut String, stmt: &Value<'_>) -> std::result::Result<(), String> {
+    match fields(stmt)? {
+        ("letLocal", [name, expr]) | ("letConstant", [name, expr]) => {
+            acc.push_str(&format!("let {} = ", text(name)?));
+            write_expr(acc, expr)?;
+        }
+        ("letIVar", [name, expr]) => {
+            acc.push_str(&format!("let @{} = ", text(name)?));
+            write_expr(acc, expr)?;
+        }
+        ("send", [_, _, _]) => write_expr(acc, stmt)?,
+        ("return", [expr]) => {
+            acc.push_str("return ");
+            write_expr(acc, expr)?;
+        }
+        ("defineMethod", [class_name, method_name, block]) => {
+            acc.push_str(&format!(
+                "[{} def: #{} do: ",
+                text(class_name)?,
+                text(method_name)?
+            ));
+            write_expr(acc, block)?;
+            acc.push(']');
+        }
+        ("defineAbstractMethod", [class_name, method_name]) => {
+            acc.push_str(&format!(
+                "[{} defAbstract: #{}]",
+                text(class_name)?,
+                text(method_name)?
+            ));
+        }
+        ("defineClass", [name, fields, super_class]) => {
+            let fields = items(fields)?
+                .iter()
+                .map(|field| text(field).map(|field| format!("#{}", field)))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            acc.push_str(&format!(
+                "[{} subclass name: #{} fields: [{}]]",
+                text(super_class)?,
+                text(name)?,
+                fields.join(" ")
+            ));
+        }
+        ("reopenClass", [class_name, fields]) => {
+            let fields = items(fields)?
+                .iter()
+                .map(|field| text(field).map(|field| format!("#{}", field)))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            acc.push_str(&format!(
+                "[{} reopen fields: [{}]]",
+                text(class_name)?,
+                fields.join(" ")
+            ));
+        }
+        ("module", [name, body]) => {
+            acc.push_str(&format!("module {} {{ ", text(name)?));
+            write_body(acc, body)?;
+            // Modules aren't followed by a semicolon
+            acc.push('}');
+            return Ok(());
+        }
+        (kind, _) => return Err(format!("`{}` is not a valid statement", kind)),
+    }
+    acc.push(';');
+    Ok(())
+}
+
+fn write_expr(acc: &mut String, expr: &Value<'_>) -> std::result::Result<(), String> {
+    match fields(expr)? {
+        ("local", [name]) => acc.push_str(&text(name)?),
+        ("ivar", [name]) => acc.push_str(&format!("@{}", text(name)?)),
+        ("send", [receiver, msg, args]) => {
+            acc.push('[');
+            write_expr(acc, receiver)?;
+            acc.push_str(&format!(" {}", text(msg)?));
+            write_args(acc, args)?;
+            acc.push(']');
+        }
+        ("new", [class_name, args]) => {
+            acc.push_str(&format!("[{} new", text(class_name)?));
+            write_args(acc, args)?;
+            acc.push(']');
+        }
+        ("className", [name]) => acc.push_str(&text(name)?),
+        ("block", [parameters, body]) => {
+            acc.push('|');
+            for (idx, parameter) in items(parameters)?.iter().enumerate() {
+                if idx > 0 {
+                    acc.push(' ');
+                }
+                acc.push_str(&format!("{}:", text(parameter)?));
+            }
+            acc.push_str("| { ");
+            write_body(acc, body)?;
+            acc.push('}');
+        }
+        ("number", [Value::Number(number)]) if *number >= 0 => acc.push_str(&number.to_string()),
+        ("number", [Value::Number(number)]) => {
+            return Err(format!("negative numbers like {} have no literal", number))
+        }
+        ("string", [string]) => acc.push_str(&escape(&text(string)?)),
+        ("list", [list]) => {
+            acc.push('[');
+            for (idx, item) in items(list)?.iter().enumerate() {
+                if idx > 0 {
+                    acc.push_str(", ");
+                }
+                write_expr(acc, item)?;
+            }
+            acc.push(']');
+        }
+        ("selector", [name]) => acc.push_str(&format!("#{}", text(name)?)),
+        ("true", []) => acc.push_str("true"),
+        ("false", []) => acc.push_str("false"),
+        ("self", []) => acc.push_str("self"),
+        ("quote", [body]) => {
+            acc.push_str("quote { ");
+            write_body(acc, body)?;
+            acc.push('}');
+        }
+        ("unquote", [name]) => acc.push_str(&format!("~{}", text(name)?)),
+        (kind, _) => return Err(format!("`{}` is not a valid expression", kind)),
+    }
+    Ok(())
+}
+
+fn write_body(acc: &mut String, stmts: &Value<'_>) -> std::result::Result<(), String> {
+    for stmt in items(stmts)? {
+        write_stmt(acc, stmt)?;
+        acc.push(' ');
+    }
+    Ok(())
+}
+
+fn write_args(acc: &mut String, args: &Value<'_>) -> std::result::Result<(), String> {
+    for arg in items(args)? {
+        match items(arg)? {
+            [name, expr] => {
+                acc.push_str(&format!(" {}: ", text(name)?));
+                write_expr(acc, expr)?;
+            }
+            _ => return Err(format!("expected an argument but got {}", arg)),
+        }
+    }
+    Ok(())
+}
+
+fn escape(string: &str) -> String {
+    let mut acc = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '"' => acc.push_str("\\\""),
+            '\\' => acc.push_str("\\\\"),
+            '\n' => acc.push_str("\\n"),
+            '\t' => acc.push_str("\\t"),
+            other => acc.push(other),
+        }
+    }
+    acc.push('"');
+    acc
+}
+
+/// Split a node into its kind and the rest of its fields.
+fn fields<'v, 'a>(node: &'v Value<'a>) -> std::result::Result<(&'a str, &'v [Value<'a>]), String> {
+    match items(node)? {
+        [Value::Selector(kind), fields @ ..] => Ok((kind, fields)),
+        _ => Err(format!("expected a syntax node but got {}", node)),
+    }
+}
+
+fn items<'v, 'a>(value: &'v Value<'a>) -> std::result::Result<&'v [Value<'a>], String> {
+    match value {
+        Value::List(items) => Ok(items),
+        other => Err(format!("expected a list but got {}", other)),
+    }
+}
+
+fn text(value: &Value<'_>) -> std::result::Result<String, String> {
+    match value {
+        Value::String(string) => Ok(string.to_string()),
+        other => Err(format!("expected a string but got {}", other)),
+    }
+}
+
+fn node<'a>(kind: &'static str, mut fields: Vec<Value<'a>>) -> Value<'a> {
+    fields.insert(0, Value::Selector(kind));
+    list(fields)
+}
+
+fn ident<'a>(ident: &Ident<'_>) -> Value<'a> {
+    string(ident.name)
+}
+
+fn string<'a>(string: &str) -> Value<'a> {
+    Value::String(Rc::from(string))
+}
+
+fn list<'a>(items: Vec<Value<'a>>) -> Value<'a> {
+    Value::List(Rc::new(items))
+}
@@ -0,0 +1,50 @@
+//! Rendering rows as an aligned ASCII table, for `[Table render: rows headers: headers]`.
+
+/// Render a table with a border around it and a line under the headers. Columns are as wide as
+/// their widest cell. Rows with fewer cells than there are headers are padded with empty cells.
+pub(super) fn render(headers: &[String], rows: &[Vec<String>]) -> String {
+    let mut widths = headers
+        .iter()
+        .map(|header| width(header))
+        .collect::<Vec<_>>();
+    for row in rows {
+        for (idx, cell) in row.iter().enumerate().take(widths.len()) {
+            widths[idx] = widths[idx].max(width(cell));
+        }
+    }
+
+    let separator = widths
+        .iter()
+        .map(|width| "-".repeat(width + 2))
+        .collect::<Vec<_>>()
+        .join("+");
+    let separator = format!("+{}+\n", separator);
+
+    let mut acc = separator.clone();
+    acc.push_str(&line(headers, &widths));
+    acc.push_str(&separator);
+    for row in rows {
+        acc.push_str(&line(row, &widths));
+    }
+    if !rows.is_empty() {
+        acc.push_str(&separator);
+    }
+    acc
+}
+
+fn line(cells: &[String], widths: &[usize]) -> String {
+    let cells = widths
+        .iter()
+        .enumerate()
+        .map(|(idx, width)| {
+            let cell = cells.get(idx).map_or("", String::as_str);
+            format!(" {}{} ", cell, " ".repeat(width - self::width(cell)))
+        })
+        .collect::<Vec<_>>()
+        .join("|");
+    format!("|{}|\n", cells)
+}
+
+fn width(cell: &str) -> usize {
+    cell.chars().count()
+}
@@ -0,0 +1,102 @@
+//! Tail calls, so methods and blocks that loop by returning the result of calling themselves, or
+//! each other, run in constant space.
+//!
+//! `return [receiver msg ...]` in a method or block doesn't make the call itself when it's to a
+//! method defined in OOPS or is `call` sent to a block, and neither does a `return` of a `caseOf:`
+//! whose arm is a block. The call is left for `Interpreter::run_call`, which makes it in place of
+//! the method or block that just returned, reusing its frame. Other messages are sent as usual.
+
+use super::{
+    call_with_arguments, case_of_body, count_send, dispatch, eval_argument_values, resolve_method,
+    ArgumentValue, Closure, Eval, Interpreter, Value,
+};
+use crate::{
+    ast::{CaseOf, Expr, MessageSend},
+    error::Result,
+    prep::{Class, Method},
+    Span,
+};
+use std::rc::Rc;
+
+pub(super) enum TailCall<'a> {
+    Method {
+        class: Rc<Class<'a>>,
+        self_: Value<'a>,
+        method: Method<'a>,
+        msg: &'a str,
+        args: Vec<ArgumentValue<'a>>,
+        call_site: Span,
+    },
+    Block {
+        closure: Rc<Closure<'a>>,
+        args: Vec<ArgumentValue<'a>>,
+        call_site: Span,
+    },
+}
+
+/// What a `return` returns, or the call whose result it returns.
+pub(super) enum Tail<'a> {
+    Value(Value<'a>),
+    Call(TailCall<'a>),
+}
+
+/// Evaluate `expr`, which is returned, except for the call it ends with.
+pub(super) fn eval<'a>(expr: &'a Expr<'a>, interpreter: &Interpreter<'a>) -> Result<'a, Tail<'a>> {
+    match expr {
+        Expr::MessageSend(send) => eval_send(send, interpreter),
+        Expr::CaseOf(case_of) => eval_case_of(case_of, interpreter),
+        expr => expr.eval(interpreter).map(Tail::Value),
+    }
+}
+
+fn eval_send<'a>(send: &'a MessageSend<'a>, interpreter: &Interpreter<'a>) -> Result<'a, Tail<'a>> {
+    let receiver = send.receiver.eval(interpreter)?;
+    let args = eval_argument_values(interpreter, &send.args)?;
+    let msg = send.msg.name;
+    let call_site = send.span;
+    count_send(interpreter, &receiver, msg, call_site)?;
+
+    if let Some((class, method)) = resolve_method(interpreter, &receiver, msg) {
+        return Ok(Tail::Call(TailCall::Method {
+            class,
+            self_: receiver,
+            method,
+            msg,
+            args,
+            call_site,
+        }));
+    }
+
+    match (receiver, msg) {
+        (Value::Block(closure), "call") => Ok(Tail::Call(TailCall::Block {
+            closure,
+            args,
+            call_site,
+        })),
+        (receiver, _) => dispatch(interpreter, receiver, msg, args, call_site).map(Tail::Value),
+    }
+}
+
+fn eval_case_of<'a>(
+    case_of: &'a CaseOf<'a>,
+    interpreter: &Interpreter<'a>,
+) -> Result<'a, Tail<'a>> {
+    let body = match case_of_body(case_of, interpreter)? {
+        Some(body) => body,
+        None => return Ok(Tail::Value(Value::Nil)),
+    };
+
+    match body.eval(interpreter)? {
+        Value::Block(closure) if closure.parameters().next().is_none() => {
+            Ok(Tail::Call(TailCall::Block {
+                closure,
+                args: vec![],
+                call_site: body.span(),
+            }))
+        }
+        block @ (Value::Block(_) | Value::Memoized(_)) => {
+            call_with_arguments(interpreter, &block, vec![], body.span()).map(Tail::Value)
+        }
+        value => Ok(Tail::Value(value)),
+    }
+}
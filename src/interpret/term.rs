@@ -0,0 +1,126 @@
+//! The messages understood by the `Term` class.
+//!
+//! Escape codes are only produced when stdout is a terminal, so programs using `Term` still
+//! print plain text when their output is piped or captured by tests.
+
+use super::{bind_arguments, ArgumentValue, Interpreter, Value};
+use crate::{
+    error::{Error, Result},
+    Span,
+};
+use std::{
+    io,
+    process::{Command, Stdio},
+    rc::Rc,
+};
+
+/// The width used when `COLUMNS` isn't set, as most terminals start out this wide.
+const DEFAULT_WIDTH: i32 = 80;
+
+pub(super) fn send<'a>(
+    interpreter: &Interpreter<'a>,
+    msg: &'a str,
+    args: Vec<ArgumentValue<'a>>,
+    call_site: Span,
+) -> Result<'a, Value<'a>> {
+    match msg {
+        "isTerminal" => {
+            bind_arguments(vec![], call_site, args)?;
+            Ok(Value::from(interpreter.terminal))
+        }
+        "width" => {
+            bind_arguments(vec![], call_site, args)?;
+            let width = interpreter
+                .env
+                .get("COLUMNS")
+                .and_then(|columns| columns.parse().ok())
+                .unwrap_or(DEFAULT_WIDTH);
+            Ok(Value::Number(width))
+        }
+        "color" => {
+            let mut args = bind_arguments(vec!["color", "text"], call_site, args)?;
+            let color = args.remove("color");
+            let code = match &color {
+                Some(Value::Selector(name)) => color_code(name),
+                _ => None,
+            }
+            .ok_or_else(|| Error::UnexpectedType {
+                expected: "a color such as #red",
+                given: color.map_or("Nil", |value| value.class_name()),
+                span: call_site,
+            })?;
+            let text = args.remove("text").unwrap_or(Value::Nil);
+            Ok(styled(interpreter, code, &text))
+        }
+        "bold" => {
+            let mut args = bind_arguments(vec!["bold"], call_site, args)?;
+            let text = args.remove("bold").unwrap_or(Value::Nil);
+            Ok(styled(interpreter, "1", &text))
+        }
+        "clear" => {
+            bind_arguments(vec![], call_site, args)?;
+            write_escape(interpreter, "\x1b[2J\x1b[H")?;
+            Ok(Value::Nil)
+        }
+        "moveToRow" => {
+            let mut args = bind_arguments(vec!["moveToRow", "column"], call_site, args)?;
+            let mut position = |name| match args.remove(name) {
+                Some(Value::Number(n)) if n >= 1 => Ok(n),
+                other => Err(Error::UnexpectedType {
+                    expected: "Number of at least 1",
+                    given: other.map_or("Nil", |value| value.class_name()),
+                    span: call_site,
+                }),
+            };
+            let row = position("moveToRow")?;
+            let column = position("column")?;
+            write_escape(interpreter, &format!("\x1b[{};{}H", row, column))?;
+            Ok(Value::Nil)
+        }
+        _ => Err(interpreter.not_understood_by_class("Term", msg, call_site)),
+    }
+}
+
+fn color_code(name: &str) -> Option<&'static str> {
+    let code = match name {
+        "black" => "30",
+        "red" => "31",
+        "green" => "32",
+        "yellow" => "33",
+        "blue" => "34",
+        "magenta" => "35",
+        "cyan" => "36",
+        "white" => "37",
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// `text` wrapped in the escape code `code` and a reset, or just `text` when not on a terminal.
+fn styled<'a>(interpreter: &Interpreter<'a>, code: &str, text: &Value<'a>) -> Value<'a> {
+    if interpreter.terminal {
+        Value::String(Rc::from(format!("\x1b[{}m{}\x1b[0m", code, text)))
+    } else {
+        Value::String(Rc::from(text.to_string()))
+    }
+}
+
+fn write_escape<'a>(interpreter: &Interpreter<'a>, escape: &str) -> Result<'a, ()> {
+    if interpreter.terminal {
+        write!(interpreter.stdout.borrow_mut(), "{}", escape)?;
+    }
+    Ok(())
+}
+
+/// Turn echoing of typed characters on or off with `stty`, for reading passwords.
+pub(super) fn set_echo(on: bool) -> io::Result<()> {
+    let status = Command::new("stty")
+        .arg(if on { "echo" } else { "-echo" })
+        .stdin(Stdio::inherit())
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other("couldn't change terminal echo with stty"))
+    }
+}
@@ -2,12 +2,58 @@ use crate::{
     error::{Error, Result},
     Span,
 };
-use lazy_static::lazy_static;
-use regex::Regex;
-use std::fmt;
+use std::{fmt, mem};
+use unicode_ident::{is_xid_continue, is_xid_start};
 
-pub fn lex<'a>(program: &'a str) -> Result<Vec<Token<'a>>> {
-    Lexer::lex(program)
+#[cfg_attr(feature = "observability", tracing::instrument(skip_all))]
+pub fn lex<'a>(program: &'a str) -> Result<'a, Vec<Token<'a>>> {
+    Lexer::lex(program, false).map(|lexed| lexed.tokens)
+}
+
+/// Lex the program one token at a time, as they're asked for. Stops after the first error.
+pub fn tokens(program: &str) -> Tokens<'_> {
+    Tokens {
+        lexer: Lexer::new(program, false),
+        failed: false,
+    }
+}
+
+pub struct Tokens<'a> {
+    lexer: Lexer<'a>,
+    failed: bool,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Result<'a, Token<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let next = self.lexer.next_token();
+        self.failed = next.is_err();
+        next.transpose()
+    }
+}
+
+/// Lex the program while keeping the whitespace and comments that are otherwise discarded.
+pub fn lex_with_trivia<'a>(program: &'a str) -> Result<'a, TokensWithTrivia<'a>> {
+    Lexer::lex(program, true)
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct TokensWithTrivia<'a> {
+    pub tokens: Vec<Token<'a>>,
+    /// The trivia preceding each token. Has the same length as `tokens`.
+    pub leading_trivia: Vec<Vec<Trivia<'a>>>,
+    /// The trivia after the last token.
+    pub trailing_trivia: Vec<Trivia<'a>>,
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub enum Trivia<'a> {
+    WhiteSpace { text: &'a str, span: Span },
+    Comment { text: &'a str, span: Span },
 }
 
 #[derive(Eq, PartialEq, Debug)]
@@ -16,8 +62,10 @@ pub enum Token<'a> {
     Self_(Self_),
     Name(Name<'a>),
     ClassName(ClassName<'a>),
+    Operator(Operator<'a>),
     Eq(Eq),
     Number(Number),
+    Str(Str),
     Semicolon(Semicolon),
     OBracket(OBracket),
     CBracket(CBracket),
@@ -30,6 +78,7 @@ pub enum Token<'a> {
     Hash(Hash),
     Comma(Comma),
     Pipe(Pipe),
+    Tilde(Tilde),
     True(True),
     False(False),
     Return(Return),
@@ -41,9 +90,11 @@ impl fmt::Display for Token<'_> {
             Token::Let(inner) => write!(f, "{}", inner),
             Token::Name(inner) => write!(f, "{}", inner),
             Token::ClassName(inner) => write!(f, "{}", inner),
+            Token::Operator(inner) => write!(f, "{}", inner),
             Token::Self_(inner) => write!(f, "{}", inner),
             Token::Eq(inner) => write!(f, "{}", inner),
             Token::Number(inner) => write!(f, "{}", inner),
+            Token::Str(inner) => write!(f, "{}", inner),
             Token::Semicolon(inner) => write!(f, "{}", inner),
             Token::OBracket(inner) => write!(f, "{}", inner),
             Token::CBracket(inner) => write!(f, "{}", inner),
@@ -56,6 +107,7 @@ impl fmt::Display for Token<'_> {
             Token::Hash(inner) => write!(f, "{}", inner),
             Token::Comma(inner) => write!(f, "{}", inner),
             Token::Pipe(inner) => write!(f, "{}", inner),
+            Token::Tilde(inner) => write!(f, "{}", inner),
             Token::True(inner) => write!(f, "{}", inner),
             Token::False(inner) => write!(f, "{}", inner),
             Token::Return(inner) => write!(f, "{}", inner),
@@ -63,6 +115,67 @@ impl fmt::Display for Token<'_> {
     }
 }
 
+impl Token<'_> {
+    /// The kind of token, as exposed to programs through `Lexer`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Token::Let(_) => "let",
+            Token::Self_(_) => "self",
+            Token::Name(_) => "name",
+            Token::ClassName(_) => "className",
+            Token::Operator(_) => "operator",
+            Token::Eq(_) => "eq",
+            Token::Number(_) => "number",
+            Token::Str(_) => "string",
+            Token::Semicolon(_) => "semicolon",
+            Token::OBracket(_) => "oBracket",
+            Token::CBracket(_) => "cBracket",
+            Token::OBrace(_) => "oBrace",
+            Token::CBrace(_) => "cBrace",
+            Token::OParen(_) => "oParen",
+            Token::CParen(_) => "cParen",
+            Token::Colon(_) => "colon",
+            Token::At(_) => "at",
+            Token::Hash(_) => "hash",
+            Token::Comma(_) => "comma",
+            Token::Pipe(_) => "pipe",
+            Token::Tilde(_) => "tilde",
+            Token::True(_) => "true",
+            Token::False(_) => "false",
+            Token::Return(_) => "return",
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Token::Let(inner) => inner.span,
+            Token::Self_(inner) => inner.span,
+            Token::Name(inner) => inner.span,
+            Token::ClassName(inner) => inner.span,
+            Token::Operator(inner) => inner.span,
+            Token::Eq(inner) => inner.span,
+            Token::Number(inner) => inner.span,
+            Token::Str(inner) => inner.span,
+            Token::Semicolon(inner) => inner.span,
+            Token::OBracket(inner) => inner.span,
+            Token::CBracket(inner) => inner.span,
+            Token::OBrace(inner) => inner.span,
+            Token::CBrace(inner) => inner.span,
+            Token::OParen(inner) => inner.span,
+            Token::CParen(inner) => inner.span,
+            Token::Colon(inner) => inner.span,
+            Token::At(inner) => inner.span,
+            Token::Hash(inner) => inner.span,
+            Token::Comma(inner) => inner.span,
+            Token::Pipe(inner) => inner.span,
+            Token::Tilde(inner) => inner.span,
+            Token::True(inner) => inner.span,
+            Token::False(inner) => inner.span,
+            Token::Return(inner) => inner.span,
+        }
+    }
+}
+
 pub trait Parse<'a>: Sized {
     fn debug_name() -> &'static str;
 
@@ -70,7 +183,7 @@ pub trait Parse<'a>: Sized {
 }
 
 macro_rules! token_with_span {
-    ( $name:ident, $re_name:ident, $pattern:expr ) => {
+    ( $name:ident, $text:expr ) => {
         #[derive(Eq, PartialEq, Debug)]
         pub struct $name {
             pub span: Span,
@@ -80,11 +193,6 @@ macro_rules! token_with_span {
             fn new(span: Span) -> Self {
                 Self { span }
             }
-
-            #[inline]
-            fn regex() -> &'static Regex {
-                &$re_name
-            }
         }
 
         impl<'a> From<$name> for Token<'a> {
@@ -93,16 +201,9 @@ macro_rules! token_with_span {
             }
         }
 
-        lazy_static! {
-            static ref $re_name: Regex = {
-                let re = format!(r#"\A({})"#, $pattern);
-                Regex::new(&re).unwrap()
-            };
-        }
-
         impl<'a> Parse<'a> for $name {
             fn debug_name() -> &'static str {
-                $pattern
+                $text
             }
 
             fn from_token<'b>(token: &'b Token<'a>) -> Option<&'b Self> {
@@ -116,38 +217,31 @@ macro_rules! token_with_span {
 
         impl fmt::Display for $name {
             fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-                write!(f, "{}", $pattern)
+                write!(f, "{}", $text)
             }
         }
     };
 }
 
-token_with_span!(Let, LET, "let");
-token_with_span!(Self_, SELF, "self");
-token_with_span!(Eq, EQ, "=");
-token_with_span!(Semicolon, SEMICOLON, ";");
-token_with_span!(OBracket, OBRACKET, r#"\["#);
-token_with_span!(CBracket, CBRACKET, r#"\]"#);
-token_with_span!(OBrace, OBRACE, r#"\{"#);
-token_with_span!(CBrace, CBRACE, r#"\}"#);
-token_with_span!(OParen, OPAREN, r#"\("#);
-token_with_span!(CParen, CPAREN, r#"\)"#);
-token_with_span!(Colon, COLON, ":");
-token_with_span!(At, AT, "@");
-token_with_span!(Hash, HASH, "#");
-token_with_span!(Comma, COMMA, ",");
-token_with_span!(Pipe, PIPE, r#"\|"#);
-token_with_span!(True, TRUE, "true");
-token_with_span!(False, FALSE, "false");
-token_with_span!(Return, RETURN, "return");
-
-lazy_static! {
-    static ref CLASS_NAME: Regex = Regex::new(r#"\A([A-Z][a-zA-Z_]*)"#).unwrap();
-    static ref NAME: Regex = Regex::new(r#"\A([a-z][a-zA-Z_]*)"#).unwrap();
-    static ref NUMBER: Regex = Regex::new(r#"\A([0-9]+)"#).unwrap();
-    static ref WHITE_SPACE: Regex = Regex::new(r#"^( +|\n+|\t+)"#).unwrap();
-    static ref COMMENT: Regex = Regex::new(r#"^(//[^\n]*)"#).unwrap();
-}
+token_with_span!(Let, "let");
+token_with_span!(Self_, "self");
+token_with_span!(Eq, "=");
+token_with_span!(Semicolon, ";");
+token_with_span!(OBracket, "[");
+token_with_span!(CBracket, "]");
+token_with_span!(OBrace, "{");
+token_with_span!(CBrace, "}");
+token_with_span!(OParen, "(");
+token_with_span!(CParen, ")");
+token_with_span!(Colon, ":");
+token_with_span!(At, "@");
+token_with_span!(Hash, "#");
+token_with_span!(Comma, ",");
+token_with_span!(Pipe, "|");
+token_with_span!(Tilde, "~");
+token_with_span!(True, "true");
+token_with_span!(False, "false");
+token_with_span!(Return, "return");
 
 #[derive(Eq, PartialEq, Debug)]
 pub struct Name<'a> {
@@ -159,11 +253,6 @@ impl<'a> Name<'a> {
     fn new(name: &'a str, span: Span) -> Self {
         Self { name, span }
     }
-
-    #[inline]
-    fn regex() -> &'static Regex {
-        &NAME
-    }
 }
 
 impl<'a> From<Name<'a>> for Token<'a> {
@@ -202,11 +291,6 @@ impl<'a> ClassName<'a> {
     fn new(name: &'a str, span: Span) -> Self {
         Self { name, span }
     }
-
-    #[inline]
-    fn regex() -> &'static Regex {
-        &CLASS_NAME
-    }
 }
 
 impl<'a> From<ClassName<'a>> for Token<'a> {
@@ -235,6 +319,45 @@ impl<'a> fmt::Display for ClassName<'a> {
     }
 }
 
+/// A binary operator such as `+` or `==`.
+#[derive(Eq, PartialEq, Debug)]
+pub struct Operator<'a> {
+    pub op: &'a str,
+    pub span: Span,
+}
+
+impl<'a> Operator<'a> {
+    fn new(op: &'a str, span: Span) -> Self {
+        Self { op, span }
+    }
+}
+
+impl<'a> From<Operator<'a>> for Token<'a> {
+    fn from(val: Operator<'a>) -> Token<'a> {
+        Token::Operator(val)
+    }
+}
+
+impl<'a> Parse<'a> for Operator<'a> {
+    fn debug_name() -> &'static str {
+        "operator"
+    }
+
+    fn from_token<'b>(token: &'b Token<'a>) -> Option<&'b Self> {
+        if let Token::Operator(inner) = token {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> fmt::Display for Operator<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.op)
+    }
+}
+
 #[derive(Eq, PartialEq, Debug)]
 pub struct Number {
     pub number: i32,
@@ -246,9 +369,17 @@ impl Number {
         Self { number, span }
     }
 
-    #[inline]
-    fn regex() -> &'static Regex {
-        &NUMBER
+    /// The value of a literal such as `1_000`, `0xFF`, or `0b1010`, unless it's too large for a
+    /// `Number`.
+    fn value(literal: &str) -> Option<i32> {
+        let digits = literal.replace('_', "");
+        if let Some(hex) = digits.strip_prefix("0x") {
+            i32::from_str_radix(hex, 16).ok()
+        } else if let Some(binary) = digits.strip_prefix("0b") {
+            i32::from_str_radix(binary, 2).ok()
+        } else {
+            digits.parse().ok()
+        }
     }
 }
 
@@ -278,129 +409,330 @@ impl fmt::Display for Number {
     }
 }
 
+/// A string literal. `value` has the escape sequences replaced.
+#[derive(Eq, PartialEq, Debug)]
+pub struct Str {
+    pub value: String,
+    pub span: Span,
+}
+
+impl Str {
+    fn new(value: String, span: Span) -> Self {
+        Self { value, span }
+    }
+
+    /// Replace the escape sequences in the literal `raw`, including its quotes, which starts at
+    /// `offset`. Returns the position of the first invalid escape sequence on failure.
+    fn unescape(raw: &str, offset: usize) -> std::result::Result<String, usize> {
+        let mut value = String::new();
+        let mut chars = raw[1..raw.len() - 1].char_indices();
+
+        while let Some((idx, c)) = chars.next() {
+            if c != '\\' {
+                value.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, '"')) => value.push('"'),
+                Some((_, '\\')) => value.push('\\'),
+                _ => return Err(offset + 1 + idx),
+            }
+        }
+
+        Ok(value)
+    }
+}
+
+impl<'a> From<Str> for Token<'a> {
+    fn from(val: Str) -> Token<'a> {
+        Token::Str(val)
+    }
+}
+
+impl<'a> Parse<'a> for Str {
+    fn debug_name() -> &'static str {
+        "string"
+    }
+
+    fn from_token<'b>(token: &'b Token<'a>) -> Option<&'b Self> {
+        if let Token::Str(inner) = token {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Str {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.value)
+    }
+}
+
 struct Lexer<'a> {
     program: &'a str,
     current_position: usize,
     tokens: Vec<Token<'a>>,
+    capture_trivia: bool,
+    pending_trivia: Vec<Trivia<'a>>,
+    leading_trivia: Vec<Vec<Trivia<'a>>>,
 }
 
 impl<'a> Lexer<'a> {
-    fn lex(program: &'a str) -> Result<'a, Vec<Token<'a>>> {
-        let mut lexer = Self {
+    fn new(program: &'a str, capture_trivia: bool) -> Self {
+        Self {
             program,
             current_position: 0,
             tokens: vec![],
+            capture_trivia,
+            pending_trivia: vec![],
+            leading_trivia: vec![],
+        }
+    }
+
+    fn lex(program: &'a str, capture_trivia: bool) -> Result<'a, TokensWithTrivia<'a>> {
+        let mut lexer = Self::new(program, capture_trivia);
+
+        while let Some(token) = lexer.next_token()? {
+            lexer.push_token(token);
+        }
+
+        Ok(TokensWithTrivia {
+            tokens: lexer.tokens,
+            leading_trivia: lexer.leading_trivia,
+            trailing_trivia: lexer.pending_trivia,
+        })
+    }
+
+    /// Lex the next token, after the trivia before it. Returns `None` at the end of the program.
+    fn next_token(&mut self) -> Result<'a, Option<Token<'a>>> {
+        while self.skip_comment() || self.skip_white_space() {}
+
+        let program = self.program;
+        let at = self.current_position;
+        let rest = &program[at..];
+        let span = |len: usize| Span::new(at, at + len);
+
+        let first = match rest.chars().next() {
+            Some(first) => first,
+            None => return Ok(None),
         };
 
-        loop {
-            if lexer.at_end() {
-                break;
-            } else {
-                lexer.step()?;
-            }
+        // Before punctuation so `==` isn't lexed as two `=`
+        let (token, len) = if let Some(len) = operator_length(rest) {
+            (Token::from(Operator::new(&rest[..len], span(len))), len)
+        } else if let Some(token) = punctuation(first, span(1)) {
+            (token, 1)
+        } else if let Some(len) = class_name_length(rest) {
+            (Token::from(ClassName::new(&rest[..len], span(len))), len)
+        } else if let Some(len) = name_length(rest) {
+            let name = &rest[..len];
+            let token =
+                keyword(name, span(len)).unwrap_or_else(|| Token::from(Name::new(name, span(len))));
+            (token, len)
+        } else if first.is_ascii_digit() {
+            let len = number_length(rest);
+            let number = Number::value(&rest[..len]).ok_or(Error::LexError { at })?;
+            (Token::from(Number::new(number, span(len))), len)
+        } else if first == '"' {
+            let len = string_length(rest).ok_or(Error::LexError { at })?;
+            let value = Str::unescape(&rest[..len], at).map_err(|at| Error::LexError { at })?;
+            (Token::from(Str::new(value, span(len))), len)
+        } else {
+            return Err(Error::LexError { at });
+        };
+
+        self.current_position += len;
+        Ok(Some(token))
+    }
+
+    /// Skip a comment, which runs until the end of the line.
+    fn skip_comment(&mut self) -> bool {
+        let rest = &self.program[self.current_position..];
+        if !rest.starts_with("//") {
+            return false;
         }
 
-        Ok(lexer.tokens)
+        let len = rest.find('\n').unwrap_or(rest.len());
+        self.skip(len, |text, span| Trivia::Comment { text, span });
+        true
     }
 
-    fn at_end(&self) -> bool {
-        self.current_position >= self.program.len()
+    /// Skip a run of spaces, newlines, or tabs. Each run only has one of them.
+    fn skip_white_space(&mut self) -> bool {
+        let rest = &self.program[self.current_position..];
+        let first = match rest.chars().next() {
+            Some(first @ (' ' | '\n' | '\t')) => first,
+            _ => return false,
+        };
+
+        let len = rest.find(|c| c != first).unwrap_or(rest.len());
+        self.skip(len, |text, span| Trivia::WhiteSpace { text, span });
+        true
     }
 
-    fn step(&mut self) -> Result<'a, ()> {
-        macro_rules! scan_for {
-            ( $ty:ty ) => {
-                if let Some(capture) = self.scan(<$ty>::regex()) {
-                    let token = <$ty>::new(self.new_span_with_length(capture.len()));
-                    let token = Token::from(token);
-                    self.tokens.push(token);
-                    self.current_position += capture.len();
-                    return Ok(());
-                }
-            };
-
-            ( $ty:ty, $make_token:expr ) => {
-                if let Some(capture) = self.scan(<$ty>::regex()) {
-                    let token = ($make_token)(capture);
-                    let token = Token::from(token);
-                    self.tokens.push(token);
-                    self.current_position += capture.len();
-                    return Ok(());
-                }
-            };
+    fn skip(&mut self, len: usize, make_trivia: impl FnOnce(&'a str, Span) -> Trivia<'a>) {
+        if self.capture_trivia {
+            let text = &self.program[self.current_position..self.current_position + len];
+            let span = self.new_span_with_length(len);
+            self.pending_trivia.push(make_trivia(text, span));
         }
+        self.current_position += len;
+    }
 
-        while self.skip(&COMMENT) || self.skip(&WHITE_SPACE) {}
-
-        scan_for!(Let);
-        scan_for!(Self_);
-        scan_for!(Eq);
-        scan_for!(OBracket);
-        scan_for!(CBracket);
-        scan_for!(OParen);
-        scan_for!(CParen);
-        scan_for!(OBrace);
-        scan_for!(CBrace);
-        scan_for!(Semicolon);
-        scan_for!(Colon);
-        scan_for!(At);
-        scan_for!(Hash);
-        scan_for!(Comma);
-        scan_for!(Pipe);
-        scan_for!(True);
-        scan_for!(False);
-        scan_for!(Return);
-
-        scan_for!(ClassName, |capture: &'a str| ClassName::new(
-            capture,
-            self.new_span_with_length(capture.len())
-        ));
-
-        scan_for!(Name, |capture: &'a str| Name::new(
-            capture,
-            self.new_span_with_length(capture.len())
-        ));
-
-        scan_for!(Number, |capture: &'a str| {
-            let number = capture
-                .parse::<i32>()
-                .expect("tokenized a number, but parsing to i32 didn't work");
-            Number::new(number, self.new_span_with_length(capture.len()))
-        });
-
-        if self.at_end() {
-            return Ok(());
+    fn push_token(&mut self, token: Token<'a>) {
+        self.tokens.push(token);
+        if self.capture_trivia {
+            let trivia = mem::take(&mut self.pending_trivia);
+            self.leading_trivia.push(trivia);
         }
+    }
 
-        Err(Error::LexError {
-            at: self.current_position,
-        })
+    fn new_span_with_length(&self, len: usize) -> Span {
+        Span::new(self.current_position, self.current_position + len)
     }
+}
 
-    fn scan(&self, re: &Regex) -> Option<&'a str> {
-        let program = &self.program[self.current_position..];
+fn operator_length(rest: &str) -> Option<usize> {
+    if rest.starts_with("==") || rest.starts_with("!=") {
+        Some(2)
+    } else if rest.starts_with(['<', '>', '+', '-', '*', '/']) {
+        Some(1)
+    } else {
+        None
+    }
+}
 
-        re.captures(program).map(|captures| {
-            let match_ = &captures[0];
-            &program[0..match_.len()]
-        })
+fn punctuation<'a>(c: char, span: Span) -> Option<Token<'a>> {
+    let token = match c {
+        '=' => Token::from(Eq::new(span)),
+        '[' => Token::from(OBracket::new(span)),
+        ']' => Token::from(CBracket::new(span)),
+        '(' => Token::from(OParen::new(span)),
+        ')' => Token::from(CParen::new(span)),
+        '{' => Token::from(OBrace::new(span)),
+        '}' => Token::from(CBrace::new(span)),
+        ';' => Token::from(Semicolon::new(span)),
+        ':' => Token::from(Colon::new(span)),
+        '@' => Token::from(At::new(span)),
+        '#' => Token::from(Hash::new(span)),
+        ',' => Token::from(Comma::new(span)),
+        '|' => Token::from(Pipe::new(span)),
+        '~' => Token::from(Tilde::new(span)),
+        _ => return None,
+    };
+    Some(token)
+}
+
+fn keyword<'a>(name: &str, span: Span) -> Option<Token<'a>> {
+    let token = match name {
+        "let" => Token::from(Let::new(span)),
+        "self" => Token::from(Self_::new(span)),
+        "true" => Token::from(True::new(span)),
+        "false" => Token::from(False::new(span)),
+        "return" => Token::from(Return::new(span)),
+        _ => return None,
+    };
+    Some(token)
+}
+
+// Identifiers follow Unicode's XID rules. Class names start with an uppercase or titlecase letter
+// and can be qualified by modules, like `Http::Client`. Names start with any other letter,
+// including those without case such as `名前`
+
+fn class_name_length(rest: &str) -> Option<usize> {
+    let mut len = identifier_length(rest, is_class_name_start)?;
+    while let Some(qualified) = rest[len..].strip_prefix("::") {
+        match identifier_length(qualified, is_class_name_start) {
+            Some(segment) => len += 2 + segment,
+            None => break,
+        }
+    }
+    Some(len)
+}
+
+fn name_length(rest: &str) -> Option<usize> {
+    identifier_length(rest, |c| is_xid_start(c) && !is_class_name_start(c))
+}
+
+fn identifier_length(rest: &str, is_start: impl Fn(char) -> bool) -> Option<usize> {
+    let mut chars = rest.char_indices();
+    match chars.next() {
+        Some((_, first)) if is_start(first) => {}
+        _ => return None,
     }
+    let len = chars
+        .find(|(_, c)| !is_xid_continue(*c))
+        .map_or(rest.len(), |(idx, _)| idx);
+    Some(len)
+}
+
+/// Whether `c` is an uppercase or titlecase letter that can start an identifier. Rust counts
+/// letter numbers like `Ⅻ` as uppercase, but they aren't letters.
+fn is_class_name_start(c: char) -> bool {
+    let titlecase = matches!(
+        c,
+        '\u{1c5}'
+            | '\u{1c8}'
+            | '\u{1cb}'
+            | '\u{1f2}'
+            | '\u{1f88}'..='\u{1f8f}'
+            | '\u{1f98}'..='\u{1f9f}'
+            | '\u{1fa8}'..='\u{1faf}'
+            | '\u{1fbc}'
+            | '\u{1fcc}'
+            | '\u{1ffc}'
+    );
+    let uppercase = c.is_uppercase() && !matches!(c, '\u{2160}'..='\u{216f}');
+    is_xid_start(c) && (uppercase || titlecase)
+}
 
-    fn skip(&mut self, re: &Regex) -> bool {
-        let program = &self.program[self.current_position..];
+/// The length of a number literal. Digits can be separated by underscores, like `1_000_000`, and
+/// `0x` and `0b` start hexadecimal and binary literals when a digit follows them.
+fn number_length(rest: &str) -> usize {
+    let bytes = rest.as_bytes();
+    let digit_at = |idx: usize, is_digit: fn(&u8) -> bool| bytes.get(idx).is_some_and(is_digit);
+    let is_binary: fn(&u8) -> bool = |b| *b == b'0' || *b == b'1';
+
+    let (mut len, is_digit): (usize, fn(&u8) -> bool) =
+        if rest.starts_with("0x") && digit_at(2, u8::is_ascii_hexdigit) {
+            (3, u8::is_ascii_hexdigit)
+        } else if rest.starts_with("0b") && digit_at(2, is_binary) {
+            (3, is_binary)
+        } else {
+            (1, u8::is_ascii_digit)
+        };
 
-        if let Some(captures) = re.captures(program) {
-            let match_ = &captures[0];
-            self.current_position += match_.len();
-            true
+    loop {
+        if digit_at(len, is_digit) {
+            len += 1;
+        } else if bytes.get(len) == Some(&b'_') && digit_at(len + 1, is_digit) {
+            len += 2;
         } else {
-            false
+            return len;
         }
     }
+}
 
-    fn new_span_with_length(&self, len: usize) -> Span {
-        Span::new(self.current_position, self.current_position + len)
+/// The length of a string literal, including its quotes, or `None` if it isn't closed. Escape
+/// sequences are checked when the literal is unescaped, except that a backslash can't escape a
+/// newline.
+fn string_length(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices().skip(1);
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '"' => return Some(idx + 1),
+            '\\' => match chars.next() {
+                Some((_, '\n')) | None => return None,
+                Some(_) => {}
+            },
+            _ => {}
+        }
     }
+    None
 }
 
 #[cfg(test)]
@@ -464,6 +796,78 @@ mod test {
         );
     }
 
+    #[test]
+    fn number_literals() {
+        let numbers = |program| {
+            lex(program)
+                .unwrap()
+                .iter()
+                .filter_map(|token| Number::from_token(token).map(|number| number.number))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec![1_000_000, 255, 10, 0x7fff_ffff, 0],
+            numbers("[1_000_000, 0xFF, 0b1010, 0x7fff_ffff, 0];")
+        );
+        assert_eq!(
+            Span::from_with(13, "0b1_0"),
+            lex("let a0b1_0 = 0b1_0;").unwrap()[3].span()
+        );
+
+        assert_error!(lex("let n = 2147483648;"), Error::LexError { at: 8 });
+        assert_error!(lex("let n = 0x1_0000_0000;"), Error::LexError { at: 8 });
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        let program = "[Ærø::Café naïve: 名前];";
+        assert_eq!(
+            lex(program).unwrap()[1..5],
+            [
+                Token::ClassName(ClassName::new("Ærø::Café", Span::from_with(1, "Ærø::Café"))),
+                Token::Name(Name::new("naïve", Span::from_with(14, "naïve"))),
+                Token::Colon(Colon::new(Span::from_with(20, ":"))),
+                Token::Name(Name::new("名前", Span::from_with(22, "名前"))),
+            ]
+        );
+
+        assert_error!(lex("let é = 1; ½"), Error::LexError { at: 12 });
+    }
+
+    #[test]
+    fn keywords_are_whole_names() {
+        let program = "let letter = [selfish returned: Http::request];";
+        assert_eq!(
+            lex(program).unwrap()[..8],
+            [
+                Token::Let(Let::new(Span::from_with(0, "let"))),
+                Token::Name(Name::new("letter", Span::from_with(4, "letter"))),
+                Token::Eq(Eq::new(Span::from_with(11, "="))),
+                Token::OBracket(OBracket::new(Span::from_with(13, "["))),
+                Token::Name(Name::new("selfish", Span::from_with(14, "selfish"))),
+                Token::Name(Name::new("returned", Span::from_with(22, "returned"))),
+                Token::Colon(Colon::new(Span::from_with(30, ":"))),
+                Token::ClassName(ClassName::new("Http", Span::from_with(32, "Http"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn string() {
+        let program = r#"let s = "say \"hi\"\n";"#;
+        assert_eq!(
+            lex(program).unwrap()[3],
+            Token::Str(Str::new(
+                "say \"hi\"\n".to_string(),
+                Span::from_with(8, r#""say \"hi\"\n""#)
+            ))
+        );
+
+        assert_error!(lex(r#""\q""#), Error::LexError { at: 1 });
+        assert_error!(lex(r#""open"#), Error::LexError { at: 0 });
+    }
+
     #[test]
     fn ignores_comments_with_newline() {
         lex("// Just a comment\n").unwrap();
@@ -473,7 +877,7 @@ mod test {
         lex("// Just a comment").unwrap();
         lex("// one\n// two").unwrap();
 
-        let program = vec![
+        let program = [
             "// a comment\n",
             "let n = 1;\n",
             "// a comment\n",
@@ -485,4 +889,46 @@ mod test {
         .join("");
         lex(&program).unwrap();
     }
+
+    #[test]
+    fn captures_trivia() {
+        let program = "// one\nlet n = 1; // two";
+        let lexed = lex_with_trivia(program).unwrap();
+
+        assert_eq!(lexed.tokens, lex(program).unwrap());
+        assert_eq!(lexed.leading_trivia.len(), lexed.tokens.len());
+        assert_eq!(
+            lexed.leading_trivia[0],
+            vec![
+                Trivia::Comment {
+                    text: "// one",
+                    span: Span::from_with(0, "// one"),
+                },
+                Trivia::WhiteSpace {
+                    text: "\n",
+                    span: Span::from_with(6, "\n"),
+                },
+            ]
+        );
+        assert_eq!(
+            lexed.leading_trivia[1],
+            vec![Trivia::WhiteSpace {
+                text: " ",
+                span: Span::from_with(10, " "),
+            }]
+        );
+        assert_eq!(
+            lexed.trailing_trivia,
+            vec![
+                Trivia::WhiteSpace {
+                    text: " ",
+                    span: Span::from_with(17, " "),
+                },
+                Trivia::Comment {
+                    text: "// two",
+                    span: Span::from_with(18, "// two"),
+                },
+            ]
+        );
+    }
 }
@@ -0,0 +1,171 @@
+//! An interpreter for OOPS, a small object oriented language where everything is done by sending
+//! messages.
+//!
+//! The `oops` binary is a command line interface to this crate. Hosts embedding the language
+//! parse and prepare a program like it does, then run it with an [`interpret::Interpreter`]:
+//!
+//! ```
+//! use oops::{interpret::{interpret, Interpreter}, parse::parse_source, prep};
+//! use std::{cell::RefCell, io, rc::Rc};
+//!
+//! let mut ast = parse_source("let greeting = \"hi\";").unwrap();
+//! prep::remove_unreachable(&mut ast);
+//! prep::qualify_names(&mut ast);
+//! prep::resolve_slots(&mut ast, &[]);
+//! let classes = prep::find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+//!
+//! let stdout = Rc::new(RefCell::new(io::sink()));
+//! let stdin = Rc::new(RefCell::new(io::empty()));
+//! let mut interpreter = Interpreter::new(classes, stdout, stdin);
+//! interpret(&mut interpreter, &ast).unwrap();
+//! assert_eq!("hi", interpreter.local("greeting").unwrap().to_string());
+//! ```
+
+#![deny(unused_must_use)]
+
+#[macro_use]
+pub mod error;
+pub mod ast;
+pub mod callgraph;
+pub mod graph;
+pub mod import;
+pub mod interpret;
+pub mod lex;
+pub mod notebook;
+pub mod optimize;
+pub mod parse;
+pub mod playground;
+pub mod pool;
+pub mod prep;
+pub mod repl;
+
+use std::fmt;
+
+/// The OOPS source the `oops` binary runs before programs, unless `--no-prelude` is given.
+pub const PRELUDE: &str = include_str!("prelude.oops");
+
+/// A 1-based line and column. Columns are counted in characters, not bytes.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct LineCol {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl LineCol {
+    fn from_offset(source: &str, offset: usize) -> Self {
+        // Spans are byte offsets, so one inside a multi-byte character is moved to its start
+        let mut offset = offset.min(source.len());
+        while !source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let before = &source[..offset];
+        let line_start = before.rfind('\n').map(|idx| idx + 1).unwrap_or(0);
+
+        Self {
+            line: before.matches('\n').count() + 1,
+            col: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl fmt::Display for LineCol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+#[derive(Eq, PartialEq, Hash, Copy, Clone)]
+pub struct Span {
+    pub from: usize,
+    pub to: usize,
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Span({}..{})", self.from, self.to)
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} to {}", self.from, self.to)
+    }
+}
+
+impl Span {
+    pub fn new(from: usize, to: usize) -> Self {
+        Self { from, to }
+    }
+
+    /// The smallest span that covers both `self` and `other`.
+    pub fn merge(self, other: Span) -> Self {
+        Self::new(self.from.min(other.from), self.to.max(other.to))
+    }
+
+    pub fn contains(self, other: Span) -> bool {
+        self.from <= other.from && other.to <= self.to
+    }
+
+    pub fn len(self) -> usize {
+        self.to - self.from
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.len() == 0
+    }
+
+    /// Convert the byte offsets of the span into lines and columns within `source`.
+    pub fn line_col_range(self, source: &str) -> (LineCol, LineCol) {
+        (
+            LineCol::from_offset(source, self.from),
+            LineCol::from_offset(source, self.to),
+        )
+    }
+
+    #[cfg(test)]
+    pub fn from_with(start: usize, s: &str) -> Self {
+        Self::new(start, start + s.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn span_merge_and_contains() {
+        let a = Span::new(2, 5);
+        let b = Span::new(4, 9);
+        let merged = a.merge(b);
+
+        assert_eq!(Span::new(2, 9), merged);
+        assert_eq!(7, merged.len());
+        assert!(merged.contains(a));
+        assert!(merged.contains(b));
+        assert!(!a.contains(b));
+        assert!(Span::new(3, 3).is_empty());
+    }
+
+    #[test]
+    fn span_line_col_range() {
+        let source = "let a = 1;\nlet b = [a foo];\n";
+        let span = Span::from_with(19, "[a foo]");
+        let (start, end) = span.line_col_range(source);
+
+        assert_eq!(LineCol { line: 2, col: 9 }, start);
+        assert_eq!(LineCol { line: 2, col: 16 }, end);
+    }
+
+    #[test]
+    fn line_col_counts_characters() {
+        let source = "let café = 1;
+let 名前 = [café foo];
+";
+        let span = Span::from_with(28, "[café foo]");
+        let (start, end) = span.line_col_range(source);
+
+        assert_eq!(LineCol { line: 2, col: 10 }, start);
+        assert_eq!(LineCol { line: 2, col: 20 }, end);
+        assert_eq!(LineCol { line: 1, col: 8 }, LineCol::from_offset(source, 8));
+    }
+}
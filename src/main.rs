@@ -1,31 +1,147 @@
-// #![deny(unused_imports)]
 #![deny(unused_must_use)]
 
-#[macro_use]
-mod error;
-mod ast;
-mod interpret;
-mod lex;
-mod parse;
-mod prep;
-
-use interpret::{interpret, Interpreter};
-use lex::lex;
-use parse::parse;
-use prep::find_classes_and_methods;
+use oops::{
+    ast::Ast,
+    callgraph,
+    error::{print_error, print_error_in, Error},
+    graph,
+    import::Sources,
+    interpret::{interpret, CancellationToken, Capabilities, EvalArena, Interpreter, Report},
+    notebook, optimize,
+    parse::{parse_source, parse_source_with_stats},
+    playground,
+    prep::{self, find_classes_and_methods},
+    repl, PRELUDE,
+};
 use std::path::PathBuf;
-use std::{fmt, fs, rc::Rc};
+use std::{
+    cell::RefCell,
+    env, fs,
+    io::{self, BufWriter, IsTerminal, Write},
+    rc::Rc,
+};
 use structopt::StructOpt;
 
 /// OOPS language interpreter
 #[derive(StructOpt, Debug)]
-#[structopt(name = "oops")]
+#[structopt(
+    name = "oops",
+    raw(setting = "structopt::clap::AppSettings::TrailingVarArg"),
+    raw(setting = "structopt::clap::AppSettings::ArgsNegateSubcommands"),
+    raw(setting = "structopt::clap::AppSettings::DisableVersion")
+)]
 struct Opt {
-    /// File to run
-    #[structopt(name = "FILE", parse(from_os_str))]
-    file: PathBuf,
+    /// File to run, followed by arguments passed to the program which are available with
+    /// `[System args]`. Everything after FILE is passed on, including arguments starting with `-`
+    #[structopt(name = "FILE")]
+    file_and_args: Vec<String>,
+
+    /// Allow the program to evaluate source with `[Runtime eval: source]`
+    #[structopt(long = "allow-eval")]
+    allow_eval: bool,
+
+    /// Allow the program to read and write files with `File`
+    #[structopt(long = "allow-files")]
+    allow_files: bool,
+
+    /// Print statistics about the run to stderr when the program finishes
+    #[structopt(long)]
+    report: bool,
+
+    /// Print how much the parser had to backtrack to stderr
+    #[structopt(long = "parse-stats")]
+    parse_stats: bool,
+
+    /// Seed `Random` so the program produces the same output every time
+    #[structopt(long)]
+    seed: Option<u64>,
+
+    /// Cut values shown in error messages short after this many characters
+    #[structopt(long = "render-limit")]
+    render_limit: Option<usize>,
+
+    /// Stop the program once it has evaluated this many statements, expressions, and calls, so
+    /// programs that loop forever can't run forever
+    #[structopt(long = "max-steps")]
+    max_steps: Option<usize>,
+
+    /// Stop the program once this many method and block calls are being run at once. Defaults to
+    /// 10000
+    #[structopt(long = "max-depth")]
+    max_depth: Option<usize>,
+
+    /// Optimize the program before running it, by folding constants
+    #[structopt(short = "O")]
+    optimize: bool,
+
+    /// Don't run the prelude, which defines methods like `[list sum]` and `[n isEven]` in OOPS
+    #[structopt(long = "no-prelude")]
+    no_prelude: bool,
+
+    /// Write output as soon as it's printed rather than buffering it until `[IO flush]`, input is
+    /// read, or the program finishes
+    #[structopt(long)]
+    unbuffered: bool,
+
+    /// Print the version
+    #[structopt(short = "V", long)]
+    version: bool,
+
+    /// With `--version`, also print what this build can do with the `--allow-*` flags given, as
+    /// listed by `[Runtime features]`
+    #[structopt(long)]
+    features: bool,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Serve a web page for running programs from a browser
+    #[structopt(name = "serve-playground")]
+    ServePlayground {
+        #[structopt(long, default_value = "8080")]
+        port: u16,
+    },
+    /// Run statements as they're typed, continuing on the next line while brackets are open
+    #[structopt(name = "repl")]
+    Repl,
+    /// Run the ```oops code blocks in a Markdown file and write their output back into it
+    #[structopt(name = "run-md")]
+    RunMd {
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Print a diagram of the classes a program defines and the classes they inherit from
+    #[structopt(name = "graph")]
+    Graph {
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file: PathBuf,
+        /// `dot` for Graphviz or `mermaid`
+        #[structopt(long, default_value = "dot")]
+        format: graph::Format,
+        /// List the methods each class defines, and which of them override or implement methods
+        /// of super classes
+        #[structopt(long)]
+        methods: bool,
+    },
+    /// Print which methods each method may call, worked out from the source without running it.
+    /// Methods nothing calls are listed as unused
+    #[structopt(name = "callgraph")]
+    Callgraph {
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file: PathBuf,
+        /// `dot` for Graphviz or `json`
+        #[structopt(long, default_value = "dot")]
+        format: callgraph::Format,
+    },
 }
 
+/// The exit status when the program is stopped with Ctrl-C, following the shell convention of 128
+/// plus the signal number.
+const INTERRUPTED_STATUS: i32 = 130;
+
 macro_rules! ok_or_exit {
     ( $result:expr ) => {
         match $result {
@@ -36,64 +152,306 @@ macro_rules! ok_or_exit {
             }
         }
     };
+
+    ( $result:expr, $sources:expr ) => {
+        match $result {
+            Ok(v) => v,
+            Err(e) => {
+                print_error_in(&$sources, &e);
+                std::process::exit(1)
+            }
+        }
+    };
+
+    ( $result:expr, $file:expr, $source:expr ) => {
+        match $result {
+            Ok(v) => v,
+            Err(e) => {
+                print_error(&$file, $source, &e);
+                std::process::exit(1)
+            }
+        }
+    };
 }
 
 fn main() {
+    #[cfg(feature = "observability")]
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(io::stderr)
+        .init();
+
     let opt = Opt::from_args();
-    let source_text = ok_or_exit!(fs::read_to_string(opt.file));
+    let capabilities = Capabilities {
+        eval: opt.allow_eval,
+        files: opt.allow_files,
+    };
+
+    if opt.version {
+        println!("oops {}", env!("CARGO_PKG_VERSION"));
+        if opt.features {
+            let features = capabilities.features();
+            if features.is_empty() {
+                println!("features: none");
+            } else {
+                println!("features: {}", features.join(", "));
+            }
+        }
+        return;
+    }
 
-    let tokens = ok_or_exit!(lex(&source_text));
-    let ast = ok_or_exit!(parse(&tokens));
+    let (file, args) = split_file_and_args(opt.file_and_args);
+    let file = match (opt.command, file) {
+        (Some(Command::ServePlayground { port }), _) => {
+            ok_or_exit!(playground::serve(port));
+            return;
+        }
+        (Some(Command::Repl), _) => {
+            let prelude = if opt.no_prelude { None } else { Some(PRELUDE) };
+            ok_or_exit!(repl::run(capabilities, prelude));
+            return;
+        }
+        (Some(Command::RunMd { file }), _) => {
+            run_md(file, capabilities, opt.no_prelude, opt.optimize);
+            return;
+        }
+        (
+            Some(Command::Graph {
+                file,
+                format,
+                methods,
+            }),
+            _,
+        ) => {
+            print_graph(file, format, methods);
+            return;
+        }
+        (Some(Command::Callgraph { file, format }), _) => {
+            print_call_graph(file, format);
+            return;
+        }
+        (None, Some(file)) => file,
+        (None, None) => {
+            Opt::clap().print_help().ok();
+            std::process::exit(1)
+        }
+    };
+    let source_text = ok_or_exit!(fs::read_to_string(&file));
+    let eval_arena = EvalArena::new();
+
+    let (ast, parse_stats) = parse_source_with_stats(&source_text);
+    if opt.parse_stats {
+        eprint!("{}", parse_stats);
+    }
+    let ast = ok_or_exit!(ast, file, &source_text);
+    let (sources, ast) = prepare(file, &source_text, ast, opt.no_prelude, opt.optimize);
 
-    let mut built_in_classes = prep::Classes::new();
-    let span = Span::new(0, 0);
-    let ident = ast::Ident {
-        name: "Object",
-        span,
+    let class_vtable = ok_or_exit!(
+        find_classes_and_methods(&ast, prep::built_in_classes()),
+        sources
+    );
+    let cancellation = CancellationToken::new();
+    ok_or_exit!(cancellation.cancel_on_interrupt(INTERRUPTED_STATUS));
+
+    let stdout: Rc<RefCell<dyn Write>> = if opt.unbuffered {
+        Rc::new(RefCell::new(Unbuffered(io::stdout())))
+    } else {
+        Rc::new(RefCell::new(BufWriter::new(io::stdout())))
     };
-    built_in_classes.insert("Object", built_in_class(&ident));
 
-    let class_vtable = ok_or_exit!(find_classes_and_methods(&ast, built_in_classes));
-    let mut interpreter = Interpreter::new(class_vtable);
-    ok_or_exit!(interpret(&mut interpreter, &ast));
-}
+    let report = Rc::new(RefCell::new(Report::default()));
+    let mut interpreter = Interpreter::new(
+        class_vtable,
+        Rc::clone(&stdout),
+        Rc::new(RefCell::new(io::stdin().lock())),
+    )
+    .with_capabilities(capabilities)
+    .with_eval_arena(&eval_arena)
+    .with_cancellation(cancellation)
+    .with_args(args)
+    .with_env(env::vars().collect())
+    .with_terminal(io::stdout().is_terminal());
+    if opt.report {
+        interpreter = interpreter.with_report(Rc::clone(&report));
+    }
+    if let Some(seed) = opt.seed {
+        interpreter = interpreter.with_seed(seed);
+    }
+    if let Some(limit) = opt.render_limit {
+        interpreter = interpreter.with_render_limit(limit);
+    }
+    if let Some(max_steps) = opt.max_steps {
+        interpreter = interpreter.with_budget(max_steps);
+    }
+    if let Some(max_depth) = opt.max_depth {
+        interpreter = interpreter.with_max_depth(max_depth);
+    }
 
-fn built_in_class<'a>(ident: &'a ast::Ident) -> Rc<prep::Class<'a>> {
-    Rc::new(prep::Class {
-        name: &ident,
-        super_class_name: &ident,
-        super_class: None,
-        fields: interpret::VTable::new(),
-        methods: interpret::VTable::new(),
-        span: ident.span,
-    })
+    let result = interpret(&mut interpreter, &ast);
+    // `process::exit` doesn't run destructors, so buffered output has to be written first
+    ok_or_exit!(stdout.borrow_mut().flush());
+    if opt.report {
+        eprint!("{}", report.borrow());
+    }
+    match result {
+        Ok(()) => {}
+        Err(Error::Exit { status, .. }) => std::process::exit(status),
+        Err(err) => {
+            print_error_in(&sources, &err);
+            match err {
+                Error::Cancelled { .. } => std::process::exit(INTERRUPTED_STATUS),
+                _ => std::process::exit(1),
+            }
+        }
+    }
 }
 
-#[derive(Eq, PartialEq, Hash, Copy, Clone)]
-pub struct Span {
-    pub from: usize,
-    pub to: usize,
+/// Split the `FILE` arguments into the file to run and the arguments passed to it.
+///
+/// A `--` right after the file is dropped, since it used to be needed before arguments starting
+/// with `-`.
+fn split_file_and_args(file_and_args: Vec<String>) -> (Option<PathBuf>, Vec<String>) {
+    let mut file_and_args = file_and_args.into_iter().peekable();
+    let file = file_and_args.next().map(PathBuf::from);
+    file_and_args.next_if(|arg| arg == "--");
+    (file, file_and_args.collect())
 }
 
-impl fmt::Debug for Span {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Span({}..{})", self.from, self.to)
+/// Flushes after every write, for `--unbuffered`.
+struct Unbuffered<W>(W);
+
+impl<W: Write> Write for Unbuffered<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.0.write(buf)?;
+        self.0.flush()?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
     }
 }
 
-impl fmt::Display for Span {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} to {}", self.from, self.to)
+/// Resolve the imports of `ast`, parsed from `source_text` in `file`, add the prelude unless
+/// `no_prelude`, and prep it to be run.
+fn prepare<'a>(
+    file: PathBuf,
+    source_text: &'a str,
+    ast: Ast<'a>,
+    no_prelude: bool,
+    optimize: bool,
+) -> (Sources<'a>, Ast<'a>) {
+    let mut sources = Sources::new(file, source_text);
+    let mut ast = ok_or_exit!(sources.resolve_imports(ast), sources);
+    if !no_prelude {
+        ast = ok_or_exit!(
+            sources.prepend(PathBuf::from("<prelude>"), PRELUDE, ast),
+            sources
+        );
     }
+    prep::remove_unreachable(&mut ast);
+    if optimize {
+        optimize::optimize(&mut ast);
+    }
+    prep::qualify_names(&mut ast);
+    prep::resolve_slots(&mut ast, &[]);
+    (sources, ast)
+}
+
+fn run_md(file: PathBuf, capabilities: Capabilities, no_prelude: bool, optimize: bool) {
+    let markdown = ok_or_exit!(fs::read_to_string(&file));
+    let source_text = notebook::program_source(&markdown);
+    let eval_arena = EvalArena::new();
+
+    let ast = ok_or_exit!(parse_source(&source_text), file, &source_text);
+    let (sources, ast) = prepare(file.clone(), &source_text, ast, no_prelude, optimize);
+    let class_vtable = ok_or_exit!(
+        find_classes_and_methods(&ast, prep::built_in_classes()),
+        sources
+    );
+
+    let stdin = Rc::new(RefCell::new(io::stdin().lock()));
+    let markdown = ok_or_exit!(
+        notebook::run(
+            &markdown,
+            &ast,
+            &sources,
+            class_vtable,
+            capabilities,
+            &eval_arena,
+            stdin
+        ),
+        sources
+    );
+    ok_or_exit!(fs::write(&file, markdown));
+}
+
+fn print_graph(file: PathBuf, format: graph::Format, methods: bool) {
+    let source_text = ok_or_exit!(fs::read_to_string(&file));
+
+    let ast = ok_or_exit!(parse_source(&source_text), file, &source_text);
+    let mut sources = Sources::new(file, &source_text);
+    let mut ast = ok_or_exit!(sources.resolve_imports(ast), sources);
+    prep::qualify_names(&mut ast);
+    let class_vtable = ok_or_exit!(
+        find_classes_and_methods(&ast, prep::built_in_classes()),
+        sources
+    );
+
+    print!("{}", graph::render(&class_vtable, format, methods));
+}
+
+fn print_call_graph(file: PathBuf, format: callgraph::Format) {
+    let source_text = ok_or_exit!(fs::read_to_string(&file));
+
+    let ast = ok_or_exit!(parse_source(&source_text), file, &source_text);
+    let mut sources = Sources::new(file, &source_text);
+    let mut ast = ok_or_exit!(sources.resolve_imports(ast), sources);
+    prep::qualify_names(&mut ast);
+    let class_vtable = ok_or_exit!(
+        find_classes_and_methods(&ast, prep::built_in_classes()),
+        sources
+    );
+
+    print!("{}", callgraph::render(&ast, &class_vtable, format));
 }
 
-impl Span {
-    pub fn new(from: usize, to: usize) -> Self {
-        Self { from, to }
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn arguments_after_file_are_passed_to_the_program() {
+        let opt = Opt::from_iter(&[
+            "oops",
+            "--seed",
+            "1",
+            "script.oops",
+            "--report",
+            "-v",
+            "graph",
+        ]);
+        let (file, args) = split_file_and_args(opt.file_and_args);
+
+        assert_eq!(Some(1), opt.seed);
+        assert!(!opt.report);
+        assert!(opt.command.is_none());
+        assert_eq!(Some(PathBuf::from("script.oops")), file);
+        assert_eq!(vec!["--report", "-v", "graph"], args);
+
+        let opt = Opt::from_iter(&["oops", "script.oops", "--", "-v"]);
+        assert_eq!(vec!["-v"], split_file_and_args(opt.file_and_args).1);
     }
 
-    #[cfg(test)]
-    pub fn from_with(start: usize, s: &str) -> Self {
-        Self::new(start, start + s.len())
+    #[test]
+    fn capability_flags() {
+        let opt = Opt::from_iter(&["oops", "--allow-eval", "script.oops"]);
+        assert!(opt.allow_eval);
+        assert!(!opt.allow_files);
+
+        let opt = Opt::from_iter(&["oops", "script.oops"]);
+        assert!(!opt.allow_eval);
+        assert!(Opt::from_iter_safe(&["oops", "--allow_eval", "script.oops"]).is_err());
     }
 }
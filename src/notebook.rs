@@ -0,0 +1,365 @@
+//! Running the ```` ```oops ```` code blocks in a Markdown file, for `oops run-md`.
+//!
+//! The blocks run in order in a single interpreter, so locals and classes defined in one block
+//! can be used in the following ones. What each block prints is written back into the file in an
+//! ```` ```output ```` block right after it, replacing the one from the previous run.
+//!
+//! The blocks are run like a program in a `.oops` file, so imports and the prelude work the same.
+//! Statements from imported files run with the block that imports them, and the prelude runs with
+//! the first block.
+
+use crate::{
+    ast::Ast,
+    error::Result,
+    import::Sources,
+    interpret::{interpret_stmt, Capabilities, EvalArena, Interpreter},
+    prep::Classes,
+};
+use std::{
+    cell::RefCell,
+    io::{BufRead, Write},
+    mem,
+    ops::Range,
+    rc::Rc,
+};
+
+const CODE_FENCE: &str = "```oops";
+const OUTPUT_FENCE: &str = "```output";
+const CLOSING_FENCE: &str = "```";
+
+#[derive(Debug, Eq, PartialEq)]
+struct CodeBlock {
+    /// The code between the fences.
+    code: Range<usize>,
+    /// Where the line after the closing fence starts.
+    end: usize,
+    /// The output block written by a previous run, including the blank line before it.
+    previous_output: Option<Range<usize>>,
+}
+
+/// Replace everything outside the code blocks with whitespace.
+///
+/// The result can be parsed as a single program whose spans are also valid in `markdown`, so
+/// errors point at the right line of the Markdown file.
+pub fn program_source(markdown: &str) -> String {
+    let blocks = find_code_blocks(markdown);
+    let mut source = String::with_capacity(markdown.len());
+    let mut cursor = 0;
+
+    for block in &blocks {
+        blank_out(&mut source, &markdown[cursor..block.code.start]);
+        source.push_str(&markdown[block.code.clone()]);
+        cursor = block.code.end;
+    }
+    blank_out(&mut source, &markdown[cursor..]);
+
+    source
+}
+
+/// Run the code blocks in `markdown` and return it with the output of each block written back.
+///
+/// `ast` is the result of parsing `program_source(markdown)` and resolving its imports with
+/// `sources`.
+pub fn run<'a>(
+    markdown: &str,
+    ast: &'a Ast<'a>,
+    sources: &Sources<'_>,
+    classes: Classes<'a>,
+    capabilities: Capabilities,
+    eval_arena: &'a EvalArena<'a>,
+    stdin: Rc<RefCell<dyn BufRead>>,
+) -> Result<'a, String> {
+    let stdout = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter =
+        Interpreter::new(classes, Rc::clone(&stdout) as Rc<RefCell<dyn Write>>, stdin)
+            .with_capabilities(capabilities)
+            .with_eval_arena(eval_arena);
+
+    let mut outputs = vec![];
+    let mut stmts = ast.iter().peekable();
+    for block in find_code_blocks(markdown) {
+        while let Some(stmt) = stmts.next_if(|stmt| sources.main_span(stmt.span()).from < block.end)
+        {
+            interpret_stmt(&mut interpreter, stmt)?;
+        }
+
+        let output = mem::take(&mut *stdout.borrow_mut());
+        outputs.push((block, String::from_utf8_lossy(&output).to_string()));
+    }
+
+    Ok(write_outputs(markdown, outputs))
+}
+
+fn write_outputs(markdown: &str, outputs: Vec<(CodeBlock, String)>) -> String {
+    let mut acc = String::with_capacity(markdown.len());
+    let mut cursor = 0;
+
+    for (block, output) in outputs {
+        acc.push_str(&markdown[cursor..block.end]);
+        cursor = block.previous_output.map_or(block.end, |range| range.end);
+
+        if output.is_empty() {
+            continue;
+        }
+
+        if !acc.ends_with('\n') {
+            acc.push('\n');
+        }
+        acc.push('\n');
+        acc.push_str(OUTPUT_FENCE);
+        acc.push('\n');
+        acc.push_str(&output);
+        if !output.ends_with('\n') {
+            acc.push('\n');
+        }
+        acc.push_str(CLOSING_FENCE);
+        acc.push('\n');
+    }
+    acc.push_str(&markdown[cursor..]);
+
+    acc
+}
+
+fn find_code_blocks(markdown: &str) -> Vec<CodeBlock> {
+    let lines = lines_with_offsets(markdown);
+    let mut blocks = vec![];
+    let mut idx = 0;
+
+    while idx < lines.len() {
+        let (start, line) = lines[idx];
+        idx += 1;
+
+        if line.trim_end() != CODE_FENCE {
+            continue;
+        }
+
+        let code_start = start + line.len();
+        let closing = lines[idx..]
+            .iter()
+            .position(|(_, line)| line.trim_end() == CLOSING_FENCE)
+            .map(|offset| idx + offset);
+
+        let (code_end, end) = match closing {
+            Some(closing) => {
+                idx = closing + 1;
+                let (closing_start, closing_line) = lines[closing];
+                (closing_start, closing_start + closing_line.len())
+            }
+            None => {
+                idx = lines.len();
+                (markdown.len(), markdown.len())
+            }
+        };
+
+        let previous_output = find_output_block(&lines[idx..]);
+        if let Some(output) = &previous_output {
+            idx = lines
+                .iter()
+                .position(|(start, _)| *start >= output.end)
+                .unwrap_or(lines.len());
+        }
+
+        blocks.push(CodeBlock {
+            code: code_start..code_end,
+            end,
+            previous_output,
+        });
+    }
+
+    blocks
+}
+
+/// Find an output block at the start of `lines`, optionally preceded by a blank line.
+fn find_output_block(lines: &[(usize, &str)]) -> Option<Range<usize>> {
+    let (start, _) = *lines.first()?;
+    let skip = match lines.first() {
+        Some((_, line)) if line.trim().is_empty() => 1,
+        _ => 0,
+    };
+
+    let (_, fence) = lines.get(skip)?;
+    if fence.trim_end() != OUTPUT_FENCE {
+        return None;
+    }
+
+    lines[skip + 1..]
+        .iter()
+        .find(|(_, line)| line.trim_end() == CLOSING_FENCE)
+        .map(|(closing_start, closing_line)| start..closing_start + closing_line.len())
+}
+
+/// The lines of `text`, including their line endings, and the offsets they start at.
+fn lines_with_offsets(text: &str) -> Vec<(usize, &str)> {
+    let mut offset = 0;
+    text.split_inclusive('\n')
+        .map(|line| {
+            let start = offset;
+            offset += line.len();
+            (start, line)
+        })
+        .collect()
+}
+
+/// Push a space for each byte in `text`, keeping the newlines.
+fn blank_out(acc: &mut String, text: &str) {
+    for c in text.chars() {
+        if c == '\n' {
+            acc.push('\n');
+        } else {
+            acc.extend(std::iter::repeat_n(' ', c.len_utf8()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    use crate::{lex::lex, parse::parse, prep, prep::find_classes_and_methods};
+    use std::{
+        fs, io,
+        path::{Path, PathBuf},
+    };
+
+    fn run_markdown(markdown: &str) -> String {
+        run_markdown_in(Path::new("."), markdown)
+    }
+
+    fn run_markdown_in(dir: &Path, markdown: &str) -> String {
+        let source = program_source(markdown);
+        let tokens = lex(&source).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let mut sources = Sources::new(dir.join("notebook.md"), &source);
+        let ast = sources.resolve_imports(ast).unwrap();
+        let mut ast = sources
+            .prepend(PathBuf::from("<prelude>"), crate::PRELUDE, ast)
+            .unwrap();
+        prep::qualify_names(&mut ast);
+        prep::resolve_slots(&mut ast, &[]);
+        let classes = find_classes_and_methods(&ast, prep::built_in_classes()).unwrap();
+        let stdin = Rc::new(RefCell::new(io::empty()));
+        let eval_arena = EvalArena::new();
+        run(
+            markdown,
+            &ast,
+            &sources,
+            classes,
+            Capabilities::default(),
+            &eval_arena,
+            stdin,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn writes_output_under_each_block() {
+        let markdown = [
+            "# Title",
+            "",
+            "```oops",
+            "let xs = [1, 2];",
+            "[Console println: xs];",
+            "```",
+            "",
+            "Nothing printed here:",
+            "",
+            "```oops",
+            "let ys = [xs map: |x:| { return [x equals: 1]; }];",
+            "```",
+            "",
+            "```oops",
+            "[Console println: ys];",
+            "```",
+            "",
+        ]
+        .join("\n");
+
+        let expected = [
+            "# Title",
+            "",
+            "```oops",
+            "let xs = [1, 2];",
+            "[Console println: xs];",
+            "```",
+            "",
+            "```output",
+            "[1, 2]",
+            "```",
+            "",
+            "Nothing printed here:",
+            "",
+            "```oops",
+            "let ys = [xs map: |x:| { return [x equals: 1]; }];",
+            "```",
+            "",
+            "```oops",
+            "[Console println: ys];",
+            "```",
+            "",
+            "```output",
+            "[true, false]",
+            "```",
+            "",
+        ]
+        .join("\n");
+
+        let output = run_markdown(&markdown);
+        assert_eq!(expected, output);
+        assert_eq!(expected, run_markdown(&output));
+    }
+
+    #[test]
+    fn spans_point_into_the_markdown() {
+        let markdown = "Some text\n\n```oops\nlet x = y;\n```\n";
+        let source = program_source(markdown);
+        assert_eq!(markdown.len(), source.len());
+
+        let tokens = lex(&source).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let (start, _) = ast[0].span().line_col_range(markdown);
+        assert_eq!(4, start.line);
+    }
+
+    #[test]
+    fn blocks_run_like_programs() {
+        let dir = std::env::temp_dir().join(format!("oops-notebook-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("greeting.oops"), "let GREETING = \"hi\";\n").unwrap();
+
+        let markdown = [
+            "```oops",
+            "[Console println: [[1, 2] sum]];",
+            "```",
+            "",
+            "```oops",
+            "[Oops import: \"greeting.oops\"];",
+            "[Console println: GREETING];",
+            "```",
+            "",
+        ]
+        .join("\n");
+        let expected = [
+            "```oops",
+            "[Console println: [[1, 2] sum]];",
+            "```",
+            "",
+            "```output",
+            "3",
+            "```",
+            "",
+            "```oops",
+            "[Oops import: \"greeting.oops\"];",
+            "[Console println: GREETING];",
+            "```",
+            "",
+            "```output",
+            "hi",
+            "```",
+            "",
+        ]
+        .join("\n");
+
+        assert_eq!(expected, run_markdown_in(&dir, &markdown));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
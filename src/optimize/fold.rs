@@ -0,0 +1,294 @@
+//! Constant folding, which does arithmetic and comparisons on literals before the program runs.
+//!
+//! `2 * 60` becomes `120`, `"a" == "b"` becomes `false`, and a `caseOf:` whose subject and arms are
+//! literals becomes the body of the arm that matches. Lists of literals are marked constant so
+//! they're only built once. Sends are left alone if the program defines a method with the same
+//! name on any class, since it could change what the message does, and so are sends that would
+//! fail, so the error happens when the program runs.
+
+use super::{each_expr, Pass};
+use crate::{
+    ast::{Argument, Ast, CaseOf, Expr, False, List, MessageSend, Number, Stmt, True},
+    Span,
+};
+use std::{cmp::Ordering, collections::HashSet, mem};
+
+pub struct ConstantFolding;
+
+impl Pass for ConstantFolding {
+    fn run(&self, ast: &mut Ast<'_>) {
+        let mut defined = HashSet::new();
+        defined_selectors(ast, &mut defined);
+
+        each_expr(ast, &mut |expr| {
+            if let Some(folded) = fold(expr, &defined) {
+                *expr = folded;
+            }
+        });
+    }
+}
+
+/// A literal, as far as folding is concerned.
+#[derive(PartialEq)]
+enum Literal<'e> {
+    Number(i32),
+    Str(&'e str),
+    Selector(&'e str),
+    Boolean(bool),
+}
+
+impl<'e> Literal<'e> {
+    fn of(expr: &'e Expr<'_>) -> Option<Self> {
+        match expr {
+            Expr::Number(number) => Some(Literal::Number(number.number)),
+            Expr::Str(string) => Some(Literal::Str(&string.value)),
+            Expr::Selector(selector) => Some(Literal::Selector(selector.ident.name)),
+            Expr::True(_) => Some(Literal::Boolean(true)),
+            Expr::False(_) => Some(Literal::Boolean(false)),
+            _ => None,
+        }
+    }
+
+    /// Whether `[self equals: other]` is true, if the literals can be compared.
+    fn equals(&self, other: &Literal<'_>) -> Option<bool> {
+        match (self, other) {
+            (Literal::Number(lhs), Literal::Number(rhs)) => Some(lhs == rhs),
+            (Literal::Str(lhs), Literal::Str(rhs)) => Some(lhs == rhs),
+            (Literal::Selector(lhs), Literal::Selector(rhs)) => Some(lhs == rhs),
+            (Literal::Boolean(lhs), Literal::Boolean(rhs)) => Some(lhs == rhs),
+            _ => None,
+        }
+    }
+}
+
+fn fold<'a>(expr: &mut Expr<'a>, defined: &HashSet<&str>) -> Option<Expr<'a>> {
+    match expr {
+        Expr::MessageSend(send) if !defined.contains(send.msg.name) => fold_send(send),
+        Expr::CaseOf(case_of) => fold_case_of(case_of),
+        Expr::List(list) if list.items.iter().all(is_constant) => {
+            let items = mem::take(&mut list.items);
+            Some(Expr::ConstantList(List {
+                items,
+                span: list.span,
+            }))
+        }
+        _ => None,
+    }
+}
+
+fn is_constant(expr: &Expr<'_>) -> bool {
+    matches!(expr, Expr::ConstantList(_)) || Literal::of(expr).is_some()
+}
+
+fn fold_send<'a>(send: &MessageSend<'a>) -> Option<Expr<'a>> {
+    let arg = match send.args.as_slice() {
+        [Argument { ident, expr, .. }] if ident.name == send.msg.name => expr,
+        _ => return None,
+    };
+    let (lhs, rhs) = (Literal::of(&send.receiver)?, Literal::of(arg)?);
+    let span = send.span;
+
+    match (send.msg.name, lhs, rhs) {
+        ("add", Literal::Number(lhs), Literal::Number(rhs)) => number(lhs.checked_add(rhs)?, span),
+        ("subtract", Literal::Number(lhs), Literal::Number(rhs)) => {
+            number(lhs.checked_sub(rhs)?, span)
+        }
+        ("multiply", Literal::Number(lhs), Literal::Number(rhs)) => {
+            number(lhs.checked_mul(rhs)?, span)
+        }
+        // Division that isn't exact results in a float, which has no literal
+        ("divide", Literal::Number(lhs), Literal::Number(rhs))
+            if lhs.checked_rem(rhs) == Some(0) =>
+        {
+            number(lhs.checked_div(rhs)?, span)
+        }
+        ("equals", lhs, rhs) => boolean(lhs.equals(&rhs)?, span),
+        ("notEquals", lhs, rhs) => boolean(!lhs.equals(&rhs)?, span),
+        ("lessThan", lhs, rhs) => boolean(compare(&lhs, &rhs)? == Ordering::Less, span),
+        ("greaterThan", lhs, rhs) => boolean(compare(&lhs, &rhs)? == Ordering::Greater, span),
+        _ => None,
+    }
+}
+
+fn compare(lhs: &Literal<'_>, rhs: &Literal<'_>) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Literal::Number(lhs), Literal::Number(rhs)) => Some(lhs.cmp(rhs)),
+        (Literal::Str(lhs), Literal::Str(rhs)) => Some(lhs.cmp(rhs)),
+        _ => None,
+    }
+}
+
+fn number<'a>(number: i32, span: Span) -> Option<Expr<'a>> {
+    Some(Expr::Number(Number { number, span }))
+}
+
+fn boolean<'a>(value: bool, span: Span) -> Option<Expr<'a>> {
+    Some(if value {
+        Expr::True(True(span))
+    } else {
+        Expr::False(False(span))
+    })
+}
+
+/// Choose the arm of a `caseOf:` on a literal whose arms are literals. The chosen body is kept in a
+/// `caseOf:` without arms when it's called if it's a block, or replaces the `caseOf:` when it's a
+/// literal.
+fn fold_case_of<'a>(case_of: &mut CaseOf<'a>) -> Option<Expr<'a>> {
+    if case_of.arms.is_empty() {
+        return None;
+    }
+    let subject = Literal::of(&case_of.subject)?;
+
+    let mut chosen = None;
+    for (index, arm) in case_of.arms.iter().enumerate() {
+        if subject.equals(&Literal::of(&arm.value)?)? {
+            chosen = Some(index);
+            break;
+        }
+    }
+
+    let body = match chosen {
+        Some(index) => Some(case_of.arms.swap_remove(index).body),
+        None => case_of.otherwise.take(),
+    };
+    case_of.arms.clear();
+
+    match body {
+        Some(body) if is_constant(&body) => Some(body),
+        body => {
+            case_of.otherwise = body;
+            None
+        }
+    }
+}
+
+/// The names of the methods the program defines anywhere, including in blocks and quotes.
+fn defined_selectors<'a>(stmts: &[Stmt<'a>], defined: &mut HashSet<&'a str>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::LetLocal(inner) => expr_selectors(&inner.body, defined),
+            Stmt::LetIVar(inner) => expr_selectors(&inner.body, defined),
+            Stmt::LetConstant(inner) => expr_selectors(&inner.body, defined),
+            Stmt::MessageSend(inner) => send_selectors(&inner.expr, defined),
+            Stmt::Return(inner) => expr_selectors(&inner.expr, defined),
+            Stmt::DefineMethod(inner) => {
+                defined.insert(inner.method_name.ident.name);
+                defined_selectors(&inner.block.body, defined);
+            }
+            Stmt::DefineAbstractMethod(inner) => {
+                defined.insert(inner.method_name.ident.name);
+            }
+            Stmt::Module(inner) => defined_selectors(&inner.body, defined),
+            Stmt::CaseOf(inner) => case_of_selectors(&inner.expr, defined),
+            Stmt::DefineClass(_) | Stmt::ReopenClass(_) => {}
+        }
+    }
+}
+
+fn expr_selectors<'a>(expr: &Expr<'a>, defined: &mut HashSet<&'a str>) {
+    match expr {
+        Expr::MessageSend(inner) => send_selectors(inner, defined),
+        Expr::ClassNew(inner) => {
+            for arg in &inner.args {
+                expr_selectors(&arg.expr, defined);
+            }
+        }
+        Expr::Block(inner) => defined_selectors(&inner.body, defined),
+        Expr::Quote(inner) => defined_selectors(&inner.body, defined),
+        Expr::List(inner) | Expr::ConstantList(inner) => {
+            for item in &inner.items {
+                expr_selectors(item, defined);
+            }
+        }
+        Expr::CaseOf(inner) => case_of_selectors(inner, defined),
+        _ => {}
+    }
+}
+
+fn send_selectors<'a>(send: &MessageSend<'a>, defined: &mut HashSet<&'a str>) {
+    expr_selectors(&send.receiver, defined);
+    for arg in &send.args {
+        expr_selectors(&arg.expr, defined);
+    }
+}
+
+fn case_of_selectors<'a>(case_of: &CaseOf<'a>, defined: &mut HashSet<&'a str>) {
+    expr_selectors(&case_of.subject, defined);
+    for arm in &case_of.arms {
+        expr_selectors(&arm.value, defined);
+        expr_selectors(&arm.body, defined);
+    }
+    if let Some(otherwise) = &case_of.otherwise {
+        expr_selectors(otherwise, defined);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{lex::lex, parse::parse};
+
+    fn folded(source: &str) -> String {
+        let tokens = lex(source).unwrap();
+        let mut ast = parse(&tokens).unwrap();
+        ConstantFolding.run(&mut ast);
+        format!("{:?}", ast)
+    }
+
+    fn same(lhs: &str, rhs: &str) {
+        let strip = |debug: String| {
+            // Folded nodes have the spans of what they replace
+            regex::Regex::new(r"Span\(\d+\.\.\d+\)")
+                .unwrap()
+                .replace_all(&debug, "_")
+                .to_string()
+        };
+        assert_eq!(strip(folded(lhs)), strip(folded(rhs)));
+    }
+
+    #[test]
+    fn arithmetic_and_comparisons() {
+        same("let a = 2 * 60 + 1;", "let a = 121;");
+        same("let a = (10 - 4) / 3;", "let a = 2;");
+        same("let a = [\"a\" equals: \"a\"];", "let a = true;");
+        same("let a = 1 < 2 == true;", "let a = true;");
+        same("let a = #a != #b;", "let a = true;");
+    }
+
+    #[test]
+    fn leaves_errors_and_redefined_messages_alone() {
+        same("let a = 7 / 2;", "let a = 7 / 2;");
+        same("let a = 1 / 0;", "let a = 1 / 0;");
+        same("let a = 2147483647 + 1;", "let a = 2147483647 + 1;");
+        same("let a = 1 == \"1\";", "let a = 1 == \"1\";");
+        same(
+            "[Number def: #add do: |add:| { return 0; }]; let a = 1 + 2;",
+            "[Number def: #add do: |add:| { return 0; }]; let a = 1 + 2;",
+        );
+        same(
+            "let a = quote { return 1 + 2; };",
+            "let a = quote { return 1 + 2; };",
+        );
+    }
+
+    #[test]
+    fn case_of() {
+        same(
+            "let a = [2 caseOf: [[1, \"one\"], [1 + 1, \"two\"]] otherwise: \"many\"];",
+            "let a = \"two\";",
+        );
+        same(
+            "let a = [3 caseOf: [[1, \"one\"]]];",
+            "let a = [3 caseOf: []];",
+        );
+        assert!(folded("let a = [true caseOf: [[true, || { return 1; }]]];")
+            .contains("otherwise: Some(Block"));
+    }
+
+    #[test]
+    fn constant_lists() {
+        same("let a = [1, [2 + 3, \"b\"]];", "let a = [1, [5, \"b\"]];");
+        assert!(folded("let a = [1, [2, \"b\"]];").contains("body: ConstantList"));
+        assert!(!folded("let a = [1, b];").contains("ConstantList"));
+    }
+}
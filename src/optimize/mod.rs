@@ -0,0 +1,90 @@
+//! Optimizations of the program's AST, run before classes are found when `oops -O FILE` is used.
+//!
+//! Each pass rewrites the AST in place into a program that prints the same thing. Optimized
+//! programs can evaluate fewer nodes, so they use less of the `--max-steps` budget. Source
+//! evaluated at runtime isn't optimized, and passes can't see methods it defines.
+
+mod fold;
+
+use crate::ast::{Ast, CaseOf, Expr, MessageSend, Stmt};
+
+pub use fold::ConstantFolding;
+
+pub trait Pass {
+    fn run(&self, ast: &mut Ast<'_>);
+}
+
+/// The passes `optimize` runs, in order.
+const PASSES: &[&dyn Pass] = &[&ConstantFolding];
+
+pub fn optimize(ast: &mut Ast<'_>) {
+    for pass in PASSES {
+        pass.run(ast);
+    }
+}
+
+/// Call `f` with every expression in `stmts`, including those in blocks and methods, after the
+/// expressions inside it. Quoted statements are data, so they're left alone.
+fn each_expr<'a>(stmts: &mut [Stmt<'a>], f: &mut dyn FnMut(&mut Expr<'a>)) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::LetLocal(inner) => expr(&mut inner.body, f),
+            Stmt::LetIVar(inner) => expr(&mut inner.body, f),
+            Stmt::LetConstant(inner) => expr(&mut inner.body, f),
+            Stmt::MessageSend(inner) => message_send(&mut inner.expr, f),
+            Stmt::Return(inner) => expr(&mut inner.expr, f),
+            Stmt::DefineMethod(inner) => each_expr(&mut inner.block.body, f),
+            Stmt::Module(inner) => each_expr(&mut inner.body, f),
+            Stmt::CaseOf(inner) => case_of(&mut inner.expr, f),
+            Stmt::DefineAbstractMethod(_) | Stmt::DefineClass(_) | Stmt::ReopenClass(_) => {}
+        }
+    }
+}
+
+fn expr<'a>(node: &mut Expr<'a>, f: &mut dyn FnMut(&mut Expr<'a>)) {
+    match node {
+        Expr::MessageSend(inner) => message_send(inner, f),
+        Expr::ClassNew(inner) => {
+            for arg in &mut inner.args {
+                expr(&mut arg.expr, f);
+            }
+        }
+        Expr::Block(inner) => each_expr(&mut inner.body, f),
+        Expr::List(inner) | Expr::ConstantList(inner) => {
+            for item in &mut inner.items {
+                expr(item, f);
+            }
+        }
+        Expr::CaseOf(inner) => case_of(inner, f),
+        Expr::Local(_)
+        | Expr::IVar(_)
+        | Expr::ClassName(_)
+        | Expr::Number(_)
+        | Expr::Str(_)
+        | Expr::Selector(_)
+        | Expr::True(_)
+        | Expr::False(_)
+        | Expr::Self_(_)
+        | Expr::Quote(_)
+        | Expr::Unquote(_) => {}
+    }
+    f(node);
+}
+
+fn message_send<'a>(send: &mut MessageSend<'a>, f: &mut dyn FnMut(&mut Expr<'a>)) {
+    expr(&mut send.receiver, f);
+    for arg in &mut send.args {
+        expr(&mut arg.expr, f);
+    }
+}
+
+fn case_of<'a>(case_of: &mut CaseOf<'a>, f: &mut dyn FnMut(&mut Expr<'a>)) {
+    expr(&mut case_of.subject, f);
+    for arm in &mut case_of.arms {
+        expr(&mut arm.value, f);
+        expr(&mut arm.body, f);
+    }
+    if let Some(otherwise) = &mut case_of.otherwise {
+        expr(otherwise, f);
+    }
+}
@@ -3,34 +3,196 @@ use crate::{
     error::{Error, Result},
     lex::{self, Token},
 };
-use std::fmt::Debug;
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug},
+};
+
+#[cfg_attr(feature = "observability", tracing::instrument(skip_all))]
+pub fn parse<'a>(tokens: &'a Vec<Token<'a>>) -> Result<'a, Vec<Stmt<'a>>> {
+    parse_with_stats(tokens).0
+}
+
+/// Parse `tokens` and count how much the parser had to backtrack to do so.
+pub fn parse_with_stats<'a>(tokens: &'a Vec<Token<'a>>) -> (Result<'a, Vec<Stmt<'a>>>, ParseStats) {
+    parse_stream(ParseStream::new(tokens))
+}
+
+/// Lex and parse `program` together, so only the tokens of the statement being parsed are kept
+/// in memory.
+#[cfg_attr(feature = "observability", tracing::instrument(skip_all))]
+pub fn parse_source(program: &str) -> Result<'_, Vec<Stmt<'_>>> {
+    parse_source_with_stats(program).0
+}
 
-pub fn parse<'a>(tokens: &'a Vec<Token<'a>>) -> Result<Vec<Stmt<'a>>> {
-    let mut stream = ParseStream::new(tokens);
-    let acc = stream.parse_many::<Stmt>();
+/// Like `parse_with_stats`, but lexing `program` as it's parsed.
+pub fn parse_source_with_stats(program: &str) -> (Result<'_, Vec<Stmt<'_>>>, ParseStats) {
+    parse_stream(ParseStream::lexing(program))
+}
+
+fn parse_stream(mut stream: ParseStream<'_>) -> (Result<'_, Vec<Stmt<'_>>>, ParseStats) {
+    let mut ast = vec![];
+    while !stream.at_eof() {
+        match stream.try_parse_node::<Stmt>() {
+            Some(stmt) => ast.push(stmt),
+            None => break,
+        }
+        // The parser never backtracks into a statement it has parsed
+        stream.release_parsed();
+    }
+
+    let at_eof = stream.at_eof();
+    let result = match stream.lex_error() {
+        Some(err) => Err(err),
+        None if !at_eof => Err(Error::ParseError("Expected EOF, but wasn't".to_string())),
+        None => Ok(ast),
+    };
+    (result, stream.stats)
+}
 
-    if !stream.at_eof() {
-        Err(Error::ParseError("Expected EOF, but wasn't".to_string()))
+/// Input typed into the REPL, parsed with `parse_interactive`.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Interactive<'a> {
+    Complete(Vec<Stmt<'a>>),
+    /// The input ends inside brackets, braces, or parentheses, so the next line may finish it.
+    Incomplete,
+}
+
+/// Parse input that may not be finished yet.
+///
+/// Input is only a syntax error if every `[`, `{`, and `(` in it is closed, or one is closed by
+/// the wrong kind of bracket. Otherwise it's `Incomplete` and the REPL asks for another line.
+pub fn parse_interactive<'a>(tokens: &'a Vec<Token<'a>>) -> Result<'a, Interactive<'a>> {
+    if has_unclosed_brackets(tokens) {
+        Ok(Interactive::Incomplete)
     } else {
-        Ok(acc)
+        parse(tokens).map(Interactive::Complete)
+    }
+}
+
+fn has_unclosed_brackets(tokens: &[Token<'_>]) -> bool {
+    let mut open = vec![];
+    for token in tokens {
+        let closes = match token {
+            Token::OBracket(_) | Token::OBrace(_) | Token::OParen(_) => {
+                open.push(token);
+                continue;
+            }
+            Token::CBracket(_) => matches!(open.pop(), Some(Token::OBracket(_))),
+            Token::CBrace(_) => matches!(open.pop(), Some(Token::OBrace(_))),
+            Token::CParen(_) => matches!(open.pop(), Some(Token::OParen(_))),
+            _ => continue,
+        };
+        if !closes {
+            return false;
+        }
+    }
+    !open.is_empty()
+}
+
+/// How much work the parser did, printed by `oops --parse-stats`.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct ParseStats {
+    /// Calls to `try_parse_node`.
+    pub node_attempts: usize,
+    /// Attempts that failed, so the stream was rewound.
+    pub backtracks: usize,
+    /// The most tokens rewound by a single backtrack.
+    pub max_backtrack: usize,
+}
+
+impl fmt::Display for ParseStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Node attempts: {}", self.node_attempts)?;
+        writeln!(f, "Backtracks: {}", self.backtracks)?;
+        writeln!(f, "Longest backtrack: {} tokens", self.max_backtrack)
     }
 }
 
 pub struct ParseStream<'a> {
-    tokens: &'a Vec<Token<'a>>,
+    tokens: Supply<'a>,
     current_position: usize,
+    stats: ParseStats,
+}
+
+/// Where a `ParseStream` gets its tokens from.
+enum Supply<'a> {
+    /// Tokens that were lexed before parsing started.
+    Lexed(&'a [Token<'a>]),
+    /// Tokens that are lexed as the parser gets to them. They're kept in `buffer` so the parser
+    /// can backtrack, until `release` drops those before `offset`.
+    Lexing {
+        lexer: Box<lex::Tokens<'a>>,
+        buffer: VecDeque<Token<'a>>,
+        offset: usize,
+        error: Option<Error<'a>>,
+    },
+}
+
+impl<'a> Supply<'a> {
+    fn get(&mut self, position: usize) -> Option<&Token<'a>> {
+        match self {
+            Supply::Lexed(tokens) => tokens.get(position),
+            Supply::Lexing {
+                lexer,
+                buffer,
+                offset,
+                error,
+            } => {
+                while *offset + buffer.len() <= position {
+                    match lexer.next()? {
+                        Ok(token) => buffer.push_back(token),
+                        Err(err) => {
+                            *error = Some(err);
+                            return None;
+                        }
+                    }
+                }
+                buffer.get(position - *offset)
+            }
+        }
+    }
+
+    /// Drop the buffered tokens before `position`, which the parser won't backtrack to.
+    fn release(&mut self, position: usize) {
+        if let Supply::Lexing { buffer, offset, .. } = self {
+            let released = position.saturating_sub(*offset).min(buffer.len());
+            buffer.drain(..released);
+            *offset += released;
+        }
+    }
 }
 
 impl<'a> ParseStream<'a> {
-    pub fn new(tokens: &'a Vec<Token<'a>>) -> Self {
+    pub fn new(tokens: &'a [Token<'a>]) -> Self {
+        Self::with_supply(Supply::Lexed(tokens))
+    }
+
+    /// A stream that lexes `program` as it's parsed.
+    pub fn lexing(program: &'a str) -> Self {
+        Self::with_supply(Supply::Lexing {
+            lexer: Box::new(lex::tokens(program)),
+            buffer: VecDeque::new(),
+            offset: 0,
+            error: None,
+        })
+    }
+
+    fn with_supply(tokens: Supply<'a>) -> Self {
         Self {
             tokens,
             current_position: 0,
+            stats: ParseStats::default(),
         }
     }
 
     pub fn parse_token<T: lex::Parse<'a>>(&mut self) -> Result<'a, &T> {
-        let token = &self.tokens[self.current_position];
+        let token = self.tokens.get(self.current_position).ok_or_else(|| {
+            Error::ParseError(format!(
+                "Expected '{}' but got end of file",
+                T::debug_name()
+            ))
+        })?;
         self.current_position += 1;
         let node = T::from_token(token);
 
@@ -46,7 +208,7 @@ impl<'a> ParseStream<'a> {
     pub fn try_parse_token<T: lex::Parse<'a>>(&mut self) -> Option<&T> {
         let start_position = self.current_position;
 
-        let token = &self.tokens[self.current_position];
+        let token = self.tokens.get(self.current_position)?;
         self.current_position += 1;
         let node = T::from_token(token);
 
@@ -58,16 +220,28 @@ impl<'a> ParseStream<'a> {
         }
     }
 
+    /// The next token if it's a `T`, without consuming it.
+    pub fn peek_token<T: lex::Parse<'a>>(&mut self) -> Option<&T> {
+        self.tokens
+            .get(self.current_position)
+            .and_then(T::from_token)
+    }
+
     pub fn parse_node<T: Parse<'a>>(&mut self) -> Result<'a, T> {
         T::parse(self)
     }
 
     pub fn try_parse_node<T: Parse<'a>>(&mut self) -> Option<T> {
         let start_position = self.current_position;
+        self.stats.node_attempts += 1;
 
         if let Ok(node) = T::parse(self) {
             Some(node)
         } else {
+            let distance = self.current_position.saturating_sub(start_position);
+            self.stats.backtracks += 1;
+            self.stats.max_backtrack = self.stats.max_backtrack.max(distance);
+
             self.current_position = start_position;
             None
         }
@@ -115,7 +289,12 @@ impl<'a> ParseStream<'a> {
         acc
     }
 
-    pub fn parse_many_delimited<Node: Parse<'a>, Token: lex::Parse<'a>>(&mut self) -> Vec<Node> {
+    /// Parse nodes separated by `Token`s. A `Token` after the last node is allowed, so lists
+    /// written over several lines can have one on every line.
+    pub fn parse_many_delimited<Node: Parse<'a>, Token: lex::Parse<'a>>(
+        &mut self,
+        delimiters: Delimiters,
+    ) -> Vec<Node> {
         let mut acc = vec![];
         loop {
             if self.at_eof() {
@@ -128,22 +307,50 @@ impl<'a> ParseStream<'a> {
                 break;
             }
 
-            if self.try_parse_token::<Token>().is_none() {
+            if self.try_parse_token::<Token>().is_none() && delimiters == Delimiters::Required {
                 break;
             }
         }
         acc
     }
 
-    pub fn at_eof(&self) -> bool {
-        self.current_position >= self.tokens.len()
+    pub fn at_eof(&mut self) -> bool {
+        self.tokens.get(self.current_position).is_none()
+    }
+
+    /// Drop the tokens that have been parsed, once the parser won't backtrack to them.
+    fn release_parsed(&mut self) {
+        self.tokens.release(self.current_position);
+    }
+
+    /// The first error in lexing the rest of the program, if the tokens are lexed as they're
+    /// parsed. It's reported instead of any parse error, like it is when lexing comes first.
+    fn lex_error(&mut self) -> Option<Error<'a>> {
+        match &mut self.tokens {
+            Supply::Lexed(_) => None,
+            Supply::Lexing { lexer, error, .. } => {
+                error.take().or_else(|| lexer.find_map(|token| token.err()))
+            }
+        }
     }
 }
 
+/// Whether `parse_many_delimited` needs a delimiter between nodes.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Delimiters {
+    /// Like the commas in `[1, 2]`.
+    Required,
+    /// Like the commas in `fields: [#x #y]`, which can also be written `fields: [#x, #y]`.
+    Optional,
+}
+
 pub trait Parse<'a>: Sized {
     fn parse(stream: &mut ParseStream<'a>) -> Result<'a, Self>;
 }
 
+#[cfg(test)]
+mod snapshot_test;
+
 #[cfg(test)]
 mod test {
     #[allow(unused_imports)]
@@ -153,7 +360,7 @@ mod test {
     #[test]
     fn let_number() {
         let program = "let number = 1;";
-        let tokens = lex(&program).unwrap();
+        let tokens = lex(program).unwrap();
         let ast = parse(&tokens).unwrap();
 
         assert_eq!(
@@ -167,6 +374,7 @@ mod test {
                     number: 1,
                     span: Span::new(13, 14)
                 }),
+                slot: Slot::default(),
                 span: Span::new(0, 15),
             })]
         );
@@ -175,7 +383,7 @@ mod test {
     #[test]
     fn let_name() {
         let program = "let a = b;";
-        let tokens = lex(&program).unwrap();
+        let tokens = lex(program).unwrap();
         let ast = parse(&tokens).unwrap();
 
         assert_eq!(
@@ -185,12 +393,188 @@ mod test {
                     name: "a",
                     span: Span::new(4, 5)
                 },
-                body: Expr::Local(Local(Ident {
-                    name: "b",
-                    span: Span::new(8, 9),
-                })),
+                body: Expr::Local(Local(
+                    Ident {
+                        name: "b",
+                        span: Span::new(8, 9),
+                    },
+                    Slot::default()
+                )),
+                slot: Slot::default(),
                 span: Span::new(0, 10),
             })]
         );
     }
+
+    #[test]
+    fn keyword_message_send() {
+        let program = "[list map: #id];";
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![Stmt::MessageSend(MessageSendStmt {
+                expr: MessageSend {
+                    receiver: Expr::Local(Local(
+                        Ident {
+                            name: "list",
+                            span: Span::new(1, 5),
+                        },
+                        Slot::default()
+                    )),
+                    msg: Ident {
+                        name: "map",
+                        span: Span::new(6, 9),
+                    },
+                    args: vec![Argument {
+                        ident: Ident {
+                            name: "map",
+                            span: Span::new(6, 9),
+                        },
+                        expr: Expr::Selector(Selector {
+                            ident: Ident {
+                                name: "id",
+                                span: Span::new(12, 14),
+                            },
+                            span: Span::new(11, 14),
+                        }),
+                        span: Span::new(6, 14),
+                    }],
+                    span: Span::new(0, 15),
+                },
+                span: Span::new(0, 16),
+            })]
+        );
+    }
+
+    #[test]
+    fn unary_message_chain() {
+        let program = "[list reverse first];";
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![Stmt::MessageSend(MessageSendStmt {
+                expr: MessageSend {
+                    receiver: Expr::MessageSend(Box::new(MessageSend {
+                        receiver: Expr::Local(Local(
+                            Ident {
+                                name: "list",
+                                span: Span::new(1, 5),
+                            },
+                            Slot::default()
+                        )),
+                        msg: Ident {
+                            name: "reverse",
+                            span: Span::new(6, 13),
+                        },
+                        args: vec![],
+                        span: Span::new(0, 13),
+                    })),
+                    msg: Ident {
+                        name: "first",
+                        span: Span::new(14, 19),
+                    },
+                    args: vec![],
+                    span: Span::new(0, 20),
+                },
+                span: Span::new(0, 21),
+            })]
+        );
+    }
+
+    #[test]
+    fn parse_stats() {
+        let program = "[list map: |x:| { return [x foo]; }];";
+        let tokens = lex(program).unwrap();
+        let (ast, stats) = parse_with_stats(&tokens);
+        ast.unwrap();
+
+        // Update these when changing how the parser looks ahead. They should only go down.
+        assert_eq!(
+            ParseStats {
+                node_attempts: 70,
+                backtracks: 58,
+                max_backtrack: 3,
+            },
+            stats
+        );
+    }
+
+    #[test]
+    fn interactive_input() {
+        let is_incomplete = |program: &str| {
+            let tokens = lex(program).unwrap();
+            let parsed = parse_interactive(&tokens).map(|parsed| parsed == Interactive::Incomplete);
+            parsed.map_err(|err| err.to_string())
+        };
+
+        assert_eq!(Ok(false), is_incomplete("let a = [1, 2];"));
+        assert_eq!(Ok(true), is_incomplete("let a = [1,"));
+        assert_eq!(Ok(true), is_incomplete("[list map: |x:| {\n  return x;"));
+        assert_eq!(Ok(true), is_incomplete("module Shapes {"));
+        assert!(is_incomplete("let a = 1").is_err());
+        assert!(is_incomplete("let a = [1, 2};").is_err());
+        assert!(is_incomplete("[a foo]];").is_err());
+    }
+
+    #[test]
+    fn trailing_commas() {
+        let program = "
+            [Object subclass name: #Point fields: [
+                #x,
+                #y,
+            ]];
+            [Point reopen fields: [#z #w,]];
+            let list = [
+                1,
+                2,
+            ];
+        ";
+        let tokens = lex(program).unwrap();
+        let ast = parse(&tokens).unwrap();
+
+        match &ast[..] {
+            [Stmt::DefineClass(class), Stmt::ReopenClass(reopen), Stmt::LetLocal(LetLocal {
+                body: Expr::List(list),
+                ..
+            })] => {
+                assert_eq!(2, class.fields.len());
+                assert_eq!(2, reopen.fields.len());
+                assert_eq!(2, list.items.len());
+            }
+            other => panic!("unexpected statements {:?}", other),
+        }
+
+        for program in &["let a = [1 2];", "let a = [1,,];", "let a = [,];"] {
+            let tokens = lex(program).unwrap();
+            assert!(parse(&tokens).is_err(), "{} parsed", program);
+        }
+    }
+
+    #[test]
+    fn lexing_while_parsing() {
+        let program = "[Object subclass name: #A fields: []];\nlet a = [1, (2 + 3)];\nreturn a;\n";
+        let tokens = lex(program).unwrap();
+        assert_eq!(parse_source(program).unwrap(), parse(&tokens).unwrap());
+
+        let mut stream = ParseStream::lexing("let a = 1; let b = [a, 2];");
+        stream.parse_node::<Stmt>().unwrap();
+        stream.release_parsed();
+        // Only the first statement has been lexed, and its tokens are gone
+        assert!(matches!(
+            &stream.tokens,
+            Supply::Lexing { buffer, offset: 5, .. } if buffer.is_empty()
+        ));
+
+        // Lex errors come first, even after a parse error
+        assert_error!(
+            parse_source("let a = ; let b = 1 ½"),
+            Error::LexError { at: 20 }
+        );
+        assert_error!(parse_source("let a = \"open"), Error::LexError { at: 8 });
+        assert_error!(parse_source("let a = ;"), Error::ParseError(_));
+    }
 }
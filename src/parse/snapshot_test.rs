@@ -0,0 +1,70 @@
+//! Snapshots of the parsed tree for every kind of node, so changes to the grammar show up as
+//! diffs in review.
+//!
+//! Run `INSTA_UPDATE=always cargo test` (or `cargo insta review`) to accept changes.
+
+use super::parse;
+use crate::lex::lex;
+use insta::assert_debug_snapshot;
+
+macro_rules! snapshot_test {
+    ( $name:ident, $program:expr ) => {
+        #[test]
+        fn $name() {
+            let program = $program;
+            let tokens = lex(program).unwrap();
+            insta::with_settings!({ description => program }, {
+                assert_debug_snapshot!(parse(&tokens));
+            });
+        }
+    };
+}
+
+//
+// Statements
+//
+
+snapshot_test!(let_local, "let a = 1;");
+snapshot_test!(let_ivar, "let @a = 1;");
+snapshot_test!(message_send_stmt, "[user save];");
+snapshot_test!(return_stmt, "return 1;");
+snapshot_test!(define_method, "[User def: #id do: || { return @id; }];");
+snapshot_test!(
+    define_class,
+    "[Object subclass name: #User fields: [#id #name]];"
+);
+
+//
+// Expressions
+//
+
+snapshot_test!(local, "let a = b;");
+snapshot_test!(ivar, "let a = @b;");
+snapshot_test!(message_send, "let a = [user follow user: other source: 1];");
+snapshot_test!(keyword_message_send, "let a = [users map: #id];");
+snapshot_test!(nested_message_send, "let a = [[user friends] first];");
+snapshot_test!(class_new, "let a = [User new id: 1];");
+snapshot_test!(class_name, "let a = User;");
+snapshot_test!(block, "let a = |x: y:| { return x; };");
+snapshot_test!(number, "let a = 123;");
+snapshot_test!(list, "let a = [1, b, [2]];");
+snapshot_test!(empty_list, "let a = [];");
+snapshot_test!(selector, "let a = #id;");
+snapshot_test!(true_, "let a = true;");
+snapshot_test!(false_, "let a = false;");
+snapshot_test!(self_, "let a = self;");
+snapshot_test!(string, r#"let a = "a \"string\"";"#);
+snapshot_test!(quote, "let a = quote { return [~b, 1]; };");
+
+//
+// Errors
+//
+
+snapshot_test!(missing_semicolon, "let a = 1");
+snapshot_test!(missing_closing_bracket, "[user save;");
+snapshot_test!(unexpected_token, "let = 1;");
+snapshot_test!(stmt_without_semicolon_before_next, "let a = 1 let b = 2;");
+snapshot_test!(
+    define_class_without_fields,
+    "[Object subclass name: #User];"
+);
@@ -0,0 +1,230 @@
+//! A small web server for trying out the language from a browser.
+//!
+//! `GET /` serves an editor and `POST /run` runs the program in the request body. Programs run
+//! without any capabilities, with an empty stdin, and are stopped once they've spent `BUDGET` or
+//! after `TIMEOUT`, whichever comes first. Lists and strings longer than `MAX_LENGTH` aren't built.
+
+use crate::{
+    error::Error,
+    interpret::{interpret, CancellationToken, Interpreter},
+    parse::parse_source,
+    prep::{self, find_classes_and_methods},
+};
+use serde_json::json;
+use std::{
+    cell::RefCell,
+    io,
+    rc::Rc,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// How many statements, expressions, and calls a program may evaluate.
+const BUDGET: usize = 100_000;
+
+/// Built-in messages like `toList` on ranges build a whole list in one step, so the size of what
+/// they build is limited too.
+const MAX_LENGTH: usize = 100_000;
+
+/// Built-in messages like `sort` can take a long time while only spending one call, so programs
+/// are also cancelled after this long.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+const INDEX: &str = include_str!("playground.html");
+
+pub fn serve(port: u16) -> io::Result<()> {
+    let server =
+        Server::http(("127.0.0.1", port)).map_err(|err| io::Error::other(err.to_string()))?;
+    eprintln!("Serving the playground at http://127.0.0.1:{}", port);
+
+    for request in server.incoming_requests() {
+        if let Err(err) = handle(request) {
+            eprintln!("Failed to respond: {}", err);
+        }
+    }
+
+    Ok(())
+}
+
+fn handle(mut request: Request) -> io::Result<()> {
+    match (request.method(), request.url()) {
+        (Method::Get, "/") => {
+            let response = Response::from_string(INDEX).with_header(content_type("text/html"));
+            request.respond(response)
+        }
+        (Method::Post, "/run") => {
+            let mut source = String::new();
+            request.as_reader().read_to_string(&mut source)?;
+
+            let token = CancellationToken::new();
+            let (sender, receiver) = mpsc::channel();
+            {
+                let token = token.clone();
+                thread::Builder::new().spawn(move || sender.send(run(&source, token)))?;
+            }
+
+            // A program that's still running when the time is up is left to stop by itself once
+            // it notices it was cancelled, so it can't hold up other requests
+            let body = match receiver.recv_timeout(TIMEOUT) {
+                Ok(body) => body,
+                Err(RecvTimeoutError::Timeout) => {
+                    token.cancel();
+                    to_json(
+                        "",
+                        &[Diagnostic {
+                            code: "E0026",
+                            message: format!("The program was cancelled after {:?}", TIMEOUT),
+                            line_col: None,
+                        }],
+                    )
+                }
+                Err(RecvTimeoutError::Disconnected) => to_json(
+                    "",
+                    &[Diagnostic {
+                        code: "",
+                        message: "The interpreter crashed".to_string(),
+                        line_col: None,
+                    }],
+                ),
+            };
+
+            let response =
+                Response::from_string(body).with_header(content_type("application/json"));
+            request.respond(response)
+        }
+        _ => request.respond(Response::from_string("Not found").with_status_code(404)),
+    }
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes("Content-Type", value).expect("Internal error: invalid header")
+}
+
+/// Run `source` and return the output and diagnostics as JSON.
+fn run(source: &str, token: CancellationToken) -> String {
+    let stdout = Rc::new(RefCell::new(Vec::new()));
+    let diagnostics = run_sandboxed(source, Rc::clone(&stdout), token)
+        .into_iter()
+        .collect::<Vec<_>>();
+    let output = String::from_utf8_lossy(&stdout.borrow()).to_string();
+    to_json(&output, &diagnostics)
+}
+
+fn run_sandboxed(
+    source: &str,
+    stdout: Rc<RefCell<Vec<u8>>>,
+    token: CancellationToken,
+) -> Option<Diagnostic> {
+    let mut ast = match parse_source(source) {
+        Ok(ast) => ast,
+        Err(err) => return Some(Diagnostic::new(&err, source)),
+    };
+    prep::remove_unreachable(&mut ast);
+    prep::qualify_names(&mut ast);
+    prep::resolve_slots(&mut ast, &[]);
+    let classes = match find_classes_and_methods(&ast, prep::built_in_classes()) {
+        Ok(classes) => classes,
+        Err(err) => return Some(Diagnostic::new(&err, source)),
+    };
+
+    let mut interpreter = Interpreter::new(classes, stdout, Rc::new(RefCell::new(io::empty())))
+        .with_budget(BUDGET)
+        .with_max_length(MAX_LENGTH)
+        .with_cancellation(token);
+    interpret(&mut interpreter, &ast)
+        .err()
+        .map(|err| Diagnostic::new(&err, source))
+}
+
+struct Diagnostic {
+    code: &'static str,
+    message: String,
+    line_col: Option<crate::LineCol>,
+}
+
+impl Diagnostic {
+    fn new(err: &Error<'_>, source: &str) -> Self {
+        Self {
+            code: err.code(),
+            message: err.to_string(),
+            line_col: err.span().map(|span| span.line_col_range(source).0),
+        }
+    }
+}
+
+fn to_json(output: &str, diagnostics: &[Diagnostic]) -> String {
+    let diagnostics = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            json!({
+                "code": diagnostic.code,
+                "message": diagnostic.message,
+                "line": diagnostic.line_col.map(|line_col| line_col.line),
+                "col": diagnostic.line_col.map(|line_col| line_col.col),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({ "output": output, "diagnostics": diagnostics }).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn runs_programs_and_reports_diagnostics() {
+        assert_eq!(
+            r#"{"output":"[\"hi\"]\n","diagnostics":[]}"#,
+            run(r#"[Console println: ["hi"]];"#, CancellationToken::new())
+        );
+
+        assert_eq!(
+            r#"{"output":"1\n","diagnostics":[{"code":"E0007","message":"Undefined local variable `x` at 40 to 41","line":2,"col":19}]}"#,
+            run(
+                "[Console println: 1];\n[Console println: x];",
+                CancellationToken::new()
+            )
+        );
+    }
+
+    #[test]
+    fn cancelled_programs_report_where_they_stopped() {
+        let token = CancellationToken::new();
+        token.cancel();
+
+        assert_eq!(
+            r#"{"output":"","diagnostics":[{"code":"E0026","message":"The program was cancelled at 0 to 21","line":1,"col":1}]}"#,
+            run("[Console println: 1];", token)
+        );
+    }
+
+    #[test]
+    fn long_lists_are_not_built() {
+        assert_eq!(
+            r#"{"output":"","diagnostics":[{"code":"E0038","message":"Stopped building a value longer than 100000 at 0 to 27","line":1,"col":1}]}"#,
+            run("[[1 to: 2000000000] toList];", CancellationToken::new())
+        );
+    }
+
+    #[test]
+    fn deep_recursion_runs_on_a_normal_stack() {
+        let program = r#"
+            [Object subclass name: #Counter fields: []];
+            [Counter def: #down do: |from:| {
+                return [from == 0 caseOf: [[true, 0]] otherwise: || {
+                    return [self down from: from - 1] + 1;
+                }];
+            }];
+            [Console println: [[Counter new] down from: 1500]];
+        "#;
+        let output = thread::spawn(move || run(program, CancellationToken::new()))
+            .join()
+            .unwrap();
+
+        assert_eq!(r#"{"output":"1500\n","diagnostics":[]}"#, output);
+    }
+}
@@ -0,0 +1,199 @@
+//! Interpreters for hosts that run many short scripts against one program, like a server letting
+//! its users script it.
+//!
+//! A `Pool` finds the program's classes and runs its statements ahead of time, so handing out an
+//! interpreter is cheap. Each script gets the pool's limits and an interpreter no other script has
+//! used. Whatever a script defined or changed is dropped with its interpreter. Hosts call
+//! `Pool::refill` when they have time to spare, like between requests, to warm replacements.
+
+use crate::{
+    ast::Ast,
+    error::Result,
+    interpret::{interpret, Interpreter},
+    prep,
+};
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+};
+
+/// The limits every script run by a `Pool` gets. Limits that are `None` aren't enforced.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Limits {
+    /// See `Interpreter::with_budget`. Only what the script evaluates counts, not the program.
+    pub budget: Option<usize>,
+    /// See `Interpreter::with_max_length`.
+    pub max_length: Option<usize>,
+    /// See `Interpreter::with_max_depth`.
+    pub max_depth: Option<usize>,
+}
+
+impl Limits {
+    fn apply<'a>(&self, mut interpreter: Interpreter<'a>) -> Interpreter<'a> {
+        if let Some(budget) = self.budget {
+            interpreter = interpreter.with_budget(budget);
+        }
+        if let Some(max_length) = self.max_length {
+            interpreter = interpreter.with_max_length(max_length);
+        }
+        if let Some(max_depth) = self.max_depth {
+            interpreter = interpreter.with_max_depth(max_depth);
+        }
+        interpreter
+    }
+}
+
+/// Interpreters that have run `program` and are waiting for a script.
+pub struct Pool<'a> {
+    program: &'a Ast<'a>,
+    stdout: Rc<RefCell<dyn Write>>,
+    size: usize,
+    limits: Limits,
+    idle: Vec<Interpreter<'a>>,
+}
+
+impl<'a> Pool<'a> {
+    /// Warm `size` interpreters for `program`, which has been prepped. `Console` writes to
+    /// `stdout` while scripts run. What the program prints while it's warmed is dropped.
+    pub fn new(
+        program: &'a Ast<'a>,
+        stdout: Rc<RefCell<dyn Write>>,
+        size: usize,
+    ) -> Result<'a, Self> {
+        let mut pool = Self {
+            program,
+            stdout,
+            size,
+            limits: Limits::default(),
+            idle: Vec::with_capacity(size),
+        };
+        pool.refill()?;
+        Ok(pool)
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Run `script` with an interpreter that has run the program, like one sending a message to
+    /// one of its locals. The interpreter isn't used again afterwards. If none are idle, one is
+    /// warmed first.
+    pub fn run<T>(
+        &mut self,
+        script: impl FnOnce(&mut Interpreter<'a>) -> Result<'a, T>,
+    ) -> Result<'a, T> {
+        let interpreter = match self.idle.pop() {
+            Some(interpreter) => interpreter,
+            None => self.warm()?,
+        };
+        let mut interpreter = self
+            .limits
+            .apply(interpreter)
+            .with_stdout(Rc::clone(&self.stdout));
+        script(&mut interpreter)
+    }
+
+    /// Warm interpreters until `size` are idle. The program ran when the pool was created, so this
+    /// only fails if it depends on something like the time.
+    pub fn refill(&mut self) -> Result<'a, ()> {
+        while self.idle.len() < self.size {
+            let interpreter = self.warm()?;
+            self.idle.push(interpreter);
+        }
+        Ok(())
+    }
+
+    /// How many interpreters are ready to run a script.
+    pub fn idle(&self) -> usize {
+        self.idle.len()
+    }
+
+    fn warm(&self) -> Result<'a, Interpreter<'a>> {
+        let classes = prep::find_classes_and_methods(self.program, prep::built_in_classes())?;
+        let stdout = Rc::new(RefCell::new(io::sink()));
+        let stdin = Rc::new(RefCell::new(io::empty()));
+        let mut interpreter = Interpreter::new(classes, stdout, stdin);
+        interpret(&mut interpreter, self.program)?;
+        Ok(interpreter)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    use crate::{error::Error, parse::parse_source};
+
+    fn pool(program: &'static str, stdout: Rc<RefCell<Vec<u8>>>, limits: Limits) -> Pool<'static> {
+        let mut ast = parse_source(program).unwrap();
+        prep::remove_unreachable(&mut ast);
+        prep::qualify_names(&mut ast);
+        prep::resolve_slots(&mut ast, &[]);
+        let ast = Box::leak(Box::new(ast));
+        Pool::new(ast, stdout, 2).unwrap().with_limits(limits)
+    }
+
+    #[test]
+    fn scripts_dont_see_each_others_changes() {
+        let stdout = Rc::new(RefCell::new(Vec::new()));
+        let mut pool = pool(
+            r#"
+            [Object subclass name: #Counter fields: [#count]];
+            [Counter def: #increment do: || {
+                let @count = @count + 1;
+                [Console println: @count];
+                return @count;
+            }];
+            let counter = [Counter new count: 0];
+            [Console println: "warmed"];
+            "#,
+            Rc::clone(&stdout),
+            Limits::default(),
+        );
+
+        for idle in [1, 0, 0] {
+            pool.run(|interpreter| {
+                let counter = interpreter.local("counter").unwrap();
+                interpreter.send(counter, "increment", &[])
+            })
+            .unwrap();
+            assert_eq!(idle, pool.idle());
+        }
+        assert_eq!(
+            "1\n1\n1\n",
+            String::from_utf8(stdout.borrow().clone()).unwrap()
+        );
+
+        pool.refill().unwrap();
+        assert_eq!(2, pool.idle());
+    }
+
+    #[test]
+    fn scripts_get_the_limits() {
+        let mut pool = pool(
+            r#"
+            [Object subclass name: #Spinner fields: []];
+            [Spinner def: #spin do: || { return [self spin]; }];
+            let spinner = [Spinner new];
+            "#,
+            Rc::new(RefCell::new(Vec::new())),
+            Limits {
+                budget: Some(1000),
+                ..Limits::default()
+            },
+        );
+
+        let spin = |interpreter: &mut Interpreter<'static>| {
+            let spinner = interpreter.local("spinner").unwrap();
+            interpreter.send(spinner, "spin", &[])
+        };
+        for _ in 0..2 {
+            match pool.run(spin) {
+                Err(Error::BudgetExceeded { limit, .. }) => assert_eq!(1000, limit),
+                other => panic!("expected BudgetExceeded, got {:?}", other.map(|_| ())),
+            }
+        }
+    }
+}
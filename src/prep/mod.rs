@@ -1,40 +1,241 @@
-use crate::ast::{visit_ast, Ast, Visitor};
+use crate::ast::{visit_ast, visit_stmt, Ast, Visitor};
 use crate::{
     ast::{self, Ident},
     error::{Error, Result},
-    interpret::{ClassVTable, VTable},
+    interpret::VTable,
     Span,
 };
-use std::{collections::HashMap, rc::Rc};
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+mod modules;
+mod slots;
+mod unreachable;
+
+pub(crate) use modules::qualify;
+pub use modules::qualify_names;
+pub use slots::resolve_slots;
+pub use unreachable::remove_unreachable;
 
 pub type Classes<'a> = VTable<'a, Rc<Class<'a>>>;
 
+macro_rules! built_in_class_idents {
+    ( $( $ident:ident = $name:expr ),* $(,)? ) => {
+        $(
+            static $ident: Ident<'static> = Ident {
+                name: $name,
+                span: Span { from: 0, to: 0 },
+            };
+        )*
+    };
+}
+
+built_in_class_idents! {
+    OBJECT = "Object",
+    CONSOLE = "Console",
+    LEXER = "Lexer",
+    PARSER = "Parser",
+    RUNTIME = "Runtime",
+    MATH = "Math",
+    RANDOM = "Random",
+    JSON = "Json",
+    REGEX = "Regex",
+    SYSTEM = "System",
+    EXCEPTION = "Exception",
+    ASSERT = "Assert",
+    DYN = "Dyn",
+    STRING_BUILDER = "StringBuilder",
+    BYTES = "Bytes",
+    FILE = "File",
+    CSV = "Csv",
+    TABLE = "Table",
+    NUMBER = "Number",
+    STRING = "String",
+    BOOLEAN = "Boolean",
+    LIST = "List",
+    IO = "IO",
+    OOPS = "Oops",
+    TERM = "Term",
+    UUID = "Uuid",
+    HASH = "Hash",
+    ENCODE = "Encode",
+    PARSE = "Parse",
+    UNDEFINED_METHOD = "UndefinedMethod",
+    MISSING_ARGUMENT = "MissingArgument",
+    DIVISION_BY_ZERO = "DivisionByZero",
+    OVERFLOW = "Overflow",
+    INDEX_OUT_OF_BOUNDS = "IndexOutOfBounds",
+    BIG_INT = "BigInt",
+}
+
+/// The classes that exist in every program. Their behavior is implemented by the interpreter.
+pub fn built_in_classes<'a>() -> Classes<'a> {
+    let mut classes = Classes::new();
+    classes.insert(OBJECT.name, built_in_class(&OBJECT, &OBJECT));
+    classes.insert(CONSOLE.name, built_in_class(&CONSOLE, &OBJECT));
+    classes.insert(LEXER.name, built_in_class(&LEXER, &OBJECT));
+    classes.insert(PARSER.name, built_in_class(&PARSER, &OBJECT));
+    classes.insert(RUNTIME.name, built_in_class(&RUNTIME, &OBJECT));
+    classes.insert(MATH.name, built_in_class(&MATH, &OBJECT));
+    classes.insert(RANDOM.name, built_in_class(&RANDOM, &OBJECT));
+    classes.insert(JSON.name, built_in_class(&JSON, &OBJECT));
+    classes.insert(REGEX.name, built_in_class(&REGEX, &OBJECT));
+    classes.insert(SYSTEM.name, built_in_class(&SYSTEM, &OBJECT));
+    classes.insert(EXCEPTION.name, built_in_class(&EXCEPTION, &OBJECT));
+    classes.insert(ASSERT.name, built_in_class(&ASSERT, &OBJECT));
+    classes.insert(DYN.name, built_in_class(&DYN, &OBJECT));
+    classes.insert(BYTES.name, built_in_class(&BYTES, &OBJECT));
+    classes.insert(FILE.name, built_in_class(&FILE, &OBJECT));
+    classes.insert(CSV.name, built_in_class(&CSV, &OBJECT));
+    classes.insert(TABLE.name, built_in_class(&TABLE, &OBJECT));
+    classes.insert(NUMBER.name, built_in_class(&NUMBER, &OBJECT));
+    classes.insert(STRING.name, built_in_class(&STRING, &OBJECT));
+    classes.insert(BOOLEAN.name, built_in_class(&BOOLEAN, &OBJECT));
+    classes.insert(LIST.name, built_in_class(&LIST, &OBJECT));
+    classes.insert(IO.name, built_in_class(&IO, &OBJECT));
+    classes.insert(OOPS.name, built_in_class(&OOPS, &OBJECT));
+    classes.insert(TERM.name, built_in_class(&TERM, &OBJECT));
+    classes.insert(
+        UUID.name,
+        built_in_class_constructing(&UUID, &OBJECT, Constructor::Uuid),
+    );
+    classes.insert(HASH.name, built_in_class(&HASH, &OBJECT));
+    classes.insert(ENCODE.name, built_in_class(&ENCODE, &OBJECT));
+    classes.insert(PARSE.name, built_in_class(&PARSE, &OBJECT));
+    classes.insert(
+        UNDEFINED_METHOD.name,
+        built_in_class(&UNDEFINED_METHOD, &EXCEPTION),
+    );
+    classes.insert(
+        MISSING_ARGUMENT.name,
+        built_in_class(&MISSING_ARGUMENT, &EXCEPTION),
+    );
+    classes.insert(
+        DIVISION_BY_ZERO.name,
+        built_in_class(&DIVISION_BY_ZERO, &EXCEPTION),
+    );
+    classes.insert(OVERFLOW.name, built_in_class(&OVERFLOW, &EXCEPTION));
+    classes.insert(
+        INDEX_OUT_OF_BOUNDS.name,
+        built_in_class(&INDEX_OUT_OF_BOUNDS, &EXCEPTION),
+    );
+    classes.insert(BIG_INT.name, built_in_class(&BIG_INT, &OBJECT));
+    classes.insert(
+        STRING_BUILDER.name,
+        built_in_class_constructing(&STRING_BUILDER, &OBJECT, Constructor::StringBuilder),
+    );
+    classes
+}
+
+fn built_in_class<'a>(name: &'a Ident<'a>, super_class_name: &'a Ident<'a>) -> Rc<Class<'a>> {
+    new_built_in_class(name, super_class_name, None)
+}
+
+fn built_in_class_constructing<'a>(
+    name: &'a Ident<'a>,
+    super_class_name: &'a Ident<'a>,
+    constructor: Constructor,
+) -> Rc<Class<'a>> {
+    new_built_in_class(name, super_class_name, Some(constructor))
+}
+
+fn new_built_in_class<'a>(
+    name: &'a Ident<'a>,
+    super_class_name: &'a Ident<'a>,
+    constructor: Option<Constructor>,
+) -> Rc<Class<'a>> {
+    Rc::new(Class {
+        name: *name,
+        super_class_name,
+        super_class: None,
+        fields: VTable::new(),
+        methods: RefCell::default(),
+        method_table: RefCell::default(),
+        abstract_methods: VTable::new(),
+        module: None,
+        constructor,
+        span: name.span,
+    })
+}
+
+/// The key of the class `name` refers to when used inside `module`. The enclosing modules are
+/// searched from the innermost out, and then the classes outside modules.
+pub fn resolve_class_name<'a>(
+    classes: &Classes<'a>,
+    module: Option<&str>,
+    name: &str,
+) -> Option<&'a str> {
+    let mut module = module;
+    while let Some(current) = module {
+        let qualified = format!("{}::{}", current, name);
+        if let Some((key, _)) = classes.get_key_value(qualified.as_str()) {
+            return Some(*key);
+        }
+        module = current.rsplit_once("::").map(|(outer, _)| outer);
+    }
+    classes.get_key_value(name).map(|(key, _)| *key)
+}
+
+#[cfg_attr(feature = "observability", tracing::instrument(skip_all))]
 pub fn find_classes_and_methods<'a>(
     ast: &'a Ast<'a>,
     built_in_classes: Classes<'a>,
 ) -> Result<'a, Classes<'a>> {
     let classes = find_classes(ast, built_in_classes)?;
-    find_methods(ast, classes)
+    let mut classes = find_methods(ast, classes)?;
+    link_super_classes(&mut classes)?;
+    flatten_method_tables(&classes);
+    Ok(classes)
+}
+
+/// Fill in the method table of every class, so looking up a method doesn't have to go through the
+/// super classes. Has to be done again when a method is added while the program runs.
+pub fn flatten_method_tables(classes: &Classes<'_>) {
+    for class in classes.values() {
+        let mut chain = vec![];
+        let mut current = Some(&**class);
+        while let Some(class) = current {
+            chain.push(class);
+            current = class.super_class.as_deref();
+        }
+
+        let mut table = VTable::new();
+        for class in chain.into_iter().rev() {
+            for (name, method) in class.methods.borrow().iter() {
+                table.insert(*name, (*method, class.name.name));
+            }
+        }
+        *class.method_table.borrow_mut() = table;
+    }
 }
 
 fn find_classes<'a>(ast: &'a Ast<'a>, built_in_classes: Classes<'a>) -> Result<'a, Classes<'a>> {
     let mut f = FindClasses {
         table: built_in_classes,
+        reopened: vec![],
+        module: None,
     };
     visit_ast(&mut f, ast)?;
-    f.setup_super_classes()?;
+    f.add_reopened_fields()?;
     Ok(f.table)
 }
 
 struct FindClasses<'a> {
     table: Classes<'a>,
+    /// Classes can be reopened before the statement defining them, so the fields are added once
+    /// every class has been found.
+    reopened: Vec<(&'a ast::ReopenClass<'a>, Option<&'a str>)>,
+    /// The module the statements being visited are in.
+    module: Option<&'a str>,
 }
 
 impl<'a> Visitor<'a> for FindClasses<'a> {
     type Error = Error<'a>;
 
     fn visit_define_class(&mut self, node: &'a ast::DefineClass<'a>) -> Result<'a, ()> {
-        let name = &node.name.class_name.0;
+        let name = Ident {
+            name: &node.qualified_name,
+            span: node.name.class_name.0.span,
+        };
         let key = name.name;
 
         self.check_for_existing_class_with_same_name(key, node)?;
@@ -42,12 +243,27 @@ impl<'a> Visitor<'a> for FindClasses<'a> {
         let fields = self.make_fields(node);
 
         let super_class_name = &node.super_class.class_name.0;
-        let class = Class::new(name, super_class_name, fields, node.span);
+        let class = Class::new(name, super_class_name, fields, self.module, node.span);
 
         self.table.insert(key, Rc::new(class));
 
         Ok(())
     }
+
+    fn visit_reopen_class(&mut self, node: &'a ast::ReopenClass<'a>) -> Result<'a, ()> {
+        self.reopened.push((node, self.module));
+        Ok(())
+    }
+
+    fn visit_module(&mut self, node: &'a ast::Module<'a>) -> Result<'a, ()> {
+        let outer = self.module;
+        self.module = Some(&node.qualified_name);
+        for stmt in &node.body {
+            visit_stmt(self, stmt)?;
+        }
+        self.module = outer;
+        Ok(())
+    }
 }
 
 impl<'a> FindClasses<'a> {
@@ -58,7 +274,7 @@ impl<'a> FindClasses<'a> {
     ) -> Result<'a, ()> {
         if let Some(other) = self.table.get(key) {
             Err(Error::ClassAlreadyDefined {
-                class: &key,
+                class: key,
                 first_span: other.span,
                 second_span: node.span,
             })
@@ -67,6 +283,24 @@ impl<'a> FindClasses<'a> {
         }
     }
 
+    fn add_reopened_fields(&mut self) -> Result<'a, ()> {
+        for (node, module) in &self.reopened {
+            let class_name = node.class_name.0.name;
+            let key = resolve_class_name(&self.table, *module, class_name).unwrap_or(class_name);
+            let class = self.table.get_mut(key).ok_or(Error::ClassNotDefined {
+                class: class_name,
+                span: node.span,
+            })?;
+            let class = Rc::get_mut(class)
+                .expect("Internal error: FindClasses.table borrowed mut more than once");
+            for field in &node.fields {
+                let ident = &field.ident;
+                class.fields.insert(ident.name, Field { name: ident });
+            }
+        }
+        Ok(())
+    }
+
     fn make_fields(&self, node: &'a ast::DefineClass<'a>) -> VTable<'a, Field<'a>> {
         node.fields
             .iter()
@@ -77,54 +311,76 @@ impl<'a> FindClasses<'a> {
             })
             .collect()
     }
+}
 
-    fn setup_super_classes(&mut self) -> Result<'a, ()> {
-        let mut acc = HashMap::new();
+/// Point every class at its super class.
+///
+/// Super classes are linked before their subclasses so a class is complete, methods and all, by
+/// the time a subclass shares it.
+fn link_super_classes<'a>(classes: &mut Classes<'a>) -> Result<'a, ()> {
+    let names = classes.keys().copied().collect::<Vec<_>>();
+    let mut linked = HashSet::new();
+    for name in names {
+        link_super_class(classes, name, &mut linked, &mut vec![])?;
+    }
+    Ok(())
+}
 
-        for (class_name, class) in &self.table {
-            let super_class_name = &class.super_class_name;
+fn link_super_class<'a>(
+    classes: &mut Classes<'a>,
+    class_name: &'a str,
+    linked: &mut HashSet<&'a str>,
+    in_progress: &mut Vec<&'a str>,
+) -> Result<'a, ()> {
+    // Object isn't supposed to have a super class
+    if class_name == "Object" || linked.contains(class_name) {
+        return Ok(());
+    }
 
-            // Object isn't supposed to have a super class
-            if class_name == &"Object" {
-                continue;
-            }
+    let (super_class_name, module, span) = {
+        let class = &classes[class_name];
+        (class.super_class_name, class.module, class.span)
+    };
+    let not_defined = || Error::ClassNotDefined {
+        class: super_class_name.name,
+        span,
+    };
+    let super_class_key = resolve_class_name(classes, module, super_class_name.name);
 
-            let super_class =
-                self.table
-                    .get(&super_class_name.name)
-                    .ok_or_else(|| Error::ClassNotDefined {
-                        class: super_class_name.name,
-                        span: class.span,
-                    })?;
-            let super_class = Rc::clone(&super_class);
+    // A class that inherits from itself, directly or not, has no super class that's defined
+    // before it
+    let super_class_key = match super_class_key {
+        Some(key) if !in_progress.contains(&class_name) => key,
+        _ => return Err(not_defined()),
+    };
 
-            acc.insert(*class_name, (super_class, class.span));
-        }
+    in_progress.push(class_name);
+    link_super_class(classes, super_class_key, linked, in_progress)?;
+    in_progress.pop();
 
-        for (class_name, (super_class, span)) in acc {
-            let mut class =
-                self.table
-                    .get_mut(class_name)
-                    .ok_or_else(|| Error::ClassNotDefined {
-                        class: class_name,
-                        span,
-                    })?;
-
-            Rc::get_mut(&mut class)
-                .expect("Internal error: Rc borrowed mut more than once")
-                .super_class = Some(super_class);
-        }
+    let super_class = Rc::clone(&classes[super_class_key]);
+    let class = classes
+        .get_mut(class_name)
+        .expect("Internal error: class removed while linking super classes");
+    Rc::get_mut(class)
+        .expect("Internal error: Rc borrowed mut more than once")
+        .super_class = Some(super_class);
+    linked.insert(class_name);
 
-        Ok(())
-    }
+    Ok(())
 }
 
 struct FindMethods<'a> {
     classes: Classes<'a>,
+    /// The module the statements being visited are in.
+    module: Option<&'a str>,
 }
 
 fn find_methods<'a>(ast: &'a Ast<'a>, classes: Classes<'a>) -> Result<'a, Classes<'a>> {
-    let mut f = FindMethods { classes };
+    let mut f = FindMethods {
+        classes,
+        module: None,
+    };
     visit_ast(&mut f, ast)?;
     Ok(f.classes)
 }
@@ -136,85 +392,134 @@ impl<'a> Visitor<'a> for FindMethods<'a> {
         let method_name = &node.method_name.ident;
         let key = method_name.name;
 
-        let class_name = &node.class_name.0.name;
+        let class_name = self.class_key(node.class_name.0.name);
 
         {
-            let class = self
-                .classes
-                .get(class_name)
-                .ok_or_else(|| Error::ClassNotDefined {
-                    class: class_name,
-                    span: node.span,
-                })?;
+            let class = self.classes.get(class_name).ok_or(Error::ClassNotDefined {
+                class: class_name,
+                span: node.span,
+            })?;
             self.check_for_existing_method_with_same_name(class, key, node)?;
         }
 
-        let method = self.make_method(method_name, &node.block, node.span);
+        let method = Method::new(node, self.module);
 
-        let mut class = self
+        let class = self
             .classes
             .get_mut(class_name)
-            .ok_or_else(|| Error::ClassNotDefined {
+            .ok_or(Error::ClassNotDefined {
                 class: class_name,
                 span: node.span,
             })?;
-        let class = Rc::get_mut(&mut class)
+        let class = Rc::get_mut(class)
             .expect("Internal error: FindMethods.classes borrowed mut more than once");
-        class.methods.insert(key, method);
+        class.methods.get_mut().insert(key, method);
 
         Ok(())
     }
+
+    fn visit_define_abstract_method(
+        &mut self,
+        node: &'a ast::DefineAbstractMethod<'a>,
+    ) -> Result<'a, ()> {
+        let key = node.method_name.ident.name;
+        let class_name = self.class_key(node.class_name.0.name);
+
+        let class = self
+            .classes
+            .get_mut(class_name)
+            .ok_or(Error::ClassNotDefined {
+                class: class_name,
+                span: node.span,
+            })?;
+        if let Some(first_span) = class.existing_method_span(key) {
+            return Err(Error::MethodAlreadyDefined {
+                class: class.name.name,
+                method: key,
+                first_span,
+                second_span: node.span,
+            });
+        }
+
+        let class = Rc::get_mut(class)
+            .expect("Internal error: FindMethods.classes borrowed mut more than once");
+        class.abstract_methods.insert(key, node.span);
+
+        Ok(())
+    }
+
+    fn visit_module(&mut self, node: &'a ast::Module<'a>) -> Result<'a, ()> {
+        let outer = self.module;
+        self.module = Some(&node.qualified_name);
+        for stmt in &node.body {
+            visit_stmt(self, stmt)?;
+        }
+        self.module = outer;
+        Ok(())
+    }
 }
 
 impl<'a> FindMethods<'a> {
+    /// The key of the class `name` refers to in the current module, or `name` if there's no such
+    /// class so the error mentions the name that was used.
+    fn class_key(&self, name: &'a str) -> &'a str {
+        resolve_class_name(&self.classes, self.module, name).unwrap_or(name)
+    }
+
     fn check_for_existing_method_with_same_name(
         &self,
         class: &Class<'a>,
         key: &'a str,
         node: &'a ast::DefineMethod<'a>,
     ) -> Result<'a, ()> {
-        if let Some(other) = class.methods.get(key) {
-            return Err(Error::MethodAlreadyDefined {
+        if let Some(first_span) = class.existing_method_span(key) {
+            Err(Error::MethodAlreadyDefined {
                 class: class.name.name,
                 method: key,
-                first_span: other.span,
+                first_span,
                 second_span: node.span,
-            });
+            })
         } else {
             Ok(())
         }
     }
-
-    fn make_method(
-        &self,
-        method_name: &'a Ident<'a>,
-        block: &'a ast::Block<'a>,
-        span: Span,
-    ) -> Method<'a> {
-        Method {
-            name: method_name,
-            parameters: &block.parameters,
-            body: &block.body,
-            span,
-        }
-    }
 }
 
 #[derive(Debug)]
 pub struct Class<'a> {
-    pub name: &'a Ident<'a>,
+    pub name: Ident<'a>,
     pub super_class_name: &'a Ident<'a>,
     pub super_class: Option<Rc<Class<'a>>>,
     pub fields: VTable<'a, Field<'a>>,
-    pub methods: VTable<'a, Method<'a>>,
+    /// Methods can be added while the program runs, so they're behind a `RefCell`.
+    pub methods: RefCell<VTable<'a, Method<'a>>>,
+    /// Every method instances of the class respond to, including inherited ones, and the class
+    /// defining each of them. Filled in by `flatten_method_tables`.
+    pub method_table: RefCell<VTable<'a, (Method<'a>, &'a str)>>,
+    /// Methods declared with `defAbstract:`, and where they were declared.
+    pub abstract_methods: VTable<'a, Span>,
+    /// The module the class was defined in, which its super class name is resolved in.
+    pub module: Option<&'a str>,
+    /// What `[Class new]` creates for built-in classes whose values aren't instances. `None` for
+    /// classes of instances.
+    pub constructor: Option<Constructor>,
     pub span: Span,
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Constructor {
+    /// An empty `StringBuilder`.
+    StringBuilder,
+    /// A random UUID string.
+    Uuid,
+}
+
 impl<'a> Class<'a> {
     fn new(
-        name: &'a Ident<'a>,
+        name: Ident<'a>,
         super_class_name: &'a Ident<'a>,
         fields: VTable<'a, Field<'a>>,
+        module: Option<&'a str>,
         span: Span,
     ) -> Self {
         Self {
@@ -222,7 +527,11 @@ impl<'a> Class<'a> {
             fields,
             super_class_name,
             super_class: None,
-            methods: VTable::new(),
+            methods: RefCell::default(),
+            method_table: RefCell::default(),
+            abstract_methods: VTable::new(),
+            module,
+            constructor: None,
             span,
         }
     }
@@ -231,24 +540,87 @@ impl<'a> Class<'a> {
         &self,
         method_name: &'a str,
         call_site: Span,
-    ) -> Result<'a, &Method<'a>> {
-        let method = self.methods.get(method_name);
+    ) -> Result<'a, Method<'a>> {
+        self.find_method(method_name).ok_or_else(|| {
+            if self.is_abstract(method_name) {
+                Error::AbstractMethodsNotImplemented {
+                    class: self.name.name,
+                    methods: vec![method_name],
+                    span: call_site,
+                }
+            } else {
+                Error::UndefinedMethod {
+                    class: self.name.name,
+                    method: method_name,
+                    span: call_site,
+                }
+            }
+        })
+    }
 
-        if let Some(method) = method {
-            return Ok(method);
+    /// The abstract methods declared by this class or its super classes that nothing implements,
+    /// sorted by name.
+    pub fn missing_implementations(&self) -> Vec<&'a str> {
+        let mut missing = vec![];
+        let mut class = Some(self);
+        while let Some(current) = class {
+            for name in current.abstract_methods.keys() {
+                if self.find_method(name).is_none() && !missing.contains(name) {
+                    missing.push(*name);
+                }
+            }
+            class = current.super_class.as_deref();
         }
+        missing.sort_unstable();
+        missing
+    }
 
-        if let Some(super_class) = &self.super_class {
-            // TODO: Change method name of returned error
-            // Otherwise it'll always be "Object"
-            return super_class.get_method_named(method_name, call_site);
+    /// Add a method while the program is running, for definitions inside blocks and methods.
+    ///
+    /// Definitions prep has already added are skipped, so running the same definition more than
+    /// once is fine. Defining a method the class already has some other way is an error, like it
+    /// is before the program runs.
+    pub fn define_method(&self, method: Method<'a>) -> Result<'a, ()> {
+        let key = method.name.name;
+        match self.existing_method_span(key) {
+            Some(span) if span == method.span => Ok(()),
+            Some(first_span) => Err(Error::MethodAlreadyDefined {
+                class: self.name.name,
+                method: key,
+                first_span,
+                second_span: method.span,
+            }),
+            None => {
+                self.methods.borrow_mut().insert(key, method);
+                Ok(())
+            }
         }
+    }
 
-        Err(Error::UndefinedMethod {
-            class: &self.name.name,
-            method: method_name,
-            span: call_site,
-        })
+    /// Where the method or abstract method named `key` was defined on this class, if it has been.
+    fn existing_method_span(&self, key: &str) -> Option<Span> {
+        let span = self.methods.borrow().get(key).map(|method| method.span);
+        span.or_else(|| self.abstract_methods.get(key).copied())
+    }
+
+    fn is_abstract(&self, method_name: &str) -> bool {
+        self.abstract_methods.contains_key(method_name)
+            || self
+                .super_class
+                .as_ref()
+                .is_some_and(|super_class| super_class.is_abstract(method_name))
+    }
+
+    /// Look up a method on this class or any of its super classes.
+    pub fn find_method(&self, method_name: &str) -> Option<Method<'a>> {
+        let table = self.method_table.borrow();
+        table.get(method_name).map(|(method, _)| *method)
+    }
+
+    /// The class defining the method instances of this class run for `method_name`.
+    pub fn method_owner(&self, method_name: &str) -> Option<&'a str> {
+        let table = self.method_table.borrow();
+        table.get(method_name).map(|(_, owner)| *owner)
     }
 }
 
@@ -257,14 +629,28 @@ pub struct Field<'a> {
     pub name: &'a Ident<'a>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Method<'a> {
     pub name: &'a Ident<'a>,
     pub parameters: &'a Vec<ast::Parameter<'a>>,
     pub body: &'a Vec<ast::Stmt<'a>>,
+    /// The module the method was defined in, which class names in its body are resolved in.
+    pub module: Option<&'a str>,
     pub span: Span,
 }
 
+impl<'a> Method<'a> {
+    pub fn new(node: &'a ast::DefineMethod<'a>, module: Option<&'a str>) -> Self {
+        Self {
+            name: &node.method_name.ident,
+            parameters: &node.block.parameters,
+            body: &node.block.body,
+            module,
+            span: node.span,
+        }
+    }
+}
+
 // TODO: Bring back
 // #[cfg(test)]
 // mod test {
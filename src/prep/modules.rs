@@ -0,0 +1,34 @@
+//! Naming classes and modules after the modules they're defined in.
+//!
+//! Classes are found at the top level and in module bodies, so only those are looked through.
+
+use crate::ast::{Ast, Stmt};
+
+/// Set the qualified name of every class and module in `ast`, like `Http::Request` for the class
+/// `Request` in `module Http { ... }`.
+pub fn qualify_names(ast: &mut Ast<'_>) {
+    qualify_stmts(ast, None);
+}
+
+fn qualify_stmts(stmts: &mut [Stmt<'_>], module: Option<&str>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::DefineClass(inner) => {
+                inner.qualified_name = qualify(module, inner.name.class_name.0.name);
+            }
+            Stmt::Module(inner) => {
+                inner.qualified_name = qualify(module, inner.name.0.name);
+                qualify_stmts(&mut inner.body, Some(&inner.qualified_name));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// `name` as it's called outside `module`.
+pub(crate) fn qualify(module: Option<&str>, name: &str) -> String {
+    match module {
+        None => name.to_string(),
+        Some(module) => format!("{}::{}", module, name),
+    }
+}
@@ -0,0 +1,196 @@
+//! Numbering the locals of each scope, so the interpreter can find them by slot rather than name.
+//!
+//! Slots are numbered in the order locals are defined. A block's scope starts with the locals of
+//! the scope it's written in, since its frame starts as a copy of that one, and a method's scope
+//! starts with its parameters. Module bodies share the scope they're written in.
+
+use crate::ast::{Ast, Block, CaseOf, Expr, MessageSend, Parameter, Slot, Stmt};
+
+/// Set the slots of the locals in `ast`, which runs in a frame that already has `locals`.
+///
+/// Locals used before they're defined get no slot, and are undefined when they're evaluated.
+pub fn resolve_slots<'a>(ast: &mut Ast<'a>, locals: &[&'a str]) {
+    let mut scope = Scope {
+        names: locals.to_vec(),
+    };
+    scope.stmts(ast);
+}
+
+struct Scope<'a> {
+    /// The name of the local in each slot.
+    names: Vec<&'a str>,
+}
+
+impl<'a> Scope<'a> {
+    fn slot(&self, name: &str) -> Slot {
+        Slot(self.names.iter().position(|slot| *slot == name))
+    }
+
+    fn define(&mut self, name: &'a str) -> Slot {
+        match self.slot(name) {
+            Slot(Some(slot)) => Slot(Some(slot)),
+            Slot(None) => {
+                self.names.push(name);
+                Slot(Some(self.names.len() - 1))
+            }
+        }
+    }
+
+    fn parameters(&mut self, parameters: &mut [Parameter<'a>]) {
+        for param in parameters {
+            param.slot = self.define(param.ident.name);
+        }
+    }
+
+    fn stmts(&mut self, stmts: &mut [Stmt<'a>]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt<'a>) {
+        match stmt {
+            Stmt::LetLocal(inner) => {
+                self.expr(&mut inner.body);
+                inner.slot = self.define(inner.ident.name);
+            }
+            Stmt::LetIVar(inner) => self.expr(&mut inner.body),
+            Stmt::LetConstant(inner) => self.expr(&mut inner.body),
+            Stmt::MessageSend(inner) => self.message_send(&mut inner.expr),
+            Stmt::CaseOf(inner) => self.case_of(&mut inner.expr),
+            Stmt::Return(inner) => self.expr(&mut inner.expr),
+            Stmt::DefineMethod(inner) => {
+                let mut method = Scope { names: vec![] };
+                method.block(&mut inner.block);
+            }
+            Stmt::Module(inner) => self.stmts(&mut inner.body),
+            Stmt::DefineAbstractMethod(_) | Stmt::DefineClass(_) | Stmt::ReopenClass(_) => {}
+        }
+    }
+
+    fn block(&mut self, block: &mut Block<'a>) {
+        self.parameters(&mut block.parameters);
+        self.stmts(&mut block.body);
+    }
+
+    fn expr(&mut self, node: &mut Expr<'a>) {
+        match node {
+            Expr::Local(inner) => inner.1 = self.slot(inner.0.name),
+            Expr::Unquote(inner) => inner.slot = self.slot(inner.ident.name),
+            Expr::MessageSend(inner) => self.message_send(inner),
+            Expr::ClassNew(inner) => {
+                for arg in &mut inner.args {
+                    self.expr(&mut arg.expr);
+                }
+            }
+            Expr::Block(inner) => {
+                let mut block = Scope {
+                    names: self.names.clone(),
+                };
+                block.block(inner);
+            }
+            Expr::List(inner) | Expr::ConstantList(inner) => {
+                for item in &mut inner.items {
+                    self.expr(item);
+                }
+            }
+            Expr::CaseOf(inner) => self.case_of(inner),
+            Expr::Quote(inner) => Quoted(self).stmts(&mut inner.body),
+            Expr::IVar(_)
+            | Expr::ClassName(_)
+            | Expr::Number(_)
+            | Expr::Str(_)
+            | Expr::Selector(_)
+            | Expr::True(_)
+            | Expr::False(_)
+            | Expr::Self_(_) => {}
+        }
+    }
+
+    fn message_send(&mut self, send: &mut MessageSend<'a>) {
+        self.expr(&mut send.receiver);
+        for arg in &mut send.args {
+            self.expr(&mut arg.expr);
+        }
+    }
+
+    fn case_of(&mut self, case_of: &mut CaseOf<'a>) {
+        self.expr(&mut case_of.subject);
+        for arm in &mut case_of.arms {
+            self.expr(&mut arm.value);
+            self.expr(&mut arm.body);
+        }
+        if let Some(otherwise) = &mut case_of.otherwise {
+            self.expr(otherwise);
+        }
+    }
+}
+
+/// Quoted statements are data, so only the unquotes in them are resolved. They're evaluated in
+/// the scope of the quote, wherever they're nested.
+struct Quoted<'s, 'a>(&'s Scope<'a>);
+
+impl<'a> Quoted<'_, 'a> {
+    fn stmts(&self, stmts: &mut [Stmt<'a>]) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::LetLocal(inner) => self.expr(&mut inner.body),
+                Stmt::LetIVar(inner) => self.expr(&mut inner.body),
+                Stmt::LetConstant(inner) => self.expr(&mut inner.body),
+                Stmt::MessageSend(inner) => self.message_send(&mut inner.expr),
+                Stmt::CaseOf(inner) => self.case_of(&mut inner.expr),
+                Stmt::Return(inner) => self.expr(&mut inner.expr),
+                Stmt::DefineMethod(inner) => self.stmts(&mut inner.block.body),
+                Stmt::Module(inner) => self.stmts(&mut inner.body),
+                Stmt::DefineAbstractMethod(_) | Stmt::DefineClass(_) | Stmt::ReopenClass(_) => {}
+            }
+        }
+    }
+
+    fn expr(&self, node: &mut Expr<'a>) {
+        match node {
+            Expr::Unquote(inner) => inner.slot = self.0.slot(inner.ident.name),
+            Expr::MessageSend(inner) => self.message_send(inner),
+            Expr::ClassNew(inner) => {
+                for arg in &mut inner.args {
+                    self.expr(&mut arg.expr);
+                }
+            }
+            Expr::Block(inner) => self.stmts(&mut inner.body),
+            Expr::List(inner) | Expr::ConstantList(inner) => {
+                for item in &mut inner.items {
+                    self.expr(item);
+                }
+            }
+            Expr::CaseOf(inner) => self.case_of(inner),
+            Expr::Quote(inner) => self.stmts(&mut inner.body),
+            Expr::Local(_)
+            | Expr::IVar(_)
+            | Expr::ClassName(_)
+            | Expr::Number(_)
+            | Expr::Str(_)
+            | Expr::Selector(_)
+            | Expr::True(_)
+            | Expr::False(_)
+            | Expr::Self_(_) => {}
+        }
+    }
+
+    fn message_send(&self, send: &mut MessageSend<'a>) {
+        self.expr(&mut send.receiver);
+        for arg in &mut send.args {
+            self.expr(&mut arg.expr);
+        }
+    }
+
+    fn case_of(&self, case_of: &mut CaseOf<'a>) {
+        self.expr(&mut case_of.subject);
+        for arm in &mut case_of.arms {
+            self.expr(&mut arm.value);
+            self.expr(&mut arm.body);
+        }
+        if let Some(otherwise) = &mut case_of.otherwise {
+            self.expr(otherwise);
+        }
+    }
+}
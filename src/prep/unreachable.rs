@@ -0,0 +1,107 @@
+//! Removing statements that come after a `return`, which can never run.
+//!
+//! Every parsed program goes through this, so the interpreter can run the statements it's given
+//! without checking whether it has already returned. Classes and methods defined at the top level
+//! or in modules are kept, since they're added before the program runs, wherever they are.
+
+use crate::ast::{Ast, CaseOf, Expr, MessageSend, Stmt};
+
+pub fn remove_unreachable(ast: &mut Ast<'_>) {
+    remove_after_return(ast, true);
+}
+
+/// Remove the statements after the first one that returns, and return whether there was one.
+/// Definitions are kept when `keep_definitions` is set.
+fn remove_after_return(stmts: &mut Vec<Stmt<'_>>, keep_definitions: bool) -> bool {
+    let mut returned = false;
+    stmts.retain_mut(|stmt| {
+        if returned {
+            return keep_definitions && is_definition(stmt);
+        }
+        returned = stmt_returns(stmt, keep_definitions);
+        true
+    });
+    returned
+}
+
+fn is_definition(stmt: &Stmt<'_>) -> bool {
+    matches!(
+        stmt,
+        Stmt::DefineMethod(_)
+            | Stmt::DefineAbstractMethod(_)
+            | Stmt::DefineClass(_)
+            | Stmt::ReopenClass(_)
+    )
+}
+
+/// Remove unreachable statements from the blocks and methods in `stmt`, and return whether it
+/// returns. A module returns when its body does, since its statements run in the outer scope.
+fn stmt_returns(stmt: &mut Stmt<'_>, keep_definitions: bool) -> bool {
+    match stmt {
+        Stmt::LetLocal(inner) => expr(&mut inner.body),
+        Stmt::LetIVar(inner) => expr(&mut inner.body),
+        Stmt::LetConstant(inner) => expr(&mut inner.body),
+        Stmt::MessageSend(inner) => message_send(&mut inner.expr),
+        Stmt::CaseOf(inner) => case_of(&mut inner.expr),
+        Stmt::Return(inner) => {
+            expr(&mut inner.expr);
+            return true;
+        }
+        Stmt::DefineMethod(inner) => {
+            remove_after_return(&mut inner.block.body, false);
+        }
+        Stmt::Module(inner) => return remove_after_return(&mut inner.body, keep_definitions),
+        Stmt::DefineAbstractMethod(_) | Stmt::DefineClass(_) | Stmt::ReopenClass(_) => {}
+    }
+    false
+}
+
+fn expr(node: &mut Expr<'_>) {
+    match node {
+        Expr::MessageSend(inner) => message_send(inner),
+        Expr::ClassNew(inner) => {
+            for arg in &mut inner.args {
+                expr(&mut arg.expr);
+            }
+        }
+        Expr::Block(inner) => {
+            remove_after_return(&mut inner.body, false);
+        }
+        Expr::List(inner) | Expr::ConstantList(inner) => {
+            for item in &mut inner.items {
+                expr(item);
+            }
+        }
+        Expr::CaseOf(inner) => case_of(inner),
+        // Quoted statements are data, and are checked if they're parsed again to be run
+        Expr::Quote(_)
+        | Expr::Local(_)
+        | Expr::IVar(_)
+        | Expr::ClassName(_)
+        | Expr::Number(_)
+        | Expr::Str(_)
+        | Expr::Selector(_)
+        | Expr::True(_)
+        | Expr::False(_)
+        | Expr::Self_(_)
+        | Expr::Unquote(_) => {}
+    }
+}
+
+fn message_send(send: &mut MessageSend<'_>) {
+    expr(&mut send.receiver);
+    for arg in &mut send.args {
+        expr(&mut arg.expr);
+    }
+}
+
+fn case_of(case_of: &mut CaseOf<'_>) {
+    expr(&mut case_of.subject);
+    for arm in &mut case_of.arms {
+        expr(&mut arm.value);
+        expr(&mut arm.body);
+    }
+    if let Some(otherwise) = &mut case_of.otherwise {
+        expr(otherwise);
+    }
+}
@@ -0,0 +1,135 @@
+//! An interactive prompt for running statements one at a time, for `oops repl`.
+//!
+//! Statements run in a single interpreter as soon as they're complete, so locals defined in one
+//! can be used in the following ones. Input that ends inside brackets or braces continues on the
+//! next line. Methods can be defined, but classes are found before the program runs, so they have
+//! to be defined in a file.
+
+use crate::{
+    ast::{Ast, Stmt},
+    error::{print_error, Error},
+    interpret::{interpret_stmt, Capabilities, EvalArena, Interpreter},
+    lex::lex,
+    parse::{parse, parse_interactive, Interactive},
+    prep::{self, find_classes_and_methods},
+};
+use std::{
+    cell::RefCell,
+    io::{self, BufRead, Write},
+    path::Path,
+    rc::Rc,
+};
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = ". ";
+
+/// Read and run statements from stdin until it's closed. `prelude` runs first, like it does before
+/// programs.
+pub fn run(capabilities: Capabilities, prelude: Option<&'static str>) -> io::Result<()> {
+    let prelude = leak_ast(prelude.unwrap_or(""))
+        .unwrap_or_else(|err| panic!("Internal error: prelude doesn't parse: {}", err));
+    let classes = find_classes_and_methods(prelude, prep::built_in_classes())
+        .unwrap_or_else(|err| panic!("Internal error: prelude doesn't prep: {}", err));
+
+    let eval_arena = EvalArena::new();
+    let stdout = Rc::new(RefCell::new(io::stdout()));
+    // The program reads from the same stdin as the prompt, so `[IO readLine]` reads the next line
+    // typed
+    let stdin: Rc<RefCell<dyn BufRead>> = Rc::new(RefCell::new(io::stdin().lock()));
+    let mut interpreter = Interpreter::new(
+        classes,
+        Rc::clone(&stdout) as Rc<RefCell<dyn Write>>,
+        Rc::clone(&stdin),
+    )
+    .with_capabilities(capabilities)
+    .with_eval_arena(&eval_arena);
+    for stmt in prelude {
+        interpret_stmt(&mut interpreter, stmt)
+            .unwrap_or_else(|err| panic!("Internal error: prelude failed: {}", err));
+    }
+
+    let mut input = String::new();
+    loop {
+        print!(
+            "{}",
+            if input.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            }
+        );
+        io::stdout().flush()?;
+        if stdin.borrow_mut().read_line(&mut input)? == 0 {
+            println!();
+            return Ok(());
+        }
+
+        let source: &'static str = Box::leak(input.clone().into_boxed_str());
+        let tokens = match lex(source) {
+            Ok(tokens) => Box::leak(Box::new(tokens)),
+            Err(err) => {
+                print_error(Path::new("<repl>"), source, &err);
+                input.clear();
+                continue;
+            }
+        };
+        let ast = match parse_interactive(tokens) {
+            Ok(Interactive::Complete(mut ast)) => {
+                prep::remove_unreachable(&mut ast);
+                prep::qualify_names(&mut ast);
+                prep::resolve_slots(&mut ast, interpreter.local_names());
+                Box::leak(Box::new(ast))
+            }
+            Ok(Interactive::Incomplete) => continue,
+            Err(err) => {
+                print_error(Path::new("<repl>"), source, &err);
+                input.clear();
+                continue;
+            }
+        };
+        input.clear();
+
+        for stmt in ast.iter() {
+            let result = match unsupported(stmt) {
+                Some(message) => Err(Error::InvalidSource {
+                    message: message.to_string(),
+                    span: stmt.span(),
+                }),
+                None => interpret_stmt(&mut interpreter, stmt),
+            };
+            stdout.borrow_mut().flush()?;
+
+            match result {
+                Ok(()) => {}
+                Err(Error::Exit { status, .. }) => std::process::exit(status),
+                Err(err) => {
+                    print_error(Path::new("<repl>"), source, &err);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn leak_ast(source: &'static str) -> Result<&'static Ast<'static>, Error<'static>> {
+    let tokens = Box::leak(Box::new(lex(source)?));
+    let mut ast = parse(tokens)?;
+    prep::remove_unreachable(&mut ast);
+    prep::qualify_names(&mut ast);
+    prep::resolve_slots(&mut ast, &[]);
+    Ok(Box::leak(Box::new(ast)))
+}
+
+/// Why `stmt` can't be run in the REPL, if it can't.
+fn unsupported(stmt: &Stmt<'_>) -> Option<&'static str> {
+    match stmt {
+        Stmt::DefineClass(_)
+        | Stmt::Module(_)
+        | Stmt::ReopenClass(_)
+        | Stmt::DefineAbstractMethod(_) => Some("classes can't be defined in the REPL"),
+        // A value returned at the top level stops the program, which would ignore everything
+        // typed afterwards
+        Stmt::Return(_) => Some("`return` can't be used outside blocks and methods in the REPL"),
+        _ => None,
+    }
+}
@@ -0,0 +1,116 @@
+//! Runs every program in `tests/diagnostics` and checks that it fails with the expected error.
+//!
+//! Each program starts with comments describing the error it should produce:
+//!
+//! ```text
+//! // code: E0007
+//! // message: Undefined local variable `b`
+//! // span: 4:9
+//! ```
+//!
+//! `message` only has to be a substring of the printed message and `span` is the line and column
+//! the error points at. `span` can be left out for errors that don't have a location. An `args`
+//! line, like `// args: --max-steps 100`, gives options to pass before the program's path.
+
+use std::{fs, path::Path, process::Command};
+
+struct Expected {
+    code: String,
+    message: String,
+    span: Option<String>,
+    args: Vec<String>,
+}
+
+impl Expected {
+    fn from_program(program: &str) -> Expected {
+        let mut code = None;
+        let mut message = None;
+        let mut span = None;
+        let mut args = vec![];
+
+        for line in program.lines() {
+            let line = match line.strip_prefix("// ") {
+                Some(line) => line,
+                None => break,
+            };
+
+            if let Some(value) = line.strip_prefix("code: ") {
+                code = Some(value.to_string());
+            } else if let Some(value) = line.strip_prefix("message: ") {
+                message = Some(value.to_string());
+            } else if let Some(value) = line.strip_prefix("span: ") {
+                span = Some(value.to_string());
+            } else if let Some(value) = line.strip_prefix("args: ") {
+                args = value.split_whitespace().map(str::to_string).collect();
+            }
+        }
+
+        Expected {
+            code: code.expect("missing `// code:`"),
+            message: message.expect("missing `// message:`"),
+            span,
+            args,
+        }
+    }
+}
+
+fn check(path: &Path) -> Result<(), String> {
+    let program = fs::read_to_string(path).unwrap();
+    let expected = Expected::from_program(&program);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_oops"))
+        .args(&expected.args)
+        .arg(path)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    if output.status.success() {
+        return Err(format!("expected an error but it succeeded:\n{}", stderr));
+    }
+
+    let location = match &expected.span {
+        Some(span) => format!("{}:{}: ", path.display(), span),
+        None => format!("{}: ", path.display()),
+    };
+    let prefix = format!("{}error[{}]: ", location, expected.code);
+
+    let matches = stderr
+        .lines()
+        .any(|line| line.starts_with(&prefix) && line.contains(&expected.message));
+
+    if matches {
+        Ok(())
+    } else {
+        Err(format!(
+            "expected `{}...{}...` but got:\n{}",
+            prefix, expected.message, stderr
+        ))
+    }
+}
+
+#[test]
+fn diagnostics() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/diagnostics");
+
+    let mut paths = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "oops"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    let failures = paths
+        .iter()
+        .filter_map(|path| {
+            check(path)
+                .err()
+                .map(|err| format!("{}: {}", path.display(), err))
+        })
+        .collect::<Vec<_>>();
+
+    if !failures.is_empty() {
+        panic!("\n\n{}\n", failures.join("\n\n"));
+    }
+}
@@ -0,0 +1,76 @@
+//! Runs programs the way a host embedding the language would, through the library's public API.
+
+use oops::{
+    interpret::{interpret, Interpreter, OopsObject, Value},
+    lex::{lex_with_trivia, Trivia},
+    parse::parse_source,
+    prep,
+};
+use std::{cell::RefCell, io, rc::Rc};
+
+fn run(program: &'static str) -> Interpreter<'static> {
+    let mut ast = parse_source(program).unwrap();
+    prep::remove_unreachable(&mut ast);
+    prep::qualify_names(&mut ast);
+    prep::resolve_slots(&mut ast, &[]);
+    let ast = Box::leak(Box::new(ast));
+    let classes = prep::find_classes_and_methods(ast, prep::built_in_classes()).unwrap();
+
+    let stdout = Rc::new(RefCell::new(io::sink()));
+    let stdin = Rc::new(RefCell::new(io::empty()));
+    let mut interpreter = Interpreter::new(classes, stdout, stdin);
+    interpret(&mut interpreter, ast).unwrap();
+    interpreter
+}
+
+#[derive(Debug)]
+struct Celsius(i32);
+
+impl OopsObject for Celsius {
+    fn class_name(&self) -> &'static str {
+        "Celsius"
+    }
+
+    fn responds_to(&self, selector: &str) -> bool {
+        selector == "degrees"
+    }
+
+    fn send<'a>(&self, _: &str, _: Vec<(&'a str, Value<'a>)>) -> Result<Value<'a>, String> {
+        Ok(Value::Number(self.0))
+    }
+}
+
+#[test]
+fn sending_messages_to_locals_and_host_objects() {
+    let mut interpreter = run(r#"
+        [Object subclass name: #Thermometer fields: []];
+        [Thermometer def: #read do: |read:| { return [read degrees] + 1; }];
+        let thermometer = [Thermometer new];
+    "#);
+
+    let thermometer = interpreter.local("thermometer").unwrap();
+    let reading = interpreter
+        .send(
+            thermometer,
+            "read",
+            &[("read", Value::Object(Rc::new(Celsius(20))))],
+        )
+        .unwrap();
+
+    assert_eq!("21", reading.to_string());
+    assert!(interpreter.local("missing").is_none());
+}
+
+#[test]
+fn lexing_with_trivia() {
+    let lexed = lex_with_trivia("// hi\nlet a = 1;").unwrap();
+
+    assert_eq!(5, lexed.tokens.len());
+    assert!(matches!(
+        lexed.leading_trivia[0].as_slice(),
+        [
+            Trivia::Comment { text: "// hi", .. },
+            Trivia::WhiteSpace { .. }
+        ]
+    ));
+}